@@ -3,7 +3,6 @@ extern crate libsmt;
 extern crate r2pipe;
 extern crate r2api;
 
-use std::collections::HashMap;
 
 use r2pipe::r2::R2;
 use r2api::structs::LRegInfo;
@@ -24,7 +23,8 @@ use rune::regstore::regfile::RuneRegFile;
 use rune::engine::engine::Engine;
 use rune::engine::rune::Rune;
 
-use rune::utils::utils::{new_rune_ctx, Key};
+use rune::context::builder::ContextBuilder;
+use rune::utils::utils::Key;
 
 use libsmt::backends::smtlib2::SMTLib2;
 
@@ -38,14 +38,13 @@ fn main() {
     let break_addr = 0x00400515;
     let branch = 0x00400513;
 
-    let mut syms = HashMap::new();
-    syms.insert(Key::Mem(bp-0x8), 8);
-
-    let mut consts = HashMap::new();
-    consts.insert(Key::Reg(String::from("rbp")), (bp as u64, 64 as u64));
-
     // Context
-    let mut ctx = new_rune_ctx(Some(ip), Some(syms), Some(consts), &mut stream);
+    let mut ctx = ContextBuilder::new()
+                      .ip(ip)
+                      .symbolic(Key::Mem(bp - 0x8), 8)
+                      .constant(Key::Reg(String::from("rbp")), bp as u64)
+                      .build(&mut stream)
+                      .expect("Unable to load the binary");
 
     // Explorer
     let mut explorer = DirectedExplorer::new();
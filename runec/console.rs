@@ -21,6 +21,24 @@ static HELP: &'static str = "runec help menu:
 Branch Follow Commands:
   t     Follow `True` branch
   f     Follow `False` branch
+  a     Explore both sides of every branch without asking, the `True` side first
+  pause Ask at every branch again
+  inputs
+        Solve for inputs that take the branch, and for inputs that do not
+The side of a branch that is not followed is kept as a state left to explore.
+-------------------------------------------------------------
+State Commands:
+  ls    List the states left to explore, with their address and constraint count
+  sel   <n>, eg. sel 2
+        Go on with state n (the current state is kept)
+  i     [n], eg. i 2
+        Inspect the path of state n, or of the current state
+  k     [n], eg. k 2
+        Kill state n, or the current state
+  stats Print the statistics of the exploration: states, paths, coverage and speed
+  seek  <n>, eg. seek 10
+        Print the registers and memory of the path before its step n that differ from now
+When a path ends, exploration pauses until a state is selected (c for the latest).
 -------------------------------------------------------------
 Interpreter Commands:
   c     Continue Execution
@@ -28,9 +46,16 @@ Interpreter Commands:
   r     Run
   dS    Debug State
   dQ    Debug contraints
+  dM    Dump memory to 'memory.json' and raw segments to 'memory/'
   ?     Add Assertion
   Q     Query Constraint Solver
   x     Add safety assertions
+  pre   <conditions>, eg. pre rdi < 0x100; [rsp+8] sym 64
+        Assume conditions on the current state
+  post  <conditions>, eg. post rax != 0
+        Check that conditions hold for every input that reaches here
+  we    <expr>, eg. we [rbp-0x10]:32
+        Print the value of an expression after every instruction
   S     Save current state as json to 'state.json'
   h     Print Help Menu
 -------------------------------------------------------------
@@ -42,6 +67,9 @@ Initial State Configuration:
  * b <addr>
    eg. b 0x9000
    Set breakpoint for the emulator
+ * w <addr> [size]
+   eg. w 0x9000 8
+   Halt when the emulator reads or writes [addr, addr+size)
 ";
 
 #[derive(Clone, Debug)]
@@ -1,38 +1,66 @@
 //! `PathExplorer` that allows interactive exploration
+//!
+//! The side of a branch that is not followed is kept as a live state, which the console lists,
+//! inspects, kills or selects to go on with. When a path ends, the exploration pauses for the
+//! next state to be chosen.
 
 use rune::explorer::explorer::PathExplorer;
 use rune::explorer::interactive::Command;
+use rune::context::call_stack::BadReturn;
 use rune::context::rune_ctx::RuneContext;
 use rune::context::context::{Context, Evaluate, MemoryRead, RegisterRead};
+use rune::engine::branch_inputs::{self, BranchInputs};
+use rune::engine::dashboard::ExplorationStats;
 use rune::engine::rune::RuneControl;
+use rune::engine::breakpt::{WatchHit, WatchValue};
+use rune::memory::memory::Access;
 use rune::memory::seg_mem::SegMem;
+use rune::memory::uninit::UninitRead;
 use rune::regstore::regfile::RuneRegFile;
+use rune::utils::conditions;
 
 use libsmt::theories::{bitvec, core};
 use libsmt::logics::qf_abv::QF_ABV_Fn;
 use libsmt::backends::z3;
 use console::Console;
 
+use petgraph::graph::NodeIndex;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
 use std::process;
+use std::rc::Rc;
 
 #[derive(Debug, Clone, Default)]
 pub struct InteractiveExplorer {
     console: Console,
     cmd_q: Vec<Command>,
     single_step: bool,
+    /// Whether branches are explored without asking, the true side first.
+    auto: bool,
+    /// States left to explore, the sides of the branches that were not followed.
+    states: Vec<RuneContext<SegMem, RuneRegFile>>,
     // TODO: Remove this breakpointing feature once BPs are implemented.
     pub bp: Vec<u64>,
+    /// Statistics of the exploration, as last reported by the engine.
+    pub stats: Rc<RefCell<Option<ExplorationStats>>>,
 }
 
 impl InteractiveExplorer {
     // Adds Assertions for safety.
     pub fn safety(&self, ctx: &mut RuneContext<SegMem, RuneRegFile>) {
-        let rbp = ctx.reg_read("rbp");
-        let const_8 = ctx.define_const(8, 64);
-        let ret_addr = ctx.eval(bitvec::OpCodes::BvAdd, vec![rbp, const_8]);
+        let width = ctx.address_width();
+        let bp = ctx.reg_of_alias("BP").unwrap_or_else(|| "rbp".to_owned());
+        let bp = ctx.reg_read(bp);
+        // The return address is right above the saved frame pointer.
+        let word = ctx.define_const((width / 8) as u64, width);
+        let ret_addr = ctx.eval(bitvec::OpCodes::BvAdd, vec![bp, word]);
         // Add an assertion to check if that memory address can be junk (0x41414141)
-        let const_trash = ctx.define_const(0x41414141, 64);
-        let mem_at_addr = ctx.mem_read(ret_addr, 64);
+        let const_trash = ctx.define_const(0x41414141, width);
+        let mem_at_addr = ctx.mem_read(ret_addr, width);
         ctx.eval(core::OpCodes::Cmp, vec![mem_at_addr, const_trash]);
     }
 
@@ -41,20 +69,186 @@ impl InteractiveExplorer {
         self.console.print_info(&format!("Constraints:\n{}", ctx.solver.generate_asserts()));
     }
 
+    pub fn dump_memory(&self, ctx: &RuneContext<SegMem, RuneRegFile>) {
+        let dump = ctx.dump_memory();
+        let json = File::create("memory.json").and_then(|mut f| f.write_all(dump.to_json().as_bytes()));
+        match json.and_then(|_| dump.write_segments(Path::new("memory"))) {
+            Ok(segments) => {
+                self.console.print_success(&format!("Dumped {} bytes ({} raw segments)",
+                                                    dump.cells.len(), segments.len()))
+            }
+            Err(e) => self.console.print_error(&format!("Unable to dump memory: {}", e)),
+        }
+    }
+
     pub fn query_constraints(&self, ctx: &mut RuneContext<SegMem, RuneRegFile>) {
         let mut z3: z3::Z3 = Default::default();
         let result = ctx.solve(&mut z3);
 
-        println!("{:?}", result);
         self.console.print_success("Results:");
+        for (name, value) in ctx.symbol_values(&result) {
+            self.console.print_success(&format!("{} = {:#x}", name, value))
+        }
+    }
+
+    pub fn precondition(&self, ctx: &mut RuneContext<SegMem, RuneRegFile>, text: &str) {
+        let result = conditions::parse(text, ctx.address_width())
+                         .and_then(|c| conditions::assume(ctx, &c));
+        match result {
+            Ok(()) => self.console.print_success("Preconditions added!"),
+            Err(e) => self.console.print_error(&e),
+        }
+    }
 
-        /*
-        for (k, v) in &ctx.syms {
-            if let Some(res) = result.get(v) {
-                self.console.print_success(&format!("{} = {:#x}", k, res))
+    pub fn postcondition(&self, ctx: &mut RuneContext<SegMem, RuneRegFile>, text: &str) {
+        let result = conditions::parse(text, ctx.address_width())
+                         .and_then(|c| conditions::check(ctx, &c));
+        match result {
+            Ok(ref violated) if violated.is_empty() => self.console.print_success("Postconditions hold."),
+            Ok(violated) => {
+                for cond in violated {
+                    self.console.print_error(&format!("Violated: {:?}", cond));
+                }
             }
+            Err(e) => self.console.print_error(&e),
+        }
+    }
+
+    /// Print inputs that take the branch `condition` of `ctx`, and inputs that do not.
+    fn branch_inputs(&self, ctx: &RuneContext<SegMem, RuneRegFile>, condition: NodeIndex) {
+        let mut inputs = BranchInputs::default();
+        inputs.fill(ctx, condition);
+        self.console.print_info(&format!("True: {}", branch_inputs::show(inputs.taken.as_ref())));
+        self.console.print_info(&format!("False: {}",
+                                         branch_inputs::show(inputs.not_taken.as_ref())));
+    }
+
+    fn describe(ctx: &RuneContext<SegMem, RuneRegFile>) -> String {
+        format!("{:#x}, {} constraints, {} calls deep",
+                ctx.ip(),
+                ctx.path_constraints().len(),
+                ctx.call_stack().depth())
+    }
+
+    pub fn list_states(&self, current: Option<&RuneContext<SegMem, RuneRegFile>>) {
+        if let Some(ctx) = current {
+            self.console.print_info(&format!("current: {}", Self::describe(ctx)));
+        }
+        for (i, state) in self.states.iter().enumerate() {
+            self.console.print_info(&format!("{}: {}", i, Self::describe(state)));
+        }
+        if self.states.is_empty() {
+            self.console.print_info("No states left to explore.");
         }
-        */
+    }
+
+    pub fn inspect_state(&self, ctx: &RuneContext<SegMem, RuneRegFile>) {
+        self.console.print_info(&format!("State at {}", Self::describe(ctx)));
+        for constraint in ctx.path_constraints() {
+            let side = if constraint.taken { "taken" } else { "not taken" };
+            self.console.print_info(&format!("  branch at {:#x} {}", constraint.ip, side));
+        }
+        if ctx.call_stack().depth() > 0 {
+            self.console.print_info(&format!("Call stack:\n{}", ctx.call_stack()));
+        }
+        self.console.print_info(&format!("Constraints:\n{}", ctx.solver.generate_asserts()));
+    }
+
+    /// Print the registers and memory of the path of `ctx` before its step `index` that differ
+    /// from its current state.
+    pub fn seek(&self, ctx: &RuneContext<SegMem, RuneRegFile>, index: usize) {
+        let steps = ctx.history().map_or(0, |h| h.len());
+        let state = match ctx.seek(index) {
+            Some(state) => state,
+            None => {
+                self.console.print_error(&format!("No step {}, the path has {}", index, steps));
+                return;
+            }
+        };
+        self.console.print_info(&format!("Before step {} of {}, at {:#x}",
+                                         index,
+                                         steps,
+                                         state.ip()));
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "unset".to_owned());
+        for reg in state.diff_registers(ctx) {
+            self.console.print_info(&format!("  {}: {} (now {})",
+                                             reg.name,
+                                             show(&reg.old),
+                                             show(&reg.new)));
+        }
+        for cell in state.diff_memory(ctx) {
+            self.console.print_info(&format!("  [{:#x}]: {} (now {})",
+                                             cell.addr,
+                                             show(&cell.old),
+                                             show(&cell.new)));
+        }
+    }
+
+    /// Handle the commands that list, inspect and kill the states left to explore. Returns false
+    /// for other commands, and for the ones on the current state if there is none.
+    fn manage_states(&mut self,
+                     current: Option<&RuneContext<SegMem, RuneRegFile>>,
+                     cmd: &Command)
+                     -> bool {
+        match *cmd {
+            Command::ListStates => self.list_states(current),
+            Command::InspectState(None) => {
+                match current {
+                    Some(ctx) => self.inspect_state(ctx),
+                    None => return false,
+                }
+            }
+            Command::InspectState(Some(n)) |
+            Command::KillState(Some(n)) if n >= self.states.len() => {
+                self.console.print_error(&format!("No state {}", n));
+            }
+            Command::InspectState(Some(n)) => self.inspect_state(&self.states[n]),
+            Command::KillState(Some(n)) => {
+                let state = self.states.remove(n);
+                self.console.print_success(&format!("Killed state {} at {:#x}", n, state.ip()));
+            }
+            Command::Seek(n) => {
+                match current {
+                    Some(ctx) => self.seek(ctx, n),
+                    None => return false,
+                }
+            }
+            Command::Help => self.console.print_help(),
+            Command::Stats => {
+                match *self.stats.borrow() {
+                    Some(ref stats) => self.console.print_info(&stats.to_string()),
+                    None => self.console.print_info("No statistics yet."),
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Assume the branch `condition` of `ctx` to be `taken`, and keep the other side as a state
+    /// left to explore if it is feasible.
+    fn follow(&mut self,
+              ctx: &mut RuneContext<SegMem, RuneRegFile>,
+              condition: NodeIndex,
+              taken: bool)
+              -> RuneControl {
+        let mut other = ctx.clone();
+        let side = if taken {
+            other.eval(bitvec::OpCodes::BvNot, &[condition])
+        } else {
+            condition
+        };
+        let width = ctx.address_width();
+        if other.is_feasible(side) {
+            let value = other.define_const(!taken as u64, width);
+            let constraint = other.eval(core::OpCodes::Cmp, &[condition, value]);
+            other.record_branch(condition, constraint, !taken);
+            self.states.push(other);
+        }
+        let value = ctx.define_const(taken as u64, width);
+        let constraint = ctx.eval(core::OpCodes::Cmp, &[condition, value]);
+        ctx.record_branch(condition, constraint, taken);
+        if taken { RuneControl::ExploreTrue } else { RuneControl::ExploreFalse }
     }
 
     pub fn add_assertion(&self, ctx: &mut RuneContext<SegMem, RuneRegFile>) {
@@ -78,9 +272,11 @@ impl InteractiveExplorer {
                     let addr = {
                         let addr_ = u64::from_str_radix(&tokens[1][3..tokens[1].len() - 1], 16)
                                         .expect("Invalid integer base16");
-                        ctx.define_const(addr_, 64)
+                        let width = ctx.address_width();
+                        ctx.define_const(addr_, width)
                     };
-                    ctx.mem_read(addr, 64)
+                    let width = ctx.address_width();
+                    ctx.mem_read(addr, width)
                 } else {
                     ctx.reg_read(tokens[1])
                 }
@@ -90,7 +286,8 @@ impl InteractiveExplorer {
                 if tokens[2].len() > 2 && &tokens[2][0..2] == "0x" {
                     let const_v = u64::from_str_radix(&tokens[2][2..], 16)
                                       .expect("Invalid base16 Integer");
-                    ctx.define_const(const_v, 64)
+                    let width = ctx.address_width();
+                    ctx.define_const(const_v, width)
                 } else {
                     ctx.reg_read(tokens[2])
                 }
@@ -110,7 +307,10 @@ impl PathExplorer for InteractiveExplorer {
             cmd_q: Vec::new(),
             console: Default::default(),
             single_step: false,
+            auto: false,
+            states: Vec::new(),
             bp: Vec::new(),
+            stats: Rc::new(RefCell::new(None)),
         }
     }
 
@@ -118,9 +318,35 @@ impl PathExplorer for InteractiveExplorer {
         if self.single_step || self.bp.contains(&ctx.ip()) {
             self.console.print_info(&format!("Halted at {:#x}", ctx.ip()));
             loop {
-                self.single_step = match self.console.read_command()[0] {
+                let cmd = self.console.read_command().swap_remove(0);
+                if self.manage_states(Some(ctx), &cmd) {
+                    continue;
+                }
+                self.single_step = match cmd {
                     Command::Step => true,
                     Command::Continue => false,
+                    Command::AutoExplore => {
+                        self.auto = true;
+                        false
+                    }
+                    Command::Pause => {
+                        self.auto = false;
+                        self.console.print_success("Branches ask again.");
+                        continue;
+                    }
+                    Command::SelectState(n) if n < self.states.len() => {
+                        let next = self.states.remove(n);
+                        let previous = mem::replace(ctx, next);
+                        self.states.push(previous);
+                        let ip = ctx.ip();
+                        self.console.print_info(&format!("Switched to the state at {:#x}", ip));
+                        continue;
+                    }
+                    Command::KillState(None) => {
+                        let ip = ctx.ip();
+                        self.console.print_info(&format!("Killed the current state at {:#x}", ip));
+                        return RuneControl::TerminatePath;
+                    }
                     Command::DebugQuery => {
                         self.print_debug(ctx);
                         continue;
@@ -129,18 +355,37 @@ impl PathExplorer for InteractiveExplorer {
                         self.add_assertion(ctx);
                         continue;
                     },
-                    Command::Query => {
-                        self.query_constraints(ctx);
+                    Command::DumpMemory => {
+                        self.dump_memory(ctx);
                         continue;
                     },
-                    Command::Help => {
-                        self.console.print_help();
+                    Command::Query => {
+                        self.query_constraints(ctx);
                         continue;
                     },
                     Command::Safety => {
                         self.safety(ctx);
                         continue;
                     },
+                    Command::Precondition(ref text) => {
+                        self.precondition(ctx, text);
+                        continue;
+                    },
+                    Command::Postcondition(ref text) => {
+                        self.postcondition(ctx, text);
+                        continue;
+                    },
+                    Command::SelectState(n) => {
+                        self.console.print_error(&format!("No state {}", n));
+                        continue;
+                    },
+                    Command::WatchExpr(ref text) => {
+                        match ctx.add_watch_expr(text) {
+                            Ok(()) => self.console.print_success(&format!("Watching {}", text)),
+                            Err(e) => self.console.print_error(&e),
+                        }
+                        continue;
+                    },
                     Command::Exit => {
                         self.console.print_info("Thanks for using rune!");
                         process::exit(1);
@@ -153,35 +398,138 @@ impl PathExplorer for InteractiveExplorer {
         RuneControl::Continue
     }
 
-    fn next_job(&mut self, _ctx: &mut Self::Ctx) -> Option<RuneControl> {
-        None
+    fn next_job(&mut self, ctx: &mut Self::Ctx) -> Option<RuneControl> {
+        if self.states.is_empty() {
+            return None;
+        }
+        if self.auto {
+            *ctx = self.states.pop().expect("no states left");
+            return Some(RuneControl::Continue);
+        }
+        self.console.print_info(&format!("Path ended at {:#x}, {} states left. Select one to go on \
+                                          with (sel <n>, c for the latest).",
+                                         ctx.ip(), self.states.len()));
+        loop {
+            let cmd = self.console.read_command().swap_remove(0);
+            if let Command::Seek(n) = cmd {
+                // Back through the path that ended.
+                self.seek(ctx, n);
+                continue;
+            }
+            if self.manage_states(None, &cmd) {
+                if self.states.is_empty() {
+                    return None;
+                }
+                continue;
+            }
+            let next = match cmd {
+                Command::Continue => self.states.len() - 1,
+                Command::AutoExplore => {
+                    self.auto = true;
+                    self.states.len() - 1
+                }
+                Command::Step => {
+                    self.single_step = true;
+                    self.states.len() - 1
+                }
+                Command::SelectState(n) if n < self.states.len() => n,
+                Command::Exit => {
+                    self.console.print_info("Thanks for using rune!");
+                    process::exit(1);
+                }
+                _ => {
+                    self.console.print_error("Select a state to go on with.");
+                    continue;
+                }
+            };
+            *ctx = self.states.remove(next);
+            self.console.print_info(&format!("Resumed the state at {:#x}", ctx.ip()));
+            return Some(RuneControl::Continue);
+        }
+    }
+
+    fn watchpoint_hit(&mut self, _ctx: &mut Self::Ctx, hits: &[WatchHit<NodeIndex>]) {
+        for hit in hits {
+            let access = match hit.access {
+                Access::Read => "Read from",
+                _ => "Write to",
+            };
+            self.console.print_info(&format!("Watchpoint: {} {:#x} ({} bits) at {:#x}",
+                                             access, hit.addr, hit.size, hit.ip));
+        }
+        // Halt before the next instruction is executed.
+        self.single_step = true;
+    }
+
+    fn watch_values(&mut self, _ctx: &mut Self::Ctx, values: &[WatchValue]) {
+        for value in values {
+            match value.constant {
+                Some(c) => self.console.print_info(&format!("{} = {:#x}", value.expr, c)),
+                None => self.console.print_info(&format!("{} = {}", value.expr, value.smtlib)),
+            }
+        }
+    }
+
+    fn uninit_read(&mut self, _ctx: &mut Self::Ctx, reads: &[UninitRead]) {
+        for read in reads {
+            self.console.print_error(&format!("Read of uninitialized memory at {:#x} ({} bits) at {:#x}",
+                                              read.addr, read.size, read.ip));
+        }
+    }
+
+    fn add_state(&mut self, ctx: Self::Ctx) -> bool {
+        self.states.push(ctx);
+        true
+    }
+
+    fn waiting(&self) -> Option<usize> {
+        Some(self.states.len())
+    }
+
+    fn bad_return(&mut self, _ctx: &mut Self::Ctx, ret: &BadReturn) {
+        self.console.print_error(&format!("Return to {:#x} at {:#x}, expected {:#x}: the stack \
+                                           may be corrupted",
+                                          ret.target, ret.site, ret.expected));
+        self.single_step = true;
     }
 
     fn register_branch(&mut self,
                        ctx: &mut Self::Ctx,
                        condition: <Self::Ctx as RegisterRead>::VarRef)
                        -> RuneControl {
-        if self.cmd_q.is_empty() {
-            self.console.print_info(&format!("Encountered Branch At {:#x}", ctx.ip()));
-            self.cmd_q = self.console.read_command();
+        if self.auto {
+            return self.follow(ctx, condition, true);
         }
-
-        if let Some(cmd) = self.cmd_q.pop() {
+        loop {
+            if self.cmd_q.is_empty() {
+                self.console.print_info(&format!("Encountered Branch At {:#x}", ctx.ip()));
+                self.cmd_q = self.console.read_command();
+            }
+            let cmd = self.cmd_q.pop().expect("no command");
             match cmd {
-                Command::FollowTrue => {
-                    let one = ctx.define_const(1, 64);
-                    ctx.eval(core::OpCodes::Cmp, &[condition, one]);
-                    RuneControl::ExploreTrue
+                Command::FollowTrue => return self.follow(ctx, condition, true),
+                Command::FollowFalse => return self.follow(ctx, condition, false),
+                Command::AutoExplore => {
+                    // Explore without asking, until `Pause`.
+                    self.auto = true;
+                    self.cmd_q.clear();
+                    return self.follow(ctx, condition, true);
+                }
+                Command::Exit => {
+                    self.console.print_info("Thanks for using rune!");
+                    process::exit(1);
                 }
-                Command::FollowFalse => {
-                    let zero = ctx.define_const(0, 64);
-                    ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
-                    RuneControl::ExploreFalse
+                Command::BranchInputs => {
+                    self.branch_inputs(ctx, condition);
+                    self.cmd_q.clear();
+                }
+                cmd => {
+                    if !self.manage_states(Some(ctx), &cmd) {
+                        self.console.print_error("Follow a side of the branch first (t, f or a).");
+                    }
+                    self.cmd_q.clear();
                 }
-                _ => panic!("Incompatible command"),
             }
-        } else {
-            unreachable!()
         }
     }
 }
@@ -9,11 +9,13 @@ extern crate docopt;
 extern crate rustc_serialize;
 extern crate r2pipe;
 extern crate r2api;
+extern crate petgraph;
 
 mod interact;
 mod console;
 
 use std::process::exit;
+use std::time::Duration;
 use docopt::Docopt;
 
 use rune::utils::utils::{Key, ValType, SAssignment};
@@ -22,6 +24,10 @@ use rune::explorer::explorer::PathExplorer;
 use rune::explorer::interactive::Command;
 use rune::engine::rune::Rune;
 use rune::engine::engine::Engine;
+use rune::engine::breakpt::Watchpoint;
+use rune::engine::events::Event;
+use rune::engine::hook;
+use rune::memory::heap;
 
 use interact::InteractiveExplorer;
 use console::Console;
@@ -41,6 +47,9 @@ Options:
   -p                                     Load a previous configuration of state
 ";
 
+/// Program counters of the targets rune supports, for 64, 32 and 16 bit x86 and ARM.
+static PC_REGS: &'static [&'static str] = &["rip", "eip", "ip", "pc"];
+
 #[derive(Debug, Clone, RustcDecodable)]
 struct Args {
     flag_help: bool,
@@ -78,10 +87,22 @@ fn main() {
                 // NOTE: This allows us to use any explorer here.
                 let mut explorer = InteractiveExplorer::new();
                 explorer.bp = is.get_breakpoints();
+                let stats = explorer.stats.clone();
 
                 let ctx = is.create_context(&mut stream);
+                let imports = hook::imports(&mut stream);
 
                 let mut rune = Rune::new(ctx, explorer, stream);
+                rune.hook_imports(&imports, &heap::allocator_hooks());
+                // For the `seek` command.
+                rune.set_history();
+                // For the `stats` command.
+                rune.set_progress_interval(Some(Duration::from_secs(1)));
+                rune.subscribe(Box::new(move |_: &_, event: &Event| {
+                    if let Event::Progress { stats: ref latest } = *event {
+                        *stats.borrow_mut() = Some(*latest);
+                    }
+                }));
                 rune.run().expect("Rune Error!");
                 break;
             },
@@ -102,15 +123,18 @@ fn main() {
                                               rvalue: ValType::Break }) => {
                 is.add_breakpoint(val as u64);
             },
+            Command::Watch(addr, size) => {
+                is.add_watchpoint(Watchpoint::access(addr, addr + size));
+            },
             Command::SetContext(SAssignment { lvalue: ref val, 
                                               rvalue: ValType::Symbolic }) => {
                 is.add_sym(val.clone());
             },
             Command::SetContext(SAssignment { lvalue: ref key,
                                               rvalue: ValType::Concrete(val) }) => {
-                // If the register to be set is rip, we infer that the user is setting 
-                // their start address
-                if *key == Key::Reg("rip".to_owned()) {
+                // If the register to be set is the program counter, we infer that the user is
+                // setting their start address
+                if PC_REGS.iter().any(|pc| *key == Key::Reg(pc.to_string())) {
                     is.set_start_addr(val as u64);
                 } else {
                     is.add_const((key.clone(), val as u64));
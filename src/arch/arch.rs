@@ -0,0 +1,538 @@
+//! Properties of the architectures rune emulates.
+//!
+//! An `Arch` describes what the register profile of r2 does not: the width of pointers, which
+//! registers hold the program counter and stack pointer, and how arguments are passed to
+//! functions and system calls. It is selected from the `bin_info` of the binary, see `from_bin`.
+//!
+//! Everything that needs to know these, such as the initial stack setup and the hooks modeling
+//! library functions, asks the context for the register of a role (`reg_of_alias`), which
+//! consults the architecture of the context before the aliases of the register profile.
+
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use r2api::structs::{Endian, LBin};
+
+use stream::IsaMode;
+
+pub trait Arch: Debug {
+    /// Name of the architecture, as reported by r2.
+    fn name(&self) -> &str;
+    /// Width of pointers in bits.
+    fn bits(&self) -> usize;
+    fn endian(&self) -> Endian {
+        Endian::Little
+    }
+
+    fn pc(&self) -> &str;
+    /// Value the program counter reads as in the instruction of `size` bytes at `addr`, which
+    /// is what PC relative operands are computed from. This is the address of the next
+    /// instruction by default.
+    fn pc_value(&self, addr: u64, size: u64, _mode: IsaMode) -> u64 {
+        addr + size
+    }
+    fn sp(&self) -> &str;
+    /// Frame pointer.
+    fn bp(&self) -> &str;
+    /// Register holding the return address of a call, if calls do not push it on the stack.
+    fn link_reg(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether the instruction after a branch, in its delay slot, executes before the branch
+    /// takes effect.
+    fn has_delay_slots(&self) -> bool {
+        false
+    }
+    /// Whether the branch `opcode` does not execute its delay slot when it is not taken.
+    fn annuls_delay_slot(&self, _opcode: &str) -> bool {
+        false
+    }
+
+    /// Registers the first arguments of a call are passed in. The remaining arguments are passed
+    /// on the stack.
+    fn args(&self) -> &[&str];
+    /// Register holding the return value of a call.
+    fn ret(&self) -> &str;
+    /// General purpose registers that a call does not preserve.
+    fn caller_saved(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Register holding the number of a system call.
+    fn syscall_num(&self) -> &str;
+    /// Registers the arguments of a system call are passed in.
+    fn syscall_args(&self) -> &[&str];
+    /// Register holding the return value of a system call.
+    fn syscall_ret(&self) -> &str;
+
+    /// Register with the role `alias` (`PC`, `SP`, `BP`, `LR`, `A0`..., `R0`, `SN`), using the
+    /// names of the register profile aliases.
+    fn reg_of_alias(&self, alias: &str) -> Option<String> {
+        let reg = match alias {
+            "PC" => Some(self.pc()),
+            "SP" => Some(self.sp()),
+            "BP" => Some(self.bp()),
+            "LR" => self.link_reg(),
+            "R0" => Some(self.ret()),
+            "SN" => Some(self.syscall_num()),
+            _ if alias.starts_with('A') => {
+                alias[1..].parse::<usize>().ok().and_then(|n| self.args().get(n).cloned())
+            }
+            _ => None,
+        };
+        reg.map(|r| r.to_owned())
+    }
+}
+
+/// x86 in 16, 32 or 64 bit mode, with the System V calling conventions. 32 bit code passes all
+/// arguments on the stack, as does 16 bit code, whose system calls (`int 0x21`) take their
+/// arguments in registers. 16 bit code is executed with a flat address space: segment registers
+/// are not added to addresses.
+#[derive(Clone, Copy, Debug)]
+pub struct X86 {
+    pub bits: usize,
+}
+
+impl X86 {
+    /// Name of the general purpose register `r` (`"ax"`, `"sp"`, ...) in the current mode.
+    fn reg(&self, r: &'static str) -> &'static str {
+        match (self.bits, r) {
+            (16, _) => r,
+            (32, "ax") => "eax",
+            (32, "sp") => "esp",
+            (32, "bp") => "ebp",
+            (32, "ip") => "eip",
+            (_, "ax") => "rax",
+            (_, "sp") => "rsp",
+            (_, "bp") => "rbp",
+            _ => "rip",
+        }
+    }
+}
+
+impl Arch for X86 {
+    fn name(&self) -> &str {
+        "x86"
+    }
+
+    fn bits(&self) -> usize {
+        self.bits
+    }
+
+    fn pc(&self) -> &str {
+        self.reg("ip")
+    }
+
+    fn sp(&self) -> &str {
+        self.reg("sp")
+    }
+
+    fn bp(&self) -> &str {
+        self.reg("bp")
+    }
+
+    fn args(&self) -> &[&str] {
+        if self.bits == 64 {
+            &["rdi", "rsi", "rdx", "rcx", "r8", "r9"]
+        } else {
+            &[]
+        }
+    }
+
+    fn ret(&self) -> &str {
+        self.reg("ax")
+    }
+
+    fn caller_saved(&self) -> &[&str] {
+        match self.bits {
+            64 => &["rax", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11"],
+            32 => &["eax", "ecx", "edx"],
+            _ => &["ax", "cx", "dx"],
+        }
+    }
+
+    fn syscall_num(&self) -> &str {
+        self.ret()
+    }
+
+    fn syscall_args(&self) -> &[&str] {
+        match self.bits {
+            64 => &["rdi", "rsi", "rdx", "r10", "r8", "r9"],
+            32 => &["ebx", "ecx", "edx", "esi", "edi", "ebp"],
+            _ => &["bx", "cx", "dx", "si", "di", "bp"],
+        }
+    }
+
+    fn syscall_ret(&self) -> &str {
+        self.ret()
+    }
+}
+
+/// 32 bit ARM, with the AAPCS calling convention and the EABI system call convention.
+#[derive(Clone, Copy, Debug)]
+pub struct Arm {
+    pub endian: Endian,
+}
+
+impl Arch for Arm {
+    fn name(&self) -> &str {
+        "arm"
+    }
+
+    fn bits(&self) -> usize {
+        32
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    fn pc(&self) -> &str {
+        "pc"
+    }
+
+    /// The program counter is two instructions ahead, as the ARM7 pipeline was.
+    fn pc_value(&self, addr: u64, _size: u64, mode: IsaMode) -> u64 {
+        match mode {
+            IsaMode::Normal => addr + 8,
+            IsaMode::Thumb => addr + 4,
+        }
+    }
+
+    fn sp(&self) -> &str {
+        "sp"
+    }
+
+    fn bp(&self) -> &str {
+        "r11"
+    }
+
+    fn link_reg(&self) -> Option<&str> {
+        Some("lr")
+    }
+
+    fn args(&self) -> &[&str] {
+        &["r0", "r1", "r2", "r3"]
+    }
+
+    fn ret(&self) -> &str {
+        "r0"
+    }
+
+    fn caller_saved(&self) -> &[&str] {
+        &["r0", "r1", "r2", "r3", "r12"]
+    }
+
+    fn syscall_num(&self) -> &str {
+        "r7"
+    }
+
+    fn syscall_args(&self) -> &[&str] {
+        &["r0", "r1", "r2", "r3", "r4", "r5"]
+    }
+
+    fn syscall_ret(&self) -> &str {
+        "r0"
+    }
+}
+
+/// AArch64, with the AAPCS64 calling convention.
+#[derive(Clone, Copy, Debug)]
+pub struct AArch64 {
+    pub endian: Endian,
+}
+
+impl Arch for AArch64 {
+    fn name(&self) -> &str {
+        "arm"
+    }
+
+    fn bits(&self) -> usize {
+        64
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    fn pc(&self) -> &str {
+        "pc"
+    }
+
+    fn pc_value(&self, addr: u64, _size: u64, _mode: IsaMode) -> u64 {
+        addr
+    }
+
+    fn sp(&self) -> &str {
+        "sp"
+    }
+
+    fn bp(&self) -> &str {
+        "x29"
+    }
+
+    fn link_reg(&self) -> Option<&str> {
+        Some("x30")
+    }
+
+    fn args(&self) -> &[&str] {
+        &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7"]
+    }
+
+    fn ret(&self) -> &str {
+        "x0"
+    }
+
+    fn caller_saved(&self) -> &[&str] {
+        &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12",
+          "x13", "x14", "x15", "x16", "x17"]
+    }
+
+    fn syscall_num(&self) -> &str {
+        "x8"
+    }
+
+    fn syscall_args(&self) -> &[&str] {
+        &["x0", "x1", "x2", "x3", "x4", "x5"]
+    }
+
+    fn syscall_ret(&self) -> &str {
+        "x0"
+    }
+}
+
+/// MIPS, with the o32 calling convention in 32 bit code and the n64 one in 64 bit code.
+#[derive(Clone, Copy, Debug)]
+pub struct Mips {
+    pub bits: usize,
+    pub endian: Endian,
+}
+
+impl Arch for Mips {
+    fn name(&self) -> &str {
+        "mips"
+    }
+
+    fn bits(&self) -> usize {
+        self.bits
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    fn pc(&self) -> &str {
+        "pc"
+    }
+
+    fn sp(&self) -> &str {
+        "sp"
+    }
+
+    fn bp(&self) -> &str {
+        "fp"
+    }
+
+    fn link_reg(&self) -> Option<&str> {
+        Some("ra")
+    }
+
+    fn has_delay_slots(&self) -> bool {
+        true
+    }
+
+    /// Branch likely instructions (`beql`, `bnezl`, ...).
+    fn annuls_delay_slot(&self, opcode: &str) -> bool {
+        let mnemonic = opcode.split_whitespace().next().unwrap_or("");
+        // Not the branch and link instructions (`bal`, `bgezal`, ...), unless they are likely.
+        mnemonic.starts_with('b') && mnemonic.ends_with('l') &&
+        (!mnemonic.ends_with("al") || mnemonic.ends_with("all"))
+    }
+
+    fn args(&self) -> &[&str] {
+        if self.bits == 64 {
+            &["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"]
+        } else {
+            &["a0", "a1", "a2", "a3"]
+        }
+    }
+
+    fn ret(&self) -> &str {
+        "v0"
+    }
+
+    fn caller_saved(&self) -> &[&str] {
+        &["v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6", "t7",
+          "t8", "t9"]
+    }
+
+    fn syscall_num(&self) -> &str {
+        "v0"
+    }
+
+    fn syscall_args(&self) -> &[&str] {
+        self.args()
+    }
+
+    fn syscall_ret(&self) -> &str {
+        "v0"
+    }
+}
+
+/// SPARC, with the arguments and return values in the registers of the caller's window.
+#[derive(Clone, Copy, Debug)]
+pub struct Sparc {
+    pub bits: usize,
+    pub endian: Endian,
+}
+
+impl Arch for Sparc {
+    fn name(&self) -> &str {
+        "sparc"
+    }
+
+    fn bits(&self) -> usize {
+        self.bits
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    fn pc(&self) -> &str {
+        "pc"
+    }
+
+    fn sp(&self) -> &str {
+        "sp"
+    }
+
+    fn bp(&self) -> &str {
+        "fp"
+    }
+
+    fn link_reg(&self) -> Option<&str> {
+        Some("o7")
+    }
+
+    fn has_delay_slots(&self) -> bool {
+        true
+    }
+
+    /// Branches with the annul bit (`bne,a`, ...).
+    fn annuls_delay_slot(&self, opcode: &str) -> bool {
+        opcode.split_whitespace().next().map_or(false, |m| m.ends_with(",a") || m.contains(",a,"))
+    }
+
+    fn args(&self) -> &[&str] {
+        &["o0", "o1", "o2", "o3", "o4", "o5"]
+    }
+
+    fn ret(&self) -> &str {
+        "o0"
+    }
+
+    /// The out registers, which become the in registers of the callee, and the global registers
+    /// that are not reserved.
+    fn caller_saved(&self) -> &[&str] {
+        &["o0", "o1", "o2", "o3", "o4", "o5", "g1", "g2", "g3", "g4"]
+    }
+
+    fn syscall_num(&self) -> &str {
+        "g1"
+    }
+
+    fn syscall_args(&self) -> &[&str] {
+        self.args()
+    }
+
+    fn syscall_ret(&self) -> &str {
+        "o0"
+    }
+}
+
+/// Architecture of the binary described by `bin`, or `None` if rune does not know it. Contexts
+/// without an architecture fall back to the aliases of the register profile.
+pub fn from_bin(bin: &LBin) -> Option<Rc<Arch>> {
+    let bits = bin.bits.unwrap_or(64);
+    let endian = bin.endian.unwrap_or(Endian::Little);
+    match (bin.arch.as_ref().map(|a| a.as_str()), bits) {
+        (Some("x86"), 16) | (Some("x86"), 32) | (Some("x86"), 64) => Some(Rc::new(X86 { bits: bits })),
+        (Some("arm"), 64) => Some(Rc::new(AArch64 { endian: endian })),
+        (Some("arm"), 16) | (Some("arm"), 32) => Some(Rc::new(Arm { endian: endian })),
+        (Some("mips"), 32) | (Some("mips"), 64) => {
+            Some(Rc::new(Mips {
+                bits: bits,
+                endian: endian,
+            }))
+        }
+        (Some("sparc"), 32) | (Some("sparc"), 64) => {
+            Some(Rc::new(Sparc {
+                bits: bits,
+                endian: endian,
+            }))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bin(arch: &str, bits: usize) -> LBin {
+        LBin {
+            arch: Some(arch.to_owned()),
+            bits: Some(bits),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn select_arch() {
+        let x64 = from_bin(&bin("x86", 64)).unwrap();
+        assert_eq!(x64.reg_of_alias("PC"), Some("rip".to_owned()));
+        assert_eq!(x64.reg_of_alias("A1"), Some("rsi".to_owned()));
+        assert_eq!(x64.reg_of_alias("LR"), None);
+
+        let x86 = from_bin(&bin("x86", 32)).unwrap();
+        assert_eq!(x86.bits(), 32);
+        assert_eq!(x86.reg_of_alias("A0"), None);
+        assert_eq!(x86.syscall_args()[0], "ebx");
+        assert_eq!(x86.reg_of_alias("PC"), Some("eip".to_owned()));
+
+        let real = from_bin(&bin("x86", 16)).unwrap();
+        assert_eq!(real.bits(), 16);
+        assert_eq!((real.pc(), real.sp(), real.ret()), ("ip", "sp", "ax"));
+
+        let a64 = from_bin(&bin("arm", 64)).unwrap();
+        assert_eq!(a64.reg_of_alias("LR"), Some("x30".to_owned()));
+        assert_eq!(a64.reg_of_alias("SN"), Some("x8".to_owned()));
+
+        assert_eq!(x64.pc_value(0x1000, 7, IsaMode::Normal), 0x1007);
+        assert_eq!(a64.pc_value(0x1000, 4, IsaMode::Normal), 0x1000);
+
+        // Thumb binaries are reported as 16 bit.
+        assert_eq!(from_bin(&bin("arm", 16)).unwrap().bits(), 32);
+        assert!(from_bin(&bin("ppc", 32)).is_none());
+    }
+
+    #[test]
+    fn delay_slots() {
+        let mips = from_bin(&bin("mips", 32)).unwrap();
+        assert!(mips.has_delay_slots());
+        assert_eq!(mips.reg_of_alias("A3"), Some("a3".to_owned()));
+        assert_eq!(mips.reg_of_alias("A4"), None);
+        assert!(mips.annuls_delay_slot("beql v0, zero, 0x400120"));
+        assert!(!mips.annuls_delay_slot("beq v0, zero, 0x400120"));
+        assert!(!mips.annuls_delay_slot("bal 0x400120"));
+        assert!(!mips.annuls_delay_slot("bgezal a0, 0x400120"));
+        assert!(mips.annuls_delay_slot("bgezall a0, 0x400120"));
+
+        let sparc = from_bin(&bin("sparc", 32)).unwrap();
+        assert!(sparc.annuls_delay_slot("bne,a 0x10074"));
+        assert!(!sparc.annuls_delay_slot("bne 0x10074"));
+        assert!(!from_bin(&bin("x86", 64)).unwrap().has_delay_slots());
+    }
+}
@@ -0,0 +1,69 @@
+//! Constraints on the bytes of symbolic buffers, see `RuneContext::make_sym_buffer`.
+
+use petgraph::graph::NodeIndex;
+
+use libsmt::backends::backend::SMTBackend;
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BufferConstraint {
+    /// Bytes are printable ASCII characters (0x20 - 0x7e).
+    Printable,
+    /// Bytes are ASCII letters or digits.
+    Alphanumeric,
+    /// Bytes lie in the (inclusive) range [lo, hi].
+    Range(u8, u8),
+    /// The last byte of the buffer is NUL, none of the others are. Other constraints do not
+    /// apply to the terminator.
+    NullTerminated,
+}
+
+/// Condition that `byte` lies in [lo, hi].
+fn in_range(solver: &mut SMTLib2<qf_abv::QF_ABV>, byte: NodeIndex, lo: u8, hi: u8) -> NodeIndex {
+    let lo = solver.new_const(bitvec::OpCodes::Const(lo as u64, 8));
+    let hi = solver.new_const(bitvec::OpCodes::Const(hi as u64, 8));
+    let ge = solver.assert(bitvec::OpCodes::BvUGe, &[byte, lo]);
+    let le = solver.assert(bitvec::OpCodes::BvULe, &[byte, hi]);
+    solver.assert(core::OpCodes::And, &[ge, le])
+}
+
+/// Constrain `bytes`, the symbols of a buffer in order, by `constraints`.
+pub fn constrain(solver: &mut SMTLib2<qf_abv::QF_ABV>,
+                 bytes: &[NodeIndex],
+                 constraints: &[BufferConstraint]) {
+    let mut body = bytes;
+    if constraints.contains(&BufferConstraint::NullTerminated) {
+        if let Some((last, rest)) = bytes.split_last() {
+            let zero = solver.new_const(bitvec::OpCodes::Const(0, 8));
+            solver.assert(core::OpCodes::Cmp, &[*last, zero]);
+            for byte in rest {
+                let eq = solver.assert(core::OpCodes::Cmp, &[*byte, zero]);
+                solver.assert(core::OpCodes::Not, &[eq]);
+            }
+            body = rest;
+        }
+    }
+
+    for constraint in constraints {
+        for byte in body {
+            match *constraint {
+                BufferConstraint::Printable => {
+                    in_range(solver, *byte, 0x20, 0x7e);
+                }
+                BufferConstraint::Alphanumeric => {
+                    let ranges = [(b'0', b'9'), (b'A', b'Z'), (b'a', b'z')];
+                    let conds: Vec<NodeIndex> = ranges.iter()
+                                                      .map(|&(lo, hi)| in_range(solver, *byte, lo, hi))
+                                                      .collect();
+                    solver.assert(core::OpCodes::Or, &conds);
+                }
+                BufferConstraint::Range(lo, hi) => {
+                    in_range(solver, *byte, lo, hi);
+                }
+                BufferConstraint::NullTerminated => {}
+            }
+        }
+    }
+}
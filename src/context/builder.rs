@@ -0,0 +1,413 @@
+//! Configuration of the initial state of a context.
+//!
+//! ```ignore
+//! let mut ctx = ContextBuilder::new()
+//!                   .ip(0x4004fa)
+//!                   .symbolic(Key::Mem(0x4ff8), 8)
+//!                   .constant(Key::Reg("rbp".to_owned()), 0x5000)
+//!                   .stack(StackSetup::default())
+//!                   .build(&mut r2)?;
+//! ```
+//!
+//! Memory starts with the sections of the binary, unless `load_sections(false)` is given. The
+//! state of a core dump or process comes next, then the stack. Symbols and constants are set
+//! on top of these, so a constant `rbp` is kept even though the stack sets it.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use petgraph::graph::NodeIndex;
+
+use r2api::api_trait::R2Api;
+use r2api::structs::{Endian, LRegInfo};
+use r2pipe::r2::R2;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use arch::arch::{self, Arch};
+use context::context::{Context, ContextAPI};
+use context::core_dump::CoreDump;
+use context::env::EnvValue;
+use context::rune_ctx::RuneContext;
+use context::stack::StackSetup;
+use engine::breakpt::Watchpoint;
+use memory::layout;
+use memory::lazy_mem::LazyMemory;
+use memory::memory::Memory;
+use memory::seg_mem::SegMem;
+use regstore::regstore::RegStore;
+use regstore::regfile::RuneRegFile;
+use utils::utils::Key;
+
+#[derive(Clone, Debug, Default)]
+pub struct ContextBuilder {
+    ip: Option<u64>,
+    /// Symbolic inputs and their widths in bits.
+    symbolic: Vec<(Key, usize)>,
+    /// Constants, with their widths in bits if given.
+    constants: Vec<(Key, u64, Option<usize>)>,
+    stack: Option<StackSetup>,
+    /// Environment variables, with either a concrete value or the length of a symbolic one.
+    env: Vec<(String, EnvValue)>,
+    load_sections: bool,
+    /// Base address to rebase the binary to.
+    image_base: Option<u64>,
+    core_dump: Option<CoreDump>,
+    arch: Option<Rc<Arch>>,
+    lazy_registers: bool,
+    system_regs: Vec<(String, u64)>,
+    watchpoints: Vec<Watchpoint>,
+    /// Start from the state of the process r2 is debugging, see `build_from_process`.
+    process: bool,
+    /// Held bytes up to which memory models accesses through a symbolic address.
+    symbolic_limit: Option<usize>,
+}
+
+impl ContextBuilder {
+    pub fn new() -> ContextBuilder {
+        ContextBuilder {
+            load_sections: true,
+            ..Default::default()
+        }
+    }
+
+    /// Address to start executing at.
+    pub fn ip(mut self, ip: u64) -> ContextBuilder {
+        self.ip = Some(ip);
+        self
+    }
+
+    /// Make the register or the memory at `key` a symbol of `width` bits. Registers narrower
+    /// than `width` are made entirely symbolic.
+    pub fn symbolic(mut self, key: Key, width: usize) -> ContextBuilder {
+        self.symbolic.push((key, width));
+        self
+    }
+
+    /// Set the register `key` to `value`, or the pointer sized word of memory at `key`.
+    pub fn constant(mut self, key: Key, value: u64) -> ContextBuilder {
+        self.constants.push((key, value, None));
+        self
+    }
+
+    /// Same as `constant`, with the width of a memory constant given in bits. Registers are
+    /// always set entirely.
+    pub fn constant_sized(mut self, key: Key, value: u64, width: usize) -> ContextBuilder {
+        self.constants.push((key, value, Some(width)));
+        self
+    }
+
+    /// Map a stack and point the stack and base pointers to it, see `RuneContext::setup_stack`.
+    pub fn stack(mut self, stack: StackSetup) -> ContextBuilder {
+        self.stack = Some(stack);
+        self
+    }
+
+    /// Declare the environment variable `name` with `value`, see `RuneContext::set_env`.
+    pub fn env(mut self, name: &str, value: &str) -> ContextBuilder {
+        self.env.push((name.to_owned(), EnvValue::Concrete(value.to_owned())));
+        self
+    }
+
+    /// Declare the environment variable `name` with a value of `len` symbolic bytes.
+    pub fn env_symbolic(mut self, name: &str, len: usize) -> ContextBuilder {
+        self.env.push((name.to_owned(), EnvValue::Symbolic(len)));
+        self
+    }
+
+    /// Whether to populate memory with the sections of the binary, which `build` does by
+    /// default. Contexts of a process always read the memory of the process instead.
+    pub fn load_sections(mut self, load: bool) -> ContextBuilder {
+        self.load_sections = load;
+        self
+    }
+
+    /// Load the binary at `base`, for position independent executables. r2 is rebased, so that
+    /// the addresses it shows are those of the context, see `memory::layout::rebase`.
+    pub fn image_base(mut self, base: u64) -> ContextBuilder {
+        self.image_base = Some(base);
+        self
+    }
+
+    /// Start from the state of the process that `core` was dumped from, see
+    /// `RuneContext::load_core_dump`. Symbols and constants are set on top of it.
+    pub fn core_dump(mut self, core: CoreDump) -> ContextBuilder {
+        self.core_dump = Some(core);
+        self
+    }
+
+    /// Use `arch` instead of the architecture reported by r2, for example for firmware that r2
+    /// does not identify.
+    pub fn arch_override(mut self, arch: Rc<Arch>) -> ContextBuilder {
+        self.arch = Some(arch);
+        self
+    }
+
+    /// Let unset registers read as fresh symbols instead of zero.
+    pub fn lazy_registers(mut self, lazy: bool) -> ContextBuilder {
+        self.lazy_registers = lazy;
+        self
+    }
+
+    /// Set the system register `reg` to `value`, see `RuneContext::set_system_reg`.
+    pub fn system_reg(mut self, reg: &str, value: u64) -> ContextBuilder {
+        self.system_regs.push((reg.to_owned(), value));
+        self
+    }
+
+    pub fn watchpoint(mut self, wp: Watchpoint) -> ContextBuilder {
+        self.watchpoints.push(wp);
+        self
+    }
+
+    /// Let memory model accesses through a symbolic address while it holds upto `cells` bytes,
+    /// see `Memory::set_symbolic_limit`. The sections of a binary usually hold more than the
+    /// default limit.
+    pub fn symbolic_limit(mut self, cells: usize) -> ContextBuilder {
+        self.symbolic_limit = Some(cells);
+        self
+    }
+
+    /// Create the context for the binary opened in `r2`. Fails if the output of r2 cannot be
+    /// parsed.
+    pub fn build(self, r2: &mut R2) -> Result<RuneContext<SegMem, RuneRegFile>, String> {
+        self.build_with(r2)
+    }
+
+    /// Create the context for the binary opened in `r2` with the memory backend `M`, eg.
+    /// `PagedMemory` to share memory between forked states.
+    pub fn build_with<M>(self, r2: &mut R2) -> Result<RuneContext<M, RuneRegFile>, String>
+        where M: Memory<VarRef = NodeIndex>
+    {
+        let (arch, bits, endian) = self.arch(r2);
+        let mut mem = M::new(bits, endian);
+        if let Some(cells) = self.symbolic_limit {
+            mem.set_symbolic_limit(cells);
+        }
+        let (mut ctx, lreginfo) = self.configure(arch, mem, r2)?;
+        self.apply(&mut ctx, &lreginfo);
+        Ok(ctx)
+    }
+
+    /// Create the context for the process that `r2` is attached to (`r2 -d`), for example to
+    /// start symbolic execution from a breakpoint. Registers start with their values in the
+    /// process and memory is fetched from the process when it is first accessed, see
+    /// `RuneContext::load_process_state`. Symbols, constants and the instruction pointer given
+    /// to the builder are set on top of it.
+    pub fn build_from_process(mut self,
+                              r2: Rc<RefCell<R2>>)
+                              -> Result<RuneContext<LazyMemory, RuneRegFile>, String> {
+        let (arch, bits, endian) = self.arch(&mut r2.borrow_mut());
+        let mut mem = LazyMemory::with_process(bits, endian, r2.clone())?;
+        if let Some(cells) = self.symbolic_limit {
+            mem.set_symbolic_limit(cells);
+        }
+        self.process = true;
+        let (mut ctx, lreginfo) = self.configure(arch, mem, &mut r2.borrow_mut())?;
+        // Memory borrows r2 to fetch the pages that the stack, symbols and constants write.
+        self.apply(&mut ctx, &lreginfo);
+        Ok(ctx)
+    }
+
+    /// The architecture of the binary, with its address width and endianness.
+    fn arch(&self, r2: &mut R2) -> (Option<Rc<Arch>>, usize, Endian) {
+        let bin = r2.bin_info().unwrap().bin.unwrap();
+        let arch = self.arch.clone().or_else(|| arch::from_bin(&bin));
+        let (bits, endian) = match arch {
+            Some(ref arch) => (arch.bits(), arch.endian()),
+            None => (bin.bits.unwrap(), bin.endian.unwrap()),
+        };
+        if self.arch.is_some() {
+            // r2 has to decode instructions and their immediates in the byte order of the
+            // overriding architecture, which it did not detect.
+            r2.send(&format!("e cfg.bigendian={}", endian == Endian::Big));
+            r2.recv();
+        }
+        (arch, bits, endian)
+    }
+
+    /// Create the context and load the state that r2 provides, returning the registers of the
+    /// context along with it.
+    fn configure<M>(&self,
+                    arch: Option<Rc<Arch>>,
+                    mem: M,
+                    r2: &mut R2)
+                    -> Result<(RuneContext<M, RuneRegFile>, LRegInfo), String>
+        where M: Memory<VarRef = NodeIndex>
+    {
+        let mut lreginfo = r2.reg_info().unwrap();
+        let regfile = RuneRegFile::new(&mut lreginfo);
+
+        let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+
+        let mut ctx = RuneContext::new(self.ip, mem, regfile, smt);
+        if let Some(arch) = arch {
+            ctx.set_arch(arch);
+        }
+
+        if let Some(base) = self.image_base {
+            ctx.set_image_layout(layout::rebase(r2, base)?);
+        }
+        if self.load_sections && !self.process {
+            ctx.load_sections(r2)?;
+        }
+        if let Some(ref core) = self.core_dump {
+            ctx.load_core_dump(core);
+        }
+        if self.process {
+            ctx.load_process_state(r2)?;
+        }
+        if let Some(ip) = self.ip {
+            // The program counter of a core dump or process should not override the ip given.
+            ctx.set_ip(ip);
+        }
+        Ok((ctx, lreginfo))
+    }
+
+    /// Set up the environment, the stack, the symbols, the constants and the registers of
+    /// `ctx`, whose registers are those of `lreginfo`.
+    fn apply<M>(&self, ctx: &mut RuneContext<M, RuneRegFile>, lreginfo: &LRegInfo)
+        where M: Memory<VarRef = NodeIndex>
+    {
+        let bits = ctx.address_width();
+        for &(ref name, ref value) in &self.env {
+            match *value {
+                EnvValue::Concrete(ref value) => ctx.set_env(name, value),
+                EnvValue::Symbolic(len) => ctx.set_env_symbolic(name, len),
+            }
+        }
+        // Symbols and constants given for the stack and base pointers override the stack.
+        if let Some(ref stack) = self.stack {
+            ctx.setup_stack(stack);
+        }
+
+        for &(ref key, width) in &self.symbolic {
+            match *key {
+                Key::Mem(addr) => {
+                    ctx.set_mem_as_sym(addr as u64, width, None);
+                }
+                Key::Reg(ref reg) => {
+                    // Registers are made entirely symbolic.
+                    let width = lreginfo.reg_info
+                                        .iter()
+                                        .find(|r| &r.name == reg)
+                                        .map(|r| r.size)
+                                        .unwrap_or(width);
+                    ctx.set_reg_as_sym(reg, width, None);
+                }
+            }
+        }
+
+        for &(ref key, value, width) in &self.constants {
+            match *key {
+                Key::Mem(addr) => {
+                    ctx.set_mem_as_const(addr as u64, value, width.unwrap_or(bits));
+                }
+                Key::Reg(ref reg) => {
+                    ctx.load_registers(&[(reg.clone(), value)]);
+                }
+            }
+        }
+
+        if self.lazy_registers {
+            // Unset registers become symbols when they are first read.
+            ctx.set_lazy_registers(true);
+        } else {
+            // Setting unset registers to zero!
+            for register in &lreginfo.reg_info {
+                ctx.set_reg_as_const(register.name.clone(), 0);
+            }
+        }
+
+        for &(ref reg, value) in &self.system_regs {
+            ctx.set_system_reg(reg, value);
+        }
+        for wp in &self.watchpoints {
+            ctx.add_watchpoint(*wp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arch::arch::X86;
+    use context::context::{MemoryRead, RegisterRead};
+    use memory::paged_mem::PagedMemory;
+    use r2api::structs::LRegProfile;
+
+    /// Apply `builder` to a 64 bit x86 context with the registers `rax`, `rsp` and `rbp`.
+    fn apply(builder: ContextBuilder) -> RuneContext<PagedMemory, RuneRegFile> {
+        let mut lreginfo = LRegInfo {
+            reg_info: ["rax", "rsp", "rbp"].iter()
+                                           .enumerate()
+                                           .map(|(i, name)| {
+                                               LRegProfile {
+                                                   name: (*name).to_owned(),
+                                                   offset: i * 64,
+                                                   size: 64,
+                                                   type_str: "gpr".to_owned(),
+                                                   ..Default::default()
+                                               }
+                                           })
+                                           .collect(),
+            ..Default::default()
+        };
+        let regfile = RuneRegFile::new(&mut lreginfo);
+        let mem = PagedMemory::new(64, Endian::Little);
+        let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut ctx = RuneContext::new(Some(0x9000), mem, regfile, smt);
+        ctx.set_arch(Rc::new(X86 { bits: 64 }));
+        builder.apply(&mut ctx, &lreginfo);
+        ctx
+    }
+
+    fn reg(ctx: &mut RuneContext<PagedMemory, RuneRegFile>, reg: &str) -> Option<u64> {
+        let value = ctx.reg_read(reg);
+        ctx.as_constant(value)
+    }
+
+    fn mem(ctx: &mut RuneContext<PagedMemory, RuneRegFile>, addr: u64) -> Option<u64> {
+        let addr = ctx.define_const(addr, 64);
+        let value = ctx.mem_read(addr, 64);
+        ctx.as_constant(value)
+    }
+
+    #[test]
+    fn constants_and_symbols() {
+        let mut ctx = apply(ContextBuilder::new()
+                                .symbolic(Key::Mem(0x1000), 64)
+                                .constant(Key::Mem(0x2000), 0x41)
+                                .symbolic(Key::Reg("rax".to_owned()), 64));
+        assert_eq!(mem(&mut ctx, 0x1000), None);
+        assert_eq!(mem(&mut ctx, 0x2000), Some(0x41));
+        assert_eq!(reg(&mut ctx, "rax"), None);
+        assert_eq!(ctx.symbols().len(), 2);
+        // Registers that are not given are zero.
+        assert_eq!(reg(&mut ctx, "rsp"), Some(0));
+    }
+
+    #[test]
+    fn constants_override() {
+        // Constants are set after the symbols and the stack, even for registers already set.
+        let stack = StackSetup::default();
+        let mut ctx = apply(ContextBuilder::new()
+                                .stack(stack.clone())
+                                .symbolic(Key::Reg("rax".to_owned()), 64)
+                                .constant(Key::Reg("rax".to_owned()), 7)
+                                .constant(Key::Reg("rbp".to_owned()), 0x5000));
+        assert_eq!(reg(&mut ctx, "rax"), Some(7));
+        assert_eq!(reg(&mut ctx, "rsp"), Some(stack.layout(8).sp));
+        assert_eq!(reg(&mut ctx, "rbp"), Some(0x5000));
+    }
+
+    #[test]
+    fn lazy_registers() {
+        let mut ctx = apply(ContextBuilder::new()
+                                .lazy_registers(true)
+                                .constant(Key::Reg("rsp".to_owned()), 0x8000));
+        assert_eq!(reg(&mut ctx, "rsp"), Some(0x8000));
+        assert_eq!(reg(&mut ctx, "rax"), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Shadow call stack of a path, see `RuneContext::call_stack`.
+//!
+//! The engine records the calls and returns of the instructions that r2 gives the types `call`
+//! and `ret` (and their conditional and indirect variants). A return goes back to the return
+//! address of the innermost call, or to the one of an outer call when the functions in between
+//! did not return the usual way, as with `longjmp`. A return to any other address, while there
+//! are calls to return from, is a `BadReturn`: the return address on the stack was overwritten.
+//! Returns from the function the path started in have no call to match, and are not checked.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Frame {
+    /// Address of the call instruction.
+    pub site: u64,
+    /// Address of the function called.
+    pub target: u64,
+    /// Address the call returns to.
+    pub return_addr: u64,
+}
+
+/// A return to an address that does not follow a call of the call stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BadReturn {
+    /// Address of the return instruction.
+    pub site: u64,
+    pub target: u64,
+    /// Return address of the innermost call.
+    pub expected: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    /// Calls that did not return, the innermost last.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn call(&mut self, site: u64, target: u64, return_addr: u64) {
+        self.frames.push(Frame {
+            site: site,
+            target: target,
+            return_addr: return_addr,
+        });
+    }
+
+    /// Return from the call that returns to `target`, and from the calls it made. The call
+    /// stack is left alone if there is no such call.
+    pub fn ret(&mut self, site: u64, target: u64) -> Result<(), BadReturn> {
+        match self.frames.iter().rposition(|f| f.return_addr == target) {
+            Some(i) => {
+                self.frames.truncate(i);
+                Ok(())
+            }
+            None => {
+                match self.frames.last() {
+                    Some(frame) => {
+                        Err(BadReturn {
+                            site: site,
+                            target: target,
+                            expected: frame.return_addr,
+                        })
+                    }
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// A backtrace, the innermost call first, one a line.
+impl fmt::Display for CallStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            writeln!(f, "#{} {:#x} called from {:#x}", i, frame.target, frame.site)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn match_returns() {
+        let mut stack = CallStack::default();
+        assert_eq!(stack.ret(0x10, 0x41414141), Ok(()));
+        stack.call(0x100, 0x200, 0x105);
+        stack.call(0x210, 0x300, 0x215);
+        stack.call(0x310, 0x400, 0x315);
+        assert_eq!(stack.to_string(),
+                   "#0 0x400 called from 0x310\n#1 0x300 called from 0x210\n#2 0x200 called \
+                    from 0x100\n");
+        assert_eq!(stack.ret(0x400, 0x315), Ok(()));
+        assert_eq!(stack.ret(0x320, 0x41414141),
+                   Err(BadReturn {
+                       site: 0x320,
+                       target: 0x41414141,
+                       expected: 0x215,
+                   }));
+        assert_eq!(stack.depth(), 2);
+        // As `longjmp` does.
+        assert_eq!(stack.ret(0x330, 0x105), Ok(()));
+        assert_eq!(stack.frames(), &[]);
+    }
+}
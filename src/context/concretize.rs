@@ -0,0 +1,151 @@
+//! Concretization of symbolic values.
+//!
+//! Some operations (such as memory accesses through a symbolic pointer) may need a concrete
+//! value. `ConcretizationStrategy` decides how such values are picked from the set of values
+//! allowed by the current path constraints.
+
+use petgraph::graph::NodeIndex;
+
+use libsmt::backends::backend::SMTBackend;
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::backends::z3;
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+
+use context::stats::Stats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConcretizationStrategy {
+    /// Do not concretize, let the memory model reason about symbolic addresses. If it can not,
+    /// the address is concretized to upto `FALLBACK_SAMPLES` values. `PagedMemory` models them
+    /// while it holds few enough bytes, see `Memory::set_symbolic_limit`.
+    Symbolic,
+    /// Smallest value that satisfies the current constraints.
+    Min,
+    /// Largest value that satisfies the current constraints.
+    Max,
+    /// Upto N values that satisfy the current constraints. The value is constrained to be one
+    /// of these.
+    Samples(usize),
+    /// Refuse to concretize, which the engine reports as `ConcretizationError::Refused`.
+    Fail,
+}
+
+/// Why a value could not be concretized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConcretizationError {
+    /// An address is symbolic, and the strategy is `Fail`.
+    Refused,
+    /// No value satisfies the constraints of the path.
+    Infeasible,
+}
+
+/// Values that addresses are concretized to with `Symbolic`, when the memory can not model
+/// accesses through them (see `Memory::models_symbolic`).
+pub const FALLBACK_SAMPLES: usize = 4;
+
+impl Default for ConcretizationStrategy {
+    fn default() -> ConcretizationStrategy {
+        ConcretizationStrategy::Symbolic
+    }
+}
+
+/// Solver instance used to query the values that `expr` can take.
+struct Query<'a> {
+    solver: SMTLib2<qf_abv::QF_ABV>,
+    var: NodeIndex,
+    width: usize,
+    stats: &'a mut Stats,
+}
+
+impl<'a> Query<'a> {
+    fn new(solver: &SMTLib2<qf_abv::QF_ABV>, expr: NodeIndex, width: usize, stats: &'a mut Stats) -> Query<'a> {
+        let mut solver = solver.clone();
+        // Models only contain values for variables, so bind the expression to a variable.
+        let var = solver.new_var(Some("rune_query"), qf_abv::bv_sort(width));
+        solver.assert(core::OpCodes::Cmp, &[var, expr]);
+        Query {
+            solver: solver,
+            var: var,
+            width: width,
+            stats: stats,
+        }
+    }
+
+    fn constant(&mut self, c: u64) -> NodeIndex {
+        self.solver.new_const(bitvec::OpCodes::Const(c, self.width))
+    }
+
+    /// Value of the expression in some model of the constraints, `None` if they are
+    /// unsatisfiable.
+    fn model(&mut self, extra: &[(bitvec::OpCodes, u64)]) -> Option<u64> {
+        let mut solver = self.solver.clone();
+        for &(ref op, c) in extra {
+            let c = solver.new_const(bitvec::OpCodes::Const(c, self.width));
+            solver.assert(op.clone(), &[self.var, c]);
+        }
+        let mut z3: z3::Z3 = Default::default();
+        self.stats.solve(&mut solver, &mut z3).ok().and_then(|model| model.get(&self.var).cloned())
+    }
+
+    fn exclude(&mut self, c: u64) {
+        let c = self.constant(c);
+        let eq = self.solver.assert(core::OpCodes::Cmp, &[self.var, c]);
+        self.solver.assert(core::OpCodes::Not, &[eq]);
+    }
+}
+
+/// Upto `n` distinct values of `expr` (of `width` bits) that satisfy the constraints in `solver`.
+/// The queries are counted in `stats`, as are those of `min` and `max`.
+pub fn samples(solver: &SMTLib2<qf_abv::QF_ABV>, expr: NodeIndex, width: usize, n: usize, stats: &mut Stats) -> Vec<u64> {
+    let mut query = Query::new(solver, expr, width, stats);
+    let mut values = Vec::new();
+    while values.len() < n {
+        match query.model(&[]) {
+            Some(v) => {
+                values.push(v);
+                query.exclude(v);
+            }
+            None => break,
+        }
+    }
+    values
+}
+
+/// Smallest value of `expr` that satisfies the constraints in `solver`.
+pub fn min(solver: &SMTLib2<qf_abv::QF_ABV>, expr: NodeIndex, width: usize, stats: &mut Stats) -> Option<u64> {
+    let mut query = Query::new(solver, expr, width, stats);
+    let mut high = match query.model(&[]) {
+        Some(v) => v,
+        None => return None,
+    };
+    let mut low = 0;
+    // Invariant: `high` is feasible, no value less than `low` is.
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match query.model(&[(bitvec::OpCodes::BvUGe, low), (bitvec::OpCodes::BvULe, mid)]) {
+            Some(v) => high = v,
+            None => low = mid + 1,
+        }
+    }
+    Some(high)
+}
+
+/// Largest value of `expr` that satisfies the constraints in `solver`.
+pub fn max(solver: &SMTLib2<qf_abv::QF_ABV>, expr: NodeIndex, width: usize, stats: &mut Stats) -> Option<u64> {
+    let mut query = Query::new(solver, expr, width, stats);
+    let mut low = match query.model(&[]) {
+        Some(v) => v,
+        None => return None,
+    };
+    let mut high = if width >= 64 { u64::max_value() } else { (1 << width) - 1 };
+    // Invariant: `low` is feasible, no value greater than `high` is.
+    while low < high {
+        let mid = high - (high - low) / 2;
+        match query.model(&[(bitvec::OpCodes::BvUGe, mid), (bitvec::OpCodes::BvULe, high)]) {
+            Some(v) => low = v,
+            None => high = mid - 1,
+        }
+    }
+    Some(low)
+}
@@ -3,10 +3,54 @@
 use std::fmt::Debug;
 use std::hash::Hash;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::Duration;
 
 use libsmt::backends::smtlib2::SMTProc;
 
+use context::call_stack::BadReturn;
+use context::image::ContextImage;
+use context::stats::SolverCost;
+use memory::memory::{Access, AccessViolation};
+use memory::heap::HeapError;
+use context::concretize::ConcretizationError;
+use memory::uninit::{UninitPolicy, UninitRead};
+use regstore::regstore::RegFault;
+use engine::breakpt::{Watchpoint, WatchHit, WatchValue};
+use engine::trace::Effect;
+use stream::IsaMode;
+use arch::arch::Arch;
+
+/// A branch condition assumed on a path.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathConstraint<V> {
+    /// Address of the branch instruction.
+    pub ip: u64,
+    pub condition: V,
+    /// Assertion of the solver that constrains `condition`.
+    pub constraint: V,
+    /// Whether the branch is assumed to be taken, that is, `condition` to be non-zero.
+    pub taken: bool,
+}
+
+/// A symbolic input, created by `ContextAPI::set_reg_as_sym` or `ContextAPI::set_mem_as_sym`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol<V> {
+    /// Name of the variable in the solver.
+    pub name: String,
+    pub var: V,
+    /// Width in bits.
+    pub width: usize,
+}
+
+impl<V: Hash + Eq> Symbol<V> {
+    /// Value of the input in a model returned by `Context::solve`.
+    pub fn value(&self, model: &HashMap<V, u64>) -> Option<u64> {
+        model.get(&self.var).cloned()
+    }
+}
+
 pub trait Context: Clone + Debug
                    + RegisterRead
                    + RegisterWrite<VarRef=<Self as RegisterRead>::VarRef>
@@ -14,6 +58,8 @@ pub trait Context: Clone + Debug
                    + MemoryRead<VarRef=<Self as RegisterRead>::VarRef>
                    + MemoryWrite<VarRef=<Self as RegisterRead>::VarRef>
 {
+    /// Address of the next instruction to execute. Reads and writes of the program counter
+    /// register (`rip`, `eip`, `pc`, ...) go to it.
     fn ip(&self) -> u64;
     fn is_symbolic(&self) -> bool {
         true
@@ -21,9 +67,18 @@ pub trait Context: Clone + Debug
     fn is_concrete(&self) -> bool {
         !self.is_symbolic()
     }
+    /// Width of addresses in bits. Rune also uses this as the width of ESIL values.
+    fn address_width(&self) -> usize {
+        64
+    }
     fn increment_ip(&mut self, u64);
     fn set_ip(&mut self, u64);
     fn define_const(&mut self, u64, usize) -> <Self as RegisterRead>::VarRef;
+    /// A fresh symbol of `width` bits for a value rune does not model, named after `name`.
+    fn fresh_value(&mut self, name: &str, width: usize) -> <Self as RegisterRead>::VarRef;
+    /// Same as `fresh_value`, for intermediate values that are not inputs of the program. These
+    /// are not registered as symbols, so they are left out of solutions and replays.
+    fn opaque_value(&mut self, name: &str, width: usize) -> <Self as RegisterRead>::VarRef;
     fn alias_of(&self, String) -> Option<String>;
     fn e_old(&self) -> <Self as RegisterRead>::VarRef;
     fn e_cur(&self) -> <Self as RegisterRead>::VarRef;
@@ -33,6 +88,195 @@ pub trait Context: Clone + Debug
     fn var_named<T: AsRef<str>>(& self, T) -> Option<<Self as RegisterRead>::VarRef>;
     fn set_e_old(&mut self, <Self as RegisterRead>::VarRef);
     fn set_e_cur(&mut self, <Self as RegisterRead>::VarRef);
+
+    /// Check if the `size` bytes of memory at a concrete address may be accessed. A violation is
+    /// recorded for the first byte whose access is not permitted.
+    fn check_access(&mut self, _addr: u64, _size: u64, _access: Access) { }
+
+    /// Also record accesses to addresses that are not mapped as access violations.
+    fn set_strict_mapping(&mut self, _on: bool) { }
+
+    /// Returns the access violation recorded since the last call, if any.
+    fn take_violation(&mut self) -> Option<AccessViolation> {
+        None
+    }
+
+    /// Register a watchpoint on a range of memory. Accesses through a symbolic address hit the
+    /// watchpoint if the address may point into the range.
+    fn add_watchpoint(&mut self, _wp: Watchpoint) { }
+
+    /// Returns the watchpoints hit since the last call.
+    fn take_watch_hits(&mut self) -> Vec<WatchHit<<Self as RegisterRead>::VarRef>> {
+        Vec::new()
+    }
+
+    /// Record the writes to registers and memory, for `take_effects`.
+    fn set_tracing(&mut self, _on: bool) { }
+
+    /// Writes made since the last call, while tracing.
+    fn take_effects(&mut self) -> Vec<Effect<<Self as RegisterRead>::VarRef>> {
+        Vec::new()
+    }
+
+    /// Bytes in [addr, addr + len) that were written since they were loaded, if the memory
+    /// they are in is executable. Symbolic bytes are `None`.
+    fn code_patches(&self, _addr: u64, _len: u64) -> Vec<(u64, Option<u8>)> {
+        Vec::new()
+    }
+
+    /// Evaluate the watch expressions after the instruction at `ip`, without changing the state.
+    fn eval_watch_exprs(&mut self, _ip: u64) -> Vec<WatchValue> {
+        Vec::new()
+    }
+
+    /// Returns the heap error (double free, use after free, ...) recorded since the last call,
+    /// if any.
+    fn take_heap_error(&mut self) -> Option<HeapError> {
+        None
+    }
+
+    /// Returns the first value that could not be concretized since the last call, if any. The
+    /// access or operation that needed the value was not carried out.
+    fn take_concretization_error(&mut self) -> Option<ConcretizationError> {
+        None
+    }
+
+    /// Returns the fault raised by a register hook since the last call, if any.
+    fn take_reg_fault(&mut self) -> Option<RegFault> {
+        None
+    }
+
+    /// How reads of uninitialized memory are handled.
+    fn uninit_policy(&self) -> UninitPolicy {
+        UninitPolicy::Ignore
+    }
+
+    /// Returns the reads of uninitialized memory recorded since the last call.
+    fn take_uninit_reads(&mut self) -> Vec<UninitRead> {
+        Vec::new()
+    }
+
+    /// Instruction set that the processor currently decodes.
+    fn isa_mode(&mut self) -> IsaMode {
+        IsaMode::Normal
+    }
+
+    /// Switch the instruction set. Returns `false` if the architecture only has one.
+    fn set_isa_mode(&mut self, _mode: IsaMode) -> bool {
+        false
+    }
+
+    /// Branch that waits for the instruction in its delay slot, as the address of the slot and
+    /// the target of the branch.
+    fn delayed_branch(&self) -> Option<(u64, u64)> {
+        None
+    }
+
+    fn set_delayed_branch(&mut self, _branch: Option<(u64, u64)>) { }
+
+    /// Name of the register that has the role `alias` (`PC`, `SP`, `A0`, ...) in the register
+    /// profile.
+    fn reg_of_alias(&self, _alias: &str) -> Option<String> {
+        None
+    }
+
+    /// Architecture of the program, if known.
+    fn arch(&self) -> Option<Rc<Arch>> {
+        None
+    }
+
+    /// Whether `reg` names a register of the context.
+    fn has_reg(&self, _reg: &str) -> bool {
+        true
+    }
+
+    /// Whether `reg` is the program counter.
+    fn is_pc(&self, reg: &str) -> bool {
+        self.alias_of(reg.to_owned()) == Some("PC".to_owned())
+    }
+
+    /// Record that the path assumes `condition` to be `taken`, which the assertion `constraint`
+    /// expresses. Explorers call this when they constrain a branch condition.
+    fn record_branch(&mut self,
+                     _condition: <Self as RegisterRead>::VarRef,
+                     _constraint: <Self as RegisterRead>::VarRef,
+                     _taken: bool) {
+    }
+
+    /// Whether the 1 bit `condition` can be set under the current path constraints. Contexts
+    /// that can not check conditions only rule out constants.
+    fn is_feasible(&mut self, condition: <Self as RegisterRead>::VarRef) -> bool {
+        self.as_constant(condition) != Some(0)
+    }
+
+    /// Value of `var` if it is known to be a constant.
+    fn as_constant(&mut self, _var: <Self as RegisterRead>::VarRef) -> Option<u64> {
+        None
+    }
+
+    /// Values that `vars`, given with their width, can take together under the current path
+    /// constraints, `None` if the path is infeasible. Contexts that can not pick values for
+    /// symbolic ones only return constants.
+    fn concrete_values(&mut self, vars: &[(<Self as RegisterRead>::VarRef, usize)]) -> Option<Vec<u64>> {
+        vars.iter().map(|&(ref v, _)| self.as_constant(v.clone())).collect()
+    }
+
+    /// Values of the symbolic inputs, by name, for an input that follows this path, `None` if
+    /// there is none. Contexts that can not solve for inputs never find one.
+    fn solve_inputs(&mut self) -> Option<BTreeMap<String, u64>> {
+        None
+    }
+
+    /// What the path so far costs the solver. Contexts that do not keep track of it cost nothing.
+    fn solver_cost(&self) -> SolverCost {
+        SolverCost::default()
+    }
+
+    /// Time the path so far spent in the solver, which is `solver_cost().solver_time` without
+    /// measuring the constraints.
+    fn solver_time(&self) -> Duration {
+        self.solver_cost().solver_time
+    }
+
+    /// Hash of the state of the context, the same for contexts in the same state, see
+    /// `RuneContext::state_hash`. Contexts that can not hash their state return `None`.
+    fn state_hash(&self) -> Option<u64> {
+        None
+    }
+
+    /// Record the steps of the path from now on, see `context::history`. Contexts that keep no
+    /// history ignore it.
+    fn start_history(&mut self) {}
+
+    /// Start a step of the history, for the instruction at `addr`.
+    fn begin_step(&mut self, _addr: u64) {}
+
+    /// Merge `other`, a fork of an ancestor of this context that reached the same address, into
+    /// this context, so that it describes both paths. Contexts that can not merge return an
+    /// error.
+    fn merge(&mut self, _other: &Self) -> Result<(), String> {
+        Err("Contexts of this type cannot be merged".to_owned())
+    }
+
+    /// Record that the instruction at `site` called `target`, to return to `return_addr`.
+    /// Contexts that do not keep a call stack ignore calls.
+    fn record_call(&mut self, _site: u64, _target: u64, _return_addr: u64) { }
+
+    /// Record that the instruction at `site` returned to `target`, see `context::call_stack`.
+    fn record_return(&mut self, _site: u64, _target: u64) -> Result<(), BadReturn> {
+        Ok(())
+    }
+
+    /// Serializable image of this context, see `context::image`. Contexts that can not be saved
+    /// return an error.
+    fn to_image(&self) -> Result<ContextImage, String> {
+        Err("Contexts of this type cannot be saved".to_owned())
+    }
+
+    /// Restore the state saved by `to_image`, into a context created for the same program.
+    fn load_image(&mut self, _image: &ContextImage) -> Result<(), String> {
+        Err("Contexts of this type cannot be loaded".to_owned())
+    }
 }
 
 pub trait MemoryRead: Sized {
@@ -71,11 +315,15 @@ pub trait Evaluate {
 pub trait ContextAPI: Context {
     /// Set register to hold either symbolic or concrete values.
     fn set_reg_as_const<T: AsRef<str>>(&mut self, T, u64) -> <Self as RegisterRead>::VarRef;
-    fn set_reg_as_sym<T: AsRef<str>>(&mut self, T) -> <Self as RegisterRead>::VarRef;
+    /// Set the low `width` bits of a register to a new symbol, named `name` or after the
+    /// register. The remaining bits are zero.
+    fn set_reg_as_sym<T: AsRef<str>>(&mut self, T, usize, Option<&str>) -> Symbol<<Self as RegisterRead>::VarRef>;
 
     /// Set memory to hold either symbolic or concrete values.
     fn set_mem_as_const(&mut self, u64, u64, usize) -> <Self as RegisterRead>::VarRef;
-    fn set_mem_as_sym(&mut self, u64, usize) -> <Self as RegisterRead>::VarRef;
+    /// Set `width` bits of memory at an address to a new symbol, named `name` or after the
+    /// address.
+    fn set_mem_as_sym(&mut self, u64, usize, Option<&str>) -> Symbol<<Self as RegisterRead>::VarRef>;
 
     /// Set registers that are not set to be a constant zero.
     fn zero_registers(&mut self);
@@ -0,0 +1,275 @@
+//! ELF core files, to re-execute the state of a crashed process symbolically.
+//!
+//! `CoreDump::parse` reads the loadable segments of a core file and the registers of its first
+//! thread (the `NT_PRSTATUS` note), for the architectures rune knows (see `arch::arch`). The
+//! dump is applied to a context created for the crashed program with
+//! `RuneContext::load_core_dump`, or `ContextBuilder::core_dump`.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use r2api::structs::Endian;
+
+use memory::memory::Permission;
+
+/// A loadable segment of a core file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub vaddr: u64,
+    /// Size of the segment in memory. Bytes past `data` were not dumped.
+    pub size: u64,
+    pub data: Vec<u8>,
+    pub perm: Permission,
+}
+
+#[derive(Clone, Debug)]
+pub struct CoreDump {
+    /// Name of the architecture, as reported by r2.
+    pub arch: String,
+    pub bits: usize,
+    pub endian: Endian,
+    /// Registers of the thread that crashed, by their names in the r2 register profiles.
+    pub registers: Vec<(String, u64)>,
+    pub segments: Vec<Segment>,
+}
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+/// Offset of `pr_reg` in `struct elf_prstatus`, for 32 and 64 bit processes.
+const PR_REG_32: usize = 72;
+const PR_REG_64: usize = 112;
+
+const X86_64_REGS: &'static [&'static str] = &["r15", "r14", "r13", "r12", "rbp", "rbx", "r11", "r10",
+                                               "r9", "r8", "rax", "rcx", "rdx", "rsi", "rdi",
+                                               "orig_rax", "rip", "cs", "rflags", "rsp", "ss",
+                                               "fs_base", "gs_base", "ds", "es", "fs", "gs"];
+const X86_REGS: &'static [&'static str] = &["ebx", "ecx", "edx", "esi", "edi", "ebp", "eax", "ds",
+                                            "es", "fs", "gs", "orig_eax", "eip", "cs", "eflags",
+                                            "esp", "ss"];
+const AARCH64_REGS: &'static [&'static str] = &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8",
+                                                "x9", "x10", "x11", "x12", "x13", "x14", "x15",
+                                                "x16", "x17", "x18", "x19", "x20", "x21", "x22",
+                                                "x23", "x24", "x25", "x26", "x27", "x28", "x29",
+                                                "x30", "sp", "pc", "pstate"];
+const ARM_REGS: &'static [&'static str] = &["r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8",
+                                            "r9", "r10", "r11", "r12", "sp", "lr", "pc", "cpsr",
+                                            "orig_r0"];
+
+/// Reads integers of an ELF file of either class and byte order.
+struct Reader<'a> {
+    data: &'a [u8],
+    endian: Endian,
+    bits: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn uint(&self, offset: usize, size: usize) -> Result<u64, String> {
+        let bytes = self.data
+                        .get(offset..offset + size)
+                        .ok_or_else(|| format!("Truncated core file at {:#x}", offset))?;
+        let mut value = 0;
+        for i in 0..size {
+            let byte = match self.endian {
+                Endian::Big => bytes[i],
+                _ => bytes[size - 1 - i],
+            };
+            value = (value << 8) | byte as u64;
+        }
+        Ok(value)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u64, String> {
+        self.uint(offset, 2)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        self.uint(offset, 4).map(|v| v as u32)
+    }
+
+    /// A word of the width of the ELF class.
+    fn word(&self, offset: usize) -> Result<u64, String> {
+        self.uint(offset, self.bits / 8)
+    }
+
+    fn bytes(&self, offset: u64, size: u64) -> Result<&'a [u8], String> {
+        let (start, end) = (offset as usize, (offset + size) as usize);
+        self.data.get(start..end).ok_or_else(|| format!("Truncated core file at {:#x}", offset))
+    }
+}
+
+impl CoreDump {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<CoreDump, String> {
+        let mut data = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut data)).map_err(|e| e.to_string())?;
+        CoreDump::parse(&data)
+    }
+
+    /// Parse the contents of a core file.
+    pub fn parse(data: &[u8]) -> Result<CoreDump, String> {
+        if data.len() < 20 || &data[0..4] != b"\x7fELF" {
+            return Err("Not an ELF file".to_owned());
+        }
+        let bits = match data[4] {
+            1 => 32,
+            2 => 64,
+            c => return Err(format!("Invalid ELF class {}", c)),
+        };
+        let endian = match data[5] {
+            1 => Endian::Little,
+            2 => Endian::Big,
+            d => return Err(format!("Invalid ELF data encoding {}", d)),
+        };
+        let r = Reader {
+            data: data,
+            endian: endian,
+            bits: bits,
+        };
+        if r.u16(16)? != 4 {
+            return Err("Not a core file".to_owned());
+        }
+        let (arch, regs) = match r.u16(18)? {
+            3 => ("x86", X86_REGS),
+            62 => ("x86", X86_64_REGS),
+            40 => ("arm", ARM_REGS),
+            183 => ("arm", AARCH64_REGS),
+            m => return Err(format!("Unsupported machine {}", m)),
+        };
+
+        // Offsets of e_phoff, e_phentsize and e_phnum.
+        let (phoff, phentsize, phnum) = if bits == 64 {
+            (r.word(32)?, r.u16(54)?, r.u16(56)?)
+        } else {
+            (r.word(28)?, r.u16(42)?, r.u16(44)?)
+        };
+
+        let mut core = CoreDump {
+            arch: arch.to_owned(),
+            bits: bits,
+            endian: endian,
+            registers: Vec::new(),
+            segments: Vec::new(),
+        };
+        for i in 0..phnum {
+            let ph = (phoff + i * phentsize) as usize;
+            let p_type = r.u32(ph)?;
+            let (flags, offset, vaddr, filesz, memsz) = if bits == 64 {
+                (r.u32(ph + 4)?, r.word(ph + 8)?, r.word(ph + 16)?, r.word(ph + 32)?, r.word(ph + 40)?)
+            } else {
+                (r.u32(ph + 24)?, r.word(ph + 4)?, r.word(ph + 8)?, r.word(ph + 16)?, r.word(ph + 20)?)
+            };
+            match p_type {
+                PT_LOAD => {
+                    core.segments.push(Segment {
+                        vaddr: vaddr,
+                        size: memsz,
+                        data: r.bytes(offset, filesz)?.to_vec(),
+                        perm: Permission::new(flags & 4 != 0, flags & 2 != 0, flags & 1 != 0),
+                    });
+                }
+                PT_NOTE if core.registers.is_empty() => {
+                    core.registers = prstatus_regs(&r, offset, filesz, regs)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(core)
+    }
+}
+
+/// Registers of the first `NT_PRSTATUS` note of the notes at [offset, offset + size).
+fn prstatus_regs(r: &Reader, offset: u64, size: u64, names: &[&str]) -> Result<Vec<(String, u64)>, String> {
+    let align = |n: u64| (n + 3) & !3;
+    let mut note = offset;
+    while note + 12 <= offset + size {
+        let namesz = r.u32(note as usize)? as u64;
+        let descsz = r.u32(note as usize + 4)? as u64;
+        let n_type = r.u32(note as usize + 8)?;
+        let desc = note + 12 + align(namesz);
+        if n_type == NT_PRSTATUS {
+            let (start, width) = if r.bits == 64 { (PR_REG_64, 8) } else { (PR_REG_32, 4) };
+            let regs = r.bytes(desc, descsz)?;
+            let regs = Reader {
+                data: regs,
+                endian: r.endian,
+                bits: r.bits,
+            };
+            return names.iter()
+                        .enumerate()
+                        .map(|(i, name)| regs.uint(start + i * width, width).map(|v| (name.to_string(), v)))
+                        .collect();
+        }
+        note = desc + align(descsz);
+    }
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn put(buf: &mut Vec<u8>, offset: usize, value: u64, size: usize) {
+        if buf.len() < offset + size {
+            buf.resize(offset + size, 0);
+        }
+        for i in 0..size {
+            buf[offset + i] = (value >> (8 * i)) as u8;
+        }
+    }
+
+    /// A little endian x86-64 core file with a note and a segment.
+    fn core_file() -> Vec<u8> {
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        put(&mut elf, 16, 4, 2);
+        put(&mut elf, 18, 62, 2);
+        put(&mut elf, 32, 64, 8);
+        put(&mut elf, 54, 56, 2);
+        put(&mut elf, 56, 2, 2);
+
+        // Notes at 0x100, with a prstatus whose rip is 0x401000 and rsp 0x7ffe0000.
+        let desc = 0x100 + 12 + 8;
+        put(&mut elf, 64, PT_NOTE as u64, 4);
+        put(&mut elf, 64 + 8, 0x100, 8);
+        put(&mut elf, 64 + 32, 12 + 8 + 336, 8);
+        put(&mut elf, desc + 336 - 1, 0, 1);
+        put(&mut elf, 0x100, 5, 4);
+        put(&mut elf, 0x104, 336, 4);
+        put(&mut elf, 0x108, NT_PRSTATUS as u64, 4);
+        elf[0x10c..0x111].copy_from_slice(b"CORE\0");
+        put(&mut elf, desc + PR_REG_64 + 16 * 8, 0x401000, 8);
+        put(&mut elf, desc + PR_REG_64 + 19 * 8, 0x7ffe0000, 8);
+
+        // Four bytes of a 0x1000 byte rw- segment at 0x600000.
+        let data = elf.len();
+        elf.extend_from_slice(b"\x41\x42\x43\x44");
+        put(&mut elf, 120, PT_LOAD as u64, 4);
+        put(&mut elf, 120 + 4, 6, 4);
+        put(&mut elf, 120 + 8, data as u64, 8);
+        put(&mut elf, 120 + 16, 0x600000, 8);
+        put(&mut elf, 120 + 32, 4, 8);
+        put(&mut elf, 120 + 40, 0x1000, 8);
+        elf
+    }
+
+    #[test]
+    fn parse_core() {
+        let core = CoreDump::parse(&core_file()).unwrap();
+        assert_eq!((core.arch.as_str(), core.bits), ("x86", 64));
+
+        let reg = |name: &str| core.registers.iter().find(|r| r.0 == name).map(|r| r.1);
+        assert_eq!(reg("rip"), Some(0x401000));
+        assert_eq!(reg("rsp"), Some(0x7ffe0000));
+        assert_eq!(reg("rax"), Some(0));
+
+        assert_eq!(core.segments,
+                   vec![Segment {
+                            vaddr: 0x600000,
+                            size: 0x1000,
+                            data: b"ABCD".to_vec(),
+                            perm: Permission::new(true, true, false),
+                        }]);
+        assert!(CoreDump::parse(b"\x7fELF").is_err());
+    }
+}
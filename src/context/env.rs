@@ -0,0 +1,101 @@
+//! Environment variables of the program.
+//!
+//! Variables are declared before execution starts, with a concrete value or as a string of
+//! symbolic bytes (see `RuneContext::set_env`). The same table provides the `envp` strings of
+//! the initial stack and the values returned by `getenv`, so both see the same symbols.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::BTreeMap;
+
+use context::rune_ctx::RuneContext;
+use engine::engine::EngineResult;
+use engine::hook::{self, Hook};
+use memory::memory::Memory;
+use regstore::regstore::RegStore;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvValue {
+    Concrete(String),
+    /// A string of this many symbolic bytes, which are not NUL.
+    Symbolic(usize),
+}
+
+impl EnvValue {
+    /// Length of the value, without the terminating NUL.
+    pub fn len(&self) -> usize {
+        match *self {
+            EnvValue::Concrete(ref s) => s.len(),
+            EnvValue::Symbolic(len) => len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvVar {
+    pub value: EnvValue,
+    /// Address of the value in memory, once the program can see it.
+    pub addr: Option<u64>,
+    /// Symbols of a symbolic value, once it is in memory.
+    pub symbols: Vec<NodeIndex>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Environment {
+    vars: BTreeMap<String, EnvVar>,
+}
+
+impl Environment {
+    /// Declare the variable `name`, replacing a previous declaration.
+    pub fn set(&mut self, name: &str, value: EnvValue) {
+        self.vars.insert(name.to_owned(),
+                         EnvVar {
+                             value: value,
+                             addr: None,
+                             symbols: Vec::new(),
+                         });
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.vars.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EnvVar> {
+        self.vars.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut EnvVar> {
+        self.vars.get_mut(name)
+    }
+
+    /// Names of the variables, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.vars.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+}
+
+/// Hook for `char *getenv(const char *name)`.
+pub fn getenv_hook<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>) -> EngineResult<()>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    let name = hook::arg(ctx, 0)?;
+    let value = ctx.getenv(name);
+    hook::return_from_call(ctx, Some(value))
+}
+
+/// Hooks for the libc environment functions, to be installed with `Rune::hook_imports`.
+pub fn env_hooks<Mem, Reg>() -> Vec<(&'static str, Hook<RuneContext<Mem, Reg>>)>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    vec![("getenv", getenv_hook::<Mem, Reg> as Hook<RuneContext<Mem, Reg>>)]
+}
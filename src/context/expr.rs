@@ -0,0 +1,214 @@
+//! Expressions over the state of a context, to state preconditions without building solver
+//! expressions by hand, see `RuneContext::assume`.
+//!
+//! ```ignore
+//! use rune::context::expr::{constant, mem, reg};
+//!
+//! // rsi < 0x100 && [0x601040] != 0
+//! ctx.assume(&reg("rsi").ult(constant(0x100)).and(mem(0x601040, 8).ne(constant(0))));
+//! ```
+//!
+//! Operands of different widths are zero-extended to the wider one. Constants take the width
+//! of the other operand, or the address width if both operands are constants.
+
+use libsmt::logics::qf_abv::QF_ABV_Fn;
+use libsmt::theories::{bitvec, core};
+
+/// A bitvector valued expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    /// Value of a register.
+    Reg(String),
+    /// `width` bits of memory at an address.
+    Mem(u64, usize),
+    /// `width` bits of memory at the address computed by an expression.
+    Load(Box<Expr>, usize),
+    /// A symbol of the context, see `RuneContext::symbol`.
+    Sym(String),
+    Const(u64),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+    Shl,
+    LShr,
+}
+
+/// A condition on expressions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cond {
+    Cmp(CmpOp, Expr, Expr),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+    Not(Box<Cond>),
+}
+
+/// Comparisons, unsigned unless noted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+}
+
+pub fn reg<T: AsRef<str>>(name: T) -> Expr {
+    Expr::Reg(name.as_ref().to_owned())
+}
+
+pub fn mem(addr: u64, width: usize) -> Expr {
+    Expr::Mem(addr, width)
+}
+
+pub fn load(addr: Expr, width: usize) -> Expr {
+    Expr::Load(Box::new(addr), width)
+}
+
+pub fn sym<T: AsRef<str>>(name: T) -> Expr {
+    Expr::Sym(name.as_ref().to_owned())
+}
+
+pub fn constant(value: u64) -> Expr {
+    Expr::Const(value)
+}
+
+impl Expr {
+    fn binary(self, op: BinOp, other: Expr) -> Expr {
+        Expr::Binary(op, Box::new(self), Box::new(other))
+    }
+
+    pub fn add(self, other: Expr) -> Expr {
+        self.binary(BinOp::Add, other)
+    }
+
+    pub fn sub(self, other: Expr) -> Expr {
+        self.binary(BinOp::Sub, other)
+    }
+
+    pub fn mul(self, other: Expr) -> Expr {
+        self.binary(BinOp::Mul, other)
+    }
+
+    pub fn and(self, other: Expr) -> Expr {
+        self.binary(BinOp::And, other)
+    }
+
+    pub fn or(self, other: Expr) -> Expr {
+        self.binary(BinOp::Or, other)
+    }
+
+    pub fn xor(self, other: Expr) -> Expr {
+        self.binary(BinOp::Xor, other)
+    }
+
+    pub fn shl(self, other: Expr) -> Expr {
+        self.binary(BinOp::Shl, other)
+    }
+
+    pub fn lshr(self, other: Expr) -> Expr {
+        self.binary(BinOp::LShr, other)
+    }
+
+    fn cmp(self, op: CmpOp, other: Expr) -> Cond {
+        Cond::Cmp(op, self, other)
+    }
+
+    pub fn eq(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Eq, other)
+    }
+
+    pub fn ne(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Ne, other)
+    }
+
+    pub fn ult(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Ult, other)
+    }
+
+    pub fn ule(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Ule, other)
+    }
+
+    pub fn ugt(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Ugt, other)
+    }
+
+    pub fn uge(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Uge, other)
+    }
+
+    pub fn slt(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Slt, other)
+    }
+
+    pub fn sle(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Sle, other)
+    }
+
+    pub fn sgt(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Sgt, other)
+    }
+
+    pub fn sge(self, other: Expr) -> Cond {
+        self.cmp(CmpOp::Sge, other)
+    }
+}
+
+impl Cond {
+    pub fn and(self, other: Cond) -> Cond {
+        Cond::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Cond) -> Cond {
+        Cond::Or(Box::new(self), Box::new(other))
+    }
+
+    pub fn not(self) -> Cond {
+        Cond::Not(Box::new(self))
+    }
+}
+
+impl From<BinOp> for QF_ABV_Fn {
+    fn from(op: BinOp) -> QF_ABV_Fn {
+        match op {
+            BinOp::Add => bitvec::OpCodes::BvAdd,
+            BinOp::Sub => bitvec::OpCodes::BvSub,
+            BinOp::Mul => bitvec::OpCodes::BvMul,
+            BinOp::And => bitvec::OpCodes::BvAnd,
+            BinOp::Or => bitvec::OpCodes::BvOr,
+            BinOp::Xor => bitvec::OpCodes::BvXor,
+            BinOp::Shl => bitvec::OpCodes::BvShl,
+            BinOp::LShr => bitvec::OpCodes::BvLShr,
+        }.into()
+    }
+}
+
+impl From<CmpOp> for QF_ABV_Fn {
+    fn from(op: CmpOp) -> QF_ABV_Fn {
+        match op {
+            CmpOp::Eq => core::OpCodes::Cmp.into(),
+            CmpOp::Ne => core::OpCodes::Distinct.into(),
+            CmpOp::Ult => bitvec::OpCodes::BvULt.into(),
+            CmpOp::Ule => bitvec::OpCodes::BvULe.into(),
+            CmpOp::Ugt => bitvec::OpCodes::BvUGt.into(),
+            CmpOp::Uge => bitvec::OpCodes::BvUGe.into(),
+            CmpOp::Slt => bitvec::OpCodes::BvSLt.into(),
+            CmpOp::Sle => bitvec::OpCodes::BvSLe.into(),
+            CmpOp::Sgt => bitvec::OpCodes::BvSGt.into(),
+            CmpOp::Sge => bitvec::OpCodes::BvSGe.into(),
+        }
+    }
+}
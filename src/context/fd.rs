@@ -0,0 +1,105 @@
+//! File descriptors the program reads its input from.
+//!
+//! The contents of a file are the bytes of the solver, usually symbols, that successive reads
+//! return. `RuneContext::symbolize_stdin` opens standard input this way, and `read_hook`
+//! summarizes `read(2)` by copying the next bytes of the file to the buffer of the program.
+
+use petgraph::graph::NodeIndex;
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+use context::rune_ctx::RuneContext;
+use engine::engine::EngineResult;
+use engine::hook::{self, Hook};
+use memory::memory::Memory;
+use regstore::regstore::RegStore;
+
+pub const STDIN: u64 = 0;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpenFile {
+    pub data: Vec<NodeIndex>,
+    /// Offset of the next byte to read.
+    pub pos: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FdTable {
+    files: BTreeMap<u64, OpenFile>,
+}
+
+impl FdTable {
+    /// Open `fd` with the contents `data`, replacing the file previously open as `fd`.
+    pub fn open(&mut self, fd: u64, data: Vec<NodeIndex>) {
+        self.files.insert(fd, OpenFile { data: data, pos: 0 });
+    }
+
+    pub fn close(&mut self, fd: u64) -> bool {
+        self.files.remove(&fd).is_some()
+    }
+
+    pub fn get(&self, fd: u64) -> Option<&OpenFile> {
+        self.files.get(&fd)
+    }
+
+    pub fn get_mut(&mut self, fd: u64) -> Option<&mut OpenFile> {
+        self.files.get_mut(&fd)
+    }
+
+    /// Open file descriptors, in order.
+    pub fn fds(&self) -> Vec<u64> {
+        self.files.keys().cloned().collect()
+    }
+
+    /// Read up to `count` bytes from `fd`, `None` if `fd` is not open. Reads at the end of the
+    /// file return no bytes.
+    pub fn read(&mut self, fd: u64, count: u64) -> Option<Vec<NodeIndex>> {
+        self.files.get_mut(&fd).map(|file| {
+            let end = cmp::min(file.data.len() as u64, file.pos as u64 + count) as usize;
+            let bytes = file.data[file.pos..end].to_vec();
+            file.pos = end;
+            bytes
+        })
+    }
+}
+
+/// Hook for `ssize_t read(int fd, void *buf, size_t count)`.
+pub fn read_hook<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>) -> EngineResult<()>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    let fd = hook::arg(ctx, 0)?;
+    let buf = hook::arg(ctx, 1)?;
+    let count = hook::arg(ctx, 2)?;
+    let n = ctx.read(fd, buf, count);
+    hook::return_from_call(ctx, Some(n))
+}
+
+/// Hooks for the libc input functions, to be installed with `Rune::hook_imports`.
+pub fn input_hooks<Mem, Reg>() -> Vec<(&'static str, Hook<RuneContext<Mem, Reg>>)>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    vec![("read", read_hook::<Mem, Reg> as Hook<RuneContext<Mem, Reg>>)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_files() {
+        let mut fds = FdTable::default();
+        let data: Vec<NodeIndex> = (0..5).map(NodeIndex::new).collect();
+        fds.open(STDIN, data.clone());
+
+        assert_eq!(fds.read(STDIN, 3), Some(data[0..3].to_vec()));
+        assert_eq!(fds.read(STDIN, 3), Some(data[3..5].to_vec()));
+        assert_eq!(fds.read(STDIN, 3), Some(vec![]));
+        assert_eq!(fds.get(STDIN).map(|f| f.pos), Some(5));
+        assert_eq!(fds.read(3, 1), None);
+        assert!(fds.close(STDIN));
+        assert_eq!(fds.read(STDIN, 1), None);
+    }
+}
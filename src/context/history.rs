@@ -0,0 +1,85 @@
+//! History of a path, to inspect the state at any of its steps, see `RuneContext::seek`.
+//!
+//! Once `Rune::set_history` starts it, the context keeps the registers and memory it had, and
+//! the writes that every instruction of its path makes to them from then on: the delta of the
+//! step. Forks share the history of the path before they forked. The state of a path before
+//! any of its steps is rebuilt from there, while the path runs or after it ended, much like rr
+//! replays a recorded run. Rebuilt states have the registers, memory, address, branches and
+//! call stack of the step, but the solver of the whole path, which has the assertions made
+//! after the step as well.
+
+use std::rc::Rc;
+
+use petgraph::graph::NodeIndex;
+
+use context::call_stack::CallStack;
+use engine::trace::Effect;
+
+/// An instruction of the path, or the hook that ran in its place.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub addr: u64,
+    /// Writes to registers and memory, in order.
+    pub effects: Vec<Effect<NodeIndex>>,
+    /// Branches the path assumed before the step.
+    pub branches: usize,
+    pub call_stack: Rc<CallStack>,
+}
+
+#[derive(Clone, Debug)]
+pub struct History<Mem, Reg> {
+    regs: Reg,
+    mem: Mem,
+    steps: Vec<Step>,
+}
+
+impl<Mem, Reg> History<Mem, Reg> {
+    /// History that starts with registers `regs` and memory `mem`.
+    pub fn new(regs: Reg, mem: Mem) -> History<Mem, Reg> {
+        History {
+            regs: regs,
+            mem: mem,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn regs(&self) -> &Reg {
+        &self.regs
+    }
+
+    pub fn mem(&self) -> &Mem {
+        &self.mem
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn begin_step(&mut self, addr: u64, branches: usize, call_stack: Rc<CallStack>) {
+        self.steps.push(Step {
+            addr: addr,
+            effects: Vec::new(),
+            branches: branches,
+            call_stack: call_stack,
+        });
+    }
+
+    /// Add `effect` to the current step. Returns false if no step began yet.
+    pub fn record(&mut self, effect: Effect<NodeIndex>) -> bool {
+        match self.steps.last_mut() {
+            Some(step) => {
+                step.effects.push(effect);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Serializable images of contexts.
+//!
+//! A `ContextImage` holds what is needed to resume the exploration of a path in another
+//! process: the instruction pointer, the memory (see `memory::image`), the registers, the
+//! constraints of the solver and those of the path, the symbols, the heap, the open files and
+//! the environment, and the statistics and the call stack of the path. Expressions are stored
+//! in SMT-LIB2 syntax, see `utils::smtlib`.
+//!
+//! Images are loaded into a context created for the same program, which provides the register
+//! profile and everything that is not part of the image (the architecture, hooks, MMIO regions,
+//! ...). The metadata of a path is not saved either, as its values can be of any type.
+
+use context::call_stack::CallStack;
+use context::context::{PathConstraint, Symbol};
+use context::env::EnvValue;
+use context::stats::Stats;
+use memory::heap::Heap;
+use memory::image::MemoryImage;
+use utils::smtlib::ExprTable;
+
+/// A file open in a context, see `context::fd`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileImage {
+    pub fd: u64,
+    /// Contents of the file, as indices into `ContextImage::exprs`.
+    pub data: Vec<usize>,
+    pub pos: usize,
+}
+
+/// An environment variable of a context, see `context::env`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvImage {
+    pub name: String,
+    pub value: EnvValue,
+    pub addr: Option<u64>,
+    /// Symbols of the value, as indices into `ContextImage::exprs`.
+    pub symbols: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextImage {
+    pub ip: u64,
+    pub memory: MemoryImage,
+    /// Expressions of the registers and constraints. They may refer to the variables of
+    /// `memory`.
+    pub exprs: ExprTable,
+    /// Registers that are set, as (name, index into `exprs`) pairs.
+    pub registers: Vec<(String, usize)>,
+    /// Constraints of the path, as indices into `exprs`.
+    pub constraints: Vec<usize>,
+    /// Tainted bytes of memory.
+    pub tainted_mem: Vec<u64>,
+    #[serde(default)]
+    pub stats: Stats,
+    #[serde(default)]
+    pub call_stack: CallStack,
+    /// Branch conditions of the path, with indices into `exprs`.
+    #[serde(default)]
+    pub path: Vec<PathConstraint<usize>>,
+    /// Symbolic inputs, as (name, symbol) pairs with indices into `exprs`.
+    #[serde(default)]
+    pub symbols: Vec<(String, Symbol<usize>)>,
+    #[serde(default)]
+    pub heap: Heap,
+    #[serde(default)]
+    pub files: Vec<FileImage>,
+    #[serde(default)]
+    pub env: Vec<EnvImage>,
+}
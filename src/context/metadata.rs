@@ -0,0 +1,68 @@
+//! Bookkeeping attached to contexts by explorers and users, see `RuneContext::metadata`.
+//!
+//! Values are stored by key with their own type, such as the depth of a path as a `usize` or
+//! the address of its parent as a `u64`, and tags mark contexts with plain strings
+//! ("after-auth"). Forks start with the metadata of the context they were forked from.
+
+use std::any::Any;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+pub struct Metadata {
+    values: HashMap<String, Rc<Any>>,
+    tags: BTreeSet<String>,
+}
+
+impl Metadata {
+    /// Set `key` to `value`, replacing the value previously stored as `key`.
+    pub fn set<T: Any>(&mut self, key: &str, value: T) {
+        self.values.insert(key.to_owned(), Rc::new(value));
+    }
+
+    /// The value stored as `key`, if there is one of type `T`.
+    pub fn get<T: Any>(&self, key: &str) -> Option<&T> {
+        self.values.get(key).and_then(|value| value.downcast_ref::<T>())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    /// Remove `key`, returning whether it was set.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        self.values.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        self.tags.insert(tag.to_owned());
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.tags.remove(tag)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut keys = self.keys();
+        keys.sort();
+        f.debug_struct("Metadata")
+         .field("keys", &keys)
+         .field("tags", &self.tags)
+         .finish()
+    }
+}
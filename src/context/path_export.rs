@@ -0,0 +1,108 @@
+//! Exports of the path predicate of a state, see `RuneContext::export_path`.
+//!
+//! A `PathExport` holds what a path that reached a goal means outside of rune: the constraints
+//! of the path as an SMT-LIB2 script that any solver takes, and a model of them, as the values
+//! of the symbolic inputs by name (see `RuneContext::symbols`). Inputs that are byte buffers,
+//! the symbols `name[0]`, `name[1]`, ... of 8 bits such as the ones of
+//! `RuneContext::symbolize_stdin` and `RuneContext::symbolize_argv`, are also given as blobs of
+//! bytes, ready to be fed to the program.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::to_string;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathExport {
+    /// Declarations of the variables and assertions of the path, then `(check-sat)` and
+    /// `(get-model)`.
+    pub smtlib: String,
+    /// Values of the inputs that satisfy the path predicate.
+    pub inputs: BTreeMap<String, u64>,
+    /// Byte buffers of the inputs, by the name of the buffer. Bytes without a value are 0.
+    pub blobs: BTreeMap<String, Vec<u8>>,
+}
+
+/// The buffer and index of the symbol `name[index]`.
+fn buffer_of(name: &str) -> Option<(&str, usize)> {
+    if !name.ends_with(']') {
+        return None;
+    }
+    let open = name.rfind('[')?;
+    let index = name[open + 1..name.len() - 1].parse().ok()?;
+    Some((&name[..open], index))
+}
+
+impl PathExport {
+    /// Export of the assertions `asserts` of a solver, with the values `inputs` for the symbols
+    /// of `widths` bits, by name.
+    pub fn new(asserts: &str,
+               inputs: BTreeMap<String, u64>,
+               widths: &BTreeMap<String, usize>)
+               -> PathExport {
+        let mut blobs: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        for (name, &width) in widths {
+            let (buffer, index) = match buffer_of(name) {
+                Some(buffer) if width == 8 => buffer,
+                _ => continue,
+            };
+            let bytes = blobs.entry(buffer.to_owned()).or_insert_with(Vec::new);
+            if bytes.len() <= index {
+                bytes.resize(index + 1, 0);
+            }
+            bytes[index] = inputs.get(name).cloned().unwrap_or(0) as u8;
+        }
+        let mut smtlib = asserts.to_owned();
+        if !smtlib.is_empty() && !smtlib.ends_with('\n') {
+            smtlib.push('\n');
+        }
+        smtlib.push_str("(check-sat)\n(get-model)\n");
+        PathExport {
+            smtlib: smtlib,
+            inputs: inputs,
+            blobs: blobs,
+        }
+    }
+
+    /// Write the export to the directory `dir`, which is created if needed: the script to
+    /// `path.smt2`, the inputs to `inputs.json`, and every blob to a file named after its
+    /// buffer, as `stdin.bin`.
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> Result<(), String> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let inputs = to_string(&self.inputs).map_err(|e| e.to_string())?;
+        let mut files = vec![("path.smt2".to_owned(), self.smtlib.as_bytes()),
+                             ("inputs.json".to_owned(), inputs.as_bytes())];
+        for (buffer, bytes) in &self.blobs {
+            files.push((format!("{}.bin", buffer), &bytes[..]));
+        }
+        for (name, data) in files {
+            let mut file = File::create(dir.join(name)).map_err(|e| e.to_string())?;
+            file.write_all(data).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_inputs() {
+        assert_eq!(buffer_of("argv1[12]"), Some(("argv1", 12)));
+        assert_eq!((buffer_of("rax"), buffer_of("a[x]")), (None, None));
+
+        let widths = vec![("stdin[0]", 8), ("stdin[2]", 8), ("key", 32), ("wide[0]", 16)];
+        let widths = widths.into_iter().map(|(n, w)| (n.to_owned(), w)).collect();
+        let inputs = vec![("stdin[0]", 0x41), ("stdin[2]", 0x43), ("key", 7), ("wide[0]", 1)];
+        let inputs = inputs.into_iter().map(|(n, v)| (n.to_owned(), v)).collect();
+        let export = PathExport::new("(assert true)", inputs, &widths);
+        assert_eq!(export.smtlib, "(assert true)\n(check-sat)\n(get-model)\n");
+        assert_eq!(export.inputs["key"], 7);
+        assert_eq!(export.blobs.keys().collect::<Vec<_>>(), vec!["stdin"]);
+        assert_eq!(export.blobs["stdin"], vec![0x41, 0, 0x43]);
+    }
+}
@@ -2,26 +2,82 @@
 
 use std::fs::File;
 use std::io::prelude::*;
+use std::mem;
 
-use r2api::structs::LRegInfo;
+use r2api::structs::{Endian, LRegInfo};
+#[cfg(test)]
+use r2api::structs::LRegProfile;
 use petgraph::graph::NodeIndex;
 use serde_json::{to_string, from_reader};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
 
 use libsmt::backends::smtlib2::{SMTLib2, SMTProc};
 use libsmt::backends::backend::SMTBackend;
+use libsmt::backends::z3;
 use libsmt::logics::qf_abv;
 use libsmt::theories::{array_ex, bitvec, core};
 
-use memory::memory::Memory;
+use context::concretize::{self, ConcretizationError, ConcretizationStrategy};
+use context::stack::StackSetup;
+use context::buffer::{self, BufferConstraint};
+use context::call_stack::{BadReturn, CallStack};
+use context::image::{ContextImage, EnvImage, FileImage};
+use context::expr::{Cond, Expr};
+use context::metadata::Metadata;
+use context::core_dump::CoreDump;
+use context::path_export::PathExport;
+use context::sat_cache::SatCache;
+use context::stats::{SolverCost, Stats};
+use context::fd::{self, FdTable};
+use context::env::{Environment, EnvValue};
+use context::history::History;
+
+use memory::memory::{Access, AccessViolation, Memory, Permission};
 use memory::qword_mem::QWordMemory;
+#[cfg(test)]
+use memory::paged_mem::PagedMemory;
+use memory::layout::ImageLayout;
+use memory::loader;
+use memory::diff::{self, CellDiff};
+use memory::dump::{self, MemoryDump};
+use memory::heap::{Heap, HeapError};
+use memory::image::MemoryImage;
+use memory::mmio::{MmioAccess, MmioRegion};
+use memory::uninit::{InitMap, UninitPolicy, UninitRead};
+
+use r2pipe::r2::R2;
 
-use regstore::regstore::{RegStore, RegEntry, RegStoreAPI};
+use stream::IsaMode;
+use arch::arch::Arch;
+
+use regstore::regstore::{RegStore, RegEntry, RegFault, RegHook, RegStoreAPI};
 use regstore::regfile::RuneRegFile;
+use regstore::diff::{self as reg_diff, RegDiff};
+
+use utils::utils::{Key, mask, to_smtlib, try_simplify_constant};
+use utils::conditions;
+use utils::smtlib::{self, Exporter, Importer};
+use engine::breakpt::{Watchpoint, WatchHit, WatchValue};
+use engine::trace::Effect;
+use context::context::{Context, ContextAPI, Evaluate, PathConstraint, RegisterRead, RegisterWrite,
+                       MemoryRead, MemoryWrite, Symbol};
 
-use utils::utils::Key;
-use context::context::{Context, ContextAPI, Evaluate, RegisterRead, RegisterWrite, MemoryRead, MemoryWrite};
+/// Default number of bytes that `memcpy` and `memset` unroll to when the length is symbolic.
+pub const DEFAULT_MEM_OP_BOUND: usize = 64;
 
+/// Longest string that is read from memory by the summaries of library functions.
+const MAX_C_STRING: u64 = 4096;
+
+/// Cloning a `RuneContext` (see `fork`) shares everything that is usually left unchanged by a
+/// path, and copies it only when it is first changed. The solver is the exception: it is
+/// always copied, as libsmt cannot share the expression graph between solvers. Explorers that
+/// return to the branches they saw in last-in first-out order avoid the copy with scopes, see
+/// `push_scope`.
 #[derive(Clone, Debug)]
 pub struct RuneContext<Mem, Reg> 
     where Mem: Memory,
@@ -33,6 +89,79 @@ pub struct RuneContext<Mem, Reg>
     mem: Mem,
     e_old: Option<NodeIndex>,
     e_cur: Option<NodeIndex>,
+    violation: Option<AccessViolation>,
+    /// Whether accesses to addresses that are not mapped are violations.
+    strict_mapping: bool,
+    /// Writes of the current instruction, when tracing.
+    effects: Option<Vec<Effect<NodeIndex>>>,
+    watchpoints: Rc<Vec<Watchpoint>>,
+    watch_hits: Vec<WatchHit<NodeIndex>>,
+    concretization: ConcretizationStrategy,
+    mem_op_bound: usize,
+    heap: Rc<Heap>,
+    heap_error: Option<HeapError>,
+    concretization_error: Option<ConcretizationError>,
+    mmio: Rc<Vec<MmioRegion>>,
+    mmio_log: Vec<MmioAccess>,
+    initialized: Rc<InitMap>,
+    uninit_policy: UninitPolicy,
+    uninit_reads: Vec<UninitRead>,
+    /// Values that depend on tainted data.
+    tainted: Rc<HashSet<NodeIndex>>,
+    /// Tainted bytes of memory.
+    tainted_mem: Rc<BTreeSet<u64>>,
+    arch: Option<Rc<Arch>>,
+    /// Where the binary is loaded, see `memory::layout`.
+    layout: Option<Rc<ImageLayout>>,
+    /// Address of the instruction being executed.
+    insn_addr: u64,
+    /// See `Context::delayed_branch`.
+    delayed_branch: Option<(u64, u64)>,
+    /// Branch conditions assumed on this path, in order.
+    path: Rc<Vec<PathConstraint<NodeIndex>>>,
+    /// Symbolic inputs by name, see `add_symbol`.
+    symbols: Rc<BTreeMap<String, Symbol<NodeIndex>>>,
+    /// Values created by `opaque_value`, which keeps their names apart.
+    opaque_values: usize,
+    metadata: Rc<Metadata>,
+    stats: Stats,
+    /// Satisfiability of the queries of this context and of its forks.
+    sat_cache: Rc<SatCache>,
+    call_stack: Rc<CallStack>,
+    files: Rc<FdTable>,
+    env: Rc<Environment>,
+    /// Watch expressions, as they were given and parsed.
+    watch_exprs: Rc<Vec<(String, Expr)>>,
+    watch_log: Rc<Vec<WatchValue>>,
+    /// Bytes written to executable memory, `None` if symbolic, see `code_patches`.
+    code: Rc<BTreeMap<u64, Option<u8>>>,
+    /// Open scopes, innermost last.
+    scopes: Vec<Scope<Mem, Reg>>,
+    /// Steps of the path, once `start_history` is called.
+    history: Option<Rc<History<Mem, Reg>>>,
+}
+
+/// A scope opened by `RuneContext::push_scope`.
+#[derive(Clone, Debug)]
+struct Scope<Mem, Reg>
+    where Mem: Memory,
+          Reg: RegStore
+{
+    /// Node of the solver created when the scope was opened. Nodes after it belong to the scope.
+    start: NodeIndex,
+    /// State of the context when the scope was opened, without its solver and scopes.
+    state: Box<RuneContext<Mem, Reg>>,
+}
+
+/// State of a context at some point of its execution, see `RuneContext::checkpoint`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint<Mem, Reg> {
+    ip: u64,
+    mem: Mem,
+    regstore: Reg,
+    path: Rc<Vec<PathConstraint<NodeIndex>>>,
+    /// Node of the solver created when the checkpoint was taken.
+    start: NodeIndex,
 }
 
 impl<Mem, Reg> Context for RuneContext<Mem, Reg>
@@ -65,16 +194,45 @@ impl<Mem, Reg> Context for RuneContext<Mem, Reg>
         true
     }
 
+    fn address_width(&self) -> usize {
+        self.mem.address_width()
+    }
+
     fn increment_ip(&mut self, by: u64) {
-        self.ip += by;
+        // Rune advances the ip past an instruction before executing it.
+        self.insn_addr = self.ip;
+        // The ip wraps around at the address width, as on 16 bit targets.
+        self.ip = mask(self.ip.wrapping_add(by), self.address_width());
+        self.stats.instructions += 1;
     }
 
     fn set_ip(&mut self, to: u64) {
-        self.ip = to;
+        self.ip = mask(to, self.address_width());
     }
 
     fn define_const(&mut self, c: u64, size: usize) -> NodeIndex {
-        self.solver.new_const(bitvec::OpCodes::Const(c, size))
+        // Constants are truncated to their width, so that for example ESIL's -1 is a valid
+        // 32 bit constant.
+        self.solver.new_const(bitvec::OpCodes::Const(mask(c, size), size))
+    }
+
+    fn fresh_value(&mut self, name: &str, width: usize) -> NodeIndex {
+        let mut unique = name.to_owned();
+        let mut n = 0;
+        while self.symbols.contains_key(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        self.new_symbol(&unique, width).var
+    }
+
+    fn opaque_value(&mut self, name: &str, width: usize) -> NodeIndex {
+        self.opaque_values += 1;
+        let key: String = format!("opaque_{}_{}", name, self.opaque_values)
+                              .chars()
+                              .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' })
+                              .collect();
+        self.solver.new_var(Some(&key), qf_abv::bv_sort(width))
     }
 
     fn alias_of(&self, reg: String) -> Option<String> {
@@ -82,11 +240,278 @@ impl<Mem, Reg> Context for RuneContext<Mem, Reg>
     }
 
     fn solve<S: SMTProc>(&mut self, p: &mut S) -> HashMap<NodeIndex, u64> {
-        self.solver.solve(p).expect("No satisfying solution.")
+        self.stats.solve(&mut self.solver, p).expect("No satisfying solution.")
     }
 
     fn var_named<T: AsRef<str>>(&self, var: T) -> Option<NodeIndex> {
-        None
+        self.symbol(var.as_ref()).map(|sym| sym.var)
+    }
+
+    fn check_access(&mut self, addr: u64, size: u64, access: Access) {
+        if self.violation.is_some() {
+            return;
+        }
+        // Accesses that wrap around the address space are checked up to its end.
+        for addr in addr..addr.saturating_add(size) {
+            // The heap and devices are mapped without permissions.
+            let mapped = self.mem.is_mapped(addr) || self.heap.chunk_at(addr).is_some() ||
+                         self.mmio_region(addr).is_some();
+            self.violation = if self.strict_mapping && !mapped {
+                Some(AccessViolation {
+                    addr: addr,
+                    access: access,
+                    perm: None,
+                })
+            } else {
+                self.mem.check_access(addr, access).err()
+            };
+            if self.violation.is_some() {
+                return;
+            }
+        }
+    }
+
+    fn set_strict_mapping(&mut self, on: bool) {
+        self.strict_mapping = on;
+    }
+
+    fn take_violation(&mut self) -> Option<AccessViolation> {
+        self.violation.take()
+    }
+
+    fn set_tracing(&mut self, on: bool) {
+        self.effects = if on { Some(Vec::new()) } else { None };
+    }
+
+    fn take_effects(&mut self) -> Vec<Effect<NodeIndex>> {
+        match self.effects {
+            Some(ref mut effects) => mem::replace(effects, Vec::new()),
+            None => Vec::new(),
+        }
+    }
+
+    fn add_watchpoint(&mut self, wp: Watchpoint) {
+        Rc::make_mut(&mut self.watchpoints).push(wp);
+    }
+
+    fn take_watch_hits(&mut self) -> Vec<WatchHit<NodeIndex>> {
+        self.watch_hits.drain(..).collect()
+    }
+
+    fn code_patches(&self, addr: u64, len: u64) -> Vec<(u64, Option<u8>)> {
+        self.code.range(addr..addr + len).map(|(&a, &b)| (a, b)).collect()
+    }
+
+    fn eval_watch_exprs(&mut self, ip: u64) -> Vec<WatchValue> {
+        if self.watch_exprs.is_empty() {
+            return Vec::new();
+        }
+        // Reading memory can concretize addresses and hit watchpoints, which a scope undoes.
+        let exprs = self.watch_exprs.clone();
+        self.push_scope();
+        let values = exprs.iter()
+                          .filter_map(|&(ref text, ref expr)| {
+                              let (value, _) = self.eval_expr(expr).ok()?;
+                              Some(WatchValue {
+                                  expr: text.clone(),
+                                  ip: ip,
+                                  constant: self.as_constant(value),
+                                  smtlib: to_smtlib(value, &self.solver),
+                              })
+                          })
+                          .collect::<Vec<_>>();
+        self.pop_scope();
+
+        for value in &values {
+            let changed = self.watch_log
+                              .iter()
+                              .rev()
+                              .find(|v| v.expr == value.expr)
+                              .map_or(true, |last| last.smtlib != value.smtlib);
+            if changed {
+                Rc::make_mut(&mut self.watch_log).push(value.clone());
+            }
+        }
+        values
+    }
+
+    fn take_heap_error(&mut self) -> Option<HeapError> {
+        self.heap_error.take()
+    }
+
+    fn take_concretization_error(&mut self) -> Option<ConcretizationError> {
+        self.concretization_error.take()
+    }
+
+    fn uninit_policy(&self) -> UninitPolicy {
+        self.uninit_policy
+    }
+
+    fn take_uninit_reads(&mut self) -> Vec<UninitRead> {
+        self.uninit_reads.drain(..).collect()
+    }
+
+    fn isa_mode(&mut self) -> IsaMode {
+        let flag = self.regstore.mode_flag().and_then(|f| self.regstore.flag(f));
+        match flag.and_then(|f| try_simplify_constant(f, &mut self.solver)) {
+            Some(1) => IsaMode::Thumb,
+            _ => IsaMode::Normal,
+        }
+    }
+
+    fn set_isa_mode(&mut self, mode: IsaMode) -> bool {
+        match self.regstore.mode_flag() {
+            Some(flag) => {
+                let bit = self.define_const((mode == IsaMode::Thumb) as u64, 1);
+                self.regstore.set_reg(flag, bit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn reg_of_alias(&self, alias: &str) -> Option<String> {
+        self.arch
+            .as_ref()
+            .and_then(|arch| arch.reg_of_alias(alias))
+            .or_else(|| self.regstore.reg_of_alias(alias))
+    }
+
+    fn arch(&self) -> Option<Rc<Arch>> {
+        self.arch.clone()
+    }
+
+    fn delayed_branch(&self) -> Option<(u64, u64)> {
+        self.delayed_branch
+    }
+
+    fn set_delayed_branch(&mut self, branch: Option<(u64, u64)>) {
+        self.delayed_branch = branch;
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), String> {
+        RuneContext::merge(self, other)
+    }
+
+    fn to_image(&self) -> Result<ContextImage, String> {
+        RuneContext::to_image(self)
+    }
+
+    fn record_call(&mut self, site: u64, target: u64, return_addr: u64) {
+        Rc::make_mut(&mut self.call_stack).call(site, target, return_addr);
+    }
+
+    fn record_return(&mut self, site: u64, target: u64) -> Result<(), BadReturn> {
+        if self.call_stack.depth() == 0 {
+            return Ok(());
+        }
+        Rc::make_mut(&mut self.call_stack).ret(site, target)
+    }
+
+    fn load_image(&mut self, image: &ContextImage) -> Result<(), String> {
+        RuneContext::load_image(self, image)
+    }
+
+    fn solve_inputs(&mut self) -> Option<BTreeMap<String, u64>> {
+        RuneContext::solve_inputs(self)
+    }
+
+    fn solver_time(&self) -> Duration {
+        self.stats.solver_time
+    }
+
+    fn state_hash(&self) -> Option<u64> {
+        Some(RuneContext::state_hash(self))
+    }
+
+    fn start_history(&mut self) {
+        RuneContext::start_history(self)
+    }
+
+    fn begin_step(&mut self, addr: u64) {
+        RuneContext::begin_step(self, addr)
+    }
+
+    fn solver_cost(&self) -> SolverCost {
+        let roots: Vec<NodeIndex> = self.path.iter().map(|c| c.constraint).collect();
+        SolverCost {
+            constraint_size: smtlib::expr_size(&self.solver, &roots),
+            solver_calls: self.stats.solver_calls,
+            solver_time: self.stats.solver_time,
+        }
+    }
+
+    fn record_branch(&mut self, condition: NodeIndex, constraint: NodeIndex, taken: bool) {
+        let ip = self.insn_addr;
+        Rc::make_mut(&mut self.path).push(PathConstraint {
+            ip: ip,
+            condition: condition,
+            constraint: constraint,
+            taken: taken,
+        });
+        self.stats.branches += 1;
+    }
+
+    fn take_reg_fault(&mut self) -> Option<RegFault> {
+        self.regstore.take_fault()
+    }
+
+    fn has_reg(&self, reg: &str) -> bool {
+        self.regstore.has_reg(reg) || self.is_pc(reg)
+    }
+
+    fn is_pc(&self, reg: &str) -> bool {
+        match self.arch {
+            // Other names of the program counter (`r15` on ARM) share its storage.
+            Some(ref arch) => {
+                arch.pc() == reg ||
+                (self.regstore.has_reg(reg) && self.regstore.has_reg(arch.pc()) && {
+                    let entry = self.regstore.get_reg_entry(reg);
+                    entry.is_whole && entry.idx == self.regstore.get_reg_entry(arch.pc()).idx
+                })
+            }
+            None => {
+                self.regstore.has_reg(reg) && self.alias_of(reg.to_owned()) == Some("PC".to_owned())
+            }
+        }
+    }
+
+    fn as_constant(&mut self, var: NodeIndex) -> Option<u64> {
+        try_simplify_constant(var, &mut self.solver)
+    }
+
+    fn is_feasible(&mut self, condition: NodeIndex) -> bool {
+        if let Some(c) = try_simplify_constant(condition, &mut self.solver) {
+            return c != 0;
+        }
+        let mut solver = self.solver.clone();
+        let one = solver.new_const(bitvec::OpCodes::Const(1, 1));
+        solver.assert(core::OpCodes::Cmp, &[condition, one]);
+        self.sat_cache.is_sat(&mut solver, &mut self.stats)
+    }
+
+    fn concrete_values(&mut self, vars: &[(NodeIndex, usize)]) -> Option<Vec<u64>> {
+        let constants: Vec<Option<u64>> = vars.iter().map(|&(v, _)| self.as_constant(v)).collect();
+        if constants.iter().all(|c| c.is_some()) {
+            return Some(constants.into_iter().map(|c| c.unwrap()).collect());
+        }
+
+        // Models only contain values for variables, so bind the values to variables.
+        let mut solver = self.solver.clone();
+        let mut bound = Vec::new();
+        for (i, (&(var, width), c)) in vars.iter().zip(&constants).enumerate() {
+            if c.is_none() {
+                let name = format!("rune_value_{}", i);
+                let v = solver.new_var(Some(&name), qf_abv::bv_sort(width));
+                solver.assert(core::OpCodes::Cmp, &[v, var]);
+                bound.push(v);
+            }
+        }
+        let mut z3: z3::Z3 = Default::default();
+        let model = self.stats.solve(&mut solver, &mut z3).ok()?;
+        let mut bound = bound.into_iter();
+        let mut value = || model.get(&bound.next().unwrap()).cloned().unwrap_or(0);
+        Some(constants.into_iter().map(|c| c.unwrap_or_else(&mut value)).collect())
     }
 }
 
@@ -97,7 +522,17 @@ where Mem: Memory<VarRef=NodeIndex>,
     type VarRef = NodeIndex;
 
     fn reg_read<T: AsRef<str>>(&mut self, reg: T) -> NodeIndex {
-        self.regstore.read(reg.as_ref(), &mut self.solver)
+        if self.is_pc(reg.as_ref()) {
+            // The program counter is the instruction pointer of the context.
+            let ip = self.ip;
+            let width = self.regstore.storage_width(reg.as_ref());
+            return self.define_const(ip, width);
+        }
+        let value = self.regstore.read(reg.as_ref(), &mut self.solver);
+        if self.regstore.is_tainted(reg.as_ref()) {
+            Rc::make_mut(&mut self.tainted).insert(value);
+        }
+        value
     }
 }
 
@@ -108,7 +543,17 @@ where Mem: Memory<VarRef=NodeIndex>,
     type VarRef = NodeIndex;
 
     fn reg_write<T: AsRef<str>>(&mut self, reg: T, source: NodeIndex) {
-        let e_old = self.regstore.write(reg.as_ref(), source);
+        if let Some(ref mut effects) = self.effects {
+            effects.push(Effect::Reg(reg.as_ref().to_owned(), source));
+        }
+        self.record_effect(Effect::Reg(reg.as_ref().to_owned(), source));
+        if self.is_pc(reg.as_ref()) {
+            if let Some(ip) = self.as_constant(source) {
+                self.ip = ip;
+            }
+        }
+        let e_old = self.regstore.write(reg.as_ref(), source, &mut self.solver);
+        self.regstore.set_taint(reg.as_ref(), self.tainted.contains(&source));
         // XXX: THIS IS A HACK!
         // IF NOT REG
         if !reg.as_ref().to_owned().ends_with('f') {
@@ -127,7 +572,30 @@ where Mem: Memory<VarRef=NodeIndex>,
     fn mem_read(&mut self, addr: NodeIndex, read_size: usize) -> NodeIndex {
         // Assert read size is multiple of 8
         assert_eq!(read_size%8, 0, "Read Size is not divisible by 8");
-        self.mem.read(addr, read_size, &mut self.solver)
+        let targets = self.concretize_addr(addr);
+        if targets.is_empty() {
+            // The read is not carried out, see `concretize_addr`.
+            return self.define_const(0, read_size);
+        }
+        if targets.len() == 1 {
+            return self.read_at(targets[0], read_size);
+        }
+
+        // addr is one of the sampled targets, select the value read from the matching one.
+        let mut result = None;
+        for target in targets.into_iter().rev() {
+            let value = self.read_at(target, read_size);
+            result = Some(match result {
+                None => value,
+                Some(other) => {
+                    let cond = self.solver.assert(core::OpCodes::Cmp, &[addr, target]);
+                    let ite = self.solver.assert(core::OpCodes::ITE, &[cond, value, other]);
+                    self.propagate_taint(ite, &[value, other]);
+                    ite
+                }
+            });
+        }
+        result.unwrap()
     }
 }
 
@@ -140,133 +608,2863 @@ where Mem: Memory<VarRef=NodeIndex>,
     fn mem_write(&mut self, addr: NodeIndex, data: NodeIndex, write_size: usize) {
         // Assert write size is multiple of 8
         assert_eq!(write_size%8, 0, "Write Size is not divisible by 8");
-        self.mem.write(addr, data, write_size, &mut self.solver);
+        if let Some(ref mut effects) = self.effects {
+            effects.push(Effect::Mem {
+                addr: addr,
+                value: data,
+                size: write_size,
+            });
+        }
+        let targets = self.concretize_addr(addr);
+        if targets.len() == 1 {
+            self.write_at(targets[0], data, write_size);
+            return;
+        }
+
+        // Only the target that matches addr is updated.
+        for target in targets {
+            let old = self.mem.read(target, write_size, &mut self.solver);
+            let cond = self.solver.assert(core::OpCodes::Cmp, &[addr, target]);
+            let new = self.solver.assert(core::OpCodes::ITE, &[cond, data, old]);
+            self.propagate_taint(new, &[data]);
+            self.write_at(target, new, write_size);
+        }
     }
 }
 
-impl<Mem, Reg> Evaluate for RuneContext<Mem, Reg>
+impl<Mem, Reg> RuneContext<Mem, Reg>
 where Mem: Memory<VarRef=NodeIndex>,
       Reg: RegStore<VarRef=NodeIndex>
 {
-    type VarRef = NodeIndex;
-    type IFn = qf_abv::QF_ABV_Fn;
+    /// Constant of the width of an address.
+    fn define_addr(&mut self, addr: u64) -> NodeIndex {
+        let width = self.mem.address_width();
+        self.define_const(addr, width)
+    }
 
-    fn eval<T, Q>(&mut self, smt_fn: T, operands: Q) -> Self::VarRef
-        where T: Into<Self::IFn>,
-              Q: AsRef<[Self::VarRef]>
-    {
-        // TODO: Add extract / concat to ensure that the registers are of compatible
-        // sizes for operations.
-        self.solver.assert(smt_fn, operands.as_ref())
+    /// Addresses that a memory access through `addr` has to consider, as decided by the
+    /// concretization strategy of the context. If there are none, the access is not carried
+    /// out and a concretization error is recorded, see `Context::take_concretization_error`.
+    fn concretize_addr(&mut self, addr: NodeIndex) -> Vec<NodeIndex> {
+        if try_simplify_constant(addr, &mut self.solver).is_some() {
+            return vec![addr];
+        }
+
+        let width = self.mem.address_width();
+        let values = match self.concretization {
+            ConcretizationStrategy::Symbolic if self.mem.models_symbolic() => return vec![addr],
+            ConcretizationStrategy::Symbolic => {
+                let n = concretize::FALLBACK_SAMPLES;
+                concretize::samples(&self.solver, addr, width, n, &mut self.stats)
+            }
+            ConcretizationStrategy::Min => concretize::min(&self.solver, addr, width, &mut self.stats).into_iter().collect(),
+            ConcretizationStrategy::Max => concretize::max(&self.solver, addr, width, &mut self.stats).into_iter().collect(),
+            ConcretizationStrategy::Samples(n) => concretize::samples(&self.solver, addr, width, n, &mut self.stats),
+            ConcretizationStrategy::Fail => {
+                self.record_concretization_error(ConcretizationError::Refused);
+                return Vec::new();
+            }
+        };
+        if values.is_empty() {
+            self.record_concretization_error(ConcretizationError::Infeasible);
+            return Vec::new();
+        }
+
+        // Constrain addr to be one of the chosen values.
+        let targets: Vec<NodeIndex> = values.iter().map(|v| self.define_addr(*v)).collect();
+        let eqs: Vec<NodeIndex> = targets.iter()
+                                         .map(|t| self.solver.assert(core::OpCodes::Cmp, &[addr, *t]))
+                                         .collect();
+        if eqs.len() > 1 {
+            self.solver.assert(core::OpCodes::Or, &eqs);
+        }
+        targets
     }
-}
 
-impl<Mem, Reg> ContextAPI for RuneContext<Mem, Reg>
-where Mem: Memory<VarRef=NodeIndex>,
-      Reg: RegStore<VarRef=NodeIndex> + RegStoreAPI
-{
-    fn set_reg_as_const<T: AsRef<str>>(&mut self, reg: T, val: u64) -> NodeIndex {
-        if let Some(cval) = self.regstore.get_reg_ref(reg.as_ref()) {
-            cval
-        } else {
-            let cval = self.define_const(val, 64);
-            self.regstore.set_reg(reg.as_ref(), cval);
-            cval
-        } 
+    /// Copy `len` bytes from `src` to `dst`, as done by `memcpy`.
+    ///
+    /// If `len` is symbolic, the copy is unrolled upto the bound set by `set_mem_op_bound`, the
+    /// `i`th byte being copied only if `i < len`. The path is then constrained to `len` being at
+    /// most the bound.
+    pub fn memcpy(&mut self, dst: NodeIndex, src: NodeIndex, len: NodeIndex) {
+        for (i, cond) in self.unroll_mem_op(len) {
+            let src_i = self.byte_addr(src, i);
+            let byte = self.mem_read(src_i, 8);
+            self.write_byte_if(dst, i, cond, byte);
+        }
     }
 
-    fn set_reg_as_sym<T: AsRef<str>>(&mut self, reg: T) -> NodeIndex {
-        let sym = self.solver.new_var(Some(reg.as_ref()), qf_abv::bv_sort(64));
-        self.regstore.set_reg(reg.as_ref(), sym);
-        // self.syms.insert(reg.as_ref().to_owned(), sym);
-        
-        sym
+    /// Set `len` bytes starting at `dst` to the 8 bit value `byte`, as done by `memset`.
+    ///
+    /// Symbolic lengths are handled as in `memcpy`.
+    pub fn memset(&mut self, dst: NodeIndex, byte: NodeIndex, len: NodeIndex) {
+        for (i, cond) in self.unroll_mem_op(len) {
+            self.write_byte_if(dst, i, cond, byte);
+        }
     }
 
-    fn set_mem_as_const(&mut self, addr: u64, val: u64, write_size: usize) -> NodeIndex {
-        // Assert that memory var is in chunks of 8
-        assert_eq!(write_size%8, 0, "Write size is not divisible by 8!");
+    /// Offsets touched by a memory operation of length `len`, along with the condition under
+    /// which each of them is touched (`None` if it always is).
+    fn unroll_mem_op(&mut self, len: NodeIndex) -> Vec<(u64, Option<NodeIndex>)> {
+        if let Some(len) = try_simplify_constant(len, &mut self.solver) {
+            return (0..len).map(|i| (i, None)).collect();
+        }
 
-        let addr = self.define_const(addr, 64);
-        let cval = self.define_const(val, write_size);
-        self.mem_write(addr, cval, write_size);
+        let bound = self.define_addr(self.mem_op_bound as u64);
+        self.solver.assert(bitvec::OpCodes::BvULe, &[len, bound]);
+        (0..self.mem_op_bound as u64)
+            .map(|i| {
+                let i_ = self.define_addr(i);
+                (i, Some(self.solver.assert(bitvec::OpCodes::BvULt, &[i_, len])))
+            })
+            .collect()
+    }
 
-        cval
+    fn byte_addr(&mut self, base: NodeIndex, i: u64) -> NodeIndex {
+        if i == 0 {
+            return base;
+        }
+        let offset = self.define_addr(i);
+        self.solver.assert(bitvec::OpCodes::BvAdd, &[base, offset])
     }
 
-    fn set_mem_as_sym(&mut self, addr: u64, write_size: usize) -> NodeIndex {
-        // Assert that memory var is in chunks of 8
-        assert_eq!(write_size%8, 0, "Write size is not divisible by 8!");
+    /// Write `byte` to `base + i` if `cond` holds.
+    fn write_byte_if(&mut self, base: NodeIndex, i: u64, cond: Option<NodeIndex>, byte: NodeIndex) {
+        let addr = self.byte_addr(base, i);
+        let byte = match cond {
+            Some(cond) => {
+                // Reading the old value is an artifact of the unrolling, not a read by the
+                // program.
+                let reads = self.uninit_reads.len();
+                let old = self.mem_read(addr, 8);
+                self.uninit_reads.truncate(reads);
+                let new = self.solver.assert(core::OpCodes::ITE, &[cond, byte, old]);
+                self.propagate_taint(new, &[byte, old]);
+                new
+            }
+            None => byte,
+        };
+        self.mem_write(addr, byte, 8);
+    }
 
-        let key = format!("mem_{}_{}", addr, write_size/8);
-        let sym = self.solver.new_var(Some(&key), qf_abv::bv_sort(write_size));
-        let addr = self.define_const(addr, 64);
+    /// Run `hook` on every read and write of the register `reg`, for example to log the writes
+    /// to the stack pointer. A hook that returns `false` stops the path with
+    /// `EngineError::RegisterFault`.
+    pub fn add_reg_hook(&mut self, reg: &str, hook: RegHook<NodeIndex>) {
+        self.regstore.add_hook(reg, hook);
+    }
 
-        self.mem_write(addr, sym, write_size);
-        // self.syms.insert(key, sym);
-        
-        sym
+    /// Set the system register `name` (`fs`, `gs`, `tpidr_el0`, ...) to `value`. Registers that
+    /// are not part of the register profile are added, as wide as an address.
+    ///
+    /// On x86-64, r2 computes `fs:[0x28]` as `fs + 0x28`, so `fs` should be set to the base of
+    /// the thread local storage.
+    pub fn set_system_reg(&mut self, name: &str, value: u64) {
+        let width = self.mem.address_width();
+        self.regstore.add_reg(name, width);
+        let width = self.regstore.storage_width(name);
+        let value = self.define_const(value, width);
+        self.regstore.set_reg(name, value);
     }
 
-    fn zero_registers(&mut self) {
-        let cval = Some(self.define_const(0, 64));
-        for reg in &mut self.regstore.get_regs() {
-            if reg.is_none() {
-                *reg = cval;
-            }
+    /// Let registers that were never written read as a fresh symbol named after the register,
+    /// instead of being undefined.
+    pub fn set_lazy_registers(&mut self, lazy: bool) {
+        self.regstore.set_lazy_symbols(lazy);
+    }
+
+    /// Mark the current value of `reg` as tainted. Taint propagates to every value computed
+    /// from it, through registers and memory.
+    pub fn taint_reg(&mut self, reg: &str) {
+        self.regstore.set_taint(reg, true);
+    }
+
+    /// Mark the `len` bytes at `addr` as tainted.
+    pub fn taint_mem(&mut self, addr: u64, len: u64) {
+        Rc::make_mut(&mut self.tainted_mem).extend(addr..addr + len);
+    }
+
+    /// Mark `value` as tainted.
+    pub fn taint_value(&mut self, value: NodeIndex) {
+        Rc::make_mut(&mut self.tainted).insert(value);
+    }
+
+    pub fn is_reg_tainted(&self, reg: &str) -> bool {
+        self.regstore.is_tainted(reg)
+    }
+
+    /// Whether any of the `len` bytes at `addr` is tainted.
+    pub fn is_mem_tainted(&self, addr: u64, len: u64) -> bool {
+        self.tainted_mem.range(addr..addr.saturating_add(len)).next().is_some()
+    }
+
+    pub fn is_tainted(&self, value: NodeIndex) -> bool {
+        self.tainted.contains(&value)
+    }
+
+    /// `result` is tainted if any of `operands` is.
+    fn propagate_taint(&mut self, result: NodeIndex, operands: &[NodeIndex]) {
+        if operands.iter().any(|o| self.tainted.contains(o)) {
+            Rc::make_mut(&mut self.tainted).insert(result);
         }
     }
 
-    fn registers(&self) -> Vec<String> {
-        unimplemented!();
+    /// Bits of the flag `name` (`zf`, `cf`, ...) as set by the last instruction that updated it.
+    pub fn flag(&self, name: &str) -> Option<NodeIndex> {
+        self.regstore.flag(name)
     }
-}
 
-impl<Mem, Reg> RuneContext<Mem, Reg>
-where Mem: Memory,
-      Reg: RegStore
-{
-    pub fn new(ip: Option<u64>,
-               mem: Mem,
-               regstore: Reg,
-               solver: SMTLib2<qf_abv::QF_ABV>)
-               -> RuneContext<Mem, Reg> {
-        RuneContext {
-            ip: ip.unwrap_or(0),
-            mem: mem,
-            regstore: regstore,
-            solver: solver,
-            e_old: None,
-            e_cur: None,
+    /// Constrain the flag `name` to be set or clear. Returns `false` if `name` is not a flag
+    /// that has been set.
+    pub fn assume_flag(&mut self, name: &str, set: bool) -> bool {
+        match self.regstore.flag(name) {
+            Some(flag) => {
+                let value = self.define_const(set as u64, 1);
+                self.solver.assert(core::OpCodes::Cmp, &[flag, value]);
+                true
+            }
+            None => false,
         }
     }
-}
 
-mod test {
-    use super::*;
-    use context::context::{Context, ContextAPI, Evaluate, MemoryRead, MemoryWrite, RegisterRead,
-                           RegisterWrite};
+    /// Make the `len` bytes starting at `addr` symbolic, with every byte constrained by
+    /// `constraints`. Returns the symbols of the bytes, in order.
+    pub fn make_sym_buffer(&mut self, addr: u64, len: u64, constraints: &[BufferConstraint]) -> Vec<NodeIndex> {
+        let bytes: Vec<NodeIndex> = (0..len)
+            .map(|i| {
+                let name = format!("buf_{:x}_{}", addr, i);
+                let byte = self.solver.new_var(Some(&name), qf_abv::bv_sort(8));
+                let addr = self.define_addr(addr + i);
+                self.mem_write(addr, byte, 8);
+                byte
+            })
+            .collect();
+        buffer::constrain(&mut self.solver, &bytes, constraints);
+        bytes
+    }
 
-    use libsmt::logics::qf_abv;
-    use libsmt::backends::smtlib2::SMTLib2;
-    use libsmt::backends::backend::SMTBackend;
-    use libsmt::theories::{bitvec, core};
-    use libsmt::backends::z3;
+    /// Check if the `len_a` bytes at `ptr_a` may overlap the `len_b` bytes at `ptr_b` under the
+    /// current path constraints. Ranges that wrap around the end of the address space are not
+    /// considered.
+    pub fn may_alias(&mut self, ptr_a: NodeIndex, len_a: u64, ptr_b: NodeIndex, len_b: u64) -> bool {
+        if len_a == 0 || len_b == 0 {
+            return false;
+        }
+        let a = try_simplify_constant(ptr_a, &mut self.solver);
+        let b = try_simplify_constant(ptr_b, &mut self.solver);
+        if let (Some(a), Some(b)) = (a, b) {
+            return a < b.saturating_add(len_b) && b < a.saturating_add(len_a);
+        }
 
-    use memory::seg_mem::SegMem;
-    use regstore::regfile::RuneRegFile;
+        // a < b + len_b && b < a + len_a
+        let mut solver = self.solver.clone();
+        let width = self.mem.address_width();
+        let len_a = solver.new_const(bitvec::OpCodes::Const(len_a, width));
+        let len_b = solver.new_const(bitvec::OpCodes::Const(len_b, width));
+        let end_a = solver.assert(bitvec::OpCodes::BvAdd, &[ptr_a, len_a]);
+        let end_b = solver.assert(bitvec::OpCodes::BvAdd, &[ptr_b, len_b]);
+        solver.assert(bitvec::OpCodes::BvULt, &[ptr_a, end_b]);
+        solver.assert(bitvec::OpCodes::BvULt, &[ptr_b, end_a]);
+        self.sat_cache.is_sat(&mut solver, &mut self.stats)
+    }
 
-    use r2api::structs::Endian;
+    /// Map the stack described by `stack` and point the stack and base pointers to it. If
+    /// program arguments are given, `argc`, `argv` and `envp` are also passed in the argument
+    /// registers so that execution can start at `main`. The environment variables declared
+    /// with `set_env` follow the ones of `stack` in `envp`.
+    pub fn setup_stack(&mut self, stack: &StackSetup) {
+        self.mem.set_permissions(stack.bottom(), stack.top, Permission::new(true, true, false));
 
-    // #[test]
-    fn teting_memory_my_dude() {
-        let mut lreginfo = Default::default();
-        let regstore = RuneRegFile::new(&mut lreginfo);
+        let names = self.env.names();
+        let mut stack = stack.clone();
+        let first_env = stack.args.as_ref().map_or(0, |args| args.len()) + stack.env.len();
+        for name in &names {
+            // Symbolic values are written over the placeholder.
+            let len = self.env.get(name).map_or(0, |var| var.value.len());
+            stack.env.push(format!("{}={}", name, "?".repeat(len)));
+        }
 
-        let mut mem = SegMem::new(64, Endian::Big);
-		let mut smt = SMTLib2::new(Some(qf_abv::QF_ABV));
-		mem.init_memory(&mut smt);
+        let ptr_width = self.mem.address_width();
+        let layout = stack.layout((ptr_width / 8) as u64);
+        for &(addr, ref bytes) in &layout.strings {
+            self.mem.write_bytes(addr, bytes, &mut self.solver);
+            Rc::make_mut(&mut self.initialized).insert(addr, addr + bytes.len() as u64);
+        }
+        for &(addr, word) in &layout.words {
+            let addr = self.define_addr(addr);
+            let word = self.define_const(word, ptr_width);
+            self.mem_write(addr, word, ptr_width);
+        }
+        if stack.args.is_some() {
+            for (i, name) in names.iter().enumerate() {
+                let value = layout.strings[first_env + i].0 + name.len() as u64 + 1;
+                self.place_env(name, value);
+            }
+        }
 
-        let mut ctx = RuneContext::new(Some(0x9000), mem, regstore, smt);
+        let mut regs = vec![("SP", layout.sp), ("BP", layout.sp)];
+        if stack.args.is_some() {
+            regs.extend(vec![("A0", layout.argc), ("A1", layout.argv), ("A2", layout.envp)]);
+        }
+        for (alias, value) in regs {
+            if let Some(reg) = self.reg_of_alias(alias) {
+                let width = self.regstore.storage_width(&reg);
+                let value = self.define_const(value, width);
+                self.regstore.set_reg(&reg, value);
+            }
+        }
+    }
+
+    /// Set up the stack for `main` with the program arguments `args`, see `setup_stack`. An
+    /// argument `SYM:n` is a string of `n` symbolic bytes, which are constrained to be non-zero
+    /// so that the string keeps its length. Returns the symbolic bytes of every argument, named
+    /// `argv1[0]`, `argv1[1]`, ...
+    pub fn symbolize_argv(&mut self, args: &[&str]) -> Result<Vec<Vec<NodeIndex>>, String> {
+        let mut lengths = Vec::new();
+        let mut strings = Vec::new();
+        for arg in args {
+            if arg.starts_with("SYM:") {
+                let len = arg[4..].parse::<usize>().map_err(|_| format!("Invalid symbolic argument {}", arg))?;
+                lengths.push(Some(len));
+                strings.push("?".repeat(len));
+            } else {
+                lengths.push(None);
+                strings.push(arg.to_string());
+            }
+        }
+        let stack = StackSetup::with_args(strings, Vec::new());
+        self.setup_stack(&stack);
+
+        let layout = stack.layout((self.mem.address_width() / 8) as u64);
+        let zero = self.define_const(0, 8);
+        let mut symbols = Vec::new();
+        for (i, len) in lengths.into_iter().enumerate() {
+            let start = layout.strings[i].0;
+            let bytes: Vec<NodeIndex> = (0..len.unwrap_or(0))
+                .map(|j| {
+                    let byte = self.new_symbol(&format!("argv{}[{}]", i, j), 8).var;
+                    self.solver.assert(core::OpCodes::Distinct, &[byte, zero]);
+                    let addr = self.define_addr(start + j as u64);
+                    self.mem_write(addr, byte, 8);
+                    byte
+                })
+                .collect();
+            symbols.push(bytes);
+        }
+        Ok(symbols)
+    }
+
+    /// Open standard input with `len` symbolic bytes, named `stdin[0]`, `stdin[1]`, ..., which
+    /// `read` returns in order. Returns the bytes.
+    pub fn symbolize_stdin(&mut self, len: usize) -> Vec<NodeIndex> {
+        let bytes: Vec<NodeIndex> = (0..len).map(|i| self.new_symbol(&format!("stdin[{}]", i), 8).var).collect();
+        Rc::make_mut(&mut self.files).open(fd::STDIN, bytes.clone());
+        bytes
+    }
+
+    /// Files open in this context, see `symbolize_stdin`.
+    pub fn files(&self) -> &FdTable {
+        &self.files
+    }
+
+    /// Read up to `count` bytes from the file `fd` to `buf`, as `read(2)` does. Returns the
+    /// number of bytes read, or -1 if `fd` is not open. A symbolic `fd` or `count` is
+    /// concretized to its smallest feasible value.
+    pub fn read(&mut self, fd: NodeIndex, buf: NodeIndex, count: NodeIndex) -> NodeIndex {
+        let fd = self.concretize_value(fd);
+        let count = self.concretize_value(count);
+        let width = self.mem.address_width();
+        let bytes = match Rc::make_mut(&mut self.files).read(fd, count) {
+            Some(bytes) => bytes,
+            None => return self.define_const(u64::max_value(), width),
+        };
+        for (i, &byte) in bytes.iter().enumerate() {
+            let offset = self.define_addr(i as u64);
+            let addr = self.solver.assert(bitvec::OpCodes::BvAdd, &[buf, offset]);
+            self.mem_write(addr, byte, 8);
+        }
+        self.define_const(bytes.len() as u64, width)
+    }
+
+    /// Declare the environment variable `name` with a concrete `value`, see `context::env`.
+    /// Variables have to be declared before the stack is set up to appear in `envp`.
+    pub fn set_env(&mut self, name: &str, value: &str) {
+        Rc::make_mut(&mut self.env).set(name, EnvValue::Concrete(value.to_owned()));
+    }
+
+    /// Declare the environment variable `name` with a value of `len` symbolic bytes, named
+    /// `env_NAME[0]`, `env_NAME[1]`, ...
+    pub fn set_env_symbolic(&mut self, name: &str, len: usize) {
+        Rc::make_mut(&mut self.env).set(name, EnvValue::Symbolic(len));
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    /// Write the value of the environment variable `name` and its terminating NUL at `addr`.
+    fn place_env(&mut self, name: &str, addr: u64) {
+        let value = match self.env.get(name) {
+            Some(var) => var.value.clone(),
+            None => return,
+        };
+        let symbols = match value {
+            EnvValue::Concrete(ref s) => {
+                let mut bytes = s.clone().into_bytes();
+                bytes.push(0);
+                self.mem.write_bytes(addr, &bytes, &mut self.solver);
+                Vec::new()
+            }
+            EnvValue::Symbolic(len) => {
+                let zero = self.define_const(0, 8);
+                let symbols: Vec<NodeIndex> = (0..len)
+                    .map(|i| {
+                        let byte = self.new_symbol(&format!("env_{}[{}]", name, i), 8).var;
+                        self.solver.assert(core::OpCodes::Distinct, &[byte, zero]);
+                        let at = self.define_addr(addr + i as u64);
+                        self.mem_write(at, byte, 8);
+                        byte
+                    })
+                    .collect();
+                self.mem.write_bytes(addr + len as u64, &[0], &mut self.solver);
+                symbols
+            }
+        };
+        self.mark_initialized(addr, addr + value.len() as u64 + 1);
+        if let Some(var) = Rc::make_mut(&mut self.env).get_mut(name) {
+            var.addr = Some(addr);
+            var.symbols = symbols;
+        }
+    }
+
+    /// The NUL terminated string at `ptr`, if its bytes are concrete.
+    fn read_c_string(&mut self, ptr: NodeIndex) -> Option<String> {
+        let start = self.concretize_value(ptr);
+        let mut bytes = Vec::new();
+        for i in 0..MAX_C_STRING {
+            let addr = self.define_addr(start + i);
+            let byte = self.mem_read(addr, 8);
+            match try_simplify_constant(byte, &mut self.solver) {
+                Some(0) => return String::from_utf8(bytes).ok(),
+                Some(b) => bytes.push(b as u8),
+                None => return None,
+            }
+        }
+        None
+    }
+
+    /// Value of the environment variable whose name is the string at `name`, as `getenv(3)`
+    /// returns it: a pointer to the value, or NULL if the variable is not declared or `name` is
+    /// symbolic. Values that are not on the stack are copied to the heap on the first call.
+    pub fn getenv(&mut self, name: NodeIndex) -> NodeIndex {
+        let name = match self.read_c_string(name) {
+            Some(name) => name,
+            None => return self.define_addr(0),
+        };
+        let (addr, len) = match self.env.get(&name) {
+            Some(var) => (var.addr, var.value.len() as u64),
+            None => return self.define_addr(0),
+        };
+        let addr = match addr {
+            Some(addr) => addr,
+            None => {
+                let addr = Rc::make_mut(&mut self.heap).malloc(len + 1, self.ip);
+                self.place_env(&name, addr);
+                addr
+            }
+        };
+        self.define_addr(addr)
+    }
+
+    /// Allocate `size` bytes on the heap and return a pointer to them.
+    ///
+    /// A symbolic `size` is concretized to its smallest feasible value.
+    pub fn malloc(&mut self, size: NodeIndex) -> NodeIndex {
+        let size = self.concretize_value(size);
+        let ptr = Rc::make_mut(&mut self.heap).malloc(size, self.ip);
+        self.define_addr(ptr)
+    }
+
+    /// Release the heap chunk `ptr` points to. Misuse is recorded as a heap error.
+    pub fn free(&mut self, ptr: NodeIndex) {
+        let ptr = self.concretize_value(ptr);
+        if let Err(e) = Rc::make_mut(&mut self.heap).free(ptr, self.ip) {
+            self.record_heap_error(e);
+        }
+    }
+
+    /// Resize the heap chunk `ptr` points to, moving its contents to a new chunk.
+    pub fn realloc(&mut self, ptr: NodeIndex, size: NodeIndex) -> NodeIndex {
+        let old = self.concretize_value(ptr);
+        let new = self.malloc(size);
+        if old != 0 {
+            let old_size = self.heap.chunk_at(old).map(|c| c.size).unwrap_or(0);
+            let new_size = self.concretize_value(size);
+            let len = self.define_addr(::std::cmp::min(old_size, new_size));
+            let ptr = self.define_addr(old);
+            self.memcpy(new, ptr, len);
+            self.free(ptr);
+        }
+        new
+    }
+
+    /// Chunks that have been allocated on the heap.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Concrete value of `v`. A symbolic value is constrained to the smallest value it can take.
+    /// If it can take none, a concretization error is recorded and the value is 0.
+    fn concretize_value(&mut self, v: NodeIndex) -> u64 {
+        if let Some(c) = try_simplify_constant(v, &mut self.solver) {
+            return c;
+        }
+        let width = self.mem.address_width();
+        let c = match concretize::min(&self.solver, v, width, &mut self.stats) {
+            Some(c) => c,
+            None => {
+                self.record_concretization_error(ConcretizationError::Infeasible);
+                return 0;
+            }
+        };
+        let c_ = self.define_const(c, width);
+        self.solver.assert(core::OpCodes::Cmp, &[v, c_]);
+        c
+    }
+
+    fn record_concretization_error(&mut self, e: ConcretizationError) {
+        if self.concretization_error.is_none() {
+            self.concretization_error = Some(e);
+        }
+    }
+
+    fn check_heap(&mut self, addr: u64, size: usize) {
+        if let Err(e) = self.heap.check_access(addr, (size / 8) as u64) {
+            self.record_heap_error(e);
+        }
+    }
+
+    fn record_heap_error(&mut self, e: HeapError) {
+        if self.heap_error.is_none() {
+            self.heap_error = Some(e);
+        }
+    }
+
+    fn check_init(&mut self, addr: u64, size: usize) {
+        if self.uninit_policy == UninitPolicy::Ignore {
+            return;
+        }
+        let end = addr.saturating_add((size / 8) as u64);
+        if let Some(first) = self.initialized.first_uninit(addr, end) {
+            self.uninit_reads.push(UninitRead {
+                ip: self.ip,
+                addr: first,
+                size: size,
+            });
+        }
+    }
+
+    fn read_at(&mut self, addr: NodeIndex, read_size: usize) -> NodeIndex {
+        // Permissions can only be checked for concrete addresses.
+        let c_addr = try_simplify_constant(addr, &mut self.solver);
+        if let Some(c_addr) = c_addr {
+            self.check_access(c_addr, (read_size / 8) as u64, Access::Read);
+            self.check_heap(c_addr, read_size);
+        }
+        let mmio = c_addr.and_then(|a| self.mmio_region(a).map(|r| (a, r)));
+        let value = if let Some((c_addr, region)) = mmio {
+            let n = self.mmio_log.len();
+            let value = region.load(&mut self.solver, c_addr, read_size, n);
+            self.log_mmio(c_addr, read_size, Access::Read, value);
+            value
+        } else {
+            if let Some(c_addr) = c_addr {
+                self.check_init(c_addr, read_size);
+            }
+            self.mem.read(addr, read_size, &mut self.solver)
+        };
+        if let Some(c_addr) = c_addr {
+            self.watch(c_addr, read_size, Access::Read, value);
+            if self.is_mem_tainted(c_addr, (read_size / 8) as u64) {
+                Rc::make_mut(&mut self.tainted).insert(value);
+            }
+        } else if !self.watchpoints.is_empty() {
+            self.watch_symbolic(addr, read_size, Access::Read, value);
+        }
+        value
+    }
+
+    fn write_at(&mut self, addr: NodeIndex, data: NodeIndex, write_size: usize) {
+        if let Some(c_addr) = try_simplify_constant(addr, &mut self.solver) {
+            self.check_access(c_addr, (write_size / 8) as u64, Access::Write);
+            self.check_heap(c_addr, write_size);
+            self.watch(c_addr, write_size, Access::Write, data);
+            if self.mmio_region(c_addr).is_some() {
+                // Stores to devices are recorded, but do not change memory.
+                self.log_mmio(c_addr, write_size, Access::Write, data);
+                return;
+            }
+            self.mark_initialized(c_addr, c_addr.saturating_add((write_size / 8) as u64));
+            if self.mem.permissions(c_addr).map_or(false, |p| p.allows(Access::Execute)) {
+                self.patch_code(c_addr, data, write_size);
+            }
+            let tainted = self.tainted.contains(&data);
+            let end = c_addr.saturating_add((write_size / 8) as u64);
+            // Only copy the taint set of a fork if it changes.
+            if tainted || self.is_mem_tainted(c_addr, end - c_addr) {
+                let tainted_mem = Rc::make_mut(&mut self.tainted_mem);
+                for a in c_addr..end {
+                    if tainted {
+                        tainted_mem.insert(a);
+                    } else {
+                        tainted_mem.remove(&a);
+                    }
+                }
+            }
+        } else if !self.watchpoints.is_empty() {
+            self.watch_symbolic(addr, write_size, Access::Write, data);
+        }
+        self.record_effect(Effect::Mem {
+            addr: addr,
+            value: data,
+            size: write_size,
+        });
+        self.mem.write(addr, data, write_size, &mut self.solver);
+    }
+}
+
+impl<Mem, Reg> Evaluate for RuneContext<Mem, Reg>
+where Mem: Memory<VarRef=NodeIndex>,
+      Reg: RegStore<VarRef=NodeIndex>
+{
+    type VarRef = NodeIndex;
+    type IFn = qf_abv::QF_ABV_Fn;
+
+    fn eval<T, Q>(&mut self, smt_fn: T, operands: Q) -> Self::VarRef
+        where T: Into<Self::IFn>,
+              Q: AsRef<[Self::VarRef]>
+    {
+        // TODO: Add extract / concat to ensure that the registers are of compatible
+        // sizes for operations.
+        let result = self.solver.assert(smt_fn, operands.as_ref());
+        self.propagate_taint(result, operands.as_ref());
+        result
+    }
+}
+
+impl<Mem, Reg> ContextAPI for RuneContext<Mem, Reg>
+where Mem: Memory<VarRef=NodeIndex>,
+      Reg: RegStore<VarRef=NodeIndex> + RegStoreAPI
+{
+    fn set_reg_as_const<T: AsRef<str>>(&mut self, reg: T, val: u64) -> NodeIndex {
+        if let Some(cval) = self.regstore.get_reg_ref(reg.as_ref()) {
+            cval
+        } else {
+            let width = self.regstore.storage_width(reg.as_ref());
+            let cval = self.define_const(val, width);
+            self.regstore.set_reg(reg.as_ref(), cval);
+            cval
+        } 
+    }
+
+    fn set_reg_as_sym<T: AsRef<str>>(&mut self, reg: T, width: usize, name: Option<&str>) -> Symbol<NodeIndex> {
+        let reg_width = self.regstore.storage_width(reg.as_ref());
+        assert!(width <= reg_width, "Symbol is wider than {}!", reg.as_ref());
+        let name = name.unwrap_or(reg.as_ref()).to_owned();
+        let sym = self.new_symbol(&name, width);
+        let value = if width < reg_width {
+            self.solver.assert(bitvec::OpCodes::ZeroExtend((reg_width - width) as u64), &[sym.var])
+        } else {
+            sym.var
+        };
+        self.regstore.set_reg(reg.as_ref(), value);
+        sym
+    }
+
+    fn set_mem_as_const(&mut self, addr: u64, val: u64, write_size: usize) -> NodeIndex {
+        // Assert that memory var is in chunks of 8
+        assert_eq!(write_size%8, 0, "Write size is not divisible by 8!");
+
+        let addr = self.define_addr(addr);
+        let cval = self.define_const(val, write_size);
+        self.mem_write(addr, cval, write_size);
+
+        cval
+    }
+
+    fn set_mem_as_sym(&mut self, addr: u64, width: usize, name: Option<&str>) -> Symbol<NodeIndex> {
+        // Assert that memory var is in chunks of 8
+        assert_eq!(width%8, 0, "Write size is not divisible by 8!");
+
+        let name = name.map(|n| n.to_owned()).unwrap_or_else(|| format!("mem_{}_{}", addr, width/8));
+        let sym = self.new_symbol(&name, width);
+        let addr = self.define_addr(addr);
+
+        self.mem_write(addr, sym.var, width);
+        sym
+    }
+
+    fn zero_registers(&mut self) {
+        let cval = Some(self.define_addr(0));
+        for reg in &mut self.regstore.get_regs() {
+            if reg.is_none() {
+                *reg = cval;
+            }
+        }
+    }
+
+    fn registers(&self) -> Vec<String> {
+        unimplemented!();
+    }
+}
+
+impl<Mem, Reg> RuneContext<Mem, Reg>
+where Mem: Memory,
+      Reg: RegStore
+{
+    pub fn new(ip: Option<u64>,
+               mem: Mem,
+               regstore: Reg,
+               solver: SMTLib2<qf_abv::QF_ABV>)
+               -> RuneContext<Mem, Reg> {
+        RuneContext {
+            ip: ip.unwrap_or(0),
+            mem: mem,
+            regstore: regstore,
+            solver: solver,
+            e_old: None,
+            e_cur: None,
+            violation: None,
+            strict_mapping: false,
+            effects: None,
+            watchpoints: Rc::new(Vec::new()),
+            watch_hits: Vec::new(),
+            concretization: Default::default(),
+            mem_op_bound: DEFAULT_MEM_OP_BOUND,
+            heap: Default::default(),
+            heap_error: None,
+            concretization_error: None,
+            mmio: Rc::new(Vec::new()),
+            mmio_log: Vec::new(),
+            initialized: Default::default(),
+            uninit_policy: Default::default(),
+            uninit_reads: Vec::new(),
+            tainted: Rc::new(HashSet::new()),
+            tainted_mem: Rc::new(BTreeSet::new()),
+            arch: None,
+            layout: None,
+            insn_addr: 0,
+            delayed_branch: None,
+            path: Rc::new(Vec::new()),
+            symbols: Rc::new(BTreeMap::new()),
+            opaque_values: 0,
+            metadata: Default::default(),
+            stats: Default::default(),
+            sat_cache: Default::default(),
+            call_stack: Default::default(),
+            files: Default::default(),
+            env: Default::default(),
+            watch_exprs: Rc::new(Vec::new()),
+            watch_log: Rc::new(Vec::new()),
+            code: Rc::new(BTreeMap::new()),
+            scopes: Vec::new(),
+            history: None,
+        }
+    }
+
+    /// Use the register roles and calling conventions of `arch`, see `arch::arch::from_bin`.
+    pub fn set_arch(&mut self, arch: Rc<Arch>) {
+        self.arch = Some(arch);
+    }
+
+    /// Record where the binary is loaded, see `memory::layout::rebase`.
+    pub fn set_image_layout(&mut self, layout: ImageLayout) {
+        self.layout = Some(Rc::new(layout));
+    }
+
+    /// Where the binary is loaded, to translate between addresses of the context, of r2 before
+    /// the binary was rebased, and offsets in the file.
+    pub fn image_layout(&self) -> Option<&ImageLayout> {
+        self.layout.as_ref().map(|l| &**l)
+    }
+
+    /// Set how symbolic addresses are concretized on memory accesses.
+    pub fn set_concretization(&mut self, strategy: ConcretizationStrategy) {
+        self.concretization = strategy;
+    }
+
+    /// Set the maximum number of bytes that `memcpy` and `memset` model when the length of the
+    /// operation is symbolic.
+    pub fn set_mem_op_bound(&mut self, bound: usize) {
+        self.mem_op_bound = bound;
+    }
+
+    /// Set how reads of memory that was never written are handled, see `memory::uninit`.
+    pub fn set_uninit_policy(&mut self, policy: UninitPolicy) {
+        self.uninit_policy = policy;
+    }
+
+    /// Consider memory in [start, end) as initialized, for example because it is known to be
+    /// set up by the loader.
+    pub fn mark_initialized(&mut self, start: u64, end: u64) {
+        if self.initialized.first_uninit(start, end).is_some() {
+            Rc::make_mut(&mut self.initialized).insert(start, end);
+        }
+    }
+
+    /// Treat `region` as memory mapped I/O, see `memory::mmio`.
+    pub fn add_mmio(&mut self, region: MmioRegion) {
+        Rc::make_mut(&mut self.mmio).push(region);
+    }
+
+    /// Loads and stores performed to MMIO regions, in order.
+    pub fn mmio_accesses(&self) -> &[MmioAccess] {
+        &self.mmio_log
+    }
+
+    /// Record the bytes of `data` written to executable memory at `addr`, so that the
+    /// instructions there are decoded again.
+    fn patch_code(&mut self, addr: u64, data: NodeIndex, size: usize) {
+        let value = try_simplify_constant(data, &mut self.solver);
+        let bytes = (size / 8) as u64;
+        let endian = self.mem.endian();
+        let code = Rc::make_mut(&mut self.code);
+        for i in 0..bytes {
+            let shift = match endian {
+                Endian::Big => (bytes - i - 1) * 8,
+                _ => i * 8,
+            };
+            code.insert(addr + i, value.map(|v| (v >> shift) as u8));
+        }
+    }
+
+    fn mmio_region(&self, addr: u64) -> Option<MmioRegion> {
+        self.mmio.iter().find(|r| r.contains(addr)).cloned()
+    }
+
+    fn log_mmio(&mut self, addr: u64, size: usize, access: Access, value: NodeIndex) {
+        self.mmio_log.push(MmioAccess {
+            ip: self.ip,
+            addr: addr,
+            size: size,
+            access: access,
+            value: value,
+        });
+    }
+
+    /// Record hits of watchpoints by an access of `size` bits at `addr`.
+    fn watch(&mut self, addr: u64, size: usize, access: Access, value: NodeIndex) {
+        for wp in self.watchpoints.iter() {
+            if wp.triggers(addr, (size / 8) as u64, access) {
+                self.watch_hits.push(WatchHit {
+                    watchpoint: *wp,
+                    ip: self.ip,
+                    addr: addr,
+                    symbolic_addr: None,
+                    size: size,
+                    access: access,
+                    value: value,
+                });
+            }
+        }
+    }
+
+    /// Record hits of watchpoints by an access of `size` bits through the symbolic address
+    /// `addr`, for the watchpoints that the address may point into.
+    fn watch_symbolic(&mut self, addr: NodeIndex, size: usize, access: Access, value: NodeIndex)
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let watchpoints = self.watchpoints.clone();
+        for wp in watchpoints.iter().filter(|wp| wp.watches(access) && wp.start < wp.end) {
+            let start = self.define_addr(wp.start);
+            if self.may_alias(addr, (size / 8) as u64, start, wp.end - wp.start) {
+                self.watch_hits.push(WatchHit {
+                    watchpoint: *wp,
+                    ip: self.ip,
+                    addr: wp.start,
+                    symbolic_addr: Some(addr),
+                    size: size,
+                    access: access,
+                    value: value,
+                });
+            }
+        }
+    }
+
+    /// Checkpoint the memory of this context, see `restore_memory`.
+    pub fn snapshot_memory(&self) -> Mem {
+        self.mem.snapshot()
+    }
+
+    /// Roll back memory to a checkpoint created by `snapshot_memory`. Note that constraints
+    /// added to the solver after the checkpoint was taken are retained, `rollback` retracts
+    /// them.
+    pub fn restore_memory(&mut self, snapshot: Mem) {
+        self.mem.restore(snapshot);
+    }
+
+    /// Checkpoint the registers of this context, see `restore_registers`.
+    pub fn snapshot_registers(&self) -> Reg {
+        self.regstore.snapshot()
+    }
+
+    /// Roll back the registers to a checkpoint created by `snapshot_registers`.
+    pub fn restore_registers(&mut self, snapshot: Reg) {
+        self.regstore.restore(snapshot);
+    }
+
+    /// Branch conditions assumed on this path so far, in the order they were assumed.
+    pub fn path_constraints(&self) -> &[PathConstraint<NodeIndex>] {
+        &self.path
+    }
+
+    /// Create a symbolic input of `width` bits and add it to the symbols of this context as
+    /// `name`. The variable in the solver is named after `name`, with the characters that
+    /// SMT-LIB2 does not allow in names (as in `stdin[0..16]`) replaced.
+    pub fn new_symbol(&mut self, name: &str, width: usize) -> Symbol<NodeIndex> {
+        let var_name: String = name.chars()
+                                   .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' })
+                                   .collect();
+        let var = self.solver.new_var(Some(&var_name), qf_abv::bv_sort(width));
+        let sym = Symbol {
+            name: var_name,
+            var: var,
+            width: width,
+        };
+        self.add_symbol(name, sym.clone());
+        sym
+    }
+
+    /// Make `sym` known as `name`, replacing the symbol previously known by that name.
+    /// Symbols created by `set_reg_as_sym`, `set_mem_as_sym` and `new_symbol` are added
+    /// automatically.
+    pub fn add_symbol(&mut self, name: &str, sym: Symbol<NodeIndex>) {
+        Rc::make_mut(&mut self.symbols).insert(name.to_owned(), sym);
+    }
+
+    /// The symbol known as `name`.
+    pub fn symbol(&self, name: &str) -> Option<&Symbol<NodeIndex>> {
+        self.symbols.get(name)
+    }
+
+    /// Symbols of this context by name.
+    pub fn symbols(&self) -> &BTreeMap<String, Symbol<NodeIndex>> {
+        &self.symbols
+    }
+
+    /// Constrain the path by `cond`, see `context::expr`.
+    pub fn assume(&mut self, cond: &Cond) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        // Conditions are roots of the expression graph, and thus asserted.
+        self.eval_cond(cond).map(|_| ())
+    }
+
+    /// Check if `cond` holds for every input that follows this path, as opposed to only some
+    /// of them.
+    pub fn holds(&mut self, cond: &Cond) -> Result<bool, String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        // Build the negation in a fork, where it is asserted.
+        let mut fork = self.fork();
+        let c = fork.eval_cond(cond)?;
+        fork.solver.assert(core::OpCodes::Not, &[c]);
+        Ok(!self.sat_cache.is_sat(&mut fork.solver, &mut self.stats))
+    }
+
+    /// Build `cond` in the solver, without asserting it.
+    pub fn eval_cond(&mut self, cond: &Cond) -> Result<NodeIndex, String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        Ok(match *cond {
+            Cond::Cmp(op, ref a, ref b) => {
+                let (a, b, _) = self.eval_operands(a, b, None)?;
+                self.eval(op, &[a, b])
+            }
+            Cond::And(ref a, ref b) => {
+                let ops = [self.eval_cond(a)?, self.eval_cond(b)?];
+                self.eval(core::OpCodes::And, &ops)
+            }
+            Cond::Or(ref a, ref b) => {
+                let ops = [self.eval_cond(a)?, self.eval_cond(b)?];
+                self.eval(core::OpCodes::Or, &ops)
+            }
+            Cond::Not(ref a) => {
+                let a = self.eval_cond(a)?;
+                self.eval(core::OpCodes::Not, &[a])
+            }
+        })
+    }
+
+    /// Evaluate the expression `expr` (for example `[rbp-0x10]:32` or `rax`, see
+    /// `utils::conditions`) after every instruction. Values that changed since the previous
+    /// instruction are recorded in the watch log of the path, see `watch_log`.
+    pub fn add_watch_expr(&mut self, expr: &str) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let parsed = conditions::parse_expr(expr, self.address_width())?;
+        // Check the registers and symbols of the expression now rather than at every step.
+        self.push_scope();
+        let valid = self.eval_expr(&parsed).map(|_| ());
+        self.pop_scope();
+        valid?;
+        Rc::make_mut(&mut self.watch_exprs).push((expr.to_owned(), parsed));
+        Ok(())
+    }
+
+    pub fn remove_watch_expr(&mut self, expr: &str) -> bool {
+        let len = self.watch_exprs.len();
+        Rc::make_mut(&mut self.watch_exprs).retain(|&(ref text, _)| text != expr);
+        self.watch_exprs.len() != len
+    }
+
+    /// Values of the watch expressions along the path, each recorded when it changed.
+    pub fn watch_log(&self) -> &[WatchValue] {
+        &self.watch_log
+    }
+
+    /// Build `expr` in the solver, returning it and its width.
+    pub fn eval_expr(&mut self, expr: &Expr) -> Result<(NodeIndex, usize), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        self.eval_expr_as(expr, None)
+    }
+
+    /// Build `expr`, giving constants the width `width` (or the address width).
+    fn eval_expr_as(&mut self, expr: &Expr, width: Option<usize>) -> Result<(NodeIndex, usize), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        Ok(match *expr {
+            Expr::Reg(ref reg) => {
+                if !self.regstore.has_reg(reg) {
+                    return Err(format!("Unknown register {}", reg));
+                }
+                (self.reg_read(reg), self.regstore.storage_width(reg))
+            }
+            Expr::Mem(addr, width) => {
+                let addr = self.define_addr(addr);
+                (self.mem_read(addr, width), width)
+            }
+            Expr::Load(ref addr, width) => {
+                let addr_width = self.address_width();
+                let (addr, from) = self.eval_expr_as(addr, Some(addr_width))?;
+                let addr = self.zero_extend(addr, from, addr_width);
+                (self.mem_read(addr, width), width)
+            }
+            Expr::Sym(ref name) => {
+                let sym = self.symbol(name).ok_or_else(|| format!("Unknown symbol {}", name))?;
+                (sym.var, sym.width)
+            }
+            Expr::Const(value) => {
+                let width = width.unwrap_or_else(|| self.address_width());
+                (self.define_const(value, width), width)
+            }
+            Expr::Binary(op, ref a, ref b) => {
+                let (a, b, width) = self.eval_operands(a, b, width)?;
+                (self.eval(op, &[a, b]), width)
+            }
+        })
+    }
+
+    /// Build the operands of a binary operation, extended to the same width.
+    fn eval_operands(&mut self, a: &Expr, b: &Expr, width: Option<usize>)
+                     -> Result<(NodeIndex, NodeIndex, usize), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        // Constants take the width of the other operand.
+        let ((a, a_width), (b, b_width)) = if let Expr::Const(_) = *a {
+            let b = self.eval_expr_as(b, width)?;
+            (self.eval_expr_as(a, Some(b.1))?, b)
+        } else {
+            let a = self.eval_expr_as(a, width)?;
+            let b = self.eval_expr_as(b, Some(a.1))?;
+            (a, b)
+        };
+        let width = ::std::cmp::max(a_width, b_width);
+        Ok((self.zero_extend(a, a_width, width), self.zero_extend(b, b_width, width), width))
+    }
+
+    fn zero_extend(&mut self, value: NodeIndex, from: usize, to: usize) -> NodeIndex {
+        if from < to {
+            self.solver.assert(bitvec::OpCodes::ZeroExtend((to - from) as u64), &[value])
+        } else {
+            value
+        }
+    }
+
+    /// Values of the symbols in a model returned by `solve`, by name. Symbols that the model
+    /// leaves unconstrained are omitted.
+    pub fn symbol_values(&self, model: &HashMap<NodeIndex, u64>) -> BTreeMap<String, u64> {
+        self.symbols
+            .iter()
+            .filter_map(|(name, sym)| sym.value(model).map(|v| (name.clone(), v)))
+            .collect()
+    }
+
+    /// Values of the symbols for an input that follows this path, `None` if there is none.
+    pub fn solve_inputs(&mut self) -> Option<BTreeMap<String, u64>> {
+        let mut z3: z3::Z3 = Default::default();
+        let model = self.stats.solve(&mut self.solver, &mut z3).ok()?;
+        Some(self.symbol_values(&model))
+    }
+
+    /// The path predicate of this context, with an input that satisfies it, see
+    /// `context::path_export`.
+    pub fn export_path(&mut self) -> Result<PathExport, String> {
+        let inputs = self.solve_inputs().ok_or_else(|| "The path is infeasible".to_owned())?;
+        let widths = self.symbols.iter().map(|(name, sym)| (name.clone(), sym.width)).collect();
+        Ok(PathExport::new(&self.solver.generate_asserts(), inputs, &widths))
+    }
+
+    /// Check if the branch `condition` is taken by the concrete `input`, which gives the values
+    /// of the symbols by name. Symbols that `input` leaves out are 0.
+    pub fn is_taken_by(&mut self, condition: NodeIndex, input: &BTreeMap<String, u64>) -> bool {
+        let mut solver = self.solver.clone();
+        for (name, sym) in self.symbols.iter() {
+            let value = input.get(name).cloned().unwrap_or(0);
+            let value = solver.new_const(bitvec::OpCodes::Const(mask(value, sym.width), sym.width));
+            solver.assert(core::OpCodes::Cmp, &[sym.var, value]);
+        }
+        let one = solver.new_const(bitvec::OpCodes::Const(1, 1));
+        solver.assert(core::OpCodes::Cmp, &[condition, one]);
+        self.sat_cache.is_sat(&mut solver, &mut self.stats)
+    }
+
+    /// Values and tags attached to this context, which its forks inherit.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        Rc::make_mut(&mut self.metadata)
+    }
+
+    /// Answers to the satisfiability queries of this context, which it shares with its forks.
+    pub fn sat_cache(&self) -> &SatCache {
+        &self.sat_cache
+    }
+
+    /// Instructions, branches and solver queries of the path that led to this context.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Calls of the path that did not return, see `context::call_stack`.
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Fork the context, in order to explore another path from the current state.
+    ///
+    /// The pages of a `PagedMemory`, the layout of a `RuneRegFile`, the heap, the taint sets,
+    /// the metadata and the rest of the bookkeeping are shared with the fork until either
+    /// context changes them.
+    ///
+    /// The expression graph of the solver is not shared. `SMTLib2` owns its graph and every
+    /// step of a path adds nodes to it, so sharing it behind an `Rc` would copy it on the first
+    /// step of the fork anyway. Sharing it needs a persistent graph in libsmt, which is out of
+    /// scope here: forking copies the graph, and remains linear in the number of expressions
+    /// built on the path so far. Explorers that visit the sides of a branch in order can use
+    /// `push_scope` instead, which does not copy the solver.
+    pub fn fork(&self) -> RuneContext<Mem, Reg> {
+        self.clone()
+    }
+
+    /// Open a scope: `pop_scope` rolls the context back to its current state, and retracts
+    /// the constraints asserted in the meantime. Unlike `fork`, the solver is not copied, so a
+    /// depth first explorer can return to the other side of a branch at the cost of a single
+    /// assertion. Returns the number of open scopes.
+    ///
+    /// Constraints asserted before the scope was opened should not be used as operands in the
+    /// scope, as `merge` does with branch conditions: they are no longer asserted once the
+    /// scope is closed.
+    pub fn push_scope(&mut self) -> usize {
+        let start = self.solver.new_const(core::OpCodes::True);
+        let solver = mem::replace(&mut self.solver, SMTLib2::new(Some(qf_abv::QF_ABV)));
+        let scopes = mem::replace(&mut self.scopes, Vec::new());
+        let state = Box::new(self.clone());
+        self.solver = solver;
+        self.scopes = scopes;
+        self.scopes.push(Scope {
+            start: start,
+            state: state,
+        });
+        self.scopes.len()
+    }
+
+    /// Close the innermost scope, see `push_scope`. Returns false if there is no open scope.
+    pub fn pop_scope(&mut self) -> bool {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return false,
+        };
+        self.retract_since(scope.start);
+
+        let solver = mem::replace(&mut self.solver, SMTLib2::new(Some(qf_abv::QF_ABV)));
+        let scopes = mem::replace(&mut self.scopes, Vec::new());
+        *self = *scope.state;
+        self.solver = solver;
+        self.scopes = scopes;
+        true
+    }
+
+    /// Retract the constraints asserted since the node `start` was created.
+    fn retract_since(&mut self, start: NodeIndex) {
+        // Using the assertions in a tautology retracts them.
+        let end = self.solver.new_const(core::OpCodes::True);
+        let mut retracted = smtlib::assertions_between(&self.solver, start, end);
+        if !retracted.is_empty() {
+            retracted.push(end);
+            self.solver.assert(core::OpCodes::Or, &retracted);
+        }
+    }
+
+    /// Number of open scopes, see `push_scope`.
+    pub fn scope_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Merge `other` into this context, so that a single state describes both paths. `other`
+    /// must be a fork of an ancestor of this context (see `fork`) that reached the same address,
+    /// typically the other side of a branch after both sides joined again.
+    ///
+    /// The branches that the two paths assumed since they diverged guard the merged state:
+    /// registers and bytes of memory that differ become `ite` expressions, which select the
+    /// value of this path when its branch conditions hold and the value of `other` otherwise.
+    /// The solver is constrained to the conditions of either path, and the path constraints
+    /// keep the branches common to both. A register that is set on only one of the paths keeps
+    /// the value of that path.
+    ///
+    /// Expressions of `other` are imported into the solver of this context, referring to the
+    /// variables of this context that have the same name.
+    pub fn merge(&mut self, other: &RuneContext<Mem, Reg>) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        if self.ip != other.ip {
+            return Err(format!("Cannot merge contexts at {:#x} and {:#x}", self.ip, other.ip));
+        }
+        if self.call_stack != other.call_stack {
+            return Err(format!("Cannot merge contexts at {:#x} in different calls", self.ip));
+        }
+
+        // Nodes are only comparable across solvers for the part of the graph built before the
+        // fork, so branches are compared by their expressions.
+        let common = self.path
+                         .iter()
+                         .zip(other.path.iter())
+                         .take_while(|&(a, b)| {
+                             a.ip == b.ip && a.taken == b.taken &&
+                             to_smtlib(a.constraint, &self.solver) == to_smtlib(b.constraint, &other.solver)
+                         })
+                         .count();
+
+        // Bytes that only this path wrote hold in `other` what its memory reads there.
+        let mut their_solver = other.solver.clone();
+        let mut their_mem = other.mem.clone();
+        let ours: BTreeMap<u64, NodeIndex> = self.mem.cells().into_iter().collect();
+        let theirs: BTreeMap<u64, NodeIndex> = their_mem.cells().into_iter().collect();
+        for &addr in ours.keys().filter(|addr| !theirs.contains_key(addr)) {
+            let addr = their_solver.new_const(bitvec::OpCodes::Const(addr, their_mem.address_width()));
+            let cell = their_mem.read(addr, 8, &mut their_solver);
+            their_mem.write(addr, cell, 8, &mut their_solver);
+        }
+        let mut image = their_mem.to_image(&their_solver);
+        {
+            let cells: HashMap<u64, NodeIndex> = their_mem.cells().into_iter().collect();
+            image.cells.retain(|&(addr, _)| {
+                ours.get(&addr).map(|&cell| to_smtlib(cell, &self.solver)) !=
+                Some(to_smtlib(cells[&addr], &their_solver))
+            });
+        }
+        // Variables of this path are shared with `other` by name.
+        let roots: Vec<NodeIndex> = self.regstore
+                                        .values()
+                                        .into_iter()
+                                        .filter_map(|(_, value)| value)
+                                        .chain(ours.values().cloned())
+                                        .chain(self.path.iter().map(|c| c.constraint))
+                                        .collect();
+        let vars = smtlib::free_vars(&self.solver, &roots);
+        let mut merged_mem = self.mem.clone();
+        let vars = merged_mem.load_image(&image, &vars, &mut self.solver)?;
+
+        // Registers that differ, and the branches `other` assumed on its own.
+        let (exprs, regs, constraints) = {
+            let mut exporter = Exporter::new(&their_solver);
+            let regs: Vec<(String, Option<NodeIndex>, usize)> =
+                self.regstore
+                    .values()
+                    .into_iter()
+                    .zip(other.regstore.values())
+                    .filter_map(|((name, a), (_, b))| {
+                        let b = match b {
+                            Some(b) => b,
+                            None => return None,
+                        };
+                        if a.map(|a| to_smtlib(a, &self.solver)) == Some(to_smtlib(b, &their_solver)) {
+                            None
+                        } else {
+                            Some((name, a, exporter.add(b)))
+                        }
+                    })
+                    .collect();
+            let constraints: Vec<usize> = other.path[common..]
+                                              .iter()
+                                              .map(|c| exporter.add(c.constraint))
+                                              .collect();
+            (exporter.finish(), regs, constraints)
+        };
+        let nodes = {
+            let mut importer = Importer::new(&mut self.solver);
+            for (name, var) in vars {
+                importer.bind(&name, var);
+            }
+            importer.import(&exprs)?
+        };
+
+        let our_guard: Vec<NodeIndex> = self.path[common..].iter().map(|c| c.constraint).collect();
+        let their_guard: Vec<NodeIndex> = constraints.into_iter().map(|c| nodes[c]).collect();
+        let guard = self.conjunction(&our_guard);
+        let their_guard = self.conjunction(&their_guard);
+        // Using the branch conditions as operands turns them from assertions into parts of the
+        // disjunction.
+        self.solver.assert(core::OpCodes::Or, &[guard, their_guard]);
+
+        for (name, ours, theirs) in regs {
+            let value = match ours {
+                Some(ours) => self.solver.assert(core::OpCodes::ITE, &[guard, ours, nodes[theirs]]),
+                None => nodes[theirs],
+            };
+            let tainted = self.regstore.is_tainted(&name) || other.regstore.is_tainted(&name);
+            self.regstore.set_reg(&name, value);
+            self.regstore.set_taint(&name, tainted);
+        }
+
+        self.mem.merge(&merged_mem, guard, &mut self.solver);
+        for &(addr, _) in &image.cells {
+            self.mark_initialized(addr, addr + 1);
+        }
+        if !Rc::ptr_eq(&self.tainted_mem, &other.tainted_mem) {
+            Rc::make_mut(&mut self.tainted_mem).extend(other.tainted_mem.iter().cloned());
+        }
+        Rc::make_mut(&mut self.path).truncate(common);
+        Ok(())
+    }
+
+    /// Whether this context and `other` are in the same state: at the same address, with the
+    /// same expressions in their registers and memory. Path constraints are not compared.
+    pub fn equivalent(&self, other: &RuneContext<Mem, Reg>) -> bool
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        self.ip == other.ip && self.diff_registers(other).is_empty() &&
+        self.memory_image() == other.memory_image()
+    }
+
+    /// Hash of the address, the call stack, the expressions in the registers and memory, and the
+    /// constraints of the solver, in any order. States that hash the same are `equivalent`
+    /// under the same constraints, barring collisions, so that exploring one of them is enough.
+    pub fn state_hash(&self) -> u64
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let mut hasher = DefaultHasher::new();
+        self.ip.hash(&mut hasher);
+        self.call_stack.hash(&mut hasher);
+        for (name, value) in self.regstore.values() {
+            (name, value.map(|v| to_smtlib(v, &self.solver))).hash(&mut hasher);
+        }
+        self.memory_image().hash(&mut hasher);
+        smtlib::canonical_assertions(&self.solver).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Same as `equivalent`, except that registers and bytes of memory only need to hold the
+    /// same values for all inputs that satisfy the constraints of this context, which is
+    /// checked by the solver. Memory backends that cannot enumerate their cells (see
+    /// `Memory::cells`) still need to hold the same expressions.
+    pub fn semantically_equivalent(&self, other: &RuneContext<Mem, Reg>) -> bool
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        if self.ip != other.ip {
+            return false;
+        }
+        let (image, their_image) = (self.memory_image(), other.memory_image());
+        if image.regions != their_image.regions ||
+           (image.array.is_some() || their_image.array.is_some()) &&
+           (image.array, &image.exprs) != (their_image.array, &their_image.exprs) {
+            return false;
+        }
+
+        // Expressions that differ, as (ours, theirs) pairs.
+        let mut solver = self.solver.clone();
+        let mut their_solver = other.solver.clone();
+        let mut pairs = Vec::new();
+        for ((_, a), (_, b)) in self.regstore.values().into_iter().zip(other.regstore.values()) {
+            match (a, b) {
+                (Some(a), Some(b)) => pairs.push((a, b)),
+                (None, None) => {}
+                _ => return false,
+            }
+        }
+        let ours: BTreeMap<u64, NodeIndex> = self.mem.cells().into_iter().collect();
+        let theirs: BTreeMap<u64, NodeIndex> = other.mem.cells().into_iter().collect();
+        let (mut mem, mut their_mem) = (self.mem.clone(), other.mem.clone());
+        for addr in ours.keys().chain(theirs.keys()).cloned().collect::<BTreeSet<u64>>() {
+            // A byte that is missing on one side holds whatever that memory reads there.
+            let a = match ours.get(&addr) {
+                Some(cell) => *cell,
+                None => {
+                    let addr = solver.new_const(bitvec::OpCodes::Const(addr, mem.address_width()));
+                    mem.read(addr, 8, &mut solver)
+                }
+            };
+            let b = match theirs.get(&addr) {
+                Some(cell) => *cell,
+                None => {
+                    let addr = their_solver.new_const(bitvec::OpCodes::Const(addr, their_mem.address_width()));
+                    their_mem.read(addr, 8, &mut their_solver)
+                }
+            };
+            pairs.push((a, b));
+        }
+        pairs.retain(|&(a, b)| to_smtlib(a, &solver) != to_smtlib(b, &their_solver));
+        if pairs.is_empty() {
+            return true;
+        }
+
+        let (exprs, indices) = {
+            let mut exporter = Exporter::new(&their_solver);
+            let indices: Vec<usize> = pairs.iter().map(|&(_, b)| exporter.add(b)).collect();
+            (exporter.finish(), indices)
+        };
+        let roots: Vec<NodeIndex> = pairs.iter().map(|&(a, _)| a).collect();
+        let vars = smtlib::free_vars(&solver, &roots);
+        let nodes = {
+            let mut importer = Importer::new(&mut solver);
+            for (name, var) in vars {
+                importer.bind(&name, var);
+            }
+            match importer.import(&exprs) {
+                Ok(nodes) => nodes,
+                Err(_) => return false,
+            }
+        };
+
+        // The states are equivalent if no input makes any of the pairs differ.
+        let distinct: Vec<NodeIndex> = pairs.iter()
+                                            .zip(indices)
+                                            .map(|(&(a, _), b)| solver.assert(core::OpCodes::Distinct, &[a, nodes[b]]))
+                                            .collect();
+        if distinct.len() > 1 {
+            solver.assert(core::OpCodes::Or, &distinct);
+        }
+        let mut z3: z3::Z3 = Default::default();
+        solver.solve(&mut z3).is_err()
+    }
+
+    /// Conjunction of the assertions `nodes`, `true` if there are none.
+    fn conjunction(&mut self, nodes: &[NodeIndex]) -> NodeIndex {
+        match nodes.len() {
+            0 => self.solver.new_const(core::OpCodes::True),
+            1 => nodes[0],
+            _ => self.solver.assert(core::OpCodes::And, nodes),
+        }
+    }
+
+    /// Checkpoint the instruction pointer, registers, memory and path constraints of this
+    /// context together, see `rollback`.
+    pub fn checkpoint(&mut self) -> Checkpoint<Mem, Reg> {
+        Checkpoint {
+            ip: self.ip,
+            mem: self.snapshot_memory(),
+            regstore: self.snapshot_registers(),
+            path: self.path.clone(),
+            start: self.solver.new_const(core::OpCodes::True),
+        }
+    }
+
+    /// Roll back to a checkpoint created by `checkpoint`, and retract the constraints asserted
+    /// since. As with scopes (see `push_scope`), constraints asserted before the checkpoint
+    /// should not be used as operands after it.
+    pub fn rollback(&mut self, checkpoint: Checkpoint<Mem, Reg>) {
+        self.ip = checkpoint.ip;
+        self.restore_memory(checkpoint.mem);
+        self.restore_registers(checkpoint.regstore);
+        self.path = checkpoint.path;
+        self.retract_since(checkpoint.start);
+    }
+
+    /// Record the steps of the path from now on, see `context::history`.
+    pub fn start_history(&mut self) {
+        let history = History::new(self.snapshot_registers(), self.snapshot_memory());
+        self.history = Some(Rc::new(history));
+    }
+
+    /// Steps of the path so far, if `start_history` was called.
+    pub fn history(&self) -> Option<&History<Mem, Reg>> {
+        self.history.as_ref().map(|h| &**h)
+    }
+
+    /// Start a step of the history, for the instruction at `addr`.
+    pub fn begin_step(&mut self, addr: u64) {
+        if let Some(ref mut history) = self.history {
+            Rc::make_mut(history).begin_step(addr, self.path.len(), self.call_stack.clone());
+        }
+    }
+
+    fn record_effect(&mut self, effect: Effect<NodeIndex>) {
+        let ip = self.ip;
+        let branches = self.path.len();
+        if let Some(ref mut history) = self.history {
+            let history = Rc::make_mut(history);
+            if !history.record(effect.clone()) {
+                // Written before the first instruction, by a hook or the user.
+                history.begin_step(ip, branches, self.call_stack.clone());
+                history.record(effect);
+            }
+        }
+    }
+
+    /// State of the path before its step `index`, or its current state if `index` is the number
+    /// of steps. `None` without a history, or past its end. The state has no history itself.
+    pub fn seek(&self, index: usize) -> Option<RuneContext<Mem, Reg>>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let history = match self.history {
+            Some(ref history) if index <= history.len() => history,
+            _ => return None,
+        };
+        let mut state = self.clone();
+        state.history = None;
+        state.effects = None;
+        if index == history.len() {
+            return Some(state);
+        }
+        state.restore_registers(history.regs().clone());
+        state.restore_memory(history.mem().clone());
+        for step in &history.steps()[..index] {
+            for effect in &step.effects {
+                match *effect {
+                    Effect::Reg(ref name, value) => {
+                        state.regstore.write(name, value, &mut state.solver);
+                    }
+                    Effect::Mem { addr, value, size } => {
+                        state.mem.write(addr, value, size, &mut state.solver);
+                    }
+                }
+            }
+        }
+        let step = &history.steps()[index];
+        state.ip = step.addr;
+        Rc::make_mut(&mut state.path).truncate(step.branches);
+        state.call_stack = step.call_stack.clone();
+        Some(state)
+    }
+
+    /// Bytes of memory that differ between this context and `other`.
+    pub fn diff_memory(&self, other: &RuneContext<Mem, Reg>) -> Vec<CellDiff>
+        where Mem: Memory<VarRef = NodeIndex>
+    {
+        diff::diff(&self.mem, &self.solver, &other.mem, &other.solver)
+    }
+
+    /// Sparse dump of the bytes of memory accessed so far.
+    pub fn dump_memory(&self) -> MemoryDump
+        where Mem: Memory<VarRef = NodeIndex>
+    {
+        dump::dump(&self.mem, &self.solver)
+    }
+
+    /// Registers that differ between this context and `other`.
+    pub fn diff_registers(&self, other: &RuneContext<Mem, Reg>) -> Vec<RegDiff>
+        where Reg: RegStore<VarRef = NodeIndex>
+    {
+        reg_diff::diff(&self.regstore, &self.solver, &other.regstore, &other.solver)
+    }
+
+    /// Serializable image of the memory of this context.
+    pub fn memory_image(&self) -> MemoryImage
+        where Mem: Memory<VarRef = NodeIndex>
+    {
+        self.mem.to_image(&self.solver)
+    }
+
+    /// Load memory saved by `memory_image`, possibly from a different context.
+    pub fn load_memory_image(&mut self, image: &MemoryImage) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>
+    {
+        self.mem.load_image(image, &HashMap::new(), &mut self.solver)?;
+        for &(addr, _) in &image.cells {
+            Rc::make_mut(&mut self.initialized).insert(addr, addr + 1);
+        }
+        Ok(())
+    }
+
+    /// Serializable image of the state of this context, see `context::image`.
+    pub fn to_image(&self) -> Result<ContextImage, String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let mut exporter = Exporter::new(&self.solver);
+        let registers = self.regstore
+                            .values()
+                            .into_iter()
+                            .filter_map(|(name, value)| value.map(|v| (name, exporter.add(v))))
+                            .collect();
+        let path = self.path
+                       .iter()
+                       .map(|c| {
+                           PathConstraint {
+                               ip: c.ip,
+                               condition: exporter.add(c.condition),
+                               constraint: exporter.add(c.constraint),
+                               taken: c.taken,
+                           }
+                       })
+                       .collect();
+        let symbols = self.symbols
+                          .iter()
+                          .map(|(name, sym)| {
+                              (name.clone(), Symbol {
+                                  name: sym.name.clone(),
+                                  var: exporter.add(sym.var),
+                                  width: sym.width,
+                              })
+                          })
+                          .collect();
+        let mut files = Vec::new();
+        for fd in self.files.fds() {
+            let file = self.files.get(fd).unwrap();
+            files.push(FileImage {
+                fd: fd,
+                data: file.data.iter().map(|&b| exporter.add(b)).collect(),
+                pos: file.pos,
+            });
+        }
+        let mut env = Vec::new();
+        for name in self.env.names() {
+            let var = self.env.get(&name).unwrap();
+            env.push(EnvImage {
+                name: name.clone(),
+                value: var.value.clone(),
+                addr: var.addr,
+                symbols: var.symbols.iter().map(|&s| exporter.add(s)).collect(),
+            });
+        }
+        let mut exprs = exporter.finish();
+        let (constraints, roots) = smtlib::assertions(&self.solver)?;
+        let offset = exprs.append(constraints);
+        Ok(ContextImage {
+            ip: self.ip,
+            memory: self.memory_image(),
+            exprs: exprs,
+            registers: registers,
+            constraints: roots.into_iter().map(|root| root + offset).collect(),
+            tainted_mem: self.tainted_mem.iter().cloned().collect(),
+            stats: self.stats,
+            call_stack: (*self.call_stack).clone(),
+            path: path,
+            symbols: symbols,
+            heap: (*self.heap).clone(),
+            files: files,
+            env: env,
+        })
+    }
+
+    /// Restore the state saved by `to_image`. The context should be newly created for the same
+    /// program, with the same register profile.
+    pub fn load_image(&mut self, image: &ContextImage) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        self.ip = image.ip;
+        let vars = self.mem.load_image(&image.memory, &HashMap::new(), &mut self.solver)?;
+        for &(addr, _) in &image.memory.cells {
+            Rc::make_mut(&mut self.initialized).insert(addr, addr + 1);
+        }
+
+        // Constraints are roots of the expression graph, importing them asserts them again.
+        let nodes = {
+            let mut importer = Importer::new(&mut self.solver);
+            for (name, var) in vars {
+                importer.bind(&name, var);
+            }
+            importer.import(&image.exprs)?
+        };
+        for &(ref reg, value) in &image.registers {
+            if !self.regstore.has_reg(reg) {
+                return Err(format!("Unknown register {}", reg));
+            }
+            let value = *nodes.get(value).ok_or_else(|| format!("Invalid register expression {}", value))?;
+            self.regstore.set_reg(reg, value);
+        }
+        let node = |i: usize| {
+            nodes.get(i).cloned().ok_or_else(|| format!("Invalid expression {}", i))
+        };
+
+        let mut path = Vec::new();
+        for c in &image.path {
+            path.push(PathConstraint {
+                ip: c.ip,
+                condition: node(c.condition)?,
+                constraint: node(c.constraint)?,
+                taken: c.taken,
+            });
+        }
+        let mut symbols = BTreeMap::new();
+        for &(ref name, ref sym) in &image.symbols {
+            symbols.insert(name.clone(), Symbol {
+                name: sym.name.clone(),
+                var: node(sym.var)?,
+                width: sym.width,
+            });
+        }
+        let mut files = FdTable::default();
+        for file in &image.files {
+            files.open(file.fd, file.data.iter().map(|&i| node(i)).collect::<Result<_, _>>()?);
+            files.get_mut(file.fd).unwrap().pos = file.pos;
+        }
+        let mut env = Environment::default();
+        for var in &image.env {
+            env.set(&var.name, var.value.clone());
+            let loaded = env.get_mut(&var.name).unwrap();
+            loaded.addr = var.addr;
+            loaded.symbols = var.symbols.iter().map(|&i| node(i)).collect::<Result<_, _>>()?;
+        }
+
+        self.path = Rc::new(path);
+        self.symbols = Rc::new(symbols);
+        self.heap = Rc::new(image.heap.clone());
+        self.files = Rc::new(files);
+        self.env = Rc::new(env);
+        Rc::make_mut(&mut self.tainted_mem).extend(image.tainted_mem.iter().cloned());
+        self.stats = image.stats;
+        self.call_stack = Rc::new(image.call_stack.clone());
+        Ok(())
+    }
+
+    /// Save the state of this context to the file `path`, as JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let json = to_string(&self.to_image()?).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        file.write_all(json.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    /// Restore the state saved to `path` by `save`, see `load_image`.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let image: ContextImage = from_reader(file).map_err(|e| e.to_string())?;
+        self.load_image(&image)
+    }
+
+    /// Restore the memory and registers of the process that `core` was dumped from. Registers
+    /// that are not part of the register profile are ignored, and so are the bytes of segments
+    /// that were not dumped.
+    pub fn load_core_dump(&mut self, core: &CoreDump)
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        for segment in &core.segments {
+            let end = segment.vaddr + segment.size;
+            self.mem.set_permissions(segment.vaddr, end, segment.perm);
+            self.mem.write_bytes(segment.vaddr, &segment.data, &mut self.solver);
+            self.mark_initialized(segment.vaddr, segment.vaddr + segment.data.len() as u64);
+        }
+        self.load_registers(&core.registers);
+    }
+
+    /// Set registers to concrete values, ignoring the registers that are not part of the
+    /// register profile. Setting the program counter also sets the instruction pointer.
+    pub fn load_registers(&mut self, registers: &[(String, u64)])
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        for &(ref reg, value) in registers {
+            if self.regstore.has_reg(reg) {
+                let width = self.regstore.storage_width(reg);
+                let value = self.define_const(value, width);
+                self.reg_write(reg, value);
+            }
+        }
+    }
+
+    /// Start from the current state of the process that `r2` is debugging (`r2 -d`): the
+    /// registers are set to their values in the process and its memory maps are considered
+    /// initialized. The memory of the process is not copied, use a memory that fetches it, such
+    /// as `LazyMemory::with_process`.
+    pub fn load_process_state(&mut self, r2: &mut R2) -> Result<(), String>
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        for map in loader::memory_maps(r2)? {
+            self.mark_initialized(map.addr, map.addr_end);
+        }
+        self.load_registers(&loader::debug_registers(r2)?);
+        Ok(())
+    }
+
+    /// Populate memory with the sections of the binary opened in `r2`.
+    pub fn load_sections(&mut self, r2: &mut R2) -> Result<(), String> {
+        for section in loader::load_sections(r2, &mut self.mem, &mut self.solver)? {
+            Rc::make_mut(&mut self.initialized).insert(section.vaddr, section.vaddr + section.mapped_size());
+        }
+        Ok(())
+    }
+}
+
+/// Context at 0x9000 with 64 bit little endian memory and the general purpose registers `regs`,
+/// given with their width and laid out one after the other, for tests.
+#[cfg(test)]
+pub fn test_ctx(regs: &[(&str, usize)]) -> RuneContext<PagedMemory, RuneRegFile> {
+    test_ctx_with(PagedMemory::new(64, Endian::Little), regs)
+}
+
+/// Same as `test_ctx`, with the memory `mem`.
+#[cfg(test)]
+pub fn test_ctx_with(mem: PagedMemory,
+                     regs: &[(&str, usize)])
+                     -> RuneContext<PagedMemory, RuneRegFile> {
+    let mut offset = 0;
+    let mut lreginfo = LRegInfo {
+        reg_info: regs.iter()
+                      .map(|&(name, size)| {
+                          let reg = LRegProfile {
+                              name: name.to_owned(),
+                              offset: offset,
+                              size: size,
+                              type_str: "gpr".to_owned(),
+                              ..Default::default()
+                          };
+                          offset += size;
+                          reg
+                      })
+                      .collect(),
+        ..Default::default()
+    };
+    let regstore = RuneRegFile::new(&mut lreginfo);
+    let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+    RuneContext::new(Some(0x9000), mem, regstore, smt)
+}
+
+mod test {
+    use super::*;
+    use context::context::{Context, ContextAPI, Evaluate, MemoryRead, MemoryWrite, RegisterRead,
+                           RegisterWrite};
+
+    use libsmt::logics::qf_abv;
+    use libsmt::backends::smtlib2::SMTLib2;
+    use libsmt::backends::backend::SMTBackend;
+    use libsmt::theories::{bitvec, core};
+    use libsmt::backends::z3;
+
+    use memory::seg_mem::SegMem;
+    use memory::paged_mem::PagedMemory;
+    use memory::mmio::MmioRegion;
+    use libsmt::logics::qf_abv::QF_ABV_Fn::BVOps;
+    use regstore::regfile::RuneRegFile;
+
+    use r2api::structs::LRegProfile;
+
+    // #[test]
+    fn teting_memory_my_dude() {
+        let mut lreginfo = Default::default();
+        let regstore = RuneRegFile::new(&mut lreginfo);
+
+        let mut mem = SegMem::new(64, Endian::Big);
+		let mut smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+		mem.init_memory(&mut smt);
+
+        let mut ctx = RuneContext::new(Some(0x9000), mem, regstore, smt);
+    }
+
+    #[test]
+    fn mmio_loads_are_fresh() {
+        let mut ctx = test_ctx(&[]);
+        ctx.add_mmio(MmioRegion::new(0x4000_0000, 0x4000_1000));
+
+        let addr = ctx.define_const(0x4000_0010, 64);
+        let data = ctx.define_const(0x41, 32);
+        ctx.mem_write(addr, data, 32);
+        let first = ctx.mem_read(addr, 32);
+        let second = ctx.mem_read(addr, 32);
+
+        assert!(first != data && first != second);
+        assert!(ctx.mem.cells().is_empty());
+        let accesses: Vec<Access> = ctx.mmio_accesses().iter().map(|a| a.access).collect();
+        assert_eq!(accesses, vec![Access::Write, Access::Read, Access::Read]);
+    }
+
+    #[test]
+    fn pointers_follow_address_width() {
+        let mem = PagedMemory::new(32, Endian::Little);
+        let mut ctx = test_ctx_with(mem, &[]);
+        ctx.set_ip(0x8048000);
+        assert_eq!(ctx.address_width(), 32);
+
+        let size = ctx.define_const(0x10, 32);
+        let ptr = ctx.malloc(size);
+        match *ctx.solver.get_node_info(ptr) {
+            BVOps(bitvec::OpCodes::Const(_, 32)) => {}
+            ref other => panic!("Expected a 32 bit pointer, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn narrow_targets_wrap_around() {
+        let mem = PagedMemory::new(32, Endian::Little);
+        let mut ctx = test_ctx_with(mem, &[]);
+        ctx.set_ip(0xffff_fffe);
+
+        // ESIL's -1 is a 32 bit constant, and arithmetic on constants wraps at 32 bits.
+        let minus_one = ctx.define_const(u64::max_value(), 32);
+        assert_eq!(ctx.as_constant(minus_one), Some(0xffff_ffff));
+        let two = ctx.define_const(2, 32);
+        let sum = ctx.eval(bitvec::OpCodes::BvAdd, vec![minus_one, two]);
+        assert_eq!(ctx.as_constant(sum), Some(1));
+        let diff = ctx.eval(bitvec::OpCodes::BvSub, vec![two, minus_one]);
+        assert_eq!(ctx.as_constant(diff), Some(3));
+
+        ctx.increment_ip(4);
+        assert_eq!(ctx.ip(), 2);
+        ctx.set_ip(0x1_0000_1000);
+        assert_eq!(ctx.ip(), 0x1000);
+
+        let mem = PagedMemory::new(16, Endian::Little);
+        let mut ctx = test_ctx_with(mem, &[]);
+        ctx.set_ip(0xfffe);
+        ctx.increment_ip(3);
+        assert_eq!(ctx.ip(), 1);
+    }
+
+    #[test]
+    fn tracing_records_writes() {
+        let mut ctx = test_ctx(&[]);
+        ctx.set_ip(0x1000);
+
+        let addr = ctx.define_const(0x2000, 64);
+        let value = ctx.define_const(0x41, 8);
+        ctx.mem_write(addr, value, 8);
+        assert!(ctx.take_effects().is_empty());
+
+        ctx.set_tracing(true);
+        ctx.mem_write(addr, value, 8);
+        assert_eq!(ctx.take_effects(),
+                   vec![Effect::Mem {
+                            addr: addr,
+                            value: value,
+                            size: 8,
+                        }]);
+        assert!(ctx.take_effects().is_empty());
+        ctx.set_tracing(false);
+        ctx.mem_write(addr, value, 8);
+        assert!(ctx.take_effects().is_empty());
+    }
+
+    #[test]
+    fn code_patches() {
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        mem.set_permissions(0x1000, 0x2000, Permission::new(true, true, true));
+        let mut ctx = test_ctx_with(mem, &[]);
+        ctx.set_ip(0x1000);
+
+        let addr = ctx.define_const(0x1004, 64);
+        let nops = ctx.define_const(0x9090, 16);
+        ctx.mem_write(addr, nops, 16);
+        let addr = ctx.define_const(0x3000, 64);
+        ctx.mem_write(addr, nops, 16);
+        assert_eq!(ctx.code_patches(0x1000, 16),
+                   vec![(0x1004, Some(0x90)), (0x1005, Some(0x90))]);
+
+        let addr = ctx.define_const(0x1005, 64);
+        let sym = ctx.new_symbol("code", 8).var;
+        ctx.mem_write(addr, sym, 8);
+        assert_eq!(ctx.code_patches(0x1005, 1), vec![(0x1005, None)]);
+    }
+
+    #[test]
+    fn big_endian_mips() {
+        use arch::arch::Mips;
+
+        let mut mem = PagedMemory::new(32, Endian::Big);
+        mem.set_permissions(0x1000, 0x2000, Permission::new(true, true, true));
+        let mut ctx = test_ctx_with(mem, &[("t9", 32), ("pc", 32)]);
+        ctx.set_ip(0x400100);
+        ctx.set_arch(Rc::new(Mips {
+            bits: 32,
+            endian: Endian::Big,
+        }));
+
+        // `lw t9, 0x10(gp)` of a pointer in the GOT, then `jr t9`.
+        ctx.mem.write_bytes(0x1010, &[0x00, 0x40, 0x01, 0x20], &mut ctx.solver);
+        let addr = ctx.define_const(0x1010, 32);
+        let target = ctx.mem_read(addr, 32);
+        ctx.reg_write("t9", target);
+        let t9 = ctx.reg_read("t9");
+        assert_eq!(ctx.as_constant(t9), Some(0x400120));
+
+        // `sh` of an immediate stores its most significant byte first.
+        let addr = ctx.define_const(0x1020, 32);
+        let imm = ctx.define_const(0xbeef, 16);
+        ctx.mem_write(addr, imm, 16);
+        let first = ctx.mem_read(addr, 8);
+        assert_eq!(ctx.as_constant(first), Some(0xbe));
+        assert_eq!(ctx.code_patches(0x1020, 2), vec![(0x1020, Some(0xbe)), (0x1021, Some(0xef))]);
+    }
+
+    #[test]
+    fn strict_mapping() {
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        mem.set_permissions(0x1000, 0x2000, Permission::new(true, false, true));
+        let mut ctx = test_ctx_with(mem, &[]);
+        ctx.set_ip(0x1000);
+
+        ctx.check_access(0x3000, 1, Access::Read);
+        assert_eq!(ctx.take_violation(), None);
+        ctx.set_strict_mapping(true);
+        ctx.check_access(0x1800, 1, Access::Read);
+        assert_eq!(ctx.take_violation(), None);
+        ctx.check_access(0x3000, 1, Access::Write);
+        assert_eq!(ctx.take_violation(),
+                   Some(AccessViolation {
+                       addr: 0x3000,
+                       access: Access::Write,
+                       perm: None,
+                   }));
+        ctx.check_access(0x1800, 1, Access::Write);
+        assert_eq!(ctx.take_violation().and_then(|v| v.perm),
+                   Some(Permission::new(true, false, true)));
+    }
+
+    #[test]
+    fn access_range() {
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        mem.set_permissions(0x1000, 0x2000, Permission::new(true, true, false));
+        mem.set_permissions(0x2000, 0x3000, Permission::new(true, false, true));
+        let mut ctx = test_ctx_with(mem, &[]);
+
+        ctx.check_access(0x1ff8, 8, Access::Write);
+        assert_eq!(ctx.take_violation(), None);
+        // The last bytes of a write may reach into read only memory.
+        ctx.check_access(0x1ffc, 8, Access::Write);
+        assert_eq!(ctx.take_violation().map(|v| v.addr), Some(0x2000));
+        let addr = ctx.define_const(0x1ffe, 64);
+        let data = ctx.define_const(0, 32);
+        ctx.mem_write(addr, data, 32);
+        assert_eq!(ctx.take_violation().map(|v| v.addr), Some(0x2000));
+
+        ctx.set_strict_mapping(true);
+        ctx.check_access(0x2ffe, 4, Access::Read);
+        assert_eq!(ctx.take_violation().map(|v| (v.addr, v.perm)), Some((0x3000, None)));
+        ctx.check_access(u64::max_value() - 1, 4, Access::Read);
+        assert_eq!(ctx.take_violation().map(|v| v.addr), Some(u64::max_value() - 1));
+    }
+
+    #[test]
+    fn memcpy_concrete_length() {
+        let mut ctx = test_ctx(&[]);
+
+        let bytes: Vec<NodeIndex> = (0..4).map(|i| ctx.set_mem_as_sym(0x1000 + i, 8, None).var).collect();
+        let src = ctx.define_const(0x1000, 64);
+        let dst = ctx.define_const(0x2000, 64);
+        let len = ctx.define_const(4, 64);
+        ctx.memcpy(dst, src, len);
+
+        for (i, byte) in bytes.iter().enumerate() {
+            let addr = ctx.define_const(0x2000 + i as u64, 64);
+            assert_eq!(ctx.mem_read(addr, 8), *byte);
+        }
+    }
+
+    #[test]
+    fn mem_ops_symbolic_length() {
+        let mut ctx = test_ctx(&[("rdx", 64)]);
+        ctx.set_mem_op_bound(4);
+        let len = ctx.set_reg_as_sym("rdx", 64, Some("len")).var;
+        let src_bytes: Vec<NodeIndex> = (0..8).map(|i| ctx.set_mem_as_sym(0x1000 + i, 8, None).var)
+                                              .collect();
+        let src = ctx.define_const(0x1000, 64);
+        let dst = ctx.define_const(0x2000, 64);
+        ctx.memcpy(dst, src, len);
+
+        // The first `bound` bytes are copied if they are below the length, the others are not
+        // touched.
+        for i in 0..4 {
+            let addr = ctx.define_const(0x2000 + i, 64);
+            let byte = ctx.mem_read(addr, 8);
+            assert_eq!(to_smtlib(byte, &ctx.solver),
+                       format!("(ite (bvult (_ bv{} 64) len) {} mem_{}_1)",
+                               i,
+                               to_smtlib(src_bytes[i as usize], &ctx.solver),
+                               0x2000 + i));
+        }
+        assert!(ctx.mem.cells().iter().all(|&(addr, _)| addr < 0x2004 || addr >= 0x3000));
+
+        let dst = ctx.define_const(0x3000, 64);
+        let zero = ctx.define_const(0, 8);
+        ctx.memset(dst, zero, len);
+        let written: Vec<u64> = ctx.mem.cells().into_iter().map(|c| c.0).filter(|a| *a >= 0x3000)
+                                   .collect();
+        assert_eq!(written, vec![0x3000, 0x3001, 0x3002, 0x3003]);
+        let addr = ctx.define_const(0x3003, 64);
+        let byte = ctx.mem_read(addr, 8);
+        assert_eq!(to_smtlib(byte, &ctx.solver),
+                   format!("(ite (bvult (_ bv3 64) len) (_ bv0 8) mem_{}_1)", 0x3003));
+
+        // The length is bounded on this path, once by each operation.
+        let end = ctx.define_const(0, 1);
+        let bounds = smtlib::assertions_between(&ctx.solver, len, end)
+                         .into_iter()
+                         .filter(|&a| match *ctx.solver.get_node_info(a) {
+                             qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvULe) => true,
+                             _ => false,
+                         })
+                         .count();
+        assert_eq!(bounds, 2);
+    }
+
+    #[test]
+    fn uninitialized_reads() {
+        let mut ctx = test_ctx(&[]);
+
+        ctx.set_mem_as_const(0x1000, 0x41, 8);
+        let addr = ctx.define_const(0x1000, 64);
+        ctx.mem_read(addr, 8);
+        assert!(ctx.take_uninit_reads().is_empty());
+
+        // Only the first byte of the two byte read was initialized.
+        ctx.mem_read(addr, 16);
+        let reads = ctx.take_uninit_reads();
+        assert_eq!(reads.len(), 1);
+        assert_eq!((reads[0].addr, reads[0].size), (0x1001, 16));
+
+        ctx.set_uninit_policy(UninitPolicy::Ignore);
+        ctx.mem_read(addr, 16);
+        assert!(ctx.take_uninit_reads().is_empty());
+    }
+
+    #[test]
+    fn top_of_address_space() {
+        let mut ctx = test_ctx(&[]);
+
+        let addr = ctx.define_const(0xffff_ffff_ffff_fffc, 64);
+        ctx.mem_read(addr, 64);
+        let reads = ctx.take_uninit_reads();
+        assert_eq!(reads.len(), 1);
+        assert_eq!(reads[0].addr, 0xffff_ffff_ffff_fffc);
+
+        let data = ctx.define_const(0x1122_3344_5566_7788, 64);
+        ctx.mem_write(addr, data, 64);
+        let value = ctx.mem_read(addr, 64);
+        assert_eq!(try_simplify_constant(value, &mut ctx.solver), Some(0x1122_3344_5566_7788));
+        assert!(ctx.take_uninit_reads().is_empty());
+    }
+
+    #[test]
+    fn may_alias_concrete() {
+        let mut ctx = test_ctx(&[]);
+
+        let a = ctx.define_const(0x1000, 64);
+        let b = ctx.define_const(0x1004, 64);
+        assert!(ctx.may_alias(a, 8, b, 4));
+        assert!(!ctx.may_alias(a, 4, b, 4));
+        assert!(!ctx.may_alias(b, 4, a, 4));
+    }
+
+    #[test]
+    fn sym_buffer() {
+        let mut ctx = test_ctx(&[]);
+
+        let constraints = [BufferConstraint::Printable, BufferConstraint::NullTerminated];
+        let bytes = ctx.make_sym_buffer(0x1000, 4, &constraints);
+        assert_eq!(bytes.len(), 4);
+        let addr = ctx.define_const(0x1003, 64);
+        assert_eq!(ctx.mem_read(addr, 8), bytes[3]);
+        assert!(ctx.take_uninit_reads().is_empty());
+    }
+
+    #[test]
+    fn taint_propagation() {
+        let mut ctx = test_ctx(&[("rax", 64), ("rbx", 64)]);
+
+        ctx.set_reg_as_sym("rax", 64, None);
+        ctx.set_reg_as_const("rbx", 1);
+        ctx.taint_reg("rax");
+
+        // rbx = rax + rbx
+        let rax = ctx.reg_read("rax");
+        let rbx = ctx.reg_read("rbx");
+        let sum = ctx.eval(bitvec::OpCodes::BvAdd, &[rax, rbx]);
+        ctx.reg_write("rbx", sum);
+        assert!(ctx.is_reg_tainted("rbx"));
+
+        // Through memory and back.
+        let addr = ctx.define_const(0x1000, 64);
+        ctx.mem_write(addr, sum, 64);
+        assert!(ctx.is_mem_tainted(0x1004, 1));
+        let value = ctx.mem_read(addr, 32);
+        assert!(ctx.is_tainted(value));
+
+        let one = ctx.define_const(1, 64);
+        ctx.reg_write("rbx", one);
+        assert!(!ctx.is_reg_tainted("rbx"));
+
+        // Byte ranges are clamped at the end of the address space.
+        ctx.set_uninit_policy(UninitPolicy::Ignore);
+        let top = ctx.define_const(0xffff_ffff_ffff_fffc, 64);
+        let value = ctx.mem_read(top, 64);
+        assert!(!ctx.is_tainted(value));
+        assert!(!ctx.is_mem_tainted(0xffff_ffff_ffff_fffc, 8));
+    }
+
+    #[test]
+    fn thumb_mode() {
+        let reg = |name: &str, offset, size| {
+            LRegProfile {
+                name: name.to_owned(),
+                offset: offset,
+                size: size,
+                type_str: "gpr".to_owned(),
+                ..Default::default()
+            }
+        };
+        let mut lreginfo = LRegInfo {
+            reg_info: vec![reg("r0", 0, 32), reg("cpsr", 512, 32), reg("tf", 517, 1)],
+            ..Default::default()
+        };
+        let regstore = RuneRegFile::new(&mut lreginfo);
+        let mem = PagedMemory::new(32, Endian::Little);
+        let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut ctx = RuneContext::new(Some(0x9000), mem, regstore, smt);
+
+        assert_eq!(ctx.isa_mode(), IsaMode::Normal);
+        assert!(ctx.set_isa_mode(IsaMode::Thumb));
+        assert_eq!(ctx.isa_mode(), IsaMode::Thumb);
+        assert!(ctx.set_isa_mode(IsaMode::Normal));
+        assert_eq!(ctx.isa_mode(), IsaMode::Normal);
+    }
+
+    #[test]
+    fn program_counter() {
+        use arch::arch::Arm;
+
+        let reg = |name: &str, offset| {
+            LRegProfile {
+                name: name.to_owned(),
+                offset: offset,
+                size: 32,
+                type_str: "gpr".to_owned(),
+                ..Default::default()
+            }
+        };
+        let mut lreginfo = LRegInfo {
+            reg_info: vec![reg("r0", 0), reg("r15", 480), reg("pc", 480)],
+            ..Default::default()
+        };
+        let regstore = RuneRegFile::new(&mut lreginfo);
+        let mem = PagedMemory::new(32, Endian::Little);
+        let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut ctx = RuneContext::new(Some(0x9000), mem, regstore, smt);
+        ctx.set_arch(Rc::new(Arm { endian: Endian::Little }));
+
+        assert!(ctx.is_pc("pc") && ctx.is_pc("r15") && !ctx.is_pc("r0"));
+        let pc = ctx.reg_read("r15");
+        assert_eq!(ctx.as_constant(pc), Some(0x9000));
+        let target = ctx.define_const(0xa000, 32);
+        ctx.reg_write("pc", target);
+        assert_eq!(ctx.ip(), 0xa000);
+    }
+
+    #[test]
+    fn fork_shares_state() {
+        let mut ctx = test_ctx(&[]);
+        ctx.set_mem_as_const(0x1000, 0x41, 8);
+        ctx.taint_mem(0x1000, 1);
+
+        let mut fork = ctx.fork();
+        assert_eq!(fork.mem.shared_page_count(), 1);
+        assert!(Rc::ptr_eq(&fork.tainted_mem, &ctx.tainted_mem));
+
+        fork.set_mem_as_const(0x1000, 0x42, 8);
+        assert_eq!(fork.mem.shared_page_count(), 0);
+        assert!(!Rc::ptr_eq(&fork.tainted_mem, &ctx.tainted_mem));
+        assert!(ctx.is_mem_tainted(0x1000, 1));
+    }
+
+    #[test]
+    fn metadata() {
+        let mut ctx = test_ctx(&[]);
+        ctx.metadata_mut().set("depth", 3usize);
+        ctx.metadata_mut().add_tag("after-auth");
+
+        let mut fork = ctx.fork();
+        assert!(Rc::ptr_eq(&fork.metadata, &ctx.metadata));
+        fork.metadata_mut().set("depth", 4usize);
+        fork.metadata_mut().set("parent", 0x9000u64);
+
+        assert_eq!(ctx.metadata().get::<usize>("depth"), Some(&3));
+        assert_eq!(fork.metadata().get::<usize>("depth"), Some(&4));
+        // Values are only returned as the type they were stored as.
+        assert_eq!(fork.metadata().get::<u32>("parent"), None);
+        assert!(!ctx.metadata().contains_key("parent"));
+        assert!(fork.metadata().has_tag("after-auth"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let ctx = || {
+            let mut lreginfo = LRegInfo {
+                reg_info: vec![LRegProfile {
+                                   name: "rax".to_owned(),
+                                   size: 64,
+                                   type_str: "gpr".to_owned(),
+                                   ..Default::default()
+                               }],
+                ..Default::default()
+            };
+            let regstore = RuneRegFile::new(&mut lreginfo);
+            let mem = QWordMemory::new(64, Endian::Little);
+            let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+            RuneContext::new(None, mem, regstore, smt)
+        };
+        let mut saved = ctx();
+        saved.increment_ip(4);
+        saved.set_ip(0x9000);
+        saved.set_reg_as_const("rax", 0x41);
+        saved.set_mem_as_const(0x1000, 0x42, 64);
+        saved.taint_mem(0x1000, 2);
+        saved.record_call(0x8ffb, 0x9000, 0x9000);
+        let x = saved.set_mem_as_sym(0x2000, 64, Some("x")).var;
+        let zero = saved.define_const(0, 64);
+        let cond = saved.eval(core::OpCodes::Cmp, &[x, zero]);
+        saved.record_branch(x, cond, false);
+        let size = saved.define_const(0x20, 64);
+        saved.malloc(size);
+        saved.symbolize_stdin(4);
+        Rc::make_mut(&mut saved.files).get_mut(0).unwrap().pos = 1;
+        saved.set_env("HOME", "/root");
+
+        let image = saved.to_image().unwrap();
+        let mut loaded = ctx();
+        loaded.load_image(&image).unwrap();
+        assert_eq!(loaded.ip(), 0x9000);
+        let rax = loaded.reg_read("rax");
+        assert_eq!(loaded.as_constant(rax), Some(0x41));
+        assert!(loaded.is_mem_tainted(0x1001, 1));
+        assert!(loaded.memory_image().array.is_some());
+        assert_eq!(loaded.stats().instructions, 1);
+        assert_eq!(loaded.call_stack(), saved.call_stack());
+
+        let path = loaded.path_constraints();
+        assert_eq!(path.len(), 1);
+        assert_eq!(to_smtlib(path[0].constraint, &loaded.solver), to_smtlib(cond, &saved.solver));
+        assert_eq!(path[0].taken, false);
+        let x = loaded.symbol("x").unwrap().var;
+        assert_eq!(to_smtlib(x, &loaded.solver), "x");
+        assert_eq!(loaded.heap().chunks(), saved.heap().chunks());
+        assert_eq!(loaded.files().fds(), vec![0]);
+        assert_eq!(loaded.files().get(0).unwrap().pos, 1);
+        assert_eq!(loaded.files().get(0).unwrap().data.len(), 4);
+        assert_eq!(loaded.env().get("HOME"), saved.env().get("HOME"));
+    }
+
+    #[test]
+    fn path_constraints() {
+        let mut ctx = test_ctx(&[]);
+        let cond = ctx.set_mem_as_sym(0x1000, 64, None).var;
+
+        let one = ctx.define_const(1, 64);
+        let zero = ctx.define_const(0, 64);
+        let taken = ctx.eval(core::OpCodes::Cmp, &[cond, one]);
+        let not_taken = ctx.eval(core::OpCodes::Cmp, &[cond, zero]);
+
+        ctx.increment_ip(2);
+        ctx.record_branch(cond, taken, true);
+        let fork = ctx.fork();
+        ctx.increment_ip(4);
+        ctx.record_branch(cond, not_taken, false);
+
+        assert_eq!(fork.path_constraints().len(), 1);
+        assert_eq!(ctx.path_constraints(),
+                   &[PathConstraint { ip: 0x9000, condition: cond, constraint: taken, taken: true },
+                     PathConstraint { ip: 0x9002, condition: cond, constraint: not_taken, taken: false }][..]);
+    }
+
+    #[test]
+    fn symbols() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+
+        let input = ctx.set_reg_as_sym("rax", 32, Some("input"));
+        assert_eq!((input.name.as_str(), input.width), ("input", 32));
+        let rax = ctx.reg_read("rax");
+        assert_eq!(to_smtlib(rax, &ctx.solver), "((_ zero_extend 32) input)");
+
+        let byte = ctx.set_mem_as_sym(0x1000, 8, None);
+        assert_eq!(byte.name, "mem_4096_1");
+
+        let mut model = HashMap::new();
+        model.insert(input.var, 0x41);
+        assert_eq!(input.value(&model), Some(0x41));
+        assert_eq!(byte.value(&model), None);
+    }
+
+    #[test]
+    fn symbol_registry() {
+        let mut ctx = test_ctx(&[]);
+
+        let stdin = ctx.new_symbol("stdin[0..16]", 128);
+        assert_eq!(stdin.name, "stdin_0__16_");
+        let argv1 = ctx.set_mem_as_sym(0x1000, 64, Some("argv1"));
+
+        let fork = ctx.fork();
+        assert_eq!(fork.symbol("stdin[0..16]"), Some(&stdin));
+        assert_eq!(fork.var_named("argv1"), Some(argv1.var));
+        assert_eq!(fork.symbols().keys().collect::<Vec<_>>(), vec!["argv1", "stdin[0..16]"]);
+
+        let mut model = HashMap::new();
+        model.insert(argv1.var, 7);
+        let values = ctx.symbol_values(&model);
+        assert_eq!(values.get("argv1"), Some(&7));
+        assert_eq!(values.len(), 1);
+    }
+
+    #[test]
+    fn assume_conditions() {
+        use context::expr::{constant, mem, reg, sym};
+
+        let mut ctx = test_ctx(&[("rsi", 64)]);
+        ctx.set_reg_as_sym("rsi", 64, None);
+        ctx.set_mem_as_sym(0x1000, 8, Some("byte"));
+
+        let cond = ctx.eval_cond(&reg("rsi").ult(constant(0x100))).unwrap();
+        assert_eq!(to_smtlib(cond, &ctx.solver), "(bvult rsi (_ bv256 64))");
+
+        // The byte is extended to the width of the register.
+        let (sum, width) = ctx.eval_expr(&sym("byte").add(reg("rsi"))).unwrap();
+        assert_eq!(width, 64);
+        assert_eq!(to_smtlib(sum, &ctx.solver), "(bvadd ((_ zero_extend 56) byte) rsi)");
+
+        ctx.assume(&reg("rsi").ult(constant(0x100)).and(mem(0x1000, 8).ne(constant(0)))).unwrap();
+        assert!(ctx.assume(&reg("rdi").eq(constant(0))).is_err());
+        assert!(ctx.assume(&sym("argv1").eq(constant(0))).is_err());
+    }
+
+    #[test]
+    fn equivalent_states() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_reg_as_sym("rax", 64, None);
+        ctx.set_mem_as_const(0x1000, 0x41, 8);
+
+        let mut other = ctx.fork();
+        assert!(ctx.equivalent(&other));
+        assert!(ctx.semantically_equivalent(&other));
+
+        // rax + 0 holds the value of rax, but is a different expression.
+        let rax = other.reg_read("rax");
+        let zero = other.define_const(0, 64);
+        let sum = other.eval(bitvec::OpCodes::BvAdd, &[rax, zero]);
+        other.reg_write("rax", sum);
+        assert!(!ctx.equivalent(&other));
+        assert!(ctx.semantically_equivalent(&other));
+
+        other.set_mem_as_const(0x1000, 0x42, 8);
+        assert!(!ctx.semantically_equivalent(&other));
+        other.set_mem_as_const(0x1000, 0x41, 8);
+        other.set_ip(0x9004);
+        assert!(!ctx.semantically_equivalent(&other));
+    }
+
+    #[test]
+    fn hash_states() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_reg_as_sym("rax", 64, None);
+        ctx.set_mem_as_const(0x1000, 0x41, 8);
+
+        let mut other = ctx.fork();
+        assert_eq!(ctx.state_hash(), other.state_hash());
+        other.set_mem_as_const(0x1000, 0x42, 8);
+        assert!(ctx.state_hash() != other.state_hash());
+        let mut other = ctx.fork();
+        other.record_call(0x9000, 0xa000, 0x9005);
+        assert!(ctx.state_hash() != other.state_hash());
+        let mut other = ctx.fork();
+        other.set_ip(0x9004);
+        assert!(ctx.state_hash() != other.state_hash());
+    }
+
+    #[test]
+    fn seek_steps() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        assert!(ctx.seek(0).is_none());
+        ctx.start_history();
+
+        // Written before the first instruction, which makes a step of its own.
+        let one = ctx.define_const(1, 64);
+        ctx.reg_write("rax", one);
+        ctx.begin_step(0x9004);
+        let two = ctx.define_const(2, 64);
+        ctx.reg_write("rax", two);
+        let addr = ctx.define_const(0x1000, 64);
+        let byte = ctx.define_const(0x41, 8);
+        ctx.mem_write(addr, byte, 8);
+        let condition = ctx.fresh_value("c", 1);
+        ctx.record_branch(condition, condition, true);
+        ctx.begin_step(0x9008);
+        ctx.set_ip(0x9008);
+        assert_eq!(ctx.history().map(|h| h.len()), Some(3));
+
+        let start = ctx.seek(0).unwrap();
+        assert_eq!(start.ip(), 0x9000);
+        assert!(start.history().is_none());
+        let regs: Vec<_> = start.diff_registers(&ctx).into_iter().map(|r| r.name).collect();
+        assert_eq!(regs, vec!["rax".to_owned()]);
+        assert_eq!(start.diff_memory(&ctx).len(), 1);
+
+        let mut before = ctx.seek(1).unwrap();
+        assert_eq!(before.ip(), 0x9004);
+        let rax = before.reg_read("rax");
+        assert_eq!(before.as_constant(rax), Some(1));
+        assert!(before.path_constraints().is_empty());
+
+        let mut after = ctx.seek(2).unwrap();
+        let rax = after.reg_read("rax");
+        assert_eq!(after.as_constant(rax), Some(2));
+        assert_eq!(after.path_constraints().len(), 1);
+        assert!(after.diff_memory(&ctx).is_empty());
+        assert_eq!(ctx.seek(3).map(|c| c.ip()), Some(0x9008));
+        assert!(ctx.seek(4).is_none());
+    }
+
+    #[test]
+    fn merge_paths() {
+        let mut ctx = test_ctx(&[("rax", 64), ("rbx", 64)]);
+        let cond = ctx.set_mem_as_sym(0x1000, 64, None).var;
+        ctx.set_reg_as_const("rbx", 7);
+        ctx.set_mem_as_const(0x2000, 0x40, 8);
+        ctx.increment_ip(2);
+
+        // Both sides of a diamond, joining at 0x9010.
+        let mut other = ctx.fork();
+        let one = ctx.define_const(1, 64);
+        let taken = ctx.eval(core::OpCodes::Cmp, &[cond, one]);
+        ctx.record_branch(cond, taken, true);
+        let value = ctx.define_const(1, 64);
+        ctx.reg_write("rax", value);
+        ctx.set_mem_as_const(0x2000, 0x41, 8);
+        ctx.set_ip(0x9010);
+
+        let zero = other.define_const(0, 64);
+        let not_taken = other.eval(core::OpCodes::Cmp, &[cond, zero]);
+        other.record_branch(cond, not_taken, false);
+        let value = other.define_const(2, 64);
+        other.reg_write("rax", value);
+        other.set_mem_as_const(0x2001, 0x42, 8);
+
+        assert!(ctx.merge(&other).is_err());
+        other.set_ip(0x9010);
+        ctx.merge(&other).unwrap();
+
+        assert!(ctx.path_constraints().is_empty());
+        let rax = ctx.reg_read("rax");
+        assert!(to_smtlib(rax, &ctx.solver).starts_with("(ite"));
+        let rbx = ctx.reg_read("rbx");
+        assert_eq!(ctx.as_constant(rbx), Some(7));
+        let changed: Vec<u64> = ctx.diff_memory(&other).into_iter().map(|d| d.addr).collect();
+        assert_eq!(changed, vec![0x2000, 0x2001]);
+        let cells: HashMap<u64, NodeIndex> = ctx.mem.cells().into_iter().collect();
+        assert!(to_smtlib(cells[&0x2001], &ctx.solver).starts_with("(ite"));
+    }
+
+    #[test]
+    fn scopes() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        let input = ctx.set_reg_as_sym("rax", 64, None).var;
+        let start = ctx.define_const(0, 1);
+        assert!(!ctx.pop_scope());
+
+        assert_eq!(ctx.push_scope(), 1);
+        let one = ctx.define_const(1, 64);
+        let taken = ctx.eval(core::OpCodes::Cmp, &[input, one]);
+        ctx.record_branch(input, taken, true);
+        ctx.reg_write("rax", one);
+        ctx.set_ip(0x9010);
+        assert!(ctx.pop_scope());
+
+        assert_eq!((ctx.scope_depth(), ctx.ip()), (0, 0x9000));
+        assert!(ctx.path_constraints().is_empty());
+        assert_eq!(ctx.reg_read("rax"), input);
+        // The branch condition is no longer asserted on its own.
+        let end = ctx.define_const(0, 1);
+        let asserted = smtlib::assertions_between(&ctx.solver, start, end);
+        assert!(!asserted.contains(&taken));
+        assert_eq!(asserted.len(), 1);
+    }
+
+    #[test]
+    fn checkpoint_rollback() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        let input = ctx.set_reg_as_sym("rax", 64, None).var;
+        ctx.set_mem_as_const(0x1000, 0x41, 8);
+        let checkpoint = ctx.checkpoint();
+
+        let one = ctx.define_const(1, 64);
+        let taken = ctx.eval(core::OpCodes::Cmp, &[input, one]);
+        ctx.record_branch(input, taken, true);
+        ctx.reg_write("rax", one);
+        ctx.set_mem_as_const(0x1000, 0x42, 8);
+        ctx.set_ip(0x9010);
+        let start = checkpoint.start;
+        ctx.rollback(checkpoint);
+
+        assert_eq!(ctx.ip(), 0x9000);
+        assert_eq!(ctx.reg_read("rax"), input);
+        let addr = ctx.define_const(0x1000, 64);
+        let byte = ctx.mem_read(addr, 8);
+        assert_eq!(ctx.as_constant(byte), Some(0x41));
+        assert!(ctx.path_constraints().is_empty());
+        let end = ctx.define_const(0, 1);
+        assert!(!smtlib::assertions_between(&ctx.solver, start, end).contains(&taken));
+    }
+
+    #[test]
+    fn stats() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        let input = ctx.set_reg_as_sym("rax", 64, None).var;
+        ctx.increment_ip(2);
+        ctx.increment_ip(3);
+
+        let one = ctx.define_const(1, 64);
+        let taken = ctx.eval(core::OpCodes::Cmp, &[input, one]);
+        let fork = ctx.fork();
+        ctx.record_branch(input, taken, true);
+        let mut z3: z3::Z3 = Default::default();
+        ctx.solve(&mut z3);
+
+        let stats = *ctx.stats();
+        assert_eq!((stats.instructions, stats.branches, stats.solver_calls), (2, 1, 1));
+        assert_eq!(fork.stats().branches, 0);
+        let cost = ctx.solver_cost();
+        assert_eq!((cost.constraint_size, cost.solver_calls), (3, 1));
+        assert_eq!(fork.solver_cost(), SolverCost::default());
+    }
+
+    #[test]
+    fn cache_sat() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        let input = ctx.set_reg_as_sym("rax", 64, None).var;
+        let one = ctx.define_const(1, 64);
+        let two = ctx.define_const(2, 64);
+        let is_one = ctx.eval(core::OpCodes::Cmp, &[input, one]);
+        let is_two = ctx.eval(core::OpCodes::Cmp, &[input, two]);
+
+        // The fork asks the same question as its parent, and shares the answer.
+        let sat = ctx.is_feasible(is_one);
+        assert_eq!(ctx.is_feasible(is_one), sat);
+        let mut fork = ctx.fork();
+        assert_eq!(fork.is_feasible(is_one), sat);
+        assert_eq!((ctx.sat_cache().misses(), ctx.sat_cache().hits()), (1, 2));
+        assert_eq!((ctx.stats().solver_calls, fork.stats().solver_calls), (1, 1));
+
+        fork.is_feasible(is_two);
+        assert_eq!((ctx.sat_cache().misses(), ctx.sat_cache().len()), (2, 2));
+        ctx.sat_cache().clear();
+        assert!(fork.sat_cache().is_empty());
+    }
+
+    #[test]
+    fn symbolic_input() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+
+        let argv = ctx.symbolize_argv(&["prog", "SYM:4"]).unwrap();
+        assert_eq!((argv[0].len(), argv[1].len()), (0, 4));
+        assert_eq!(ctx.var_named("argv1[3]"), Some(argv[1][3]));
+        assert!(ctx.symbolize_argv(&["prog", "SYM:x"]).is_err());
+
+        let stdin = ctx.symbolize_stdin(3);
+        let (fd, buf, count) = (ctx.define_const(0, 64), ctx.define_addr(0x2000), ctx.define_const(2, 64));
+        let n = ctx.read(fd, buf, count);
+        assert_eq!(ctx.as_constant(n), Some(2));
+        let addr = ctx.define_addr(0x2001);
+        let byte = ctx.mem_read(addr, 8);
+        assert_eq!(to_smtlib(byte, &ctx.solver), to_smtlib(stdin[1], &ctx.solver));
+        let n = ctx.read(fd, buf, count);
+        assert_eq!(ctx.as_constant(n), Some(1));
+
+        let closed = ctx.define_const(4, 64);
+        let n = ctx.read(closed, buf, count);
+        assert_eq!(ctx.as_constant(n), Some(u64::max_value()));
+    }
+
+    #[test]
+    fn environment() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_env("HOME", "/root");
+        ctx.set_env_symbolic("KEY", 2);
+        let mut heap_ctx = ctx.fork();
+        ctx.setup_stack(&StackSetup::with_args(vec!["prog".to_owned()], Vec::new()));
+
+        let mut getenv = |ctx: &mut RuneContext<PagedMemory, RuneRegFile>, name: &str| {
+            let mut bytes = name.as_bytes().to_vec();
+            bytes.push(0);
+            ctx.mem.write_bytes(0x1000, &bytes, &mut ctx.solver);
+            let name = ctx.define_addr(0x1000);
+            let value = ctx.getenv(name);
+            ctx.as_constant(value).unwrap()
+        };
+
+        // The value follows "KEY=" in the strings of envp.
+        let key = getenv(&mut ctx, "KEY");
+        let addr = ctx.define_addr(key - 4);
+        let byte = ctx.mem_read(addr, 8);
+        assert_eq!(ctx.as_constant(byte), Some(b'K' as u64));
+        let addr = ctx.define_addr(key + 1);
+        let byte = ctx.mem_read(addr, 8);
+        assert_eq!(Some(byte), ctx.var_named("env_KEY[1]"));
+        assert_eq!(getenv(&mut ctx, "NOPE"), 0);
+
+        // Without a stack, values are copied to the heap.
+        let home = getenv(&mut heap_ctx, "HOME");
+        assert!(home != 0);
+        let addr = heap_ctx.define_addr(home);
+        let byte = heap_ctx.mem_read(addr, 8);
+        assert_eq!(heap_ctx.as_constant(byte), Some(b'/' as u64));
+        assert_eq!(heap_ctx.env().get("HOME").and_then(|var| var.addr), Some(home));
+    }
+
+    /// Context with the bytes 1, 2, 3, 4 at 0x1000 and `rax` a symbolic address of one of them.
+    #[cfg(test)]
+    fn ranged_addr_ctx() -> (RuneContext<PagedMemory, RuneRegFile>, NodeIndex) {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.mem.write_bytes(0x1000, &[1, 2, 3, 4], &mut ctx.solver);
+        let addr = ctx.set_reg_as_sym("rax", 64, None).var;
+        let low = ctx.define_const(0x1000, 64);
+        let high = ctx.define_const(0x1003, 64);
+        ctx.solver.assert(bitvec::OpCodes::BvUGe, &[addr, low]);
+        ctx.solver.assert(bitvec::OpCodes::BvULe, &[addr, high]);
+        (ctx, addr)
+    }
+
+    #[test]
+    fn concretization_strategies() {
+        let strategies = [(ConcretizationStrategy::Min, vec![0x1000]),
+                          (ConcretizationStrategy::Max, vec![0x1003]),
+                          (ConcretizationStrategy::Samples(2), vec![0x1000, 0x1003]),
+                          (ConcretizationStrategy::Samples(8), vec![0x1000, 0x1003])];
+        for &(strategy, ref expected) in &strategies {
+            let (mut ctx, addr) = ranged_addr_ctx();
+            ctx.set_concretization(strategy);
+            let mut targets: Vec<u64> = ctx.concretize_addr(addr)
+                                           .into_iter()
+                                           .filter_map(|t| ctx.as_constant(t))
+                                           .collect();
+            targets.sort();
+            assert_eq!(ctx.take_concretization_error(), None);
+            match strategy {
+                ConcretizationStrategy::Samples(n) => {
+                    // Any of the feasible addresses, which `expected` bounds.
+                    assert_eq!(targets.len(), n.min(4));
+                    assert!(targets.iter().all(|t| *t >= expected[0] && *t <= expected[1]));
+                }
+                _ => assert_eq!(&targets, expected),
+            }
+        }
+
+        // The value read is that of the concretized address.
+        let (mut ctx, addr) = ranged_addr_ctx();
+        ctx.set_concretization(ConcretizationStrategy::Max);
+        let value = ctx.mem_read(addr, 8);
+        assert_eq!(ctx.as_constant(value), Some(4));
+    }
+
+    #[test]
+    fn concretization_errors() {
+        let (mut ctx, addr) = ranged_addr_ctx();
+        ctx.set_concretization(ConcretizationStrategy::Fail);
+        let data = ctx.define_const(0x41, 8);
+        ctx.mem_write(addr, data, 8);
+        assert_eq!(ctx.take_concretization_error(), Some(ConcretizationError::Refused));
+        // The write was not carried out.
+        let first = ctx.define_const(0x1000, 64);
+        let byte = ctx.mem_read(first, 8);
+        assert_eq!(ctx.as_constant(byte), Some(1));
+        assert_eq!(ctx.take_concretization_error(), None);
+
+        // No address satisfies the path.
+        let (mut ctx, addr) = ranged_addr_ctx();
+        let beyond = ctx.define_const(0x2000, 64);
+        ctx.solver.assert(bitvec::OpCodes::BvUGe, &[addr, beyond]);
+        ctx.set_concretization(ConcretizationStrategy::Samples(4));
+        ctx.mem_read(addr, 8);
+        assert_eq!(ctx.take_concretization_error(), Some(ConcretizationError::Infeasible));
+        let size = ctx.reg_read("rax");
+        ctx.malloc(size);
+        assert_eq!(ctx.take_concretization_error(), Some(ConcretizationError::Infeasible));
+    }
+
+    #[test]
+    fn watch_symbolic_addresses() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_concretization(ConcretizationStrategy::Symbolic);
+        ctx.add_watchpoint(Watchpoint::new(0x1000, 0x1008, false, true));
+        ctx.add_watchpoint(Watchpoint::new(0x2000, 0x2008, true, false));
+        let addr = ctx.set_reg_as_sym("rax", 64, None).var;
+
+        let data = ctx.define_const(0x41, 8);
+        ctx.write_at(addr, data, 8);
+        let hits = ctx.take_watch_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].addr, hits[0].symbolic_addr, hits[0].value),
+                   (0x1000, Some(addr), data));
+
+        // Concrete accesses only hit the watchpoints they touch.
+        let addr = ctx.define_const(0x2004, 64);
+        ctx.read_at(addr, 32);
+        let hits = ctx.take_watch_hits();
+        assert_eq!(hits.iter().map(|h| (h.addr, h.symbolic_addr)).collect::<Vec<_>>(),
+                   vec![(0x2004, None)]);
+    }
+
+    #[test]
+    fn watch_exprs() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_reg_as_const("rax", 1);
+        ctx.mem.write_bytes(0x1000, b"A", &mut ctx.solver);
+        ctx.add_watchpoint(Watchpoint::access(0x1000, 0x1001));
+
+        assert!(ctx.add_watch_expr("rbx").is_err());
+        ctx.add_watch_expr("rax + 1").unwrap();
+        ctx.add_watch_expr("[0x1000]:8").unwrap();
+
+        let values = ctx.eval_watch_exprs(0x9000);
+        assert_eq!(values.iter().map(|v| v.constant).collect::<Vec<_>>(), vec![Some(2), Some(0x41)]);
+        // Evaluating the expressions does not count as an access.
+        assert!(ctx.take_watch_hits().is_empty());
+
+        let two = ctx.define_const(2, 64);
+        ctx.reg_write("rax", two);
+        ctx.eval_watch_exprs(0x9004);
+        ctx.eval_watch_exprs(0x9008);
+        let log: Vec<(&str, u64)> = ctx.watch_log().iter().map(|v| (v.expr.as_str(), v.ip)).collect();
+        assert_eq!(log, vec![("rax + 1", 0x9000), ("[0x1000]:8", 0x9000), ("rax + 1", 0x9004)]);
+
+        assert!(ctx.remove_watch_expr("rax + 1"));
+        assert_eq!(ctx.eval_watch_exprs(0x900c).len(), 1);
     }
 }
@@ -0,0 +1,64 @@
+//! Cache of the satisfiability of solver queries, see `RuneContext::sat_cache`.
+//!
+//! Sibling states ask the solver the same questions: both sides of a branch are checked with
+//! the constraints they share, and paths that join again check the same conditions. Queries
+//! are keyed by their SMT-LIB2 script, which the solver would be sent, rather than a hash of it,
+//! so that two queries never share an answer by accident. A context shares its cache with its
+//! forks.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use libsmt::backends::backend::SMTBackend;
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::backends::z3;
+use libsmt::logics::qf_abv;
+
+use context::stats::Stats;
+
+#[derive(Debug, Default)]
+pub struct SatCache {
+    results: RefCell<HashMap<String, bool>>,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+}
+
+impl SatCache {
+    /// Whether the constraints of `solver` are satisfiable. The solver is only asked, and the
+    /// query counted in `stats`, if the cache does not know the answer.
+    pub fn is_sat(&self, solver: &mut SMTLib2<qf_abv::QF_ABV>, stats: &mut Stats) -> bool {
+        let key = solver.generate_asserts();
+        if let Some(&sat) = self.results.borrow().get(&key) {
+            self.hits.set(self.hits.get() + 1);
+            return sat;
+        }
+        let mut z3: z3::Z3 = Default::default();
+        let sat = stats.solve(solver, &mut z3).is_ok();
+        self.results.borrow_mut().insert(key, sat);
+        self.misses.set(self.misses.get() + 1);
+        sat
+    }
+
+    /// Queries answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+
+    /// Queries sent to the solver.
+    pub fn misses(&self) -> u64 {
+        self.misses.get()
+    }
+
+    /// Queries whose answer is cached.
+    pub fn len(&self) -> usize {
+        self.results.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.borrow().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.results.borrow_mut().clear();
+    }
+}
@@ -0,0 +1,138 @@
+//! Initial stack of a process.
+//!
+//! `StackSetup` describes the stack a context starts with. When program arguments are given,
+//! the stack is laid out as the kernel does it before jumping to the entry point: `argc` at the
+//! stack pointer, followed by the NULL terminated `argv` and `envp` vectors, with the strings
+//! themselves stored at the top of the stack.
+
+/// Default address of the top (highest address) of the stack.
+pub const STACK_TOP: u64 = 0x7fff_0000;
+
+/// Default size of the stack, in bytes.
+pub const STACK_SIZE: u64 = 0x10_0000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackSetup {
+    /// Address one past the highest byte of the stack.
+    pub top: u64,
+    pub size: u64,
+    /// Program arguments, including the program name. If `None`, only the stack pointer is set
+    /// up.
+    pub args: Option<Vec<String>>,
+    /// Environment variables, as `NAME=value` strings.
+    pub env: Vec<String>,
+}
+
+impl Default for StackSetup {
+    fn default() -> StackSetup {
+        StackSetup {
+            top: STACK_TOP,
+            size: STACK_SIZE,
+            args: None,
+            env: Vec::new(),
+        }
+    }
+}
+
+/// Addresses and contents of the initial stack, as computed by `StackSetup::layout`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackLayout {
+    /// Initial value of the stack pointer.
+    pub sp: u64,
+    pub argc: u64,
+    /// Address of the `argv` vector.
+    pub argv: u64,
+    /// Address of the `envp` vector.
+    pub envp: u64,
+    /// NUL terminated strings, by address.
+    pub strings: Vec<(u64, Vec<u8>)>,
+    /// Pointer sized words, by address.
+    pub words: Vec<(u64, u64)>,
+}
+
+impl StackSetup {
+    pub fn with_args(args: Vec<String>, env: Vec<String>) -> StackSetup {
+        StackSetup {
+            args: Some(args),
+            env: env,
+            ..Default::default()
+        }
+    }
+
+    /// Lowest address of the stack.
+    pub fn bottom(&self) -> u64 {
+        self.top - self.size
+    }
+
+    /// Lay out the stack for pointers of `ptr_size` bytes.
+    pub fn layout(&self, ptr_size: u64) -> StackLayout {
+        let mut sp = self.top;
+        let args = match self.args {
+            Some(ref args) => args,
+            None => {
+                return StackLayout {
+                    sp: sp,
+                    ..Default::default()
+                };
+            }
+        };
+
+        let mut strings = Vec::new();
+        let mut push_str = |s: &String| {
+            let mut bytes = s.clone().into_bytes();
+            bytes.push(0);
+            sp -= bytes.len() as u64;
+            strings.push((sp, bytes));
+            sp
+        };
+        let argv: Vec<u64> = args.iter().map(&mut push_str).collect();
+        let envp: Vec<u64> = self.env.iter().map(&mut push_str).collect();
+
+        // argc, argv[0..argc], NULL, envp[..], NULL
+        let mut vector = vec![argv.len() as u64];
+        vector.extend(argv.iter().cloned());
+        vector.push(0);
+        vector.extend(envp.iter().cloned());
+        vector.push(0);
+
+        let sp = (sp - vector.len() as u64 * ptr_size) & !0xf;
+        let words = vector.into_iter()
+                          .enumerate()
+                          .map(|(i, w)| (sp + i as u64 * ptr_size, w))
+                          .collect();
+
+        StackLayout {
+            sp: sp,
+            argc: argv.len() as u64,
+            argv: sp + ptr_size,
+            envp: sp + (argv.len() as u64 + 2) * ptr_size,
+            strings: strings,
+            words: words,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout_args_and_env() {
+        let setup = StackSetup::with_args(vec!["a.out".to_owned(), "x".to_owned()],
+                                          vec!["A=1".to_owned()]);
+        let layout = setup.layout(8);
+
+        assert_eq!(layout.sp % 16, 0);
+        assert_eq!(layout.argc, 2);
+        assert_eq!(layout.words[0], (layout.sp, 2));
+        assert_eq!(layout.argv, layout.sp + 8);
+        assert_eq!(layout.envp, layout.sp + 8 * 4);
+
+        // argv[0] points to "a.out\0" at the very top of the stack.
+        assert_eq!(layout.strings[0], (STACK_TOP - 6, b"a.out\0".to_vec()));
+        assert_eq!(layout.words[1], (layout.argv, STACK_TOP - 6));
+        // NULL after argv and envp.
+        assert_eq!(layout.words[3], (layout.sp + 24, 0));
+        assert_eq!(layout.words[5], (layout.sp + 40, 0));
+    }
+}
@@ -0,0 +1,47 @@
+//! Execution statistics of a context, see `RuneContext::stats`.
+//!
+//! The statistics describe the path that led to a context: a fork starts with the statistics
+//! of the context it was forked from.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libsmt::backends::backend::{SMTBackend, SMTResult};
+use libsmt::backends::smtlib2::{SMTLib2, SMTProc};
+use libsmt::logics::qf_abv;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    pub instructions: u64,
+    /// Branches taken, in either direction.
+    pub branches: u64,
+    /// Queries sent to the solver, including the ones made to concretize values.
+    pub solver_calls: u64,
+    /// Time spent waiting for the solver.
+    pub solver_time: Duration,
+}
+
+/// What the path of a context costs the solver, see `Context::solver_cost`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SolverCost {
+    /// Nodes of the expressions of the path constraints.
+    pub constraint_size: usize,
+    pub solver_calls: u64,
+    pub solver_time: Duration,
+}
+
+impl Stats {
+    /// Solve the constraints of `solver` with `p`, and count the query.
+    pub fn solve<S: SMTProc>(&mut self,
+                             solver: &mut SMTLib2<qf_abv::QF_ABV>,
+                             p: &mut S)
+                             -> SMTResult<HashMap<NodeIndex, u64>> {
+        let start = Instant::now();
+        let result = solver.solve(p);
+        self.solver_calls += 1;
+        self.solver_time += start.elapsed();
+        result
+    }
+}
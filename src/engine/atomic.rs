@@ -0,0 +1,445 @@
+//! Atomic instructions (`lock cmpxchg`, `xadd`, `ldrex`/`strex`, `cas`, `ldadd`, ...).
+//!
+//! r2 gives many of these instructions no ESIL, or ESIL that rune does not support, which
+//! stops the reference counting and locking code of real programs. A path runs on a single
+//! thread, so the engine executes them with their sequential semantics instead: the exclusive
+//! store of a load-exclusive/store-exclusive pair always succeeds, and the `lock` prefix does
+//! not change anything. Instructions whose ESIL is supported are evaluated as usual.
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+
+use context::context::{Context, RegisterRead};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtomicOp {
+    /// x86 `cmpxchg`: memory takes the source if it equals the accumulator, which takes the
+    /// value of memory otherwise.
+    CmpXchg,
+    /// x86 `xadd`: memory takes the sum, the source takes the old value of memory.
+    XAdd,
+    /// `ldrex`, `ldxr`, `ldaxr`: a load of the first register.
+    LoadExclusive,
+    /// `strex`, `stxr`, `stlxr`: a store of the second register, which sets the status in the
+    /// first one to success.
+    StoreExclusive,
+    /// AArch64 `cas`: memory takes the second register if it equals the first one, which takes
+    /// the old value of memory.
+    CompareSwap,
+    /// AArch64 `ldadd`: memory takes the sum with the first register, the second register takes
+    /// the old value of memory.
+    FetchAdd,
+}
+
+/// The memory operand `[base + index * scale + disp]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemOperand {
+    pub base: Option<String>,
+    pub index: Option<(String, u64)>,
+    /// Displacement, two's complement if negative.
+    pub disp: u64,
+}
+
+/// A number of the disassembly, such as `0x10`, `#-4` or `8`.
+fn parse_num(text: &str) -> Option<u64> {
+    let text = text.trim_left_matches('#');
+    let (negative, text) = match text.starts_with('-') {
+        true => (true, &text[1..]),
+        false => (false, text),
+    };
+    let value = if text.starts_with("0x") {
+        u64::from_str_radix(&text[2..], 16).ok()?
+    } else {
+        text.parse().ok()?
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+impl MemOperand {
+    /// The memory operand `text`, in brackets as disassembled for x86 or ARM.
+    pub fn parse(text: &str) -> Option<MemOperand> {
+        let text = text.trim();
+        if !text.starts_with('[') || !text.ends_with(']') {
+            return None;
+        }
+        // The offset of ARM operands is separated by a comma.
+        let inner = text[1..text.len() - 1].replace(',', " + ");
+        let mut mem = MemOperand::default();
+        let mut negative = false;
+        for term in inner.split_whitespace() {
+            match term {
+                "+" => continue,
+                "-" => {
+                    negative = true;
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(star) = term.find('*') {
+                if negative || mem.index.is_some() {
+                    return None;
+                }
+                let scale = parse_num(&term[star + 1..])?;
+                mem.index = Some((term[..star].to_owned(), scale));
+            } else if let Some(n) = parse_num(term) {
+                let n = if negative { n.wrapping_neg() } else { n };
+                mem.disp = mem.disp.wrapping_add(n);
+            } else if negative {
+                return None;
+            } else if mem.base.is_none() {
+                mem.base = Some(term.to_owned());
+            } else if mem.index.is_none() {
+                mem.index = Some((term.to_owned(), 1));
+            } else {
+                return None;
+            }
+            negative = false;
+        }
+        Some(mem)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AtomicInsn {
+    pub op: AtomicOp,
+    /// Width of the access to memory in bits.
+    pub size: usize,
+    /// Registers of the instruction, in the order of the disassembly.
+    pub regs: Vec<String>,
+    pub mem: MemOperand,
+}
+
+/// The operands of `text`, split at the commas that are not in brackets.
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(text[start..].trim());
+    operands
+}
+
+fn parse_x86(mnemonic: &str, operands: &[&str]) -> Option<AtomicInsn> {
+    let op = match mnemonic {
+        "cmpxchg" => AtomicOp::CmpXchg,
+        "xadd" => AtomicOp::XAdd,
+        _ => return None,
+    };
+    if operands.len() != 2 {
+        return None;
+    }
+    // Only the forms with a memory destination, `dword [rdi + 8]`. A segment is not supported.
+    let open = operands[0].find('[')?;
+    let prefix = &operands[0][..open];
+    if prefix.contains(':') {
+        return None;
+    }
+    let size = match prefix.split_whitespace().next()? {
+        "byte" => 8,
+        "word" => 16,
+        "dword" => 32,
+        "qword" => 64,
+        _ => return None,
+    };
+    Some(AtomicInsn {
+        op: op,
+        size: size,
+        regs: vec![operands[1].to_owned()],
+        mem: MemOperand::parse(&operands[0][open..])?,
+    })
+}
+
+fn parse_arm(mnemonic: &str, operands: &[&str]) -> Option<AtomicInsn> {
+    let (base, size) = if mnemonic.ends_with('b') {
+        (&mnemonic[..mnemonic.len() - 1], Some(8))
+    } else if mnemonic.ends_with('h') {
+        (&mnemonic[..mnemonic.len() - 1], Some(16))
+    } else {
+        (mnemonic, None)
+    };
+    let (op, count) = match base {
+        "ldrex" | "ldxr" | "ldaxr" => (AtomicOp::LoadExclusive, 1),
+        "strex" | "stxr" | "stlxr" => (AtomicOp::StoreExclusive, 2),
+        "cas" | "casa" | "casl" | "casal" => (AtomicOp::CompareSwap, 2),
+        "ldadd" | "ldadda" | "ldaddl" | "ldaddal" => (AtomicOp::FetchAdd, 2),
+        _ => return None,
+    };
+    if operands.len() != count + 1 {
+        return None;
+    }
+    let regs: Vec<String> = operands[..count].iter().map(|r| (*r).to_owned()).collect();
+    // The width of the register that is loaded or stored, `w` registers of AArch64 are 32 bit.
+    let data = if op == AtomicOp::StoreExclusive { &regs[1] } else { &regs[0] };
+    let size = size.unwrap_or(if data.starts_with('x') { 64 } else { 32 });
+    Some(AtomicInsn {
+        op: op,
+        size: size,
+        regs: regs,
+        mem: MemOperand::parse(operands[count])?,
+    })
+}
+
+impl AtomicInsn {
+    /// The atomic instruction of the disassembly `opcode` on the architecture `arch`, as named
+    /// by `Arch::name`, if it is one.
+    pub fn parse(opcode: &str, arch: Option<&str>) -> Option<AtomicInsn> {
+        let opcode = opcode.trim();
+        let opcode = if opcode.starts_with("lock ") { opcode[5..].trim_left() } else { opcode };
+        let space = opcode.find(' ')?;
+        let (mnemonic, operands) = (&opcode[..space], split_operands(&opcode[space + 1..]));
+        match arch {
+            Some("x86") => parse_x86(mnemonic, &operands),
+            Some("arm") => parse_arm(mnemonic, &operands),
+            None => parse_x86(mnemonic, &operands).or_else(|| parse_arm(mnemonic, &operands)),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Value of the register `reg` in an address. The instruction pointer has already been advanced
+/// past the instruction, which is what `rip` reads as.
+fn reg_value<Ctx>(ctx: &mut Ctx, reg: &str) -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if ctx.is_pc(reg) {
+        let (ip, width) = (ctx.ip(), ctx.address_width());
+        ctx.define_const(ip, width)
+    } else {
+        ctx.reg_read(reg)
+    }
+}
+
+fn address<Ctx>(ctx: &mut Ctx, mem: &MemOperand) -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let mut addr = ctx.define_const(mem.disp, width);
+    if let Some(ref base) = mem.base {
+        let base = reg_value(ctx, base);
+        addr = ctx.eval(bitvec::OpCodes::BvAdd, vec![base, addr]);
+    }
+    if let Some((ref index, scale)) = mem.index {
+        let index = reg_value(ctx, index);
+        let offset = match ctx.as_constant(index.clone()) {
+            Some(i) => ctx.define_const(i.wrapping_mul(scale), width),
+            None => {
+                let scale = ctx.define_const(scale, width);
+                ctx.eval(bitvec::OpCodes::BvMul, vec![index, scale])
+            }
+        };
+        addr = ctx.eval(bitvec::OpCodes::BvAdd, vec![addr, offset]);
+    }
+    addr
+}
+
+/// The low `size` bits of the register `reg`.
+fn read<Ctx>(ctx: &mut Ctx, reg: &str, size: usize) -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let value = ctx.reg_read(reg);
+    if size < ctx.address_width() {
+        ctx.eval(bitvec::OpCodes::Extract(size as u64 - 1, 0), vec![value])
+    } else {
+        value
+    }
+}
+
+/// Write the `size` bit `value` to the register `reg`, zero extended as values computed by the
+/// engine are.
+fn write<Ctx>(ctx: &mut Ctx, reg: &str, value: <Ctx as RegisterRead>::VarRef, size: usize)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let value = if size < width {
+        ctx.eval(bitvec::OpCodes::ZeroExtend((width - size) as u64), vec![value])
+    } else {
+        value
+    };
+    ctx.reg_write(reg, value);
+}
+
+fn accumulator(size: usize) -> &'static str {
+    match size {
+        8 => "al",
+        16 => "ax",
+        32 => "eax",
+        _ => "rax",
+    }
+}
+
+/// `then` if `a` equals `b`, `otherwise` if not, with the 1 bit result of the comparison.
+fn select_equal<Ctx>(ctx: &mut Ctx,
+                     a: <Ctx as RegisterRead>::VarRef,
+                     b: <Ctx as RegisterRead>::VarRef,
+                     then: <Ctx as RegisterRead>::VarRef,
+                     otherwise: <Ctx as RegisterRead>::VarRef)
+                     -> (<Ctx as RegisterRead>::VarRef, <Ctx as RegisterRead>::VarRef)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    match (ctx.as_constant(a.clone()), ctx.as_constant(b.clone())) {
+        (Some(x), Some(y)) => {
+            let bit = ctx.define_const((x == y) as u64, 1);
+            (if x == y { then } else { otherwise }, bit)
+        }
+        _ => {
+            let equal = ctx.eval(core::OpCodes::Cmp, vec![a, b]);
+            let value = ctx.eval(core::OpCodes::ITE, vec![equal.clone(), then, otherwise]);
+            let one = ctx.define_const(1, 1);
+            let zero = ctx.define_const(0, 1);
+            (value, ctx.eval(core::OpCodes::ITE, vec![equal, one, zero]))
+        }
+    }
+}
+
+/// Set the zero flag of x86 to the 1 bit `zf`.
+fn set_zf<Ctx>(ctx: &mut Ctx, zf: <Ctx as RegisterRead>::VarRef)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if ctx.has_reg("zf") {
+        ctx.reg_write("zf", zf);
+    }
+}
+
+/// Execute the atomic instruction `insn`. Of the flags, only the zero flag is set.
+pub fn execute<Ctx>(ctx: &mut Ctx, insn: &AtomicInsn)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let size = insn.size;
+    let addr = address(ctx, &insn.mem);
+    let old = ctx.mem_read(addr.clone(), size);
+    match insn.op {
+        AtomicOp::CmpXchg => {
+            let acc = accumulator(size);
+            let expected = read(ctx, acc, size);
+            let src = read(ctx, &insn.regs[0], size);
+            let (new, zf) = select_equal(ctx, old.clone(), expected.clone(), src, old.clone());
+            ctx.mem_write(addr, new, size);
+            let (acc_value, _) = select_equal(ctx, old.clone(), expected.clone(), expected, old);
+            write(ctx, acc, acc_value, size);
+            set_zf(ctx, zf);
+        }
+        AtomicOp::XAdd => {
+            let src = read(ctx, &insn.regs[0], size);
+            let sum = ctx.eval(bitvec::OpCodes::BvAdd, vec![old.clone(), src]);
+            ctx.mem_write(addr, sum.clone(), size);
+            write(ctx, &insn.regs[0], old, size);
+            let zero = ctx.define_const(0, size);
+            let one = ctx.define_const(1, 1);
+            let clear = ctx.define_const(0, 1);
+            let (zf, _) = select_equal(ctx, sum, zero, one, clear);
+            set_zf(ctx, zf);
+        }
+        AtomicOp::LoadExclusive => write(ctx, &insn.regs[0], old, size),
+        AtomicOp::StoreExclusive => {
+            let value = read(ctx, &insn.regs[1], size);
+            ctx.mem_write(addr, value, size);
+            let width = ctx.address_width();
+            let success = ctx.define_const(0, width);
+            ctx.reg_write(&insn.regs[0], success);
+        }
+        AtomicOp::CompareSwap => {
+            let expected = read(ctx, &insn.regs[0], size);
+            let src = read(ctx, &insn.regs[1], size);
+            let (new, _) = select_equal(ctx, old.clone(), expected, src, old.clone());
+            ctx.mem_write(addr, new, size);
+            write(ctx, &insn.regs[0], old, size);
+        }
+        AtomicOp::FetchAdd => {
+            let value = read(ctx, &insn.regs[0], size);
+            let sum = ctx.eval(bitvec::OpCodes::BvAdd, vec![old.clone(), value]);
+            ctx.mem_write(addr, sum, size);
+            write(ctx, &insn.regs[1], old, size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+
+    use context::context::{ContextAPI, MemoryRead, RegisterWrite};
+    use context::rune_ctx::{RuneContext, test_ctx};
+
+    fn mem(base: Option<&str>, index: Option<(&str, u64)>, disp: u64) -> MemOperand {
+        MemOperand {
+            base: base.map(|b| b.to_owned()),
+            index: index.map(|(i, s)| (i.to_owned(), s)),
+            disp: disp,
+        }
+    }
+
+    #[test]
+    fn parse_atomics() {
+        let insn = AtomicInsn::parse("lock cmpxchg dword [rdi + rcx*4 - 8], esi", Some("x86"))
+                       .unwrap();
+        assert_eq!((insn.op, insn.size), (AtomicOp::CmpXchg, 32));
+        assert_eq!(insn.regs, vec!["esi".to_owned()]);
+        assert_eq!(insn.mem, mem(Some("rdi"), Some(("rcx", 4)), 8u64.wrapping_neg()));
+        let insn = AtomicInsn::parse("lock xadd qword [rip + 0x200e5a], rax", None).unwrap();
+        assert_eq!((insn.op, insn.size), (AtomicOp::XAdd, 64));
+        assert_eq!(insn.mem, mem(Some("rip"), None, 0x200e5a));
+        assert!(AtomicInsn::parse("cmpxchg ecx, edx", Some("x86")).is_none());
+        assert!(AtomicInsn::parse("lock xadd dword fs:[rax], ecx", Some("x86")).is_none());
+
+        let insn = AtomicInsn::parse("strexb r2, r1, [r0, #4]", Some("arm")).unwrap();
+        assert_eq!((insn.op, insn.size), (AtomicOp::StoreExclusive, 8));
+        assert_eq!(insn.regs, vec!["r2".to_owned(), "r1".to_owned()]);
+        assert_eq!(insn.mem, mem(Some("r0"), None, 4));
+        let insn = AtomicInsn::parse("ldaxr x1, [x0]", Some("arm")).unwrap();
+        assert_eq!((insn.op, insn.size), (AtomicOp::LoadExclusive, 64));
+        let insn = AtomicInsn::parse("ldaddal w1, w0, [x2]", Some("arm")).unwrap();
+        assert_eq!((insn.op, insn.size), (AtomicOp::FetchAdd, 32));
+        assert!(AtomicInsn::parse("ldrexd r0, r1, [r2]", Some("arm")).is_none());
+        assert!(AtomicInsn::parse("ldaxr x1, [x0]", Some("x86")).is_none());
+    }
+
+    #[test]
+    fn execute_atomics() {
+        let mut ctx = test_ctx(&[("rax", 64), ("rcx", 64), ("rdi", 64)]);
+        ctx.set_ip(0x1000);
+        ctx.set_reg_as_const("rdi", 0x8000);
+        ctx.set_mem_as_const(0x8008, 1, 64);
+
+        // A reference count is incremented...
+        let set = |ctx: &mut RuneContext<_, _>, reg: &str, value| {
+            let value = ctx.define_const(value, 64);
+            ctx.reg_write(reg, value);
+        };
+        set(&mut ctx, "rcx", 1);
+        let xadd = AtomicInsn::parse("lock xadd qword [rdi + 8], rcx", None).unwrap();
+        execute(&mut ctx, &xadd);
+        let read = |ctx: &mut RuneContext<_, _>, reg: &str| {
+            let value = ctx.reg_read(reg);
+            ctx.as_constant(value)
+        };
+        assert_eq!(read(&mut ctx, "rcx"), Some(1));
+        let addr = ctx.define_const(0x8008, 64);
+        let count = ctx.mem_read(addr, 64);
+        assert_eq!(ctx.as_constant(count), Some(2));
+
+        // ...and swapped only if it has the expected value.
+        set(&mut ctx, "rax", 2);
+        set(&mut ctx, "rcx", 7);
+        let cmpxchg = AtomicInsn::parse("lock cmpxchg qword [rdi + 8], rcx", None).unwrap();
+        execute(&mut ctx, &cmpxchg);
+        let count = ctx.mem_read(addr, 64);
+        assert_eq!(ctx.as_constant(count), Some(7));
+        execute(&mut ctx, &cmpxchg);
+        assert_eq!(read(&mut ctx, "rax"), Some(7));
+        set(&mut ctx, "rax", 3);
+        execute(&mut ctx, &cmpxchg);
+        assert_eq!(read(&mut ctx, "rax"), Some(7));
+        let count = ctx.mem_read(addr, 64);
+        assert_eq!(ctx.as_constant(count), Some(7));
+    }
+}
@@ -0,0 +1,111 @@
+//! Inputs that drive execution down either side of a branch, see `Rune::branch_inputs`.
+//!
+//! Where a path reaches the branch, its condition is assumed both ways on copies of the state,
+//! and the solver gives the values of the symbolic inputs (see `RuneContext::symbols`) that lead
+//! there and then down that side. A side that is infeasible on one path may be feasible on
+//! another, so paths are explored until both sides have an input or none are left. Contexts that
+//! can not solve for inputs find none (see `Context::solve_inputs`).
+
+use std::collections::BTreeMap;
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::core;
+
+use context::context::{Context, RegisterRead};
+
+/// Values of the symbolic inputs, by name.
+pub type Input = BTreeMap<String, u64>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BranchInputs {
+    pub taken: Option<Input>,
+    pub not_taken: Option<Input>,
+}
+
+/// `input` as the console prints it: the values by name, or `infeasible` if there is none.
+pub fn show(input: Option<&Input>) -> String {
+    match input {
+        Some(input) => {
+            let values: Vec<String> =
+                input.iter().map(|(name, v)| format!("{} = {:#x}", name, v)).collect();
+            values.join(", ")
+        }
+        None => "infeasible".to_owned(),
+    }
+}
+
+/// Input that takes the path of `ctx` to the side `taken` of the branch `condition`.
+fn solve_side<Ctx>(ctx: &Ctx,
+                   condition: <Ctx as RegisterRead>::VarRef,
+                   taken: bool)
+                   -> Option<Input>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let mut side = ctx.clone();
+    let value = side.define_const(taken as u64, 1);
+    side.eval(core::OpCodes::Cmp, vec![condition, value]);
+    side.solve_inputs()
+}
+
+impl BranchInputs {
+    /// Whether both sides have an input.
+    pub fn is_complete(&self) -> bool {
+        self.taken.is_some() && self.not_taken.is_some()
+    }
+
+    /// Solve for the sides that do not have an input yet, at the branch `condition` of `ctx`.
+    pub fn fill<Ctx>(&mut self, ctx: &Ctx, condition: <Ctx as RegisterRead>::VarRef)
+        where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+    {
+        if self.taken.is_none() {
+            self.taken = solve_side(ctx, condition.clone(), true);
+        }
+        if self.not_taken.is_none() {
+            self.not_taken = solve_side(ctx, condition, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use libsmt::theories::bitvec;
+
+    use context::context::{ContextAPI, Evaluate};
+    use context::rune_ctx::test_ctx;
+
+    #[test]
+    fn fill_sides() {
+        // The lowest bit of `x` decides the branch.
+        let mut ctx = test_ctx(&[]);
+        let x = ctx.set_mem_as_sym(0x1000, 8, Some("x")).var;
+        let condition = ctx.eval(bitvec::OpCodes::Extract(0, 0), vec![x]);
+        let mut inputs = BranchInputs::default();
+        inputs.fill(&ctx, condition);
+        assert!(inputs.is_complete());
+        assert_eq!(inputs.taken.as_ref().and_then(|i| i.get("x")).map(|x| x & 1), Some(1));
+        assert_eq!(inputs.not_taken.as_ref().and_then(|i| i.get("x")).map(|x| x & 1), Some(0));
+
+        // Only the sides without an input are solved, here on a path where `x` is odd.
+        let one = ctx.define_const(1, 1);
+        let odd = ctx.eval(core::OpCodes::Cmp, vec![condition, one]);
+        ctx.record_branch(condition, odd, true);
+        let mut inputs = BranchInputs::default();
+        inputs.fill(&ctx, condition);
+        assert!(inputs.taken.is_some() && inputs.not_taken.is_none());
+        let taken = inputs.taken.clone();
+        inputs.fill(&ctx, condition);
+        assert_eq!(inputs.taken, taken);
+    }
+
+    #[test]
+    fn show_inputs() {
+        let mut input = Input::new();
+        input.insert("x".to_owned(), 0x41);
+        input.insert("len".to_owned(), 3);
+        assert_eq!(show(Some(&input)), "len = 0x3, x = 0x41");
+        assert_eq!(show(Some(&Input::new())), "");
+        assert_eq!(show(None), "infeasible");
+    }
+}
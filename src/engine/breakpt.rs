@@ -0,0 +1,98 @@
+//! Breakpoints and watchpoints for the symbolic engine.
+
+use memory::memory::Access;
+
+/// Watches accesses to the memory range [start, end).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watchpoint {
+    pub start: u64,
+    pub end: u64,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    pub fn new(start: u64, end: u64, read: bool, write: bool) -> Watchpoint {
+        Watchpoint {
+            start: start,
+            end: end,
+            read: read,
+            write: write,
+        }
+    }
+
+    /// Watch both reads and writes to [start, end).
+    pub fn access(start: u64, end: u64) -> Watchpoint {
+        Watchpoint::new(start, end, true, true)
+    }
+
+    /// Whether this watchpoint watches accesses of the kind `access`.
+    pub fn watches(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => false,
+        }
+    }
+
+    /// Check if an access of `size` bytes at `addr` triggers this watchpoint. Accesses that
+    /// wrap around the address space are checked up to its end.
+    pub fn triggers(&self, addr: u64, size: u64, access: Access) -> bool {
+        self.watches(access) && addr < self.end && addr.saturating_add(size) > self.start
+    }
+}
+
+/// Details of a memory access that triggered a watchpoint.
+#[derive(Clone, Debug)]
+pub struct WatchHit<V> {
+    pub watchpoint: Watchpoint,
+    /// Address of the instruction that performed the access.
+    pub ip: u64,
+    /// Address of the access, or the start of the watched range for an access through a
+    /// symbolic address.
+    pub addr: u64,
+    /// The address of an access through a symbolic address that may point into the watched
+    /// range.
+    pub symbolic_addr: Option<V>,
+    /// Size of the access in bits.
+    pub size: usize,
+    pub access: Access,
+    /// Value that was read or written.
+    pub value: V,
+}
+
+/// Value of a watch expression after an instruction, see `RuneContext::add_watch_expr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchValue {
+    /// The expression, as it was given.
+    pub expr: String,
+    /// Address of the instruction after which the expression was evaluated.
+    pub ip: u64,
+    /// The value, if it simplifies to a constant.
+    pub constant: Option<u64>,
+    /// The value in SMT-LIB2 syntax.
+    pub smtlib: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn watched_accesses() {
+        let wp = Watchpoint::new(0x1000, 0x1010, false, true);
+        assert!(wp.triggers(0xffc, 8, Access::Write));
+        assert!(wp.triggers(0x100f, 1, Access::Write));
+        assert!(!wp.triggers(0xff8, 8, Access::Write));
+        assert!(!wp.triggers(0x1010, 4, Access::Write));
+        assert!(!wp.triggers(0x1000, 4, Access::Read));
+        assert!(!wp.triggers(0x1000, 4, Access::Execute));
+
+        // Neither overflow at the end of the address space.
+        let end = u64::max_value();
+        let wp = Watchpoint::access(end - 3, end);
+        assert!(wp.triggers(end - 1, 8, Access::Read));
+        assert!(!wp.triggers(end, 8, Access::Read));
+        assert!(!Watchpoint::access(0x1000, 0x1010).triggers(end - 3, 8, Access::Read));
+    }
+}
@@ -0,0 +1,93 @@
+//! Limits on the work of a run, see `Rune::set_budget`.
+//!
+//! Once the instructions executed, the forks or the time spent since the budget was set reach a
+//! limit, the engine stops with `EngineError::BudgetExhausted` before the next instruction. The
+//! state of the engine is kept, so the run can be inspected, or resumed with a new budget.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// Instructions executed, on all paths.
+    pub max_steps: Option<u64>,
+    /// Branches on symbolic conditions, each of which may add a path.
+    pub max_forks: Option<u64>,
+    /// Wall-clock time.
+    pub timeout: Option<Duration>,
+}
+
+/// The limit of a budget that a run reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    Forks(u64),
+    Time(Duration),
+}
+
+/// Work done since a budget was set.
+#[derive(Clone, Copy, Debug)]
+pub struct Usage {
+    pub steps: u64,
+    pub forks: u64,
+    pub start: Instant,
+}
+
+impl Default for Usage {
+    fn default() -> Usage {
+        Usage {
+            steps: 0,
+            forks: 0,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Budget {
+    /// The limit that `usage` reached, if any.
+    pub fn exhausted(&self, usage: &Usage) -> Option<Limit> {
+        if let Some(max) = self.max_steps {
+            if usage.steps >= max {
+                return Some(Limit::Steps(max));
+            }
+        }
+        if let Some(max) = self.max_forks {
+            if usage.forks >= max {
+                return Some(Limit::Forks(max));
+            }
+        }
+        match self.timeout {
+            Some(timeout) if usage.start.elapsed() >= timeout => Some(Limit::Time(timeout)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exhausted_limits() {
+        let mut usage = Usage::default();
+        assert_eq!(Budget::default().exhausted(&usage), None);
+
+        let budget = Budget {
+            max_steps: Some(10),
+            max_forks: Some(2),
+            timeout: Some(Duration::from_secs(3600)),
+        };
+        usage.steps = 9;
+        usage.forks = 1;
+        assert_eq!(budget.exhausted(&usage), None);
+        usage.forks = 2;
+        assert_eq!(budget.exhausted(&usage), Some(Limit::Forks(2)));
+        usage.steps = 10;
+        assert_eq!(budget.exhausted(&usage), Some(Limit::Steps(10)));
+
+        let budget = Budget {
+            timeout: Some(Duration::from_secs(0)),
+            ..Default::default()
+        };
+        assert_eq!(budget.exhausted(&usage), Some(Limit::Time(Duration::from_secs(0))));
+    }
+}
@@ -0,0 +1,156 @@
+//! Cache of decoded instructions.
+//!
+//! Decoding an instruction asks the stream for it, splits its ESIL (see `float::split_esil`) and
+//! tokenizes the parts, which dominates the time spent in tight loops. Instructions are cached
+//! by address and bytes. The bytes are identified by the code patches of the context (see
+//! `Context::code_patches`), as the other bytes are the ones that were loaded, so an instruction
+//! that the program overwrites is decoded again.
+
+use r2api::structs::LOpInfo;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use esil::lexer::{Token, Tokenize, Tokenizer};
+use engine::float::EsilPart;
+use engine::atomic::AtomicInsn;
+use engine::cpu::CpuInsn;
+use engine::rep::RepInsn;
+use stream::IsaMode;
+
+/// Address, instruction set and patched bytes of an instruction.
+pub type DecodeKey = (u64, IsaMode, Vec<(u64, Option<u8>)>);
+
+/// How an instruction changes the call stack, see `context::call_stack`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Call,
+    Return,
+}
+
+impl Flow {
+    /// Flow of the instructions of type `optype`, as r2 gives it.
+    pub fn of(optype: &str) -> Option<Flow> {
+        match optype {
+            // `call`, `ucall`, `rcall`, `ccall`, ...
+            t if t.ends_with("call") => Some(Flow::Call),
+            "ret" | "cret" => Some(Flow::Return),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Decoded {
+    pub opinfo: LOpInfo,
+    pub parts: Vec<EsilPart>,
+    /// The first operation of the ESIL that rune does not implement, or the opcode if the
+    /// instruction has no ESIL.
+    pub unsupported: Option<String>,
+    /// Whether the instruction makes a system call.
+    pub syscall: bool,
+    /// The repeated string instruction, if the instruction is one.
+    pub rep: Option<RepInsn>,
+    /// The instruction that queries the processor, if the instruction is one.
+    pub cpu: Option<CpuInsn>,
+    /// The atomic instruction, if the instruction is one whose ESIL is not supported.
+    pub atomic: Option<AtomicInsn>,
+    /// Index of the closure that a lifter gave for the instruction, see `engine::lifter`.
+    pub native: Option<usize>,
+    /// Whether the bytes are not an instruction.
+    pub invalid: bool,
+    /// Whether the instruction calls or returns.
+    pub flow: Option<Flow>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DecodeCache {
+    insns: HashMap<DecodeKey, Rc<Decoded>>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl DecodeCache {
+    pub fn get(&mut self, key: &DecodeKey) -> Option<Rc<Decoded>> {
+        let decoded = self.insns.get(key).cloned();
+        if decoded.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        decoded
+    }
+
+    pub fn insert(&mut self, key: DecodeKey, decoded: Decoded) -> Rc<Decoded> {
+        let decoded = Rc::new(decoded);
+        self.insns.insert(key, decoded.clone());
+        decoded
+    }
+
+    pub fn len(&self) -> usize {
+        self.insns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.insns.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.insns.clear();
+    }
+}
+
+thread_local! {
+    static TOKENS: RefCell<HashMap<String, Vec<Token>>> = RefCell::new(HashMap::new());
+}
+
+/// Tokenizer that tokenizes every ESIL string once. The parser tokenizes the expression it is
+/// given each time it is evaluated, and the `Tokenize` trait has no state, so the tokens are
+/// kept per thread.
+pub struct CachedTokenizer;
+
+impl Tokenize for CachedTokenizer {
+    type Token = Token;
+
+    fn tokenize<T: AsRef<str>>(esil: T) -> Vec<Token> {
+        let esil = esil.as_ref();
+        TOKENS.with(|tokens| {
+            tokens.borrow_mut()
+                  .entry(esil.to_owned())
+                  .or_insert_with(|| Tokenizer::tokenize(esil))
+                  .clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_cache() {
+        let mut cache = DecodeCache::default();
+        let key = (0x1000, IsaMode::Normal, vec![]);
+        assert!(cache.get(&key).is_none());
+        cache.insert(key.clone(),
+                     Decoded {
+                         opinfo: LOpInfo::default(),
+                         parts: vec![EsilPart::Esil("rax,rbx,=".to_owned())],
+                         unsupported: None,
+                         syscall: false,
+                         rep: None,
+                         cpu: None,
+                         native: None,
+                         atomic: None,
+                         invalid: false,
+                         flow: None,
+                     });
+        assert_eq!(cache.get(&key).map(|d| d.parts.len()), Some(1));
+        // The same address with other bytes, or in another mode, is another instruction.
+        assert!(cache.get(&(0x1000, IsaMode::Thumb, vec![])).is_none());
+        assert!(cache.get(&(0x1000, IsaMode::Normal, vec![(0x1000, Some(0x90))])).is_none());
+        assert_eq!((cache.hits, cache.misses), (1, 3));
+        assert_eq!(cache.len(), 1);
+    }
+}
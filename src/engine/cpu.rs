@@ -0,0 +1,218 @@
+//! Instructions of x86 that query the processor (`cpuid`, `rdtsc`, `rdrand`, ...).
+//!
+//! Their results depend on the machine rather than on the program, and r2 gives most of them no
+//! ESIL. Checks for virtual machines and debuggers test vendor strings, feature bits and timings,
+//! so the engine returns the results of a `CpuModel` (see `Rune::set_cpu_model`) instead. Results
+//! the model does not give are fresh symbols, and the checks on them can go either way.
+
+use std::collections::HashMap;
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec;
+
+use context::context::{Context, RegisterRead};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CpuInsn {
+    Cpuid,
+    Rdtsc,
+    /// `rdtscp`, which also reads the processor id into `ecx`, always 0.
+    Rdtscp,
+    /// `rdrand` or `rdseed` of the register.
+    Random(String),
+}
+
+impl CpuInsn {
+    /// The instruction of the disassembly `opcode`, if it is one.
+    pub fn parse(opcode: &str) -> Option<CpuInsn> {
+        let mut words = opcode.split_whitespace();
+        let insn = match words.next()? {
+            "cpuid" => CpuInsn::Cpuid,
+            "rdtsc" => CpuInsn::Rdtsc,
+            "rdtscp" => CpuInsn::Rdtscp,
+            "rdrand" | "rdseed" => CpuInsn::Random(words.next()?.to_owned()),
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(insn),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuModel {
+    /// Results of `cpuid` as `[eax, ebx, ecx, edx]`, by leaf (`eax`) and subleaf (`ecx`). A
+    /// result with the subleaf `None` is the one of every subleaf of its leaf.
+    pub cpuid: HashMap<(u32, Option<u32>), [u32; 4]>,
+    /// Value of the time stamp counter.
+    pub tsc: Option<u64>,
+    /// Result of `rdrand` and `rdseed`.
+    pub random: Option<u64>,
+}
+
+impl CpuModel {
+    /// Return `result` for the `cpuid` leaf `leaf`, of any subleaf.
+    pub fn set_cpuid(&mut self, leaf: u32, result: [u32; 4]) {
+        self.cpuid.insert((leaf, None), result);
+    }
+
+    /// The `cpuid` result for `leaf` and `subleaf`, if the model gives one.
+    pub fn cpuid_result(&self, leaf: u32, subleaf: u32) -> Option<[u32; 4]> {
+        self.cpuid.get(&(leaf, Some(subleaf))).or_else(|| self.cpuid.get(&(leaf, None))).cloned()
+    }
+}
+
+/// Width of the general purpose register `reg` in bits.
+fn reg_width(reg: &str) -> usize {
+    if reg.starts_with('r') {
+        match reg.chars().last() {
+            Some('d') => 32,
+            Some('w') => 16,
+            _ => 64,
+        }
+    } else if reg.starts_with('e') {
+        32
+    } else {
+        16
+    }
+}
+
+/// Write the `width` bit `value` to `reg`, zero extended.
+fn write<Ctx>(ctx: &mut Ctx, reg: &str, value: <Ctx as RegisterRead>::VarRef, width: usize)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let address_width = ctx.address_width();
+    let value = if width < address_width {
+        let extension = (address_width - width) as u64;
+        ctx.eval(bitvec::OpCodes::ZeroExtend(extension), vec![value])
+    } else {
+        value
+    };
+    ctx.reg_write(reg, value);
+}
+
+/// `value` as a constant, or a fresh symbol named after `name` if it is `None`.
+fn value_of<Ctx>(ctx: &mut Ctx,
+                 value: Option<u64>,
+                 name: &str,
+                 width: usize)
+                 -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    match value {
+        Some(value) => ctx.define_const(value, width),
+        None => ctx.fresh_value(name, width),
+    }
+}
+
+/// Set the carry flag and clear the other status flags, as a successful `rdrand` does.
+fn set_success<Ctx>(ctx: &mut Ctx)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    for flag in &["cf", "of", "sf", "zf", "af", "pf"] {
+        if ctx.has_reg(flag) {
+            let value = ctx.define_const((*flag == "cf") as u64, 1);
+            ctx.reg_write(flag, value);
+        }
+    }
+}
+
+/// Execute `insn` with the results of `model`.
+pub fn execute<Ctx>(ctx: &mut Ctx, insn: &CpuInsn, model: &CpuModel)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    match *insn {
+        CpuInsn::Cpuid => {
+            let leaf = ctx.reg_read("eax");
+            let subleaf = ctx.reg_read("ecx");
+            let leaf = ctx.as_constant(leaf);
+            let subleaf = ctx.as_constant(subleaf);
+            let result = match (leaf, subleaf) {
+                (Some(leaf), Some(subleaf)) => model.cpuid_result(leaf as u32, subleaf as u32),
+                (Some(leaf), None) => model.cpuid_result(leaf as u32, 0),
+                _ => None,
+            };
+            for (i, reg) in ["eax", "ebx", "ecx", "edx"].iter().enumerate() {
+                let name = match leaf {
+                    Some(leaf) => format!("cpuid_{:x}_{}", leaf, reg),
+                    None => format!("cpuid_{}", reg),
+                };
+                let value = value_of(ctx, result.map(|r| r[i] as u64), &name, 32);
+                write(ctx, reg, value, 32);
+            }
+        }
+        CpuInsn::Rdtsc | CpuInsn::Rdtscp => {
+            let tsc = value_of(ctx, model.tsc, "tsc", 64);
+            let low = ctx.eval(bitvec::OpCodes::Extract(31, 0), vec![tsc.clone()]);
+            let high = ctx.eval(bitvec::OpCodes::Extract(63, 32), vec![tsc]);
+            write(ctx, "eax", low, 32);
+            write(ctx, "edx", high, 32);
+            if *insn == CpuInsn::Rdtscp {
+                let id = ctx.define_const(0, 32);
+                write(ctx, "ecx", id, 32);
+            }
+        }
+        CpuInsn::Random(ref reg) => {
+            let width = reg_width(reg);
+            let value = value_of(ctx, model.random, "random", width);
+            write(ctx, reg, value, width);
+            set_success(ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use r2api::structs::Endian;
+
+    use context::context::RegisterWrite;
+    use context::rune_ctx::{RuneContext, test_ctx_with};
+    use memory::memory::Memory;
+    use memory::paged_mem::PagedMemory;
+
+    #[test]
+    fn parse_insns() {
+        assert_eq!(CpuInsn::parse("cpuid"), Some(CpuInsn::Cpuid));
+        assert_eq!(CpuInsn::parse("rdtscp"), Some(CpuInsn::Rdtscp));
+        assert_eq!(CpuInsn::parse("rdrand r8d"), Some(CpuInsn::Random("r8d".to_owned())));
+        assert_eq!(CpuInsn::parse("rdseed"), None);
+        assert_eq!(CpuInsn::parse("cpuid eax"), None);
+        assert_eq!((reg_width("rax"), reg_width("r8d"), reg_width("ecx"), reg_width("si")),
+                   (64, 32, 32, 16));
+    }
+
+    #[test]
+    fn model_results() {
+        let mem = PagedMemory::new(32, Endian::Little);
+        let mut ctx = test_ctx_with(mem, &[("eax", 32), ("ebx", 32), ("ecx", 32), ("edx", 32)]);
+        ctx.set_ip(0x1000);
+        let mut model = CpuModel {
+            tsc: Some(0x1_0000_0002),
+            ..Default::default()
+        };
+        model.set_cpuid(0, [0xd, 0x756e6547, 0x6c65746e, 0x49656e69]);
+
+        let mut run = |ctx: &mut RuneContext<_, _>, insn, leaf| {
+            let (leaf, subleaf) = (ctx.define_const(leaf, 32), ctx.define_const(0, 32));
+            ctx.reg_write("eax", leaf);
+            ctx.reg_write("ecx", subleaf);
+            execute(ctx, &insn, &model);
+            ["eax", "ebx", "ecx", "edx"]
+                .iter()
+                .map(|reg| {
+                    let value = ctx.reg_read(reg);
+                    ctx.as_constant(value)
+                })
+                .collect::<Vec<_>>()
+        };
+        // "GenuineIntel", and a symbolic leaf 1 whose features can be anything.
+        assert_eq!(run(&mut ctx, CpuInsn::Cpuid, 0),
+                   vec![Some(0xd), Some(0x756e6547), Some(0x6c65746e), Some(0x49656e69)]);
+        assert_eq!(run(&mut ctx, CpuInsn::Cpuid, 1), vec![None; 4]);
+        let values = run(&mut ctx, CpuInsn::Rdtscp, 0);
+        assert_eq!((values[0], values[2], values[3]), (Some(2), Some(0), Some(1)));
+    }
+}
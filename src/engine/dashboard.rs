@@ -0,0 +1,204 @@
+//! Statistics of an exploration as it runs, see `Rune::stats`.
+//!
+//! The engine counts the paths that forked and the ones that ended (see `engine::events`), the
+//! instructions executed, the blocks they covered and the time the solver took, since the engine
+//! was created. The states alive are the current one and the ones the explorer has left to
+//! explore, if it keeps count (see `PathExplorer::waiting`). With `Rune::set_progress_interval`,
+//! the subscribers are sent the statistics every so often as `Event::Progress`, a dashboard of
+//! long campaigns.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use engine::events::Event;
+use explorer::coverage::Coverage;
+use explorer::target::Cfg;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExplorationStats {
+    /// States left to explore, the current one included, if the explorer counts them.
+    pub alive: Option<usize>,
+    /// Paths that ended, with or without a fault.
+    pub dead: u64,
+    /// Paths that ended without a fault.
+    pub completed: u64,
+    pub faulted: u64,
+    /// Branches and jumps that forked the state.
+    pub forks: u64,
+    /// Blocks of the `Cfg` that were reached, or instructions if there is no graph.
+    pub blocks: usize,
+    pub instructions: u64,
+    pub elapsed: Duration,
+    pub solver_time: Duration,
+}
+
+fn seconds(time: Duration) -> f64 {
+    time.as_secs() as f64 + time.subsec_nanos() as f64 / 1e9
+}
+
+impl ExplorationStats {
+    pub fn instructions_per_sec(&self) -> f64 {
+        let elapsed = seconds(self.elapsed);
+        if elapsed > 0.0 { self.instructions as f64 / elapsed } else { 0.0 }
+    }
+
+    /// Share of the time spent in the solver, from 0 to 1.
+    pub fn solver_share(&self) -> f64 {
+        let elapsed = seconds(self.elapsed);
+        if elapsed > 0.0 { (seconds(self.solver_time) / elapsed).min(1.0) } else { 0.0 }
+    }
+}
+
+/// A line of a dashboard.
+impl fmt::Display for ExplorationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.alive {
+            Some(alive) => write!(f, "{} alive", alive)?,
+            None => write!(f, "? alive")?,
+        }
+        write!(f,
+               ", {} dead ({} completed, {} faulted), {} forks, {} blocks, {} instructions \
+                ({:.0}/s), {:.0}% in the solver",
+               self.dead,
+               self.completed,
+               self.faulted,
+               self.forks,
+               self.blocks,
+               self.instructions,
+               self.instructions_per_sec(),
+               self.solver_share() * 100.0)
+    }
+}
+
+/// What the engine counts for `ExplorationStats`.
+#[derive(Clone, Debug)]
+pub struct Dashboard {
+    coverage: Coverage,
+    stats: ExplorationStats,
+    start: Instant,
+    interval: Option<Duration>,
+    /// When the statistics were last reported.
+    reported: Instant,
+}
+
+impl Default for Dashboard {
+    fn default() -> Dashboard {
+        Dashboard::new(Cfg::default())
+    }
+}
+
+impl Dashboard {
+    /// Count the blocks of `cfg` that are covered.
+    pub fn new(cfg: Cfg) -> Dashboard {
+        let now = Instant::now();
+        Dashboard {
+            coverage: Coverage::new(cfg),
+            stats: ExplorationStats::default(),
+            start: now,
+            interval: None,
+            reported: now,
+        }
+    }
+
+    /// Count the instruction at `ip`, which is about to execute.
+    pub fn visit(&mut self, ip: u64) {
+        self.stats.instructions += 1;
+        self.coverage.visit(ip);
+    }
+
+    pub fn add_solver_time(&mut self, time: Duration) {
+        self.stats.solver_time += time;
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        match *event {
+            Event::Forked { .. } => self.stats.forks += 1,
+            Event::Died { ref fault, .. } => {
+                self.stats.dead += 1;
+                if fault.is_some() {
+                    self.stats.faulted += 1;
+                } else {
+                    self.stats.completed += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The statistics so far, with `alive` states.
+    pub fn stats(&self, alive: Option<usize>) -> ExplorationStats {
+        ExplorationStats {
+            alive: alive,
+            blocks: self.coverage.covered().len(),
+            elapsed: self.start.elapsed(),
+            ..self.stats
+        }
+    }
+
+    /// Report the statistics every `interval`, or never.
+    pub fn set_interval(&mut self, interval: Option<Duration>) {
+        self.interval = interval;
+        self.reported = Instant::now();
+    }
+
+    /// Whether it is time to report the statistics again, in which case the next report is
+    /// due one interval from now.
+    pub fn report_due(&mut self) -> bool {
+        match self.interval {
+            Some(interval) if self.reported.elapsed() >= interval => {
+                self.reported = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use engine::fault::FaultKind;
+
+    #[test]
+    fn count_paths() {
+        let mut dashboard = Dashboard::default();
+        for &ip in &[0x10, 0x14, 0x10] {
+            dashboard.visit(ip);
+        }
+        dashboard.record(&Event::Forked { ip: 0x14 });
+        dashboard.record(&Event::Died {
+            ip: 0x18,
+            fault: None,
+        });
+        dashboard.record(&Event::Died {
+            ip: 0x1c,
+            fault: Some(FaultKind::DivisionByZero),
+        });
+        dashboard.add_solver_time(Duration::from_millis(5));
+        let stats = dashboard.stats(Some(1));
+        assert_eq!((stats.dead, stats.completed, stats.faulted, stats.forks), (2, 1, 1, 1));
+        // Every address is a block of its own without a graph.
+        assert_eq!((stats.blocks, stats.instructions), (2, 3));
+        assert_eq!(stats.solver_time, Duration::from_millis(5));
+        assert!(!dashboard.report_due());
+        dashboard.set_interval(Some(Duration::from_secs(0)));
+        assert!(dashboard.report_due());
+
+        let stats = ExplorationStats {
+            alive: Some(3),
+            dead: 4,
+            completed: 3,
+            faulted: 1,
+            forks: 6,
+            blocks: 12,
+            instructions: 3000,
+            elapsed: Duration::from_secs(2),
+            solver_time: Duration::from_millis(500),
+        };
+        assert_eq!(stats.to_string(),
+                   "3 alive, 4 dead (3 completed, 1 faulted), 6 forks, 12 blocks, 3000 \
+                    instructions (1500/s), 25% in the solver");
+        assert_eq!(ExplorationStats::default().solver_share(), 0.0);
+    }
+}
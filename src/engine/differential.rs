@@ -0,0 +1,228 @@
+//! Differential testing of the semantics of instructions, against a concrete emulator.
+//!
+//! The ESIL of an instruction is written by hand, and a wrong flag or a missing extension only
+//! shows much later, as a state that the program can not reach. `Rune::check_step` executes the
+//! next instruction both with the engine and with a `ConcreteExecutor` (see `engine::fallback`)
+//! on the same concrete state, and compares the registers the executor models, the bytes either
+//! of them wrote and the address of the next instruction. The engine goes on from its own state.
+//!
+//! `random_x86` gives sequences of instructions to check, to be written to memory at the ip, so
+//! that they are decoded from the bytes of the context.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use libsmt::logics::qf_abv;
+
+use context::context::Context;
+use utils::utils::Rng;
+
+/// Status flags of x86 that the arithmetic instructions define, CF, PF, ZF, SF and OF.
+pub const X86_FLAGS: u64 = 0x8c5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Differential {
+    /// Registers that are not compared.
+    pub ignore: BTreeSet<String>,
+    /// Bits compared of registers, every bit for registers that are not listed. Only the status
+    /// flags of `X86_FLAGS` are compared by default, the others are undefined or not modeled.
+    pub masks: BTreeMap<String, u64>,
+}
+
+impl Default for Differential {
+    fn default() -> Differential {
+        Differential {
+            ignore: BTreeSet::new(),
+            masks: vec![("rflags".to_owned(), X86_FLAGS), ("eflags".to_owned(), X86_FLAGS)]
+                       .into_iter()
+                       .collect(),
+        }
+    }
+}
+
+/// State after an instruction, with `None` for symbolic values.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub regs: BTreeMap<String, Option<u64>>,
+    pub mem: BTreeMap<u64, Option<u8>>,
+    pub ip: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Reg {
+        name: String,
+        engine: Option<u64>,
+        native: Option<u64>,
+    },
+    Mem {
+        addr: u64,
+        engine: Option<u8>,
+        native: Option<u8>,
+    },
+    NextIp { engine: u64, native: u64 },
+}
+
+/// The check of the instruction at `addr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub addr: u64,
+    pub disasm: String,
+    /// Where the engine and the executor disagree, empty if they agree.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Differential {
+    /// Where the state of the engine differs from the state of the executor.
+    pub fn compare(&self, engine: &Snapshot, native: &Snapshot) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        for (name, &value) in &native.regs {
+            if self.ignore.contains(name) {
+                continue;
+            }
+            let mask = self.masks.get(name).cloned().unwrap_or(!0);
+            let found = engine.regs.get(name).cloned().unwrap_or(None);
+            if found.map(|v| v & mask) != value.map(|v| v & mask) {
+                mismatches.push(Mismatch::Reg {
+                    name: name.clone(),
+                    engine: found,
+                    native: value,
+                });
+            }
+        }
+        for (&addr, &byte) in &native.mem {
+            let found = engine.mem.get(&addr).cloned().unwrap_or(None);
+            if found != byte {
+                mismatches.push(Mismatch::Mem {
+                    addr: addr,
+                    engine: found,
+                    native: byte,
+                });
+            }
+        }
+        if engine.ip != native.ip {
+            mismatches.push(Mismatch::NextIp {
+                engine: engine.ip,
+                native: native.ip,
+            });
+        }
+        mismatches
+    }
+}
+
+/// The byte at `addr` of `ctx`, `None` if it is symbolic.
+pub fn byte_at<Ctx>(ctx: &mut Ctx, addr: u64) -> Option<u8>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let addr = ctx.define_const(addr, width);
+    let byte = ctx.mem_read(addr, 8);
+    ctx.as_constant(byte).map(|b| b as u8)
+}
+
+/// `count` random arithmetic, logic, shift and move instructions between general purpose
+/// registers, for x86 code of `bits` bits, from `seed`. They do not access memory, the stack
+/// pointer or the flow of control, and do not fault.
+pub fn random_x86(seed: u64, count: usize, bits: usize) -> Vec<u8> {
+    // Registers besides the stack pointer, as numbered in ModRM.
+    const REGS: [u8; 7] = [0, 1, 2, 3, 5, 6, 7];
+    let mut rng = Rng::new(seed);
+    let mut code = Vec::new();
+    for _ in 0..count {
+        let reg = REGS[rng.below(7) as usize];
+        let rm = REGS[rng.below(7) as usize];
+        let modrm = |n: u8| 0xc0 | n << 3 | rm;
+        if bits == 64 && rng.below(2) == 0 {
+            // REX.W, for 64 bit operands.
+            code.push(0x48);
+        }
+        match rng.below(9) {
+            // add, or, adc, sbb, and, sub, xor, cmp, test and mov of registers.
+            0 => {
+                let op = [0x01, 0x09, 0x11, 0x19, 0x21, 0x29, 0x31, 0x39, 0x85, 0x89];
+                code.extend(&[op[rng.below(10) as usize], modrm(reg)]);
+            }
+            // The same with an immediate.
+            1 => code.extend(&[0x83, modrm(rng.below(8) as u8), rng.next() as u8]),
+            // rol, ror, shl, shr and sar.
+            2 => {
+                let op = [0, 1, 4, 5, 7];
+                code.extend(&[0xc1, modrm(op[rng.below(5) as usize]), rng.below(64) as u8]);
+            }
+            // not, neg.
+            3 => code.extend(&[0xf7, modrm(2 + rng.below(2) as u8)]),
+            // inc, dec.
+            4 => code.extend(&[0xff, modrm(rng.below(2) as u8)]),
+            // imul.
+            5 => code.extend(&[0x0f, 0xaf, modrm(reg)]),
+            // movzx, movsx of a byte.
+            6 => code.extend(&[0x0f, [0xb6, 0xbe][rng.below(2) as usize], modrm(reg)]),
+            // cmovcc.
+            7 => code.extend(&[0x0f, 0x40 + rng.below(16) as u8, modrm(reg)]),
+            // setcc.
+            _ => code.extend(&[0x0f, 0x90 + rng.below(16) as u8, modrm(0)]),
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshot(rax: Option<u64>, rflags: u64, byte: Option<u8>, ip: u64) -> Snapshot {
+        Snapshot {
+            regs: vec![("rax".to_owned(), rax), ("rflags".to_owned(), Some(rflags))]
+                      .into_iter()
+                      .collect(),
+            mem: vec![(0x1000, byte)].into_iter().collect(),
+            ip: ip,
+        }
+    }
+
+    #[test]
+    fn compare_states() {
+        let config = Differential::default();
+        let native = snapshot(Some(1), 0x246, Some(7), 0x403);
+        // The adjust flag (0x10) is not compared.
+        let engine = snapshot(Some(1), 0x256, Some(7), 0x403);
+        assert!(config.compare(&engine, &native).is_empty());
+
+        let engine = snapshot(None, 0x247, Some(8), 0x405);
+        assert_eq!(config.compare(&engine, &native),
+                   vec![Mismatch::Reg {
+                            name: "rax".to_owned(),
+                            engine: None,
+                            native: Some(1),
+                        },
+                        Mismatch::Reg {
+                            name: "rflags".to_owned(),
+                            engine: Some(0x247),
+                            native: Some(0x246),
+                        },
+                        Mismatch::Mem {
+                            addr: 0x1000,
+                            engine: Some(8),
+                            native: Some(7),
+                        },
+                        Mismatch::NextIp {
+                            engine: 0x405,
+                            native: 0x403,
+                        }]);
+
+        let mut config = Differential::default();
+        config.ignore.insert("rax".to_owned());
+        config.masks.insert("rflags".to_owned(), 0);
+        let engine = snapshot(None, 0x247, Some(7), 0x403);
+        assert!(config.compare(&engine, &native).is_empty());
+    }
+
+    #[test]
+    fn random_code() {
+        let code = random_x86(42, 100, 64);
+        assert_eq!(code, random_x86(42, 100, 64));
+        assert!(code != random_x86(43, 100, 64));
+        assert!(code.len() >= 200 && code.len() <= 400);
+        // Without REX prefixes, instructions have at most 3 bytes.
+        assert!(random_x86(42, 100, 32).len() <= 300);
+    }
+}
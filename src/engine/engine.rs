@@ -1,9 +1,46 @@
 //! Defines traits and structs that perform the actual symbolic emulation.
 
-#[derive(Clone, Copy, Debug)]
+use memory::memory::AccessViolation;
+use memory::heap::HeapError;
+use context::concretize::ConcretizationError;
+use memory::uninit::UninitRead;
+use engine::budget::Limit;
+use engine::replay::Divergence;
+use regstore::regstore::RegFault;
+
+#[derive(Clone, Debug)]
 pub enum EngineError {
     Undefined,
     InCorrectOperand,
+    /// Memory was accessed in a way that is not permitted by its permissions.
+    AccessViolation(AccessViolation),
+    /// Misuse of the heap, such as a double free or a use after free.
+    HeapError(HeapError),
+    /// A symbolic value could not be concretized, see `RuneContext::set_concretization`.
+    Concretization(ConcretizationError),
+    /// Memory that was never initialized was read, with `UninitPolicy::Error`.
+    UninitializedRead(UninitRead),
+    /// A register hook rejected an access to a register.
+    RegisterFault(RegFault),
+    /// The ESIL of the instruction at `insn_addr` uses an operation that rune does not
+    /// implement, or a register that the register profile does not have.
+    UnsupportedEsil { op: String, insn_addr: u64 },
+    /// The program wrote a symbolic value over the instruction at this address.
+    SymbolicCode(u64),
+    /// The fallback executor failed to execute an instruction, see `engine::fallback`.
+    FallbackFailed(String),
+    /// r2 could not decode the instruction at this address.
+    InvalidInstruction(u64),
+    /// A replayed path differs from its recording at the step `step`, see `Rune::replay`, or
+    /// a followed trace from the execution, see `Rune::follow_trace`.
+    Diverged { step: usize, divergence: Divergence },
+    /// The syscall handler does not model the system call with this number.
+    UnhandledSyscall(u64),
+    /// The run reached a limit of its budget, see `Rune::set_budget`.
+    BudgetExhausted(Limit),
+    /// A register, or the byte of memory at an address, that a differential check needs is
+    /// symbolic, see `engine::differential`.
+    SymbolicInput(String),
 }
 
 pub type EngineResult<T> = Result<T, EngineError>;
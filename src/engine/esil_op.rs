@@ -0,0 +1,87 @@
+//! Operations that plugins of r2 add to ESIL.
+//!
+//! An operation is registered with `Rune::add_esil_op`, by the word that stands for it in ESIL
+//! and the number of operands it takes off the stack. As floating point operations, the
+//! operations are split out of the expression of an instruction before it is parsed (see
+//! `float::split_esil`), and evaluated by the closure they were registered with. The result of
+//! the closure, if any, is pushed on the stack.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use engine::engine::EngineResult;
+
+/// An operation, called with the values of its operands, the first operand being the top of
+/// the stack.
+pub type EsilOp<Ctx, V> = Rc<Fn(&mut Ctx, &[V]) -> EngineResult<Option<V>>>;
+
+pub struct EsilOps<Ctx, V> {
+    ops: HashMap<String, (usize, EsilOp<Ctx, V>)>,
+}
+
+impl<Ctx, V> Default for EsilOps<Ctx, V> {
+    fn default() -> EsilOps<Ctx, V> {
+        EsilOps { ops: HashMap::new() }
+    }
+}
+
+impl<Ctx, V> EsilOps<Ctx, V> {
+    /// Register `op` as the operation `name` of `arity` operands, replacing a previous one.
+    pub fn add(&mut self, name: &str, arity: usize, op: EsilOp<Ctx, V>) {
+        self.ops.insert(name.to_owned(), (arity, op));
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.ops.remove(name).is_some()
+    }
+
+    /// The number of operands and the closure of the operation `name`.
+    pub fn get(&self, name: &str) -> Option<(usize, EsilOp<Ctx, V>)> {
+        self.ops.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.ops.contains_key(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use engine::engine::EngineError;
+
+    #[test]
+    fn esil_ops() {
+        let mut ops: EsilOps<Vec<u64>, u64> = EsilOps::default();
+        ops.add("SWAP16",
+                1,
+                Rc::new(|_: &mut Vec<u64>, args: &[u64]| {
+                    Ok(Some((args[0] as u16).swap_bytes() as u64))
+                }));
+        ops.add("LOG",
+                2,
+                Rc::new(|log: &mut Vec<u64>, args: &[u64]| {
+                    log.extend_from_slice(args);
+                    Ok(None)
+                }));
+        ops.add("FAIL", 0, Rc::new(|_: &mut Vec<u64>, _: &[u64]| Err(EngineError::Undefined)));
+
+        let mut log = Vec::new();
+        let (arity, swap) = ops.get("SWAP16").unwrap();
+        assert_eq!(arity, 1);
+        assert_eq!(swap(&mut log, &[0x1234]).unwrap(), Some(0x3412));
+        let (_, record) = ops.get("LOG").unwrap();
+        assert_eq!(record(&mut log, &[1, 2]).unwrap(), None);
+        assert_eq!(log, vec![1, 2]);
+        assert!(ops.get("FAIL").map(|(_, fail)| fail(&mut log, &[]).is_err()).unwrap());
+
+        assert!(ops.contains("LOG"));
+        assert!(ops.remove("LOG"));
+        assert!(!ops.contains("LOG"));
+        assert!(ops.get("F+").is_none());
+    }
+}
@@ -0,0 +1,90 @@
+//! Events of an exploration, see `Rune::subscribe`.
+//!
+//! Subscribers observe what happens to the paths of a campaign while rune runs it, without
+//! being part of the explorer: a GUI drawing the tree of states, a logger, or a dashboard of
+//! the coverage. They are told of an event with the state of the path it happened on, and
+//! cannot change it.
+
+use std::fmt;
+use std::time::Duration;
+
+use engine::dashboard::ExplorationStats;
+use engine::fault::FaultKind;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The branch at `ip` depends on symbolic values, so the explorer may follow both sides.
+    Forked { ip: u64 },
+    /// The path ended at `ip`, with the fault it raised, if any. Faulted states forked off a
+    /// path end as well, see `Rune::set_fault_states`.
+    Died { ip: u64, fault: Option<FaultKind> },
+    /// The instruction at `ip` executed for the first time.
+    NewCoverage { ip: u64 },
+    /// The path reached the address `ip` that `Rune::explore` looks for. The path dies next.
+    GoalReached { ip: u64 },
+    /// The solver queries of the instruction at `ip` took `time`, longer than the limit set
+    /// with `Rune::set_solver_timeout`.
+    SolverTimeout { ip: u64, time: Duration },
+    /// The statistics of the exploration so far, sent as often as `Rune::set_progress_interval`
+    /// says.
+    Progress { stats: ExplorationStats },
+}
+
+pub trait Subscriber<Ctx> {
+    fn notify(&mut self, ctx: &Ctx, event: &Event);
+}
+
+impl<Ctx, F: FnMut(&Ctx, &Event)> Subscriber<Ctx> for F {
+    fn notify(&mut self, ctx: &Ctx, event: &Event) {
+        self(ctx, event)
+    }
+}
+
+/// A line of a log.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Forked { ip } => write!(f, "forked at {:#x}", ip),
+            Event::Died { ip, fault: None } => write!(f, "died at {:#x}", ip),
+            Event::Died { ip, fault: Some(ref fault) } => {
+                write!(f, "died at {:#x}: {:?}", ip, fault)
+            }
+            Event::NewCoverage { ip } => write!(f, "covered {:#x}", ip),
+            Event::GoalReached { ip } => write!(f, "reached {:#x}", ip),
+            Event::SolverTimeout { ip, time } => {
+                let millis = time.as_secs() * 1000 + time.subsec_nanos() as u64 / 1_000_000;
+                write!(f, "solver took {}ms at {:#x}", millis, ip)
+            }
+            Event::Progress { ref stats } => write!(f, "progress: {}", stats),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log_events() {
+        let mut log = Vec::new();
+        {
+            let mut subscriber = |ctx: &u64, event: &Event| log.push(format!("{}: {}", ctx, event));
+            let events = [Event::Forked { ip: 0x10 },
+                          Event::Died {
+                              ip: 0x20,
+                              fault: Some(FaultKind::DivisionByZero),
+                          },
+                          Event::SolverTimeout {
+                              ip: 0x30,
+                              time: Duration::from_millis(1500),
+                          }];
+            for event in &events {
+                subscriber.notify(&1, event);
+            }
+        }
+        assert_eq!(log,
+                   vec!["1: forked at 0x10",
+                        "1: died at 0x20: DivisionByZero",
+                        "1: solver took 1500ms at 0x30"]);
+    }
+}
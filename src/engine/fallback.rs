@@ -0,0 +1,61 @@
+//! Concrete execution of the instructions that rune can not execute symbolically.
+//!
+//! An instruction without ESIL, or whose ESIL uses an operation that rune does not implement,
+//! stops the engine with `EngineError::UnsupportedEsil`. With a `ConcreteExecutor` installed
+//! (see `Rune::set_fallback`), such an instruction is instead executed concretely, on values
+//! that the current path allows for the registers and memory it uses. Unless all of them were
+//! constants, the values the instruction writes are replaced by fresh values, as they only
+//! hold for the values that were picked. These are not inputs of the program, see
+//! `Context::opaque_value`.
+//!
+//! `engine::unicorn` implements an executor with the Unicorn emulator, behind the `unicorn`
+//! feature.
+
+use std::collections::BTreeMap;
+
+/// What an instruction did when it was executed concretely.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConcreteEffects {
+    /// Registers whose value changed, with their new value.
+    pub regs: BTreeMap<String, u64>,
+    /// Bytes written to memory.
+    pub mem: BTreeMap<u64, u8>,
+    /// Address of the next instruction.
+    pub next_ip: u64,
+}
+
+pub trait ConcreteExecutor {
+    /// Registers the executor models, with their width in bits, named as in the register
+    /// profile of the context.
+    fn registers(&self) -> Vec<(String, usize)>;
+
+    /// Execute the instruction `bytes` at `addr`, with the values `regs` for the registers.
+    /// `read(addr, len)` gives the bytes of memory.
+    fn execute(&mut self,
+               addr: u64,
+               bytes: &[u8],
+               regs: &BTreeMap<String, u64>,
+               read: &mut FnMut(u64, usize) -> Vec<u8>)
+               -> Result<ConcreteEffects, String>;
+}
+
+/// Bytes of the hex string `hex`, as in the `bytes` of an instruction decoded by r2.
+pub fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len() / 2).map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn instruction_bytes() {
+        assert_eq!(parse_hex("0fa2"), Some(vec![0x0f, 0xa2]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("0fa"), None);
+        assert_eq!(parse_hex("zz"), None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Faults of the program, as terminal states of the paths that raise them.
+//!
+//! With `Rune::set_fault_states`, a division by zero, an access to memory that is not mapped
+//! (or that its permissions do not allow), an invalid instruction, a misuse of the heap and a
+//! value that can not be concretized end the path that raises
+//! them, instead of being errors of the engine. The state of the path at the fault is kept,
+//! so that the inputs that trigger the fault can be solved for. A divisor that is symbolic
+//! forks the path: the state where it is zero faults, and the path continues with a divisor
+//! that is not.
+
+use context::concretize::ConcretizationError;
+use memory::heap::HeapError;
+use memory::memory::{Access, AccessViolation};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    DivisionByZero,
+    /// Access to an address that is not mapped.
+    Unmapped { addr: u64, access: Access },
+    /// Access that the permissions of the memory do not allow.
+    AccessViolation(AccessViolation),
+    /// The bytes at the address are not an instruction, with the bytes r2 decoded, if any.
+    InvalidInstruction(Option<String>),
+    /// An address or operand could not be concretized.
+    Concretization(ConcretizationError),
+    /// Misuse of the heap, such as a double free or a use after free.
+    Heap(HeapError),
+}
+
+impl From<AccessViolation> for FaultKind {
+    fn from(violation: AccessViolation) -> FaultKind {
+        match violation.perm {
+            Some(_) => FaultKind::AccessViolation(violation),
+            None => {
+                FaultKind::Unmapped {
+                    addr: violation.addr,
+                    access: violation.access,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    /// Address of the instruction that raised the fault.
+    pub ip: u64,
+    pub kind: FaultKind,
+}
+
+/// A path that ended with a fault.
+#[derive(Clone, Debug)]
+pub struct FaultedState<Ctx> {
+    pub fault: Fault,
+    /// State of the path when the fault was raised, constrained to raise it.
+    pub ctx: Ctx,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use memory::memory::Permission;
+
+    #[test]
+    fn violations_as_faults() {
+        let unmapped = AccessViolation {
+            addr: 0x4000,
+            access: Access::Read,
+            perm: None,
+        };
+        assert_eq!(FaultKind::from(unmapped),
+                   FaultKind::Unmapped {
+                       addr: 0x4000,
+                       access: Access::Read,
+                   });
+        let denied = AccessViolation {
+            perm: Some(Permission::new(true, false, false)),
+            ..unmapped
+        };
+        assert_eq!(FaultKind::from(denied), FaultKind::AccessViolation(denied));
+    }
+}
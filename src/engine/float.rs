@@ -0,0 +1,193 @@
+//! Floating point operations of ESIL.
+//!
+//! The ESIL lexer does not know the floating point operations of r2 (`F+`, `D2I`, ...), so the
+//! engine splits them out of the expression of an instruction before parsing it (see
+//! `split_esil`) and evaluates them itself.
+//!
+//! The solver has no floating point theory, so results are opaque by default: every operation
+//! returns a fresh value, which lets programs that incidentally execute floating point code
+//! continue. With `FloatMode::Concrete`, operations on constants are evaluated with the IEEE
+//! semantics of the host, and only operations on symbolic values are opaque. Values of the
+//! width of ESIL values are doubles. 32 bit floats are converted with `F2D` and `D2F`. Opaque
+//! results are not inputs of the program, see `Context::opaque_value`.
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec;
+
+use context::context::{Context, RegisterRead};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatMode {
+    /// Results are fresh values.
+    Opaque,
+    /// Operations on constants are evaluated, operations on symbols are opaque.
+    Concrete,
+}
+
+impl Default for FloatMode {
+    fn default() -> FloatMode {
+        FloatMode::Opaque
+    }
+}
+
+/// A floating point operation, with the number of operands it takes off the stack and the name
+/// of its opaque results.
+struct FloatOp {
+    op: &'static str,
+    arity: usize,
+    name: &'static str,
+}
+
+const FLOAT_OPS: &'static [FloatOp] = &[FloatOp { op: "F+", arity: 2, name: "fadd" },
+                                        FloatOp { op: "F-", arity: 2, name: "fsub" },
+                                        FloatOp { op: "F*", arity: 2, name: "fmul" },
+                                        FloatOp { op: "F/", arity: 2, name: "fdiv" },
+                                        FloatOp { op: "F==", arity: 2, name: "feq" },
+                                        FloatOp { op: "F!=", arity: 2, name: "fne" },
+                                        FloatOp { op: "F<", arity: 2, name: "flt" },
+                                        FloatOp { op: "F<=", arity: 2, name: "fle" },
+                                        FloatOp { op: "-F", arity: 1, name: "fneg" },
+                                        FloatOp { op: "NAN", arity: 1, name: "isnan" },
+                                        FloatOp { op: "I2D", arity: 1, name: "i2d" },
+                                        FloatOp { op: "S2D", arity: 1, name: "s2d" },
+                                        FloatOp { op: "D2I", arity: 1, name: "d2i" },
+                                        // `value,bits,F2D`, with the width of the float in bits.
+                                        FloatOp { op: "F2D", arity: 2, name: "f2d" },
+                                        FloatOp { op: "D2F", arity: 2, name: "d2f" },
+                                        FloatOp { op: "SQRT", arity: 1, name: "sqrt" },
+                                        FloatOp { op: "CEIL", arity: 1, name: "ceil" },
+                                        FloatOp { op: "FLOOR", arity: 1, name: "floor" },
+                                        FloatOp { op: "ROUND", arity: 1, name: "round" }];
+
+fn float_op(op: &str) -> Option<&'static FloatOp> {
+    FLOAT_OPS.iter().find(|f| f.op == op)
+}
+
+/// Number of operands of the floating point operation `op`, `None` if `op` is not one.
+pub fn arity(op: &str) -> Option<usize> {
+    float_op(op).map(|f| f.arity)
+}
+
+/// A part of the ESIL expression of an instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EsilPart {
+    /// ESIL that the lexer knows.
+    Esil(String),
+    /// An operation that the engine evaluates itself.
+    Op(String),
+}
+
+/// Split `esil` at the operations for which `is_op` is true.
+pub fn split_esil<F: Fn(&str) -> bool>(esil: &str, is_op: F) -> Vec<EsilPart> {
+    let mut parts = Vec::new();
+    let mut words = Vec::new();
+    for word in esil.split(',') {
+        if is_op(word) {
+            if !words.is_empty() {
+                parts.push(EsilPart::Esil(words.join(",")));
+                words.clear();
+            }
+            parts.push(EsilPart::Op(word.to_owned()));
+        } else {
+            words.push(word);
+        }
+    }
+    if !words.is_empty() {
+        parts.push(EsilPart::Esil(words.join(",")));
+    }
+    parts
+}
+
+/// Value of the floating point operation `op` on constant operands, the first operand being the
+/// top of the stack.
+fn eval_concrete(op: &str, args: &[u64]) -> Option<u64> {
+    let f = |i: usize| f64::from_bits(args[i]);
+    let flag = |b: bool| b as u64;
+    Some(match op {
+        // As for the other binary operations, `a,b,F-` is b - a.
+        "F+" => (f(0) + f(1)).to_bits(),
+        "F-" => (f(0) - f(1)).to_bits(),
+        "F*" => (f(0) * f(1)).to_bits(),
+        "F/" => (f(0) / f(1)).to_bits(),
+        "F==" => flag(f(0) == f(1)),
+        "F!=" => flag(f(0) != f(1)),
+        "F<" => flag(f(0) < f(1)),
+        "F<=" => flag(f(0) <= f(1)),
+        "-F" => (-f(0)).to_bits(),
+        "NAN" => flag(f(0).is_nan()),
+        "I2D" => (args[0] as f64).to_bits(),
+        "S2D" => (args[0] as i64 as f64).to_bits(),
+        "D2I" => f(0) as i64 as u64,
+        "F2D" if args[0] == 32 => (f32::from_bits(args[1] as u32) as f64).to_bits(),
+        "D2F" if args[0] == 32 => (f64::from_bits(args[1]) as f32).to_bits() as u64,
+        "F2D" | "D2F" if args[0] == 64 => args[1],
+        "SQRT" => f(0).sqrt().to_bits(),
+        "CEIL" => f(0).ceil().to_bits(),
+        "FLOOR" => f(0).floor().to_bits(),
+        "ROUND" => f(0).round().to_bits(),
+        _ => return None,
+    })
+}
+
+/// Evaluate the floating point operation `op` on `args`, the first operand being the top of the
+/// stack.
+pub fn eval<Ctx>(ctx: &mut Ctx,
+                 mode: FloatMode,
+                 op: &str,
+                 args: &[<Ctx as RegisterRead>::VarRef])
+                 -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    if mode == FloatMode::Concrete {
+        let values: Option<Vec<u64>> = args.iter().map(|a| ctx.as_constant(a.clone())).collect();
+        if let Some(value) = values.and_then(|v| eval_concrete(op, &v)) {
+            return ctx.define_const(value, width);
+        }
+    }
+
+    let f = float_op(op).expect("Not a floating point operation");
+    match op {
+        "F==" | "F!=" | "F<" | "F<=" | "NAN" => {
+            // Comparisons are 0 or 1.
+            let bit = ctx.opaque_value(f.name, 1);
+            ctx.eval(bitvec::OpCodes::ZeroExtend((width - 1) as u64), vec![bit])
+        }
+        _ => ctx.opaque_value(f.name, width),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use context::rune_ctx::test_ctx;
+
+    #[test]
+    fn split_float_ops() {
+        let parts = split_esil("xmm1,xmm0,F+,xmm0,=", |w| arity(w).is_some());
+        assert_eq!(parts,
+                   vec![EsilPart::Esil("xmm1,xmm0".to_owned()),
+                        EsilPart::Op("F+".to_owned()),
+                        EsilPart::Esil("xmm0,=".to_owned())]);
+        assert_eq!(split_esil("rax,rbx,+=", |w| arity(w).is_some()),
+                   vec![EsilPart::Esil("rax,rbx,+=".to_owned())]);
+
+        let two = 2f64.to_bits();
+        let three = 3f64.to_bits();
+        assert_eq!(eval_concrete("F-", &[three, two]), Some(1f64.to_bits()));
+        assert_eq!(eval_concrete("F<", &[two, three]), Some(1));
+        assert_eq!(eval_concrete("D2I", &[2.5f64.to_bits()]), Some(2));
+        assert_eq!(eval_concrete("F2D", &[32, 1.5f32.to_bits() as u64]), Some(1.5f64.to_bits()));
+    }
+
+    #[test]
+    fn opaque_results() {
+        let mut ctx = test_ctx(&[]);
+        let x = ctx.new_symbol("x", 64).var;
+        let sum = eval(&mut ctx, FloatMode::Concrete, "F+", &[x, x]);
+        let less = eval(&mut ctx, FloatMode::Opaque, "F<", &[x, x]);
+        assert!(sum != less);
+        // Only the input is a symbol.
+        assert_eq!(ctx.symbols().keys().collect::<Vec<_>>(), vec!["x"]);
+    }
+}
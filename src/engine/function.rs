@@ -0,0 +1,209 @@
+//! Exploration of a function on its own, see `Rune::explore_function`.
+//!
+//! The context is set up as if the function had just been called: the stack pointer points to a
+//! synthetic frame, the return address is a sentinel (in the link register, or pushed on the
+//! stack), and the arguments are fresh symbols `arg0`, `arg1`, ..., passed as the calling
+//! convention of the architecture passes them (see `hook::arg`). Every path that returns to the
+//! sentinel gives one `FunctionReturn`: under the constraints of the path, the return register
+//! holds an expression of the arguments. This is what the function computes, one case a path,
+//! which comes in handy to understand a function without its source.
+
+use std::fmt;
+
+use libsmt::logics::qf_abv;
+use petgraph::graph::NodeIndex;
+
+use context::context::{Context, RegisterRead};
+use context::rune_ctx::RuneContext;
+use context::stack::STACK_TOP;
+use engine::engine::{EngineError, EngineResult};
+use memory::memory::Memory;
+use regstore::regstore::RegStore;
+use utils::utils::{to_smtlib, try_simplify_constant};
+
+/// Address that the function returns to, which no code is expected at.
+pub const RETURN_SENTINEL: u64 = 0xdead_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FunctionSetup {
+    pub entry: u64,
+    /// Number of arguments.
+    pub args: usize,
+    /// Stack pointer before the call, where the arguments passed on the stack are. It should be
+    /// in the stack of the context, see `ContextBuilder::stack`.
+    pub sp: u64,
+    pub return_addr: u64,
+}
+
+impl FunctionSetup {
+    /// Call of the function at `entry` with `args` arguments, with a frame below the top of
+    /// the default stack.
+    pub fn new(entry: u64, args: usize) -> FunctionSetup {
+        FunctionSetup {
+            entry: entry,
+            args: args,
+            sp: STACK_TOP - 0x1000,
+            return_addr: RETURN_SENTINEL,
+        }
+    }
+}
+
+/// A path through the function, up to its return.
+#[derive(Clone, Debug)]
+pub struct FunctionReturn<Ctx: RegisterRead> {
+    /// State at the return address.
+    pub ctx: Ctx,
+    /// Arguments, with their width.
+    pub args: Vec<(Ctx::VarRef, usize)>,
+    /// Value of the return register.
+    pub ret: Ctx::VarRef,
+}
+
+/// Set up `ctx` to call `function`. Returns the symbols of the arguments, with their width.
+pub fn setup<Ctx>(ctx: &mut Ctx,
+                  function: &FunctionSetup)
+                  -> EngineResult<Vec<(<Ctx as RegisterRead>::VarRef, usize)>>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let size = (width / 8) as u64;
+    let in_regs = ctx.arch().map_or(function.args, |arch| arch.args().len());
+    let mut args = Vec::new();
+    for n in 0..function.args {
+        let arg = ctx.fresh_value(&format!("arg{}", n), width);
+        if n < in_regs {
+            let reg = ctx.reg_of_alias(&format!("A{}", n)).ok_or(EngineError::Undefined)?;
+            ctx.reg_write(reg, arg.clone());
+        } else {
+            let addr = ctx.define_const(function.sp + (n - in_regs) as u64 * size, width);
+            ctx.mem_write(addr, arg.clone(), width);
+        }
+        args.push((arg, width));
+    }
+
+    let mut sp = function.sp;
+    let return_addr = ctx.define_const(function.return_addr, width);
+    match ctx.reg_of_alias("LR") {
+        Some(lr) => ctx.reg_write(lr, return_addr),
+        None => {
+            sp -= size;
+            let addr = ctx.define_const(sp, width);
+            ctx.mem_write(addr, return_addr, width);
+        }
+    }
+    let sp_reg = ctx.reg_of_alias("SP").ok_or(EngineError::Undefined)?;
+    let sp = ctx.define_const(sp, width);
+    ctx.reg_write(sp_reg, sp);
+    ctx.set_ip(function.entry);
+    Ok(args)
+}
+
+impl<Ctx: Context> FunctionReturn<Ctx> {
+    /// Values of the arguments, and the value returned for them, on this path.
+    pub fn example(&mut self) -> Option<(Vec<u64>, u64)> {
+        let mut vars = self.args.clone();
+        vars.push((self.ret.clone(), self.ctx.address_width()));
+        let mut values = self.ctx.concrete_values(&vars)?;
+        let ret = values.pop()?;
+        Some((values, ret))
+    }
+}
+
+/// What a path through the function returns, and when, in SMT-LIB2 syntax.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionReport {
+    /// Expression of the return value, or its value if it is a constant.
+    pub ret: String,
+    /// Branch conditions of the path.
+    pub conditions: Vec<String>,
+    pub example: Option<(Vec<u64>, u64)>,
+}
+
+impl<Mem, Reg> FunctionReturn<RuneContext<Mem, Reg>>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    pub fn report(&mut self) -> FunctionReport {
+        let ret = match try_simplify_constant(self.ret, &mut self.ctx.solver) {
+            Some(c) => format!("{:#x}", c),
+            None => to_smtlib(self.ret, &self.ctx.solver),
+        };
+        let conditions = self.ctx
+                             .path_constraints()
+                             .iter()
+                             .map(|c| to_smtlib(c.constraint, &self.ctx.solver))
+                             .collect();
+        FunctionReport {
+            ret: ret,
+            conditions: conditions,
+            example: self.example(),
+        }
+    }
+}
+
+/// `returns <ret> if <condition> and ...`, followed by the example as a call.
+impl fmt::Display for FunctionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "returns {}", self.ret)?;
+        if !self.conditions.is_empty() {
+            write!(f, " if {}", self.conditions.join(" and "))?;
+        }
+        if let Some((ref args, ret)) = self.example {
+            let args: Vec<String> = args.iter().map(|a| format!("{:#x}", a)).collect();
+            write!(f, ", e.g. f({}) = {:#x}", args.join(", "), ret)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::rc::Rc;
+
+
+    use arch::arch::X86;
+    use context::context::{MemoryRead, RegisterRead};
+    use context::rune_ctx::test_ctx;
+
+    #[test]
+    fn setup_frame() {
+        let regs = ["rip", "rsp", "rdi", "rsi", "rdx", "rcx", "r8", "r9", "rax"];
+        let regs: Vec<_> = regs.iter().map(|&r| (r, 64)).collect();
+        let mut ctx = test_ctx(&regs);
+        ctx.set_ip(0);
+        ctx.set_arch(Rc::new(X86 { bits: 64 }));
+
+        let function = FunctionSetup::new(0x400000, 7);
+        let args = setup(&mut ctx, &function).unwrap();
+        assert_eq!(ctx.ip(), 0x400000);
+        assert_eq!(args.len(), 7);
+        let rsi = ctx.reg_read("rsi");
+        assert_eq!(to_smtlib(rsi, &ctx.solver), "arg1");
+
+        // The return address is pushed below the seventh argument.
+        let rsp = ctx.reg_read("rsp");
+        assert_eq!(ctx.as_constant(rsp), Some(function.sp - 8));
+        let ret = ctx.mem_read(rsp, 64);
+        assert_eq!(ctx.as_constant(ret), Some(RETURN_SENTINEL));
+        let addr = ctx.define_const(function.sp, 64);
+        let seventh = ctx.mem_read(addr, 64);
+        assert!(to_smtlib(seventh, &ctx.solver).ends_with("((_ extract 7 0) arg6))))))))"));
+
+        let mut path = FunctionReturn {
+            ret: ctx.reg_read("rdi"),
+            ctx: ctx,
+            args: args,
+        };
+        assert_eq!(path.report().ret, "arg0");
+        let report = FunctionReport {
+            ret: "(bvadd arg0 arg1)".to_owned(),
+            conditions: vec!["(bvult arg0 #x0000000000000010)".to_owned()],
+            example: Some((vec![1, 2], 3)),
+        };
+        assert_eq!(report.to_string(),
+                   "returns (bvadd arg0 arg1) if (bvult arg0 #x0000000000000010), e.g. f(0x1, \
+                    0x2) = 0x3");
+    }
+}
@@ -0,0 +1,204 @@
+//! Hooks replace the execution of the code at an address with a rust function.
+//!
+//! They are used to model the environment of the program, for example to summarize calls to
+//! library functions. The helpers in this module use the register roles (`A0`, `R0`, `SP`, ...)
+//! of the architecture of the context, or else the register profile aliases, to access the
+//! arguments and return value of a call.
+//!
+//! Instruction hooks (see `InsnHooks`) run in addition to an instruction, before or after its
+//! ESIL, at one address or at every instruction. Tracers and detectors are built on them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec;
+
+use r2pipe::r2::R2;
+use serde_json;
+
+use context::context::{Context, RegisterRead};
+use engine::engine::{EngineError, EngineResult};
+
+/// A hook is run instead of the instruction at the address it is installed at. It is responsible
+/// for updating the instruction pointer, usually by calling `return_from_call`.
+pub type Hook<Ctx> = fn(&mut Ctx) -> EngineResult<()>;
+
+/// When an instruction hook runs, relative to the ESIL of the instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookPoint {
+    /// Before the instruction is decoded. A hook that changes ip skips the instruction.
+    Before,
+    /// After the ESIL of the instruction, before errors of the instruction are reported.
+    After,
+}
+
+/// Instructions that an instruction hook runs at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookTarget {
+    Addr(u64),
+    Every,
+}
+
+/// An instruction hook, called with the address of the instruction.
+pub type InsnHook<Ctx> = Rc<Fn(&mut Ctx, u64) -> EngineResult<()>>;
+
+/// Handle of an installed instruction hook, to remove it with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HookId(usize);
+
+/// Instruction hooks, which are run in the order they were added.
+pub struct InsnHooks<Ctx> {
+    hooks: BTreeMap<HookId, (HookTarget, HookPoint, InsnHook<Ctx>)>,
+    next: usize,
+}
+
+impl<Ctx> Default for InsnHooks<Ctx> {
+    fn default() -> InsnHooks<Ctx> {
+        InsnHooks {
+            hooks: BTreeMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<Ctx> InsnHooks<Ctx> {
+    pub fn add(&mut self, target: HookTarget, point: HookPoint, hook: InsnHook<Ctx>) -> HookId {
+        let id = HookId(self.next);
+        self.next += 1;
+        self.hooks.insert(id, (target, point, hook));
+        id
+    }
+
+    pub fn remove(&mut self, id: HookId) -> bool {
+        self.hooks.remove(&id).is_some()
+    }
+
+    /// Hooks to run at `point` of the instruction at `addr`.
+    pub fn at(&self, addr: u64, point: HookPoint) -> Vec<InsnHook<Ctx>> {
+        self.hooks
+            .values()
+            .filter(|&&(target, p, _)| {
+                p == point && (target == HookTarget::Every || target == HookTarget::Addr(addr))
+            })
+            .map(|&(_, _, ref hook)| hook.clone())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Import {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    plt: u64,
+}
+
+/// Addresses of the imported functions of the binary opened in `r2`, by name.
+pub fn imports(r2: &mut R2) -> HashMap<String, u64> {
+    r2.send("iij");
+    let imports: Vec<Import> = serde_json::from_str(&r2.recv()).unwrap_or_default();
+    imports.into_iter()
+           .filter(|i| i.plt != 0)
+           .map(|i| (i.name, i.plt))
+           .collect()
+}
+
+fn alias_reg<Ctx: Context>(ctx: &Ctx, alias: &str) -> EngineResult<String> {
+    ctx.reg_of_alias(alias).ok_or(EngineError::Undefined)
+}
+
+/// Value of the `n`th argument of the current call.
+///
+/// Arguments that the calling convention of the architecture does not pass in registers are read
+/// from the stack, where they follow the return address unless the architecture has a link
+/// register.
+pub fn arg<Ctx>(ctx: &mut Ctx, n: usize) -> EngineResult<<Ctx as RegisterRead>::VarRef>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if let Some(arch) = ctx.arch() {
+        let in_regs = arch.args().len();
+        if n >= in_regs {
+            let width = ctx.address_width();
+            let slot = n - in_regs + if arch.link_reg().is_some() { 0 } else { 1 };
+            let sp = ctx.reg_read(arch.sp());
+            let offset = ctx.define_const((slot * width / 8) as u64, width);
+            let addr = ctx.eval(bitvec::OpCodes::BvAdd, vec![sp, offset]);
+            return Ok(ctx.mem_read(addr, width));
+        }
+    }
+    let reg = alias_reg(ctx, &format!("A{}", n))?;
+    Ok(ctx.reg_read(reg))
+}
+
+/// Return from the current call with `value` as the return value.
+///
+/// The return address is taken from the link register if the architecture has one, otherwise it
+/// is popped off the stack.
+pub fn return_from_call<Ctx>(ctx: &mut Ctx, value: Option<<Ctx as RegisterRead>::VarRef>) -> EngineResult<()>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if let Some(value) = value {
+        let reg = alias_reg(ctx, "R0")?;
+        ctx.reg_write(reg, value);
+    }
+
+    let ret = if let Some(lr) = ctx.reg_of_alias("LR") {
+        ctx.reg_read(lr)
+    } else {
+        let sp_reg = alias_reg(ctx, "SP")?;
+        let sp = ctx.reg_read(&sp_reg);
+        let width = ctx.address_width();
+        let ret = ctx.mem_read(sp.clone(), width);
+        let size = ctx.define_const((width / 8) as u64, width);
+        let sp = ctx.eval(bitvec::OpCodes::BvAdd, vec![sp, size]);
+        ctx.reg_write(&sp_reg, sp);
+        ret
+    };
+
+    let ret = ctx.as_constant(ret).ok_or(EngineError::Undefined)?;
+    ctx.set_ip(ret);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insn_hooks() {
+        let mut hooks: InsnHooks<Vec<u64>> = InsnHooks::default();
+        let every = hooks.add(HookTarget::Every,
+                              HookPoint::Before,
+                              Rc::new(|trace: &mut Vec<u64>, addr| {
+                                  trace.push(addr);
+                                  Ok(())
+                              }));
+        hooks.add(HookTarget::Addr(0x10),
+                  HookPoint::Before,
+                  Rc::new(|trace: &mut Vec<u64>, _| {
+                      trace.push(0);
+                      Ok(())
+                  }));
+        hooks.add(HookTarget::Addr(0x10), HookPoint::After, Rc::new(|_: &mut Vec<u64>, _| Ok(())));
+
+        let mut trace = Vec::new();
+        for hook in hooks.at(0x10, HookPoint::Before) {
+            hook(&mut trace, 0x10).unwrap();
+        }
+        for hook in hooks.at(0x20, HookPoint::Before) {
+            hook(&mut trace, 0x20).unwrap();
+        }
+        assert_eq!(trace, vec![0x10, 0, 0x20]);
+        assert_eq!(hooks.at(0x10, HookPoint::After).len(), 1);
+        assert_eq!(hooks.at(0x20, HookPoint::After).len(), 0);
+
+        assert!(hooks.remove(every));
+        assert!(!hooks.remove(every));
+        assert_eq!(hooks.at(0x20, HookPoint::Before).len(), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Targets of jumps to symbolic addresses, as through jump tables and vtables.
+//!
+//! When the program counter is set to a symbolic value, the solver is asked for an address that
+//! it may be under the constraints of the path, then for another one, and so on, each query
+//! excluding the addresses found so far, up to a limit (see `Rune::set_jump_targets`). The path
+//! forks once per target: the current state goes on at the first one, assuming that the jump
+//! goes there, and the explorer is given a copy of the state at each of the others (see
+//! `PathExplorer::add_state`). The copies do not execute what is left of the instruction after
+//! the jump, which is usually nothing. Targets past the limit are not explored, and neither are
+//! the others if the explorer does not take states.
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::core;
+
+use context::context::{Context, RegisterRead};
+
+/// Targets of a jump at most, by default.
+pub const DEFAULT_JUMP_TARGETS: usize = 16;
+
+/// Condition, as a bit, that the jump to `target` goes to `addr`.
+pub fn goes_to<Ctx>(ctx: &mut Ctx,
+                    target: <Ctx as RegisterRead>::VarRef,
+                    addr: u64)
+                    -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let addr = ctx.define_const(addr, width);
+    let equal = ctx.eval(core::OpCodes::Cmp, vec![target, addr]);
+    let one = ctx.define_const(1, 1);
+    let zero = ctx.define_const(0, 1);
+    ctx.eval(core::OpCodes::ITE, vec![equal, one, zero])
+}
+
+/// Assume the jump to `target` to go to `addr`, as a branch of the path of `ctx`.
+pub fn assume<Ctx>(ctx: &mut Ctx, target: <Ctx as RegisterRead>::VarRef, addr: u64)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let condition = goes_to(ctx, target, addr);
+    let one = ctx.define_const(1, 1);
+    let constraint = ctx.eval(core::OpCodes::Cmp, vec![condition.clone(), one]);
+    ctx.record_branch(condition, constraint, true);
+}
+
+/// Addresses that the jump to `target` may go to on the path of `ctx`, `limit` at most.
+pub fn targets<Ctx>(ctx: &mut Ctx,
+                    target: <Ctx as RegisterRead>::VarRef,
+                    limit: usize)
+                    -> Vec<u64>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let mut rest = ctx.clone();
+    let mut targets = Vec::new();
+    while targets.len() < limit {
+        let addr = match rest.concrete_values(&[(target.clone(), width)]) {
+            Some(values) => values[0],
+            None => break,
+        };
+        if targets.contains(&addr) {
+            // The target is a constant under the constraints.
+            break;
+        }
+        targets.push(addr);
+        let condition = goes_to(&mut rest, target.clone(), addr);
+        let zero = rest.define_const(0, 1);
+        rest.eval(core::OpCodes::Cmp, vec![condition, zero]);
+    }
+    targets
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+
+    use context::context::Evaluate;
+    use context::rune_ctx::test_ctx;
+    use utils::utils::to_smtlib;
+
+    #[test]
+    fn jump_table() {
+        let mut ctx = test_ctx(&[("rip", 64)]);
+        ctx.set_ip(0);
+
+        let constant = ctx.define_const(0x400100, 64);
+        assert_eq!(targets(&mut ctx, constant, 4), vec![0x400100]);
+        assert_eq!(targets(&mut ctx, constant, 0), vec![]);
+
+        // A table of two entries, selected by a symbolic index.
+        let index = ctx.fresh_value("index", 64);
+        let zero = ctx.define_const(0, 64);
+        let is_first = ctx.eval(core::OpCodes::Cmp, vec![index, zero]);
+        let first = ctx.define_const(0x400200, 64);
+        let second = ctx.define_const(0x400300, 64);
+        let target = ctx.eval(core::OpCodes::ITE, vec![is_first, first, second]);
+        let mut found = targets(&mut ctx, target, 4);
+        found.sort();
+        assert_eq!(found, vec![0x400200, 0x400300]);
+
+        assume(&mut ctx, target, 0x400300);
+        let constraints = ctx.path_constraints();
+        assert_eq!(constraints.len(), 1);
+        assert!(to_smtlib(constraints[0].condition, &ctx.solver).starts_with("(ite (= (ite"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Sources of the semantics of instructions, besides the ESIL of r2.
+//!
+//! ESIL loses the flags of ARM, floating point and vector operations. A `Lifter` (see
+//! `Rune::set_lifter`) gives the semantics of instructions from another intermediate
+//! representation, such as RzIL or VEX through their bindings, either as ESIL translated from
+//! it, which the engine evaluates as the ESIL of r2, or as a closure that executes the
+//! instruction on the context. A lifter is asked once for every decoded instruction, and the
+//! instructions it does not lift keep the ESIL of r2.
+
+use std::rc::Rc;
+
+use r2api::structs::LOpInfo;
+
+use engine::engine::EngineResult;
+use stream::IsaMode;
+
+/// Executes an instruction. The ip of the context is already past the instruction, and is set
+/// by the closure for instructions that jump.
+pub type Semantics<Ctx> = Rc<Fn(&mut Ctx) -> EngineResult<()>>;
+
+pub enum Lifted<Ctx> {
+    /// ESIL to evaluate instead of the one of r2.
+    Esil(String),
+    Native(Semantics<Ctx>),
+}
+
+pub trait Lifter<Ctx> {
+    /// Name of the representation, as reported by `Rune::lifter_name`.
+    fn name(&self) -> &str;
+
+    /// The semantics of `insn`, decoded by r2 in `mode`, or `None` to keep its ESIL.
+    fn lift(&mut self, insn: &LOpInfo, mode: IsaMode) -> Option<Lifted<Ctx>>;
+}
+
+/// The ESIL of r2, which the instruction stream decodes already.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Esil;
+
+impl<Ctx> Lifter<Ctx> for Esil {
+    fn name(&self) -> &str {
+        "esil"
+    }
+
+    fn lift(&mut self, _: &LOpInfo, _: IsaMode) -> Option<Lifted<Ctx>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Lifts `nop` to a closure that counts the instructions it executes.
+    struct Nops;
+
+    impl Lifter<u64> for Nops {
+        fn name(&self) -> &str {
+            "nops"
+        }
+
+        fn lift(&mut self, insn: &LOpInfo, _: IsaMode) -> Option<Lifted<u64>> {
+            match insn.opcode.as_ref().map(|o| &o[..]) {
+                Some("nop") => Some(Lifted::Native(Rc::new(|count: &mut u64| Ok(*count += 1)))),
+                Some("ret") => Some(Lifted::Esil("rsp,[8],rip,=,8,rsp,+=".to_owned())),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn lift_instructions() {
+        let insn = |opcode: &str| {
+            LOpInfo {
+                opcode: Some(opcode.to_owned()),
+                ..Default::default()
+            }
+        };
+        let mut lifters: Vec<Box<Lifter<u64>>> = vec![Box::new(Esil), Box::new(Nops)];
+        assert!(lifters[0].lift(&insn("nop"), IsaMode::Normal).is_none());
+        let mut count = 0;
+        match lifters[1].lift(&insn("nop"), IsaMode::Normal) {
+            Some(Lifted::Native(f)) => f(&mut count).unwrap(),
+            _ => panic!("nop is not lifted"),
+        }
+        assert_eq!(count, 1);
+        match lifters[1].lift(&insn("ret"), IsaMode::Thumb) {
+            Some(Lifted::Esil(esil)) => assert!(esil.ends_with("rsp,+=")),
+            _ => panic!("ret is not lifted to ESIL"),
+        }
+        assert!(lifters[1].lift(&insn("hlt"), IsaMode::Normal).is_none());
+        assert_eq!(lifters.iter().map(|l| l.name()).collect::<Vec<_>>(), vec!["esil", "nops"]);
+    }
+}
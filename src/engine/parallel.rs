@@ -0,0 +1,272 @@
+//! Exploration of independent states on several threads.
+//!
+//! Every context owns its solver, so states do not share anything once they are forked. The
+//! contexts are not `Send` (and neither is r2), so each thread builds its own worker, typically
+//! with its own r2 session, and states travel between threads as images (see `context::image`).
+//! A worker explores the path of a state, for example with a `Rune` driven by an
+//! `explorer::fork::ForkExplorer`, and returns the images of the states it forked, which join
+//! its queue of the shared frontier, for it or any idle worker to pick up. The exploration ends
+//! once every queue is empty and no worker is busy. A worker that panics is no longer busy, the
+//! others explore the rest of the states, and `explore` then panics with its message.
+
+use std::collections::VecDeque;
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use context::image::ContextImage;
+
+pub trait PathWorker {
+    type Output: Send;
+
+    /// Explore the path that starts from `image`, with its result and the images of the states
+    /// forked off it.
+    fn explore(&mut self, image: ContextImage) -> (Self::Output, Vec<ContextImage>);
+}
+
+struct Frontier<T> {
+    /// States left to explore, a queue for every worker.
+    queues: Vec<VecDeque<ContextImage>>,
+    /// Workers exploring a path, which may fork more states.
+    busy: usize,
+    outputs: Vec<T>,
+}
+
+impl<T> Frontier<T> {
+    /// Next state for worker `i`: the last one it forked, or else the oldest state of the
+    /// worker with the most states left.
+    fn take(&mut self, i: usize) -> Option<ContextImage> {
+        if let Some(image) = self.queues[i].pop_back() {
+            return Some(image);
+        }
+        let victim = (0..self.queues.len()).max_by_key(|&j| self.queues[j].len())?;
+        self.queues[victim].pop_front()
+    }
+}
+
+type Shared<T> = (Mutex<Frontier<T>>, Condvar);
+
+/// A worker exploring a path. It is idle again once this is dropped, even if it panicked.
+struct Busy<'a, T: 'a> {
+    shared: &'a Shared<T>,
+}
+
+impl<'a, T> Drop for Busy<'a, T> {
+    fn drop(&mut self) {
+        let &(ref frontier, ref ready) = self.shared;
+        let mut frontier = frontier.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        frontier.busy -= 1;
+        ready.notify_all();
+    }
+}
+
+/// Explore the states of `initial`, and the states forked off them, on `threads` threads with
+/// workers made by `factory`. Returns the results of the paths, in the order they ended.
+///
+/// A worker goes on with the states it forked, depth first, and steals the oldest states of
+/// another worker once it has none left, which are the closest to the root and so likely the
+/// ones with the most work left under them.
+pub fn explore<W, F>(initial: Vec<ContextImage>, threads: usize, factory: F) -> Vec<W::Output>
+    where W: PathWorker + 'static,
+          W::Output: 'static,
+          F: Fn() -> W + Send + Sync + 'static
+{
+    let threads = threads.max(1);
+    let mut queues = vec![VecDeque::new(); threads];
+    for (i, image) in initial.into_iter().enumerate() {
+        queues[i % threads].push_back(image);
+    }
+    let frontier = Frontier {
+        queues: queues,
+        busy: 0,
+        outputs: Vec::new(),
+    };
+    let shared = Arc::new((Mutex::new(frontier), Condvar::new()));
+    let factory = Arc::new(factory);
+    let handles: Vec<_> = (0..threads)
+                              .map(|i| {
+                                  let shared = shared.clone();
+                                  let factory = factory.clone();
+                                  thread::spawn(move || work(i, &shared, &*factory))
+                              })
+                              .collect();
+    let mut panicked = None;
+    for handle in handles {
+        if let Err(payload) = handle.join() {
+            panicked = panicked.or(Some(payload));
+        }
+    }
+    if let Some(payload) = panicked {
+        panic::resume_unwind(payload);
+    }
+    let &(ref frontier, _) = &*shared;
+    let mut frontier = frontier.lock().unwrap();
+    frontier.outputs.drain(..).collect()
+}
+
+/// Explore states as the `i`th worker. A state is only ever on one thread, as an image while it
+/// waits in the frontier and as a context of the worker that took it.
+fn work<W: PathWorker>(i: usize, shared: &Shared<W::Output>, factory: &Fn() -> W) {
+    let &(ref frontier, ref ready) = shared;
+    let mut worker = factory();
+    loop {
+        let image = {
+            let mut frontier = frontier.lock().unwrap();
+            loop {
+                if let Some(image) = frontier.take(i) {
+                    frontier.busy += 1;
+                    break image;
+                }
+                if frontier.busy == 0 {
+                    return;
+                }
+                frontier = ready.wait(frontier).unwrap();
+            }
+        };
+        let busy = Busy { shared: shared };
+        let (output, forks) = worker.explore(image);
+        {
+            let mut frontier = frontier.lock().unwrap();
+            frontier.queues[i].extend(forks);
+            frontier.outputs.push(output);
+        }
+        drop(busy);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use libsmt::theories::bitvec;
+
+    use context::context::{ContextAPI, Evaluate};
+    use context::rune_ctx::test_ctx;
+    use explorer::explorer::PathExplorer;
+    use explorer::fork::ForkExplorer;
+
+    /// Forks every state twice, down to a depth of 3, with the depth as the ip.
+    struct Tree;
+
+    impl PathWorker for Tree {
+        type Output = u64;
+
+        fn explore(&mut self, image: ContextImage) -> (u64, Vec<ContextImage>) {
+            let forks = if image.ip < 3 {
+                let child = ContextImage {
+                    ip: image.ip + 1,
+                    ..Default::default()
+                };
+                vec![child.clone(), child]
+            } else {
+                Vec::new()
+            };
+            (image.ip, forks)
+        }
+    }
+
+    /// Branches on the bits of the symbol `x`, from the lowest, down to a depth of 3, with a
+    /// `ForkExplorer`. The contexts only travel as images.
+    struct Bits;
+
+    impl PathWorker for Bits {
+        type Output = Vec<bool>;
+
+        fn explore(&mut self, image: ContextImage) -> (Vec<bool>, Vec<ContextImage>) {
+            let mut ctx = test_ctx(&[]);
+            ctx.load_image(&image).unwrap();
+            let mut explorer = ForkExplorer::new();
+            while ctx.path_constraints().len() < 3 {
+                let bit = ctx.path_constraints().len() as u64;
+                let x = ctx.symbol("x").unwrap().var;
+                let condition = ctx.eval(bitvec::OpCodes::Extract(bit, bit), vec![x]);
+                explorer.register_branch(&mut ctx, condition);
+            }
+            let taken = ctx.path_constraints().iter().map(|c| c.taken).collect();
+            let forks = explorer.take_forks().iter().map(|f| f.to_image().unwrap()).collect();
+            (taken, forks)
+        }
+    }
+
+    /// `Tree`, panicking on the first leaf of any worker, which counts the states explored.
+    struct Panics {
+        explored: Arc<AtomicUsize>,
+        panicked: Arc<AtomicBool>,
+    }
+
+    impl PathWorker for Panics {
+        type Output = u64;
+
+        fn explore(&mut self, image: ContextImage) -> (u64, Vec<ContextImage>) {
+            if image.ip == 3 && !self.panicked.swap(true, Ordering::SeqCst) {
+                panic!("Unable to explore");
+            }
+            self.explored.fetch_add(1, Ordering::SeqCst);
+            Tree.explore(image)
+        }
+    }
+
+    #[test]
+    fn explore_forks() {
+        let mut ctx = test_ctx(&[]);
+        ctx.set_mem_as_sym(0x1000, 64, Some("x"));
+        let image = ctx.to_image().unwrap();
+        let mut paths = explore(vec![image], 2, || Bits);
+        paths.sort();
+        let all = (0..8).map(|n| (0..3).map(|bit| n >> bit & 1 == 0).collect::<Vec<_>>());
+        let mut all = all.collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(paths, all);
+    }
+
+    #[test]
+    fn worker_panics() {
+        let explored = Arc::new(AtomicUsize::new(0));
+        let panicked = Arc::new(AtomicBool::new(false));
+        let (counter, flag) = (explored.clone(), panicked.clone());
+        let result = panic::catch_unwind(move || {
+            explore(vec![ContextImage::default()], 2, move || {
+                Panics {
+                    explored: counter.clone(),
+                    panicked: flag.clone(),
+                }
+            })
+        });
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"Unable to explore"));
+        // The other worker explored every other state, including the ones left in the queue of
+        // the worker that panicked.
+        assert_eq!(explored.load(Ordering::SeqCst), 14);
+    }
+
+    #[test]
+    fn explore_in_parallel() {
+        let mut depths = explore(vec![ContextImage::default()], 4, || Tree);
+        depths.sort();
+        assert_eq!(depths.len(), 15);
+        assert_eq!(depths.iter().filter(|&&d| d == 3).count(), 8);
+        assert!(explore(Vec::new(), 2, || Tree).is_empty());
+    }
+
+    #[test]
+    fn steal_states() {
+        let image = |ip| {
+            ContextImage {
+                ip: ip,
+                ..Default::default()
+            }
+        };
+        let mut frontier: Frontier<()> = Frontier {
+            queues: vec![VecDeque::new(), vec![image(1), image(2), image(3)].into_iter().collect()],
+            busy: 0,
+            outputs: Vec::new(),
+        };
+        // The idle worker takes the oldest state of the other, which goes on with its newest.
+        assert_eq!(frontier.take(0).map(|i| i.ip), Some(1));
+        assert_eq!(frontier.take(1).map(|i| i.ip), Some(3));
+        assert_eq!(frontier.take(0).map(|i| i.ip), Some(2));
+        assert!(frontier.take(1).is_none());
+    }
+}
@@ -0,0 +1,114 @@
+//! Execution along an external trace of instruction addresses, see `Rune::follow_trace`.
+//!
+//! A trace recorded by instrumentation (PIN, QEMU, Frida, ...) of a concrete run lists the
+//! instructions it executed. Following it, the branches whose conditions are symbolic are
+//! assumed to go the way the trace went, and symbolic targets of indirect jumps are resolved to
+//! the next address of the trace, instead of asking the explorer or the solver. An instruction
+//! is the only thing the trace knows about, so the branches of an instruction are tried until
+//! it ends at the next address. Position independent executables have to be loaded at the base
+//! of the traced run, see `ContextBuilder::image_base`.
+
+use std::cmp;
+
+use engine::engine::EngineError;
+use engine::replay::Divergence;
+
+/// Symbolic branches per instruction whose outcomes are tried, at most.
+const MAX_BRANCHES: usize = 16;
+
+/// The addresses of a trace with one instruction per line. The first word of a line is the
+/// address, in hexadecimal, with or without `0x`. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn parse(text: &str) -> Result<Vec<u64>, String> {
+    let mut pcs = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let word = match line.split_whitespace().next() {
+            Some(word) if !word.starts_with('#') => word.trim_right_matches(':'),
+            _ => continue,
+        };
+        let hex = word.trim_left_matches("0x").trim_left_matches("0X");
+        let pc = u64::from_str_radix(hex, 16)
+                     .map_err(|_| format!("Invalid address `{}` on line {}", word, n + 1))?;
+        pcs.push(pc);
+    }
+    Ok(pcs)
+}
+
+/// Progress through the instruction at `step` of a followed trace.
+#[derive(Clone, Debug)]
+pub struct Guide {
+    step: usize,
+    /// Address the trace continues at, `None` at its last instruction.
+    pub next: Option<u64>,
+    /// Outcomes of the symbolic branches of this attempt, as bits, the first branch lowest.
+    attempt: u64,
+    /// Symbolic branches met by this attempt.
+    branch: usize,
+    /// Symbolic branches met by any attempt.
+    branches: usize,
+}
+
+impl Guide {
+    pub fn new(step: usize, next: Option<u64>) -> Guide {
+        Guide {
+            step: step,
+            next: next,
+            attempt: 0,
+            branch: 0,
+            branches: 0,
+        }
+    }
+
+    /// The error for `divergence` at the current instruction.
+    pub fn diverged(&self, divergence: Divergence) -> EngineError {
+        EngineError::Diverged {
+            step: self.step,
+            divergence: divergence,
+        }
+    }
+
+    /// Outcome of the next symbolic branch of the instruction in this attempt.
+    pub fn next_branch(&mut self) -> bool {
+        let taken = self.branch < MAX_BRANCHES && (self.attempt >> self.branch) & 1 == 1;
+        self.branch += 1;
+        self.branches = cmp::max(self.branches, self.branch);
+        taken
+    }
+
+    /// Move on to the next combination of outcomes, unless every one was tried.
+    pub fn retry(&mut self) -> bool {
+        self.attempt += 1;
+        self.branch = 0;
+        self.attempt < 1 << cmp::min(self.branches, MAX_BRANCHES)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_traces() {
+        let text = "# pin itrace\n0x401000\n\n401004 mov eax, 1\n0X40100A: ret\n";
+        assert_eq!(parse(text).unwrap(), vec![0x401000, 0x401004, 0x40100a]);
+        assert!(parse("0x401000\nmov eax, 1\n").unwrap_err().contains("line 2"));
+    }
+
+    #[test]
+    fn guide_attempts() {
+        let mut guide = Guide::new(3, Some(0x10));
+        assert!(!guide.retry());
+
+        let mut guide = Guide::new(3, Some(0x10));
+        assert_eq!((guide.next_branch(), guide.next_branch()), (false, false));
+        let mut seen = vec![];
+        while guide.retry() {
+            seen.push((guide.next_branch(), guide.next_branch()));
+        }
+        assert_eq!(seen, vec![(true, false), (false, true), (true, true)]);
+        match guide.diverged(Divergence::Ended) {
+            EngineError::Diverged { step: 3, .. } => {}
+            other => panic!("Expected a divergence, found {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Repeated string instructions of x86 (`rep movsb`, `rep stosq`, `repne scasb`, ...).
+//!
+//! The ESIL of these instructions loops with `GOTO`, and checks the counter at the start of every
+//! iteration, in the middle of the expression. Instead of evaluating it, the engine executes
+//! the whole instruction at once. With a concrete counter this is the loop itself. With a
+//! symbolic counter, the counter is assumed to be at most the repeat limit (see
+//! `Rune::set_rep_limit`), and every iteration up to the limit is guarded by the counter, so
+//! that a single state describes every count.
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+
+use context::context::{Context, RegisterRead};
+
+/// Iterations assumed at most for a symbolic counter, by default.
+pub const DEFAULT_REP_LIMIT: u64 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringOp {
+    Movs,
+    Stos,
+    Lods,
+    Scas,
+    Cmps,
+}
+
+/// When a repeated instruction stops, besides the counter reaching 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepCond {
+    Always,
+    /// `repe`: once a comparison is not equal.
+    WhileEqual,
+    /// `repne`: once a comparison is equal.
+    WhileNotEqual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepInsn {
+    pub op: StringOp,
+    pub cond: RepCond,
+    /// Width of the elements in bits.
+    pub size: usize,
+}
+
+impl RepInsn {
+    /// The repeated string instruction of the disassembly `opcode`, if it is one.
+    pub fn parse(opcode: &str) -> Option<RepInsn> {
+        let mut words = opcode.split_whitespace();
+        let prefix = words.next()?;
+        let insn = words.next()?;
+        let op = match insn.get(..4)? {
+            "movs" => StringOp::Movs,
+            "stos" => StringOp::Stos,
+            "lods" => StringOp::Lods,
+            "scas" => StringOp::Scas,
+            "cmps" => StringOp::Cmps,
+            _ => return None,
+        };
+        let compares = op == StringOp::Scas || op == StringOp::Cmps;
+        let cond = match prefix {
+            "rep" if !compares => RepCond::Always,
+            "rep" | "repe" | "repz" => RepCond::WhileEqual,
+            "repne" | "repnz" => RepCond::WhileNotEqual,
+            _ => return None,
+        };
+        let size = match &insn[4..] {
+            "b" => 8,
+            "w" => 16,
+            "d" => 32,
+            "q" => 64,
+            _ => return None,
+        };
+        Some(RepInsn {
+            op: op,
+            cond: cond,
+            size: size,
+        })
+    }
+}
+
+/// Names of the counter, source and destination registers for addresses of `width` bits.
+fn string_regs(width: usize) -> (&'static str, &'static str, &'static str) {
+    match width {
+        16 => ("cx", "si", "di"),
+        32 => ("ecx", "esi", "edi"),
+        _ => ("rcx", "rsi", "rdi"),
+    }
+}
+
+fn accumulator(size: usize) -> &'static str {
+    match size {
+        8 => "al",
+        16 => "ax",
+        32 => "eax",
+        _ => "rax",
+    }
+}
+
+/// The 1 bit value of the boolean `b`, so that it is not asserted.
+fn bit<Ctx>(ctx: &mut Ctx, b: <Ctx as RegisterRead>::VarRef) -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let one = ctx.define_const(1, 1);
+    let zero = ctx.define_const(0, 1);
+    ctx.eval(core::OpCodes::ITE, vec![b, one, zero])
+}
+
+/// `then` if the 1 bit `guard` is set, `otherwise` if not.
+fn select<Ctx>(ctx: &mut Ctx,
+               guard: <Ctx as RegisterRead>::VarRef,
+               then: <Ctx as RegisterRead>::VarRef,
+               otherwise: <Ctx as RegisterRead>::VarRef)
+               -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let one = ctx.define_const(1, 1);
+    let set = ctx.eval(core::OpCodes::Cmp, vec![guard, one]);
+    ctx.eval(core::OpCodes::ITE, vec![set, then, otherwise])
+}
+
+/// Execute the repeated instruction `insn`, with at most `limit` iterations if the counter is
+/// symbolic.
+pub fn execute<Ctx>(ctx: &mut Ctx, insn: RepInsn, limit: u64)
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    let (counter_reg, src_reg, dst_reg) = string_regs(width);
+    let counter = ctx.reg_read(counter_reg);
+    let count = ctx.as_constant(counter.clone());
+    let iterations = match count {
+        Some(n) => n,
+        None => {
+            let bound = ctx.define_const(limit, width);
+            // Asserted, as a root of the expression graph.
+            ctx.eval(bitvec::OpCodes::BvULe, vec![counter.clone(), bound]);
+            limit
+        }
+    };
+    // Whether the iterations have to be guarded by the values of the counter and the flags.
+    let guarded = count.is_none() || insn.cond != RepCond::Always;
+
+    // The elements are walked down if the direction flag is set.
+    let bytes = (insn.size / 8) as u64;
+    let df = ctx.reg_read("df");
+    let delta = match ctx.as_constant(df.clone()) {
+        Some(0) => ctx.define_const(bytes, width),
+        Some(_) => ctx.define_const(bytes.wrapping_neg(), width),
+        None => {
+            let up = ctx.define_const(bytes, width);
+            let down = ctx.define_const(bytes.wrapping_neg(), width);
+            let set = ctx.define_const(1, 1);
+            let set = ctx.eval(core::OpCodes::Cmp, vec![df, set]);
+            ctx.eval(core::OpCodes::ITE, vec![set, down, up])
+        }
+    };
+
+    let mut src = ctx.reg_read(src_reg);
+    let mut dst = ctx.reg_read(dst_reg);
+    let mut acc = ctx.reg_read(accumulator(insn.size));
+    let mut remaining = counter;
+    let mut zf = ctx.reg_read("zf");
+    // Set if the iteration runs: the counter is not 0, and no comparison stopped the
+    // instruction.
+    let mut active = if guarded { Some(ctx.define_const(1, 1)) } else { None };
+
+    for _ in 0..iterations {
+        if let Some(a) = active.take() {
+            let zero = ctx.define_const(0, width);
+            let done = ctx.eval(core::OpCodes::Cmp, vec![remaining.clone(), zero]);
+            let more = ctx.eval(core::OpCodes::Not, vec![done]);
+            let more = bit(ctx, more);
+            active = Some(ctx.eval(bitvec::OpCodes::BvAnd, vec![a, more]));
+        }
+        // Whether this iteration runs, before its comparison decides about the next one.
+        let ran = active.clone();
+
+        match insn.op {
+            StringOp::Movs | StringOp::Stos => {
+                let value = if insn.op == StringOp::Movs {
+                    ctx.mem_read(src.clone(), insn.size)
+                } else {
+                    acc.clone()
+                };
+                let value = match ran {
+                    Some(ref g) => {
+                        let old = ctx.mem_read(dst.clone(), insn.size);
+                        select(ctx, g.clone(), value, old)
+                    }
+                    None => value,
+                };
+                ctx.mem_write(dst.clone(), value, insn.size);
+            }
+            StringOp::Lods => {
+                let value = ctx.mem_read(src.clone(), insn.size);
+                acc = guard(ctx, &ran, value, acc);
+            }
+            StringOp::Scas | StringOp::Cmps => {
+                let a = if insn.op == StringOp::Scas {
+                    acc.clone()
+                } else {
+                    ctx.mem_read(src.clone(), insn.size)
+                };
+                let b = ctx.mem_read(dst.clone(), insn.size);
+                let equal = ctx.eval(core::OpCodes::Cmp, vec![a, b]);
+                let equal = bit(ctx, equal);
+                zf = guard(ctx, &ran, equal.clone(), zf);
+                let next = if insn.cond == RepCond::WhileEqual {
+                    equal
+                } else {
+                    ctx.eval(bitvec::OpCodes::BvNot, vec![equal])
+                };
+                if let Some(a) = active.take() {
+                    active = Some(ctx.eval(bitvec::OpCodes::BvAnd, vec![a, next]));
+                }
+            }
+        }
+
+        let one = ctx.define_const(1, width);
+        let next = ctx.eval(bitvec::OpCodes::BvSub, vec![remaining.clone(), one]);
+        remaining = guard(ctx, &ran, next, remaining);
+        let next = ctx.eval(bitvec::OpCodes::BvAdd, vec![src.clone(), delta.clone()]);
+        src = guard(ctx, &ran, next, src);
+        let next = ctx.eval(bitvec::OpCodes::BvAdd, vec![dst.clone(), delta.clone()]);
+        dst = guard(ctx, &ran, next, dst);
+    }
+
+    ctx.reg_write(counter_reg, remaining);
+    match insn.op {
+        StringOp::Movs | StringOp::Cmps | StringOp::Lods => ctx.reg_write(src_reg, src),
+        _ => {}
+    }
+    match insn.op {
+        StringOp::Lods => ctx.reg_write(accumulator(insn.size), acc),
+        _ => ctx.reg_write(dst_reg, dst),
+    }
+    if insn.op == StringOp::Scas || insn.op == StringOp::Cmps {
+        ctx.reg_write("zf", zf);
+    }
+}
+
+/// `then` if the iteration guarded by `ran` runs, `otherwise` if not.
+fn guard<Ctx>(ctx: &mut Ctx,
+              ran: &Option<<Ctx as RegisterRead>::VarRef>,
+              then: <Ctx as RegisterRead>::VarRef,
+              otherwise: <Ctx as RegisterRead>::VarRef)
+              -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    match *ran {
+        Some(ref g) => select(ctx, g.clone(), then, otherwise),
+        None => then,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rep_insns() {
+        assert_eq!(RepInsn::parse("rep movsb byte [rdi], byte ptr [rsi]"),
+                   Some(RepInsn {
+                       op: StringOp::Movs,
+                       cond: RepCond::Always,
+                       size: 8,
+                   }));
+        assert_eq!(RepInsn::parse("rep stosq qword [rdi], rax").map(|i| (i.op, i.size)),
+                   Some((StringOp::Stos, 64)));
+        assert_eq!(RepInsn::parse("repne scasb al, byte [rdi]").map(|i| i.cond),
+                   Some(RepCond::WhileNotEqual));
+        assert_eq!(RepInsn::parse("repe cmpsd").map(|i| (i.op, i.cond, i.size)),
+                   Some((StringOp::Cmps, RepCond::WhileEqual, 32)));
+        assert_eq!(RepInsn::parse("movsb"), None);
+        assert_eq!(RepInsn::parse("rep ret"), None);
+        assert_eq!(RepInsn::parse("mov rax, rbx"), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Replay of a recorded path, see `Rune::replay`.
+//!
+//! A path is recorded as the addresses of its instructions and the outcomes of their branches,
+//! which `record` takes out of a trace. Replaying it follows the recorded branches instead of
+//! asking the explorer, and stops with `EngineError::Diverged` as soon as execution differs from
+//! the recording. The recording can be saved as JSON, to reproduce a finding later, or shortened
+//! to minimize it.
+
+use engine::engine::{EngineError, EngineResult};
+use engine::rune::RuneControl;
+use engine::trace::Trace;
+
+/// An instruction of a recorded path.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedStep {
+    pub addr: u64,
+    /// Whether the branches of the instruction were taken, in order.
+    pub branches: Vec<bool>,
+}
+
+/// How a replayed path differs from its recording.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    /// The instruction at `found` executed instead of the one at `expected`.
+    Address { expected: u64, found: u64 },
+    /// The recorded outcome of a branch of the instruction at `addr` is infeasible.
+    Branch { addr: u64, taken: bool },
+    /// The instruction at `addr` has `found` branches, `expected` were recorded.
+    BranchCount {
+        addr: u64,
+        expected: usize,
+        found: usize,
+    },
+    /// The indirect jump of the instruction at `addr` can not go to `target`.
+    Target { addr: u64, target: u64 },
+    /// There was nothing left to execute.
+    Ended,
+}
+
+/// The recording of the path that `trace` followed.
+pub fn record<V>(trace: &Trace<V>) -> Vec<RecordedStep> {
+    trace.iter()
+         .map(|entry| {
+             RecordedStep {
+                 addr: entry.addr,
+                 branches: entry.branches
+                                .iter()
+                                .map(|&(_, control)| control == RuneControl::ExploreTrue)
+                                .collect(),
+             }
+         })
+         .collect()
+}
+
+/// Progress of a replay.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    steps: Vec<RecordedStep>,
+    step: usize,
+    /// Branches of the current step that were followed.
+    branch: usize,
+}
+
+impl Replay {
+    pub fn new(steps: Vec<RecordedStep>) -> Replay {
+        Replay {
+            steps: steps,
+            step: 0,
+            branch: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step >= self.steps.len()
+    }
+
+    /// The error for `divergence` at the current step.
+    pub fn diverged(&self, divergence: Divergence) -> EngineError {
+        EngineError::Diverged {
+            step: self.step,
+            divergence: divergence,
+        }
+    }
+
+    /// Check that the instruction at `addr` is the one of the current step.
+    pub fn expect(&self, addr: u64) -> EngineResult<()> {
+        match self.steps.get(self.step) {
+            Some(step) if step.addr == addr => Ok(()),
+            Some(step) => {
+                Err(self.diverged(Divergence::Address {
+                    expected: step.addr,
+                    found: addr,
+                }))
+            }
+            None => Err(self.diverged(Divergence::Ended)),
+        }
+    }
+
+    /// Outcome of the next branch of the instruction at `addr`.
+    pub fn next_branch(&mut self, addr: u64) -> EngineResult<bool> {
+        let taken = self.steps.get(self.step).and_then(|s| s.branches.get(self.branch).cloned());
+        match taken {
+            Some(taken) => {
+                self.branch += 1;
+                Ok(taken)
+            }
+            None => {
+                let expected = self.steps.get(self.step).map_or(0, |s| s.branches.len());
+                Err(self.diverged(Divergence::BranchCount {
+                    addr: addr,
+                    expected: expected,
+                    found: self.branch + 1,
+                }))
+            }
+        }
+    }
+
+    /// Move on to the next step, once the instruction at `addr` of the current one executed.
+    pub fn finish_step(&mut self, addr: u64) -> EngineResult<()> {
+        self.expect(addr)?;
+        let expected = self.steps[self.step].branches.len();
+        if self.branch != expected {
+            return Err(self.diverged(Divergence::BranchCount {
+                addr: addr,
+                expected: expected,
+                found: self.branch,
+            }));
+        }
+        self.step += 1;
+        self.branch = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use engine::trace::TraceEntry;
+
+    fn entry(addr: u64, branches: Vec<(u32, RuneControl)>) -> TraceEntry<u32> {
+        TraceEntry {
+            addr: addr,
+            disasm: String::new(),
+            effects: Vec::new(),
+            branches: branches,
+        }
+    }
+
+    #[test]
+    fn replay_steps() {
+        let branches = vec![(0, RuneControl::ExploreFalse), (1, RuneControl::ExploreTrue)];
+        let trace = vec![entry(0x10, vec![]), entry(0x14, branches)];
+        let steps = record(&trace);
+        assert_eq!(steps[1],
+                   RecordedStep {
+                       addr: 0x14,
+                       branches: vec![false, true],
+                   });
+
+        let mut replay = Replay::new(steps);
+        replay.expect(0x10).unwrap();
+        replay.finish_step(0x10).unwrap();
+        match replay.expect(0x18) {
+            Err(EngineError::Diverged { step: 1, divergence }) => {
+                assert_eq!(divergence,
+                           Divergence::Address {
+                               expected: 0x14,
+                               found: 0x18,
+                           })
+            }
+            other => panic!("Expected a divergence, found {:?}", other),
+        }
+        assert_eq!(replay.next_branch(0x14).unwrap(), false);
+        match replay.finish_step(0x14) {
+            Err(EngineError::Diverged { divergence, .. }) => {
+                assert_eq!(divergence,
+                           Divergence::BranchCount {
+                               addr: 0x14,
+                               expected: 2,
+                               found: 1,
+                           })
+            }
+            other => panic!("Expected a divergence, found {:?}", other),
+        }
+        assert_eq!(replay.next_branch(0x14).unwrap(), true);
+        assert!(replay.next_branch(0x14).is_err());
+        replay.finish_step(0x14).unwrap();
+        assert!(replay.is_done());
+        assert!(replay.expect(0x18).is_err());
+    }
+}
@@ -2,11 +2,42 @@
 
 use r2api::structs::LOpInfo;
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+use std::usize;
+
 use context::context::{Context, RegisterRead};
+use memory::memory::Access;
+use memory::uninit::UninitPolicy;
 use explorer::explorer::PathExplorer;
-use stream::InstructionStream;
+use explorer::target::Cfg;
+use stream::{InstructionStream, IsaMode, MAX_INSN_SIZE};
 use engine::engine::{Engine, EngineError, EngineResult};
-use esil::lexer::{Token, Tokenizer};
+use engine::branch_inputs::BranchInputs;
+use engine::budget::{Budget, Usage};
+use engine::cache::{CachedTokenizer, DecodeCache, Decoded, Flow};
+use engine::esil_op::{EsilOp, EsilOps};
+use engine::dashboard::{Dashboard, ExplorationStats};
+use engine::differential::{self, Differential, Report, Snapshot};
+use engine::events::{Event, Subscriber};
+use engine::fallback::{self, ConcreteExecutor};
+use engine::function::{self, FunctionReturn, FunctionSetup};
+use engine::fault::{Fault, FaultKind, FaultedState};
+use engine::float::{self, EsilPart, FloatMode};
+use engine::indirect;
+use engine::rep::{self, RepInsn};
+use engine::atomic::{self, AtomicInsn};
+use engine::cpu::{self, CpuInsn, CpuModel};
+use engine::pc_trace::Guide;
+use engine::replay::{Divergence, RecordedStep, Replay};
+use engine::summary::{self, Summary};
+use engine::syscall::{self, SyscallAction, SyscallHandler};
+use engine::trace::{Effect, Trace, TraceEntry};
+use engine::lifter::{Lifted, Lifter, Semantics};
+use engine::hook::{Hook, HookId, HookPoint, HookTarget, InsnHook, InsnHooks};
+use esil::lexer::{Token, Tokenize};
 use esil::parser::{Parse, Parser};
 
 use libsmt::theories::{bitvec, core};
@@ -37,6 +68,65 @@ pub struct Rune<Ctx, Exp, S>
     intermediates: Vec<<Ctx as RegisterRead>::VarRef>,
     stream: S,
     skip: bool,
+    /// Hooks that are run instead of the instruction at their address.
+    hooks: HashMap<u64, Hook<Ctx>>,
+    /// Summaries that are applied instead of the instruction at their address.
+    summaries: HashMap<u64, Summary>,
+    /// Hooks that are run in addition to instructions.
+    insn_hooks: InsnHooks<Ctx>,
+    /// Operations that plugins of r2 add to ESIL.
+    esil_ops: EsilOps<Ctx, <Ctx as RegisterRead>::VarRef>,
+    /// Instruction set that `stream` currently decodes.
+    mode: IsaMode,
+    /// Address and size of the instruction being executed.
+    current: (u64, u64),
+    /// Whether the instruction being executed calls or returns.
+    flow: Option<Flow>,
+    float_mode: FloatMode,
+    rep_limit: u64,
+    /// Targets of a jump to a symbolic address at most, see `engine::indirect`.
+    jump_targets: usize,
+    cpu_model: CpuModel,
+    /// Instructions decoded so far.
+    decoded: DecodeCache,
+    /// Executes the instructions that rune does not support.
+    fallback: Option<Box<ConcreteExecutor>>,
+    /// Semantics of instructions besides the ESIL of r2.
+    lifter: Option<Box<Lifter<Ctx>>>,
+    /// Closures of the instructions that the lifter lifted, by `Decoded::native`.
+    natives: Vec<Semantics<Ctx>>,
+    /// Models the system calls, instead of their ESIL.
+    syscalls: Option<Box<SyscallHandler<Ctx>>>,
+    /// Parser of the ESIL of the current instruction, `None` while an instruction executes.
+    parser: Option<Parser>,
+    /// Branches of the current instruction.
+    branches: Vec<(<Ctx as RegisterRead>::VarRef, RuneControl)>,
+    /// Whether there is nothing left to execute.
+    halted: bool,
+    /// Whether faults end paths instead of being errors.
+    fault_states: bool,
+    /// Fault raised by the current instruction.
+    fault: Option<Fault>,
+    /// Paths that ended with a fault.
+    faulted: Vec<FaultedState<Ctx>>,
+    /// Path being replayed, which decides the branches instead of the explorer.
+    replay: Option<Replay>,
+    /// Instruction of a followed trace, which decides the symbolic branches and jumps.
+    guide: Option<Guide>,
+    budget: Budget,
+    /// Work done since the budget was set.
+    usage: Usage,
+    subscribers: Vec<Box<Subscriber<Ctx>>>,
+    /// Instructions executed so far, while there are subscribers.
+    covered: HashSet<u64>,
+    solver_timeout: Option<Duration>,
+    /// Branch to solve for inputs at, and the inputs found so far, see `branch_inputs`.
+    probe: Option<(u64, BranchInputs)>,
+    /// Time the path spent in the solver before the current instruction.
+    solver_time: Duration,
+    dashboard: Dashboard,
+    /// Paths that ended without a fault, if `set_history` was called.
+    ended: Option<Vec<Ctx>>,
 }
 
 
@@ -53,6 +143,469 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
             intermediates: Vec::new(),
             stream: stream,
             skip: false,
+            hooks: HashMap::new(),
+            summaries: HashMap::new(),
+            insn_hooks: InsnHooks::default(),
+            esil_ops: EsilOps::default(),
+            mode: IsaMode::Normal,
+            current: (0, 0),
+            flow: None,
+            float_mode: Default::default(),
+            rep_limit: rep::DEFAULT_REP_LIMIT,
+            jump_targets: indirect::DEFAULT_JUMP_TARGETS,
+            cpu_model: CpuModel::default(),
+            decoded: DecodeCache::default(),
+            fallback: None,
+            lifter: None,
+            natives: Vec::new(),
+            syscalls: None,
+            parser: None,
+            branches: Vec::new(),
+            halted: false,
+            fault_states: false,
+            fault: None,
+            faulted: Vec::new(),
+            replay: None,
+            guide: None,
+            budget: Budget::default(),
+            usage: Usage::default(),
+            subscribers: Vec::new(),
+            covered: HashSet::new(),
+            solver_timeout: None,
+            probe: None,
+            solver_time: Duration::default(),
+            dashboard: Dashboard::default(),
+            ended: None,
+        }
+    }
+
+    /// Run `hook` whenever execution reaches `addr`, instead of the instruction at `addr`.
+    pub fn add_hook(&mut self, addr: u64, hook: Hook<Ctx>) {
+        self.hooks.insert(addr, hook);
+    }
+
+    /// Apply `summary` whenever execution reaches `addr`, instead of the instruction at `addr`,
+    /// see `engine::summary`.
+    pub fn add_summary(&mut self, addr: u64, summary: Summary) {
+        self.summaries.insert(addr, summary);
+    }
+
+    pub fn remove_summary(&mut self, addr: u64) -> bool {
+        self.summaries.remove(&addr).is_some()
+    }
+
+    /// Run `hook` at `point` of the instructions `target`, in addition to them.
+    pub fn add_insn_hook(&mut self,
+                         target: HookTarget,
+                         point: HookPoint,
+                         hook: InsnHook<Ctx>)
+                         -> HookId {
+        self.insn_hooks.add(target, point, hook)
+    }
+
+    pub fn remove_insn_hook(&mut self, id: HookId) -> bool {
+        self.insn_hooks.remove(id)
+    }
+
+    fn run_insn_hooks(&mut self, ip: u64, point: HookPoint) -> EngineResult<()> {
+        if self.insn_hooks.is_empty() {
+            return Ok(());
+        }
+        for hook in self.insn_hooks.at(ip, point) {
+            hook(&mut self.ctx, ip)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluate the ESIL operation `name` of `arity` operands with `op`, see `engine::esil_op`.
+    pub fn add_esil_op(&mut self,
+                       name: &str,
+                       arity: usize,
+                       op: EsilOp<Ctx, <Ctx as RegisterRead>::VarRef>) {
+        self.esil_ops.add(name, arity, op);
+        // Expressions are split at the operations when they are decoded.
+        self.decoded.clear();
+    }
+
+    pub fn remove_esil_op(&mut self, name: &str) -> bool {
+        self.decoded.clear();
+        self.esil_ops.remove(name)
+    }
+
+    /// Take the semantics of instructions from `lifter`, and the ESIL of r2 for those it does
+    /// not lift, see `engine::lifter`.
+    pub fn set_lifter(&mut self, lifter: Box<Lifter<Ctx>>) {
+        self.lifter = Some(lifter);
+        self.decoded.clear();
+        self.natives.clear();
+    }
+
+    /// Name of the representation the semantics of instructions come from.
+    pub fn lifter_name(&self) -> &str {
+        self.lifter.as_ref().map_or("esil", |lifter| lifter.name())
+    }
+
+    /// Execute the instructions that rune can not execute symbolically with `executor`,
+    /// instead of stopping, see `engine::fallback`.
+    pub fn set_fallback(&mut self, executor: Box<ConcreteExecutor>) {
+        self.fallback = Some(executor);
+    }
+
+    /// Model system calls with `handler` instead of executing the instructions that make them,
+    /// see `engine::syscall`.
+    pub fn set_syscall_handler(&mut self, handler: Box<SyscallHandler<Ctx>>) {
+        self.syscalls = Some(handler);
+    }
+
+    /// Assume repeated string instructions (`rep movsb`, ...) with a symbolic counter to
+    /// repeat at most `limit` times, see `engine::rep`.
+    pub fn set_rep_limit(&mut self, limit: u64) {
+        self.rep_limit = limit;
+    }
+
+    /// Fork a path that jumps to a symbolic address into `limit` paths at most, one for each
+    /// address it may jump to, see `engine::indirect`.
+    pub fn set_jump_targets(&mut self, limit: usize) {
+        self.jump_targets = limit;
+    }
+
+    /// Return the results of `model` for `cpuid`, `rdtsc` and `rdrand`, see `engine::cpu`.
+    pub fn set_cpu_model(&mut self, model: CpuModel) {
+        self.cpu_model = model;
+    }
+
+    /// End paths that divide by zero, access memory that is not mapped or that its permissions
+    /// do not allow, misuse the heap, or execute invalid instructions with a faulted state (see
+    /// `engine::fault`), instead of stopping with an error.
+    pub fn set_fault_states(&mut self, on: bool) {
+        self.fault_states = on;
+        self.ctx.set_strict_mapping(on);
+    }
+
+    /// Paths that ended with a fault so far, see `set_fault_states`.
+    pub fn faulted(&self) -> &[FaultedState<Ctx>] {
+        &self.faulted
+    }
+
+    fn add_faulted(&mut self, mut ctx: Ctx, fault: Fault) {
+        self.explorer.faulted(&mut ctx, &fault);
+        let event = Event::Died {
+            ip: fault.ip,
+            fault: Some(fault.kind.clone()),
+        };
+        self.dashboard.record(&event);
+        for subscriber in &mut self.subscribers {
+            subscriber.notify(&ctx, &event);
+        }
+        self.faulted.push(FaultedState {
+            fault: fault,
+            ctx: ctx,
+        });
+    }
+
+    /// End the current path with `fault`, and continue with the next one.
+    fn end_with_fault(&mut self, fault: Fault) {
+        let ctx = self.ctx.clone();
+        self.add_faulted(ctx, fault);
+        self.next_path();
+    }
+
+    /// End the current path, and continue with the next one.
+    fn end_path(&mut self) {
+        let ip = self.ctx.ip();
+        self.notify(Event::Died {
+            ip: ip,
+            fault: None,
+        });
+        if let Some(ref mut ended) = self.ended {
+            ended.push(self.ctx.clone());
+        }
+        self.next_path();
+    }
+
+    fn next_path(&mut self) {
+        self.skip = false;
+        if self.explorer.next_job(&mut self.ctx).is_none() {
+            self.halted = true;
+        }
+    }
+
+    /// Fork the state where `divisor` is zero off the current path, as a faulted state. Returns
+    /// false if the divisor is zero on every path, which ends the path once the instruction
+    /// finishes.
+    fn check_divisor(&mut self, divisor: <Ctx as RegisterRead>::VarRef) -> bool {
+        let fault = Fault {
+            ip: self.current.0,
+            kind: FaultKind::DivisionByZero,
+        };
+        match self.ctx.as_constant(divisor.clone()) {
+            Some(0) => {
+                self.fault = Some(fault);
+                return false;
+            }
+            Some(_) => return true,
+            None => {}
+        }
+
+        let width = self.ctx.address_width();
+        let zero = self.ctx.define_const(0, width);
+        let is_zero = self.ctx.eval(core::OpCodes::Cmp, vec![divisor, zero]);
+        let one = self.ctx.define_const(1, 1);
+        let zero = self.ctx.define_const(0, 1);
+        let is_zero = self.ctx.eval(core::OpCodes::ITE, vec![is_zero, one.clone(), zero.clone()]);
+        if !self.ctx.is_feasible(is_zero.clone()) {
+            return true;
+        }
+        let nonzero = self.ctx.eval(bitvec::OpCodes::BvNot, vec![is_zero.clone()]);
+        if !self.ctx.is_feasible(nonzero) {
+            self.fault = Some(fault);
+            return false;
+        }
+
+        let mut state = self.ctx.clone();
+        let constraint = state.eval(core::OpCodes::Cmp, vec![is_zero.clone(), one]);
+        state.record_branch(is_zero.clone(), constraint, true);
+        self.add_faulted(state, fault);
+        let constraint = self.ctx.eval(core::OpCodes::Cmp, vec![is_zero.clone(), zero]);
+        self.ctx.record_branch(is_zero, constraint, false);
+        true
+    }
+
+    /// Tell `subscriber` of the events of the exploration from now on, see `engine::events`.
+    pub fn subscribe(&mut self, subscriber: Box<Subscriber<Ctx>>) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Report the instructions whose solver queries take longer than `timeout` to the
+    /// subscribers. The queries are not interrupted.
+    pub fn set_solver_timeout(&mut self, timeout: Option<Duration>) {
+        self.solver_timeout = timeout;
+    }
+
+    /// Send the statistics of the exploration to the subscribers every `interval`, as
+    /// `Event::Progress`, or never.
+    pub fn set_progress_interval(&mut self, interval: Option<Duration>) {
+        self.dashboard.set_interval(interval);
+    }
+
+    /// Count the blocks of `cfg` in the statistics, instead of the instructions, from now on.
+    pub fn set_stats_cfg(&mut self, cfg: Cfg) {
+        self.dashboard = Dashboard::new(cfg);
+    }
+
+    /// Statistics of the exploration so far, see `engine::dashboard`.
+    pub fn stats(&self) -> ExplorationStats {
+        let current = if self.halted { 0 } else { 1 };
+        self.dashboard.stats(self.explorer.waiting().map(|n| n + current))
+    }
+
+    /// Record the steps of the current path and of its forks from now on, and keep the paths
+    /// once they end, so that their state at any step can be inspected (see
+    /// `RuneContext::seek`). Paths that end with a fault are kept with `faulted`.
+    pub fn set_history(&mut self) {
+        self.ctx.start_history();
+        if self.ended.is_none() {
+            self.ended = Some(Vec::new());
+        }
+    }
+
+    /// Paths that ended without a fault since `set_history` was called.
+    pub fn ended_paths(&self) -> &[Ctx] {
+        self.ended.as_ref().map_or(&[], |ended| &ended[..])
+    }
+
+    fn notify(&mut self, event: Event) {
+        self.dashboard.record(&event);
+        for subscriber in &mut self.subscribers {
+            subscriber.notify(&self.ctx, &event);
+        }
+    }
+
+    /// Limit the rest of the run to `budget`, counting from now.
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+        self.usage = Usage::default();
+    }
+
+    /// Work done since the budget was set.
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Set how floating point operations are evaluated, see `engine::float`.
+    pub fn set_float_mode(&mut self, mode: FloatMode) {
+        self.float_mode = mode;
+    }
+
+    /// Install the hooks for the imported functions they are named after. `imports` maps
+    /// function names to their address, see `hook::imports`.
+    pub fn hook_imports(&mut self, imports: &HashMap<String, u64>, hooks: &[(&str, Hook<Ctx>)]) {
+        for &(name, hook) in hooks {
+            if let Some(addr) = imports.get(name) {
+                self.add_hook(*addr, hook);
+            }
+        }
+    }
+
+    /// Report errors and watchpoint hits caused by the instruction at `ip`, and let the explorer
+    /// decide how to continue.
+    fn finish_instruction(&mut self, ip: u64) -> EngineResult<()> {
+        let flow = self.flow.take();
+        let solver_time = self.ctx.solver_time().checked_sub(self.solver_time).unwrap_or_default();
+        self.dashboard.add_solver_time(solver_time);
+        if let Some((slot, target)) = self.ctx.delayed_branch() {
+            if slot == ip {
+                self.ctx.set_delayed_branch(None);
+                self.ctx.set_ip(target);
+            }
+        }
+
+        if let Some(violation) = self.ctx.take_violation() {
+            if !self.fault_states {
+                return Err(EngineError::AccessViolation(violation));
+            }
+            self.fault = Some(Fault {
+                ip: ip,
+                kind: violation.into(),
+            });
+        }
+
+        if let Some(e) = self.ctx.take_heap_error() {
+            if !self.fault_states {
+                return Err(EngineError::HeapError(e));
+            }
+            if self.fault.is_none() {
+                self.fault = Some(Fault {
+                    ip: ip,
+                    kind: FaultKind::Heap(e),
+                });
+            }
+        }
+
+        if let Some(e) = self.ctx.take_concretization_error() {
+            if !self.fault_states {
+                return Err(EngineError::Concretization(e));
+            }
+            if self.fault.is_none() {
+                self.fault = Some(Fault {
+                    ip: ip,
+                    kind: FaultKind::Concretization(e),
+                });
+            }
+        }
+
+        if let Some(fault) = self.fault.take() {
+            self.end_with_fault(fault);
+            return Ok(());
+        }
+
+        if let Some(flow) = flow {
+            self.record_flow(ip, flow);
+        }
+
+        if let Some(timeout) = self.solver_timeout {
+            if solver_time > timeout {
+                self.notify(Event::SolverTimeout {
+                    ip: ip,
+                    time: solver_time,
+                });
+            }
+        }
+
+        if let Some(fault) = self.ctx.take_reg_fault() {
+            return Err(EngineError::RegisterFault(fault));
+        }
+
+        let mut reads = self.ctx.take_uninit_reads();
+        if !reads.is_empty() {
+            for read in &mut reads {
+                read.ip = ip;
+            }
+            if self.ctx.uninit_policy() == UninitPolicy::Error {
+                return Err(EngineError::UninitializedRead(reads[0]));
+            }
+            self.explorer.uninit_read(&mut self.ctx, &reads);
+        }
+
+        let mut hits = self.ctx.take_watch_hits();
+        if !hits.is_empty() {
+            // The context has already advanced ip past the instruction.
+            for hit in &mut hits {
+                hit.ip = ip;
+            }
+            self.explorer.watchpoint_hit(&mut self.ctx, &hits);
+        }
+
+        let values = self.ctx.eval_watch_exprs(ip);
+        if !values.is_empty() {
+            self.explorer.watch_values(&mut self.ctx, &values);
+        }
+
+        match self.explorer.next(&mut self.ctx) {
+            RuneControl::Continue => {}
+            RuneControl::TerminatePath => {
+                // The explorer dropped the path.
+                self.end_path();
+            }
+            _ => unimplemented!(),
+        }
+        Ok(())
+    }
+
+    /// Update the call stack with the call or return made by the instruction at `ip`, unless
+    /// the instruction was conditional and did not branch.
+    fn record_flow(&mut self, ip: u64, flow: Flow) {
+        let fallthrough = ip + self.current.1;
+        let delays = self.ctx.arch().map_or(false, |arch| arch.has_delay_slots());
+        let (target, return_addr) = match self.ctx.delayed_branch() {
+            // Calls return past their delay slot.
+            Some((slot, target)) if slot == fallthrough => (target, slot + self.current.1),
+            _ if delays => return,
+            _ => (self.ctx.ip(), fallthrough),
+        };
+        if target == fallthrough {
+            return;
+        }
+        match flow {
+            Flow::Call => self.ctx.record_call(ip, target, return_addr),
+            Flow::Return => {
+                if let Err(bad) = self.ctx.record_return(ip, target) {
+                    self.explorer.bad_return(&mut self.ctx, &bad);
+                }
+            }
+        }
+    }
+
+    /// On architectures with delay slots, hold back the branch that the instruction at `ip`,
+    /// of `size` bytes, took until the next instruction has executed. A branch that annuls its
+    /// delay slot skips it instead if it is not taken.
+    fn delay_branch(&mut self, ip: u64, size: u64, opcode: &str) {
+        let arch = match self.ctx.arch() {
+            Some(arch) => arch,
+            None => return,
+        };
+        if !arch.has_delay_slots() || self.ctx.delayed_branch().is_some() {
+            // A branch in a delay slot is not delayed again.
+            return;
+        }
+        let slot = ip + size;
+        let target = self.ctx.ip();
+        if target != slot {
+            self.ctx.set_delayed_branch(Some((slot, target)));
+            self.ctx.set_ip(slot);
+        } else if arch.annuls_delay_slot(opcode) {
+            // Instructions are of the same size on these architectures.
+            self.ctx.set_ip(slot + size);
+        }
+    }
+
+    /// Value of the program counter in the current instruction. The context has already been
+    /// advanced past it, which is the value on architectures that do not say otherwise.
+    fn pc_value(&mut self) -> u64 {
+        let (addr, size) = self.current;
+        match self.ctx.arch() {
+            Some(arch) => arch.pc_value(addr, size, self.mode),
+            None => self.ctx.ip(),
         }
     }
 
@@ -62,25 +615,35 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
         if t.is_none() {
             return Ok(None);
         }
+        let width = self.ctx.address_width();
         let read = match *t.unwrap() {
             Token::ERegister(ref name) | Token::EIdentifier(ref name) => {
-                if self.ctx.alias_of(name.clone()) == Some("PC".to_owned()) {
-                    let ip = self.ctx.ip();
-                    self.ctx.define_const(ip, 64)
+                if !self.ctx.has_reg(name) {
+                    // Words the lexer does not know and that are not `STACK_OPS`, such as
+                    // `RPICK`, end up as identifiers.
+                    return Err(EngineError::UnsupportedEsil {
+                        op: name.clone(),
+                        insn_addr: self.current.0,
+                    });
+                }
+                if self.ctx.is_pc(name) {
+                    let pc = self.pc_value();
+                    self.ctx.define_const(pc, width)
                 } else {
                     self.ctx.reg_read(name)
                 }
             }
-            Token::EEntry(ref id, Some(64)) => self.intermediates[*id].clone(),
-            Token::EConstant(value) => self.ctx.define_const(value, 64),
+            Token::EEntry(ref id, _) => self.intermediates[*id].clone(),
+            Token::EConstant(value) => self.ctx.define_const(value, width),
             Token::EAddress => {
-                let ip = self.ctx.ip();
-                self.ctx.define_const(ip, 64)
+                // `$$` is the address of the current instruction.
+                let addr = self.current.0;
+                self.ctx.define_const(addr, width)
             }
             Token::EOld => self.ctx.e_old(),
             Token::ECur => self.ctx.e_cur(),
-            Token::ELastsz => self.ctx.define_const(64, 64),
-            _ => unreachable!(),
+            Token::ELastsz => self.ctx.define_const(width as u64, width),
+            ref token => return Err(self.unsupported(token)),
         };
         Ok(Some(read))
     }
@@ -102,6 +665,18 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
             return Ok(None);
         }
 
+        match token {
+            Token::EBreak => {
+                // Stop evaluating the expression of this instruction.
+                *control = RuneControl::Break;
+                return Ok(None);
+            }
+            Token::EInterrupt | Token::ETrap | Token::EGoto | Token::ETodo | Token::EInvalid => {
+                return Err(self.unsupported(&token));
+            }
+            _ => {}
+        }
+
         // asserts to check validity.
         if token.is_arity_zero() {
             return Ok(None);
@@ -109,8 +684,8 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
         // println!("****");
 
         // println!("OPERANDS TO {:?}: {:?} {:?}", token, lhs, rhs);
-        let l_op = self.process_in(lhs.as_ref()).expect("LHS is ERR");
-        let r_op = self.process_in(rhs.as_ref()).expect("RHS is ERR");
+        let l_op = self.process_in(lhs.as_ref())?;
+        let r_op = self.process_in(rhs.as_ref())?;
         // Since the operator arity us _atleast_ one. assert! that lhs is some.
         assert!(l_op.is_some());
         if token.is_binary() {
@@ -122,9 +697,22 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
         match token {
             Token::EEq => {
                 let res = if let Some(Token::EIdentifier(ref reg)) = lhs {
-                    if self.ctx.alias_of(reg.clone()) == Some("PC".to_owned()) {
+                    if self.ctx.is_pc(reg) {
                         if let Token::EConstant(const_) = rhs.unwrap() {
                             self.ctx.set_ip(const_);
+                        } else if let Some(target) = self.ctx.as_constant(r_op.clone().unwrap()) {
+                            // Branches through a register interwork: bit 0 of the target
+                            // selects Thumb.
+                            let thumb = if target & 1 == 1 { IsaMode::Thumb } else { IsaMode::Normal };
+                            if self.ctx.set_isa_mode(thumb) {
+                                self.ctx.set_ip(target & !1);
+                            } else {
+                                self.ctx.set_ip(target);
+                            }
+                        } else if let Some(next) = self.guide.as_ref().and_then(|g| g.next) {
+                            self.resolve_jump(r_op.unwrap(), next)?;
+                        } else {
+                            self.fork_jump(r_op.unwrap());
                         }
                     } else {
                         // println!("REGISTER WRITE: {:?} = {:?}", reg, r_op);
@@ -137,7 +725,38 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                 return res;
             }
             Token::EIf => {
-                *control = self.explorer.register_branch(&mut self.ctx, l_op.unwrap());
+                let condition = l_op.unwrap();
+                if let Some((addr, ref mut inputs)) = self.probe {
+                    if addr == self.current.0 {
+                        inputs.fill(&self.ctx, condition.clone());
+                    }
+                }
+                let taken = match self.replay {
+                    Some(ref mut replay) => Some(replay.next_branch(self.current.0)?),
+                    None => None,
+                };
+                let taken = match (taken, self.guide.is_some()) {
+                    (None, true) => {
+                        // Only the symbolic branches are up to the trace.
+                        match self.ctx.as_constant(condition.clone()) {
+                            Some(c) => Some(c != 0),
+                            None => Some(self.guide.as_mut().unwrap().next_branch()),
+                        }
+                    }
+                    (taken, _) => taken,
+                };
+                *control = match taken {
+                    Some(taken) => self.follow_branch(condition.clone(), taken)?,
+                    None => {
+                        if self.ctx.as_constant(condition.clone()).is_none() {
+                            self.usage.forks += 1;
+                            let ip = self.current.0;
+                            self.notify(Event::Forked { ip: ip });
+                        }
+                        self.explorer.register_branch(&mut self.ctx, condition.clone())
+                    }
+                };
+                self.branches.push((condition, *control));
                 return Ok(None);
             }
             Token::EPoke(size) => {
@@ -161,15 +780,27 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                                                r_op.as_ref().unwrap().clone()]);
                 self.ctx.set_e_cur(e_cur);
                 self.ctx.set_e_old(l_op.as_ref().unwrap().clone());
-                let const_0 = self.ctx.define_const(0, 64);
-                let const_1 = self.ctx.define_const(1, 64);
-                let eq = self.ctx.eval(token.to_smt(), vec![l_op.unwrap(), r_op.unwrap()]);
+                let width = self.ctx.address_width();
+                let const_0 = self.ctx.define_const(0, width);
+                let const_1 = self.ctx.define_const(1, width);
+                let op = token.to_smt().ok_or_else(|| self.unsupported(&token))?;
+                let eq = self.ctx.eval(op, vec![l_op.unwrap(), r_op.unwrap()]);
                 self.ctx.eval(core::OpCodes::ITE, vec![eq, const_1, const_0])
             }
-            Token::EPop => unimplemented!(),
-            Token::EGoto => unimplemented!(),
-            Token::EBreak => unimplemented!(),
+            // The parser already took the operand off the stack.
+            Token::EPop => return Ok(None),
+            // Pushed twice by `run`.
+            Token::EDup => l_op.unwrap(),
+            Token::ERol | Token::ERor => {
+                self.rotate(token == Token::ERol, l_op.unwrap(), r_op.unwrap())
+            }
+            Token::EDiv | Token::EMod if self.fault_states &&
+                                         !self.check_divisor(r_op.clone().unwrap()) => {
+                *control = RuneControl::TerminatePath;
+                return Ok(None);
+            }
             _ => {
+                let op = token.to_smt().ok_or_else(|| self.unsupported(&token))?;
                 let operands = {
                     if token.is_unary() {
                         vec![l_op.unwrap()]
@@ -177,93 +808,1037 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                         vec![l_op.unwrap(), r_op.unwrap()]
                     }
                 };
-                self.ctx.eval(token.to_smt(), operands)
+                self.ctx.eval(op, operands)
             }
         };
 
         Ok(Some(result))
     }
 
-    // Write out to intermediates and return a token to it.
-    fn process_out(&mut self, res: &<Ctx as RegisterRead>::VarRef) -> Token {
-        self.intermediates.push(res.clone());
-        Token::EEntry(self.intermediates.len() - 1, Some(64))
+    /// The error for `divergence` of the replayed path or the followed trace.
+    fn diverged(&self, divergence: Divergence) -> EngineError {
+        match self.replay {
+            Some(ref replay) => replay.diverged(divergence),
+            None => self.guide.as_ref().unwrap().diverged(divergence),
+        }
     }
-}
 
-impl<Ctx, Exp, S> Engine for Rune<Ctx, Exp, S>
-where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
-      Exp: PathExplorer<C = RuneControl, Ctx = Ctx>,
-      S: InstructionStream<Output = LOpInfo, Index = u64>
-{
-    fn run(&mut self) -> EngineResult<()> {
-        let mut p = Parser::init(None, Some(64));
+    /// Assume the branch `condition` of a replayed path, or of a followed trace, to be `taken`.
+    fn follow_branch(&mut self,
+                     condition: <Ctx as RegisterRead>::VarRef,
+                     taken: bool)
+                     -> EngineResult<RuneControl> {
+        let side = if taken {
+            condition.clone()
+        } else {
+            self.ctx.eval(bitvec::OpCodes::BvNot, vec![condition.clone()])
+        };
+        if !self.ctx.is_feasible(side) {
+            return Err(self.diverged(Divergence::Branch {
+                addr: self.current.0,
+                taken: taken,
+            }));
+        }
+        let value = self.ctx.define_const(taken as u64, 1);
+        let constraint = self.ctx.eval(core::OpCodes::Cmp, vec![condition.clone(), value]);
+        self.ctx.record_branch(condition, constraint, taken);
+        Ok(if taken { RuneControl::ExploreTrue } else { RuneControl::ExploreFalse })
+    }
+
+    /// Assume the symbolic `target` of a jump of a followed trace to be `next`, where the trace
+    /// continues.
+    fn resolve_jump(&mut self,
+                    target: <Ctx as RegisterRead>::VarRef,
+                    next: u64)
+                    -> EngineResult<()> {
+        let condition = indirect::goes_to(&mut self.ctx, target.clone(), next);
+        if !self.ctx.is_feasible(condition) {
+            return Err(self.diverged(Divergence::Target {
+                addr: self.current.0,
+                target: next,
+            }));
+        }
+        indirect::assume(&mut self.ctx, target, next);
+        self.ctx.set_ip(next);
+        Ok(())
+    }
+
+    /// Fork the path at the jump to the symbolic `target` of the current instruction, once for
+    /// each address that it may go to, see `engine::indirect`. The jump is left alone if it may
+    /// go nowhere, as on an infeasible path.
+    fn fork_jump(&mut self, target: <Ctx as RegisterRead>::VarRef) {
+        let targets = indirect::targets(&mut self.ctx, target.clone(), self.jump_targets);
+        let (&first, others) = match targets.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        if !others.is_empty() {
+            let ip = self.current.0;
+            self.notify(Event::Forked { ip: ip });
+        }
+        for &addr in others {
+            let mut fork = self.ctx.clone();
+            indirect::assume(&mut fork, target.clone(), addr);
+            self.finish_jump(&mut fork, addr);
+            if !self.explorer.add_state(fork) {
+                break;
+            }
+        }
+        indirect::assume(&mut self.ctx, target, first);
+        self.ctx.set_ip(first);
+    }
+
+    /// Jump to `addr` in `fork`, a state forked off the current instruction, as the rest of
+    /// the instruction and `finish_instruction` would: through the delay slot, and recording
+    /// the call or return.
+    fn finish_jump(&mut self, fork: &mut Ctx, addr: u64) {
+        let (ip, size) = self.current;
+        if let Some((slot, target)) = fork.delayed_branch() {
+            if slot == ip {
+                // A branch in a delay slot is overridden by the delayed one.
+                fork.set_delayed_branch(None);
+                fork.set_ip(target);
+                return;
+            }
+        }
+        let return_addr = if fork.arch().map_or(false, |arch| arch.has_delay_slots()) {
+            fork.set_delayed_branch(Some((ip + size, addr)));
+            fork.set_ip(ip + size);
+            ip + 2 * size
+        } else {
+            fork.set_ip(addr);
+            ip + size
+        };
+        match self.flow {
+            Some(Flow::Call) => fork.record_call(ip, addr, return_addr),
+            Some(Flow::Return) => {
+                if let Err(bad) = fork.record_return(ip, addr) {
+                    self.explorer.bad_return(fork, &bad);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Rotate `value` left (or right) by `amount` bits, modulo the width of ESIL values.
+    fn rotate(&mut self,
+              left: bool,
+              value: <Ctx as RegisterRead>::VarRef,
+              amount: <Ctx as RegisterRead>::VarRef)
+              -> <Ctx as RegisterRead>::VarRef {
+        let width = self.ctx.address_width();
+        let bits = self.ctx.define_const(width as u64, width);
+        let amount = self.ctx.eval(bitvec::OpCodes::BvURem, vec![amount, bits.clone()]);
+        // The amount of the opposite shift is (width - amount) % width, so that rotating by
+        // zero does not shift by the full width.
+        let rest = self.ctx.eval(bitvec::OpCodes::BvSub, vec![bits.clone(), amount.clone()]);
+        let rest = self.ctx.eval(bitvec::OpCodes::BvURem, vec![rest, bits]);
+        let (first, second) = if left {
+            (bitvec::OpCodes::BvShl, bitvec::OpCodes::BvLShr)
+        } else {
+            (bitvec::OpCodes::BvLShr, bitvec::OpCodes::BvShl)
+        };
+        let a = self.ctx.eval(first, vec![value.clone(), amount]);
+        let b = self.ctx.eval(second, vec![value, rest]);
+        self.ctx.eval(bitvec::OpCodes::BvOr, vec![a, b])
+    }
+
+    fn unsupported(&self, token: &Token) -> EngineError {
+        self.unsupported_op(&format!("{:?}", token))
+    }
+
+    fn unsupported_op(&self, op: &str) -> EngineError {
+        EngineError::UnsupportedEsil {
+            op: op.to_owned(),
+            insn_addr: self.current.0,
+        }
+    }
+
+    /// Evaluate the ESIL `esil`, which the lexer knows. Returns false if the rest of the
+    /// expression of the instruction should not be evaluated.
+    fn eval_esil(&mut self, p: &mut Parser, esil: &str) -> EngineResult<bool> {
         let mut control = RuneControl::Continue;
+        while let Some(ref token) = p.parse::<_, CachedTokenizer>(esil) {
+            // println!("{:?}", token);
+            // If skip is active, we do not want to modify the esil stack
+            let (lhs, rhs) = if self.skip {
+                (None, None)
+            } else {
+                p.fetch_operands(token)
+            };
 
-        loop {
-            // println!("{}", self.ctx.ip());
-            let opinfo = if let Some(opinfo_) = self.stream.at(self.ctx.ip()) {
-                opinfo_
-            } else if self.explorer.next_job(&mut self.ctx).is_some() {
-                // Request for next instruction from queue
-                self.stream.at(self.ctx.ip()).unwrap()
+            if let Some(ref res) = self.process_op(token.clone(), lhs, rhs, &mut control)? {
+                let copies = if *token == Token::EDup { 2 } else { 1 };
+                for _ in 0..copies {
+                    let rt = self.process_out(res);
+                    p.push(rt);
+                }
+            }
+
+            // `ExploreTrue` -> Don't skip the section inside the ?{,...,}
+            // `ExploreFalse` -> Skip the section inside the ?{,...,}
+            match control {
+                RuneControl::ExploreTrue => {
+                    self.skip = false;
+                }
+                RuneControl::ExploreFalse => {
+                    self.skip = true;
+                }
+                RuneControl::Continue => continue,
+                RuneControl::Break | RuneControl::TerminatePath => {
+                    // Drop the rest of the expression, so that it does not run as part of
+                    // the next instruction.
+                    while p.parse::<_, CachedTokenizer>(esil).is_some() {}
+                    return Ok(false);
+                }
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Evaluate the operation `op`, which was split out of the ESIL of the instruction, on the
+    /// operands on top of the stack. Returns false if the path ends.
+    fn eval_op(&mut self, p: &mut Parser, op: &str) -> EngineResult<bool> {
+        let (arity, custom) = match self.esil_ops.get(op) {
+            Some(custom) => custom,
+            None if STACK_OPS.contains(&op) => return self.eval_stack_op(p, op),
+            None => return self.eval_float(p, op).map(|_| true),
+        };
+        let mut args = Vec::new();
+        while args.len() < arity {
+            // Take the operands off the stack two at a time, as binary operations would.
+            let pair = arity - args.len() >= 2;
+            let (lhs, rhs) = p.fetch_operands(if pair { &Token::EAdd } else { &Token::ENeg });
+            args.push(self.process_in(lhs.as_ref())?.ok_or(EngineError::InCorrectOperand)?);
+            if pair {
+                args.push(self.process_in(rhs.as_ref())?.ok_or(EngineError::InCorrectOperand)?);
+            }
+        }
+        if let Some(result) = custom(&mut self.ctx, &args)? {
+            let rt = self.process_out(&result);
+            p.push(rt);
+        }
+        Ok(true)
+    }
+
+    /// Evaluate `op`, one of `STACK_OPS`, on the stack of `p`. Returns false if the path ends.
+    fn eval_stack_op(&mut self, p: &mut Parser, op: &str) -> EngineResult<bool> {
+        match op {
+            "~/" | "~%" => {
+                // As for `/`, `a,b,~/` is b / a.
+                let (lhs, rhs) = p.fetch_operands(&Token::EAdd);
+                let lhs = self.process_in(lhs.as_ref())?.ok_or(EngineError::InCorrectOperand)?;
+                let rhs = self.process_in(rhs.as_ref())?.ok_or(EngineError::InCorrectOperand)?;
+                if self.fault_states && !self.check_divisor(rhs.clone()) {
+                    return Ok(false);
+                }
+                let div = if op == "~/" {
+                    bitvec::OpCodes::BvSDiv
+                } else {
+                    bitvec::OpCodes::BvSRem
+                };
+                let result = self.ctx.eval(div, vec![lhs, rhs]);
+                let rt = self.process_out(&result);
+                p.push(rt);
+            }
+            "SWAP" => {
+                let (top, next) = p.fetch_operands(&Token::EAdd);
+                let (top, next) = match (top, next) {
+                    (Some(top), Some(next)) => (top, next),
+                    _ => return Err(EngineError::InCorrectOperand),
+                };
+                p.push(top);
+                p.push(next);
+            }
+            _ => {
+                // `n,PICK` pushes a copy of the n-th value of the stack, the top being the first.
+                let (n, _) = p.fetch_operands(&Token::ENeg);
+                let n = self.process_in(n.as_ref())?.ok_or(EngineError::InCorrectOperand)?;
+                let n = match self.ctx.as_constant(n) {
+                    Some(0) => return Err(EngineError::InCorrectOperand),
+                    Some(n) => n,
+                    None => return Err(self.unsupported_op(op)),
+                };
+                let mut taken = Vec::new();
+                while (taken.len() as u64) < n {
+                    match p.fetch_operands(&Token::ENeg) {
+                        (Some(token), _) => taken.push(token),
+                        _ => return Err(EngineError::InCorrectOperand),
+                    }
+                }
+                let picked = taken.last().cloned().unwrap();
+                for token in taken.into_iter().rev() {
+                    p.push(token);
+                }
+                p.push(picked);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Evaluate the floating point operation `op` on the operands on top of the stack.
+    fn eval_float(&mut self, p: &mut Parser, op: &str) -> EngineResult<()> {
+        // Take the operands off the stack as a unary or binary operation would.
+        let (lhs, rhs) = match float::arity(op) {
+            Some(1) => p.fetch_operands(&Token::ENeg),
+            _ => p.fetch_operands(&Token::EAdd),
+        };
+        let mut args = vec![self.process_in(lhs.as_ref())?.ok_or(EngineError::InCorrectOperand)?];
+        if float::arity(op) == Some(2) {
+            args.push(self.process_in(rhs.as_ref())?.ok_or(EngineError::InCorrectOperand)?);
+        }
+        let result = float::eval(&mut self.ctx, self.float_mode, op, &args);
+        let rt = self.process_out(&result);
+        p.push(rt);
+        Ok(())
+    }
+
+    pub fn ctx(&self) -> &Ctx {
+        &self.ctx
+    }
+
+    pub fn ctx_mut(&mut self) -> &mut Ctx {
+        &mut self.ctx
+    }
+
+    pub fn explorer(&self) -> &Exp {
+        &self.explorer
+    }
+
+    pub fn explorer_mut(&mut self) -> &mut Exp {
+        &mut self.explorer
+    }
+
+    /// The context and the explorer, to inspect the state once the engine has stopped.
+    pub fn into_parts(self) -> (Ctx, Exp) {
+        (self.ctx, self.explorer)
+    }
+
+    /// Instructions decoded so far, see `engine::cache`.
+    pub fn decode_cache(&self) -> &DecodeCache {
+        &self.decoded
+    }
+
+    /// Decode the instruction at the current ip, with the bytes that the program wrote over it.
+    fn fetch(&mut self) -> EngineResult<Option<Rc<Decoded>>> {
+        let ip = self.ctx.ip();
+        let patches = self.ctx.code_patches(ip, MAX_INSN_SIZE);
+        let key = (ip, self.mode, patches);
+        if let Some(decoded) = self.decoded.get(&key) {
+            return Ok(Some(decoded));
+        }
+
+        let opinfo = if key.2.is_empty() {
+            self.stream.at(ip)
+        } else {
+            // Symbolic bytes are decoded as zero, which is only wrong if they are part of the
+            // instruction.
+            let bytes: Vec<(u64, u8)> = key.2.iter().map(|&(a, b)| (a, b.unwrap_or(0))).collect();
+            let opinfo = self.stream.at_patched(ip, &bytes);
+            if let Some(size) = opinfo.as_ref().and_then(|o| o.size) {
+                if key.2.iter().any(|&(a, b)| b.is_none() && a < ip + size) {
+                    return Err(EngineError::SymbolicCode(ip));
+                }
+            }
+            opinfo
+        };
+        Ok(opinfo.map(|mut opinfo| {
+            let lifted = match self.lifter {
+                Some(ref mut lifter) => lifter.lift(&opinfo, self.mode),
+                None => None,
+            };
+            let native = match lifted {
+                Some(Lifted::Esil(esil)) => {
+                    opinfo.esil = Some(esil);
+                    None
+                }
+                Some(Lifted::Native(semantics)) => {
+                    self.natives.push(semantics);
+                    Some(self.natives.len() - 1)
+                }
+                None => None,
+            };
+            let (parts, unsupported) = match opinfo.esil {
+                _ if native.is_some() => (Vec::new(), None),
+                Some(ref esil) => {
+                    let ops = &self.esil_ops;
+                    let parts = float::split_esil(esil, |word| {
+                        float::arity(word).is_some() || ops.contains(word) ||
+                        STACK_OPS.contains(&word)
+                    });
+                    let unsupported = self.find_unsupported(&parts);
+                    (parts, unsupported)
+                }
+                None => (Vec::new(), Some(opinfo.opcode.clone().unwrap_or_default())),
+            };
+            // r2 gives `syscall`, `int 0x80`, `svc` and the like the type `swi`.
+            let syscall = opinfo.optype.as_ref().map_or(false, |t| t == "swi");
+            let x86 = self.ctx.arch().map_or(true, |arch| arch.name() == "x86");
+            let rep = opinfo.opcode.as_ref().and_then(|o| RepInsn::parse(o)).filter(|_| x86);
+            let cpu = opinfo.opcode.as_ref().and_then(|o| CpuInsn::parse(o)).filter(|_| x86);
+            let atomic = match unsupported {
+                Some(_) => {
+                    let arch = self.ctx.arch();
+                    let name = arch.as_ref().map(|arch| arch.name());
+                    opinfo.opcode.as_ref().and_then(|o| AtomicInsn::parse(o, name))
+                }
+                None => None,
+            };
+            let invalid = opinfo.optype.as_ref().map_or(false, |t| t == "ill") ||
+                          opinfo.opcode.as_ref().map_or(false, |o| o == "invalid") ||
+                          opinfo.size.map_or(true, |size| size == 0);
+            let flow = opinfo.optype.as_ref().and_then(|t| Flow::of(t));
+            self.decoded.insert(key,
+                                Decoded {
+                                    opinfo: opinfo,
+                                    parts: parts,
+                                    unsupported: unsupported,
+                                    syscall: syscall,
+                                    rep: rep,
+                                    cpu: cpu,
+                                    native: native,
+                                    atomic: atomic,
+                                    invalid: invalid,
+                                    flow: flow,
+                                })
+        }))
+    }
+
+    /// The first operation of `parts` that `process_in` and `process_op` reject.
+    fn find_unsupported(&self, parts: &[EsilPart]) -> Option<String> {
+        for part in parts {
+            if let EsilPart::Esil(ref esil) = *part {
+                for token in CachedTokenizer::tokenize(esil) {
+                    match token {
+                        Token::ERegister(ref name) | Token::EIdentifier(ref name) => {
+                            if !self.ctx.has_reg(name) {
+                                return Some(name.clone());
+                            }
+                        }
+                        Token::EInterrupt | Token::ETrap | Token::EGoto | Token::ETodo |
+                        Token::EInvalid => return Some(format!("{:?}", token)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Let the syscall handler model the system call made by the current instruction. Returns
+    /// false if the path ends.
+    fn syscall(&mut self) -> EngineResult<bool> {
+        let num = syscall::number(&mut self.ctx)?;
+        let action = match self.syscalls {
+            Some(ref mut handler) => handler.syscall(&mut self.ctx, num)?,
+            None => return Ok(true),
+        };
+        match action {
+            SyscallAction::Return(Some(value)) => syscall::set_return(&mut self.ctx, value)?,
+            SyscallAction::Return(None) => {}
+            SyscallAction::Exit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Execute the instruction at `ip` with the fallback executor, see `engine::fallback`.
+    fn execute_concretely(&mut self, ip: u64, decoded: &Decoded) -> EngineResult<()> {
+        let unsupported = || {
+            EngineError::UnsupportedEsil {
+                op: decoded.unsupported.clone().unwrap_or_default(),
+                insn_addr: ip,
+            }
+        };
+        let bytes = decoded.opinfo
+                           .bytes
+                           .as_ref()
+                           .and_then(|b| fallback::parse_hex(b))
+                           .ok_or_else(&unsupported)?;
+        let regs: Vec<(String, usize)> = match self.fallback {
+            Some(ref executor) => executor.registers(),
+            None => return Err(unsupported()),
+        };
+        let regs: Vec<(String, usize)> = regs.into_iter()
+                                             .filter(|&(ref r, _)| self.ctx.has_reg(r))
+                                             .collect();
+
+        // Registers are read at the start of the instruction, as ESIL does.
+        let pc = self.pc_value();
+        let width = self.ctx.address_width();
+        let mut vars = Vec::new();
+        for &(ref reg, w) in &regs {
+            let var = if self.ctx.is_pc(reg) {
+                self.ctx.define_const(pc, width)
             } else {
-                break;
+                self.ctx.reg_read(reg)
             };
+            vars.push((var, w));
+        }
+        let mut concrete = vars.iter().all(|&(ref v, _)| self.ctx.as_constant(v.clone()).is_some());
+        let values = self.ctx.concrete_values(&vars).ok_or(EngineError::Undefined)?;
+        let values: BTreeMap<String, u64> = regs.iter()
+                                                .map(|&(ref r, _)| r.clone())
+                                                .zip(values)
+                                                .collect();
 
-            let esil = opinfo.esil.as_ref().unwrap();
+        let effects = {
+            let ctx = &mut self.ctx;
+            let concrete = &mut concrete;
+            let mut read = |addr: u64, len: usize| -> Vec<u8> {
+                let bytes: Vec<_> = (0..len as u64)
+                    .map(|i| {
+                        let a = ctx.define_const(addr.wrapping_add(i), width);
+                        (ctx.mem_read(a, 8), 8)
+                    })
+                    .collect();
+                if bytes.iter().any(|&(ref b, _)| ctx.as_constant(b.clone()).is_none()) {
+                    *concrete = false;
+                }
+                ctx.concrete_values(&bytes)
+                   .map(|v| v.into_iter().map(|b| b as u8).collect())
+                   .unwrap_or_else(|| vec![0; len])
+            };
+            let executor = self.fallback.as_mut().unwrap();
+            executor.execute(ip, &bytes, &values, &mut read).map_err(EngineError::FallbackFailed)?
+        };
 
-            // Increment ip by instruction width
-            let width = opinfo.size.as_ref().unwrap();
-            self.ctx.increment_ip(*width);
-
-            while let Some(ref token) = p.parse::<_, Tokenizer>(esil) {
-                // println!("{:?}", token);
-                // If skip is active, we do not want to modify the esil stack
-                let (lhs, rhs) = if self.skip {
-                    (None, None)
+        for &(ref reg, w) in &regs {
+            if self.ctx.is_pc(reg) {
+                continue;
+            }
+            if let Some(&value) = effects.regs.get(reg) {
+                let value = if concrete {
+                    self.ctx.define_const(value, w)
                 } else {
-                    p.fetch_operands(token)
+                    self.ctx.opaque_value(&format!("{}_{:x}", reg, ip), w)
                 };
+                self.ctx.reg_write(reg, value);
+            }
+        }
+        for (&addr, &byte) in &effects.mem {
+            let value = if concrete {
+                self.ctx.define_const(byte as u64, 8)
+            } else {
+                self.ctx.opaque_value(&format!("mem_{:x}", addr), 8)
+            };
+            let addr = self.ctx.define_const(addr, width);
+            self.ctx.mem_write(addr, value, 8);
+        }
+        self.ctx.set_ip(effects.next_ip);
+        Ok(())
+    }
 
-                if let Ok(Some(ref res)) = self.process_op(token.clone(), lhs, rhs, &mut control) {
-                    let rt = self.process_out(res);
-                    p.push(rt);
+    /// Execute the next instruction of the current path, or of the next path once it ends.
+    fn step_with(&mut self,
+                 p: &mut Parser)
+                 -> EngineResult<Option<TraceEntry<<Ctx as RegisterRead>::VarRef>>> {
+        if self.halted {
+            return Ok(None);
+        }
+        loop {
+            // println!("{}", self.ctx.ip());
+            let ip = self.ctx.ip();
+            self.solver_time = self.ctx.solver_time();
+            self.ctx.begin_step(ip);
+            self.run_insn_hooks(ip, HookPoint::Before)?;
+            if self.ctx.ip() != ip {
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, String::new())));
+            }
+
+            if let Some(hook) = self.hooks.get(&ip).cloned() {
+                hook(&mut self.ctx)?;
+                // Hooks of functions return in their place, which is not checked as they may
+                // as well jump anywhere.
+                let target = self.ctx.ip();
+                let _ = self.ctx.record_return(ip, target);
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, String::new())));
+            }
+
+            let mode = self.ctx.isa_mode();
+            if mode != self.mode {
+                self.stream.set_mode(mode);
+                self.mode = mode;
+            }
+
+            let decoded = match self.fetch()? {
+                Some(decoded) => decoded,
+                None => {
+                    self.end_path();
+                    if self.halted {
+                        return Ok(None);
+                    }
+                    // Continue with the next path from the queue, starting with its hooks.
+                    continue;
                 }
+            };
+            let disasm = decoded.opinfo.opcode.clone().unwrap_or_default();
 
-                // `ExploreTrue` -> Don't skip the section inside the ?{,...,}
-                // `ExploreFalse` -> Skip the section inside the ?{,...,}
-                match control {
-                    RuneControl::ExploreTrue => {
-                        self.skip = false;
+            let ip = self.ctx.ip();
+            let size = decoded.opinfo.size.unwrap_or(0).max(1);
+            self.ctx.check_access(ip, size as u64, Access::Execute);
+            if self.fault_states {
+                // The instruction is not executed.
+                let kind = match self.ctx.take_violation() {
+                    Some(violation) => Some(violation.into()),
+                    None if decoded.invalid => {
+                        Some(FaultKind::InvalidInstruction(decoded.opinfo.bytes.clone()))
                     }
-                    RuneControl::ExploreFalse => {
-                        self.skip = true;
+                    None => None,
+                };
+                if let Some(kind) = kind {
+                    self.end_with_fault(Fault {
+                        ip: ip,
+                        kind: kind,
+                    });
+                    return Ok(Some(self.trace_entry(ip, disasm)));
+                }
+            }
+
+            // Increment ip by instruction width
+            let width = match decoded.opinfo.size {
+                Some(size) if size > 0 => size,
+                _ => return Err(EngineError::InvalidInstruction(ip)),
+            };
+            self.current = (ip, width);
+            self.flow = decoded.flow;
+            self.dashboard.visit(ip);
+            if !self.subscribers.is_empty() && self.covered.insert(ip) {
+                self.notify(Event::NewCoverage { ip: ip });
+            }
+            self.ctx.increment_ip(width);
+
+            if let Some(summary) = self.summaries.get(&ip).cloned() {
+                summary::apply(&mut self.ctx, &summary)?;
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            if decoded.syscall && self.syscalls.is_some() {
+                let more = self.syscall()?;
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                if !more {
+                    self.end_path();
+                }
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            if let Some(i) = decoded.native {
+                let semantics = self.natives[i].clone();
+                semantics(&mut self.ctx)?;
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            if let Some(insn) = decoded.rep {
+                rep::execute(&mut self.ctx, insn, self.rep_limit);
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            if let Some(ref insn) = decoded.cpu {
+                cpu::execute(&mut self.ctx, insn, &self.cpu_model);
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            if let Some(ref insn) = decoded.atomic {
+                atomic::execute(&mut self.ctx, insn);
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            // Without a fallback, instructions stop at the operation that is not supported.
+            let fallback = self.fallback.is_some() || decoded.opinfo.esil.is_none();
+            if decoded.unsupported.is_some() && fallback {
+                self.execute_concretely(ip, &decoded)?;
+                self.delay_branch(ip, width, &disasm);
+                self.run_insn_hooks(ip, HookPoint::After)?;
+                self.finish_instruction(ip)?;
+                return Ok(Some(self.trace_entry(ip, disasm)));
+            }
+
+            for part in &decoded.parts {
+                let more = match *part {
+                    EsilPart::Esil(ref esil) => self.eval_esil(p, esil)?,
+                    EsilPart::Op(ref op) => self.skip || self.eval_op(p, op)?,
+                };
+                if !more {
+                    break;
+                }
+            }
+            self.delay_branch(ip, width, &disasm);
+
+            self.run_insn_hooks(ip, HookPoint::After)?;
+            self.finish_instruction(ip)?;
+            return Ok(Some(self.trace_entry(ip, disasm)));
+        }
+    }
+
+    fn trace_entry(&mut self,
+                   addr: u64,
+                   disasm: String)
+                   -> TraceEntry<<Ctx as RegisterRead>::VarRef> {
+        TraceEntry {
+            addr: addr,
+            disasm: disasm,
+            effects: self.ctx.take_effects(),
+            branches: mem::replace(&mut self.branches, Vec::new()),
+        }
+    }
+
+    /// Execute one instruction, `None` once there is nothing left to execute.
+    pub fn step(&mut self) -> EngineResult<Option<TraceEntry<<Ctx as RegisterRead>::VarRef>>> {
+        if let Some(limit) = self.budget.exhausted(&self.usage) {
+            return Err(EngineError::BudgetExhausted(limit));
+        }
+        let width = self.ctx.address_width() as u64;
+        let mut p = self.parser.take().unwrap_or_else(|| Parser::init(None, Some(width)));
+        let entry = self.step_with(&mut p);
+        if entry.is_err() {
+            // The rest of the expression of the failed instruction.
+            p = Parser::init(None, Some(width));
+            self.fault = None;
+        }
+        if let Ok(Some(_)) = entry {
+            self.usage.steps += 1;
+            if self.dashboard.report_due() {
+                let stats = self.stats();
+                self.notify(Event::Progress { stats: stats });
+            }
+        }
+        self.parser = Some(p);
+        entry
+    }
+
+    /// Execute up to `n` instructions, and trace them.
+    pub fn step_n(&mut self, n: usize) -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>> {
+        self.trace(|_| false, Some(n))
+    }
+
+    /// Execute instructions until `pred` holds before one, and trace them.
+    pub fn run_until<F>(&mut self, pred: F) -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>>
+        where F: FnMut(&Ctx) -> bool
+    {
+        self.trace(pred, None)
+    }
+
+    /// Explore every path, and return the states of the ones that reached one of the addresses
+    /// of `find`, see `explore_n`.
+    pub fn explore(&mut self, find: &[u64], avoid: &[u64]) -> EngineResult<Vec<Ctx>> {
+        self.explore_n(find, avoid, usize::MAX)
+    }
+
+    /// Explore until `n` paths reached one of the addresses of `find`, and return their states,
+    /// before the instruction there executes. Paths end where they are found, and where they
+    /// reach one of the addresses of `avoid`. The paths left to explore stay with the explorer,
+    /// so that exploring again finds more.
+    pub fn explore_n(&mut self, find: &[u64], avoid: &[u64], n: usize) -> EngineResult<Vec<Ctx>> {
+        let mut found = Vec::new();
+        while found.len() < n && !self.halted {
+            let ip = self.ctx.ip();
+            if find.contains(&ip) {
+                found.push(self.ctx.clone());
+                self.notify(Event::GoalReached { ip: ip });
+                self.end_path();
+            } else if avoid.contains(&ip) {
+                self.end_path();
+            } else if self.step()?.is_none() {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Explore until the paths that reach the conditional branch at `addr` give inputs that take
+    /// it and inputs that do not, see `engine::branch_inputs`. A side is `None` if no path takes
+    /// it. The paths left to explore stay with the explorer.
+    pub fn branch_inputs(&mut self, addr: u64) -> EngineResult<BranchInputs> {
+        self.probe = Some((addr, BranchInputs::default()));
+        let result = loop {
+            if self.probe.as_ref().map_or(true, |&(_, ref inputs)| inputs.is_complete()) {
+                break Ok(());
+            }
+            match self.step() {
+                Ok(Some(_)) => {}
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        let inputs = self.probe.take().map(|(_, inputs)| inputs).unwrap_or_default();
+        result.map(|_| inputs)
+    }
+
+    /// Call `function` with symbolic arguments from the current state, and explore every path
+    /// up to its return, see `engine::function`.
+    pub fn explore_function(&mut self,
+                            function: &FunctionSetup)
+                            -> EngineResult<Vec<FunctionReturn<Ctx>>> {
+        let args = function::setup(&mut self.ctx, function)?;
+        let ret = self.ctx.reg_of_alias("R0").ok_or(EngineError::Undefined)?;
+        let states = self.explore(&[function.return_addr], &[])?;
+        Ok(states.into_iter()
+                 .map(|mut ctx| {
+                     FunctionReturn {
+                         ret: ctx.reg_read(&ret),
+                         ctx: ctx,
+                         args: args.clone(),
+                     }
+                 })
+                 .collect())
+    }
+
+    /// Execute the path recorded in `steps` (see `replay::record`) and trace it. Its branches
+    /// follow the recording instead of the explorer, and the replay stops with
+    /// `EngineError::Diverged` where the path differs from it.
+    pub fn replay(&mut self,
+                  steps: &[RecordedStep])
+                  -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>> {
+        self.replay = Some(Replay::new(steps.to_vec()));
+        self.ctx.set_tracing(true);
+        let result = self.replay_steps();
+        self.ctx.set_tracing(false);
+        self.replay = None;
+        result
+    }
+
+    fn replay_steps(&mut self) -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>> {
+        let mut trace = Vec::new();
+        while !self.replay.as_ref().unwrap().is_done() {
+            self.replay.as_ref().unwrap().expect(self.ctx.ip())?;
+            let entry = match self.step()? {
+                Some(entry) => entry,
+                None => return Err(self.replay.as_ref().unwrap().diverged(Divergence::Ended)),
+            };
+            self.replay.as_mut().unwrap().finish_step(entry.addr)?;
+            trace.push(entry);
+        }
+        Ok(trace)
+    }
+
+    /// Execute along `pcs`, the addresses of the instructions of an external trace (see
+    /// `pc_trace::parse`), and trace it. Symbolic branches and jump targets follow the trace,
+    /// and the execution stops with `EngineError::Diverged` where it can not. The branches of an
+    /// instruction are tried from the state before it, so its hooks may run more than once.
+    pub fn follow_trace(&mut self,
+                        pcs: &[u64])
+                        -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>> {
+        self.ctx.set_tracing(true);
+        let result = self.follow_pcs(pcs);
+        self.ctx.set_tracing(false);
+        self.guide = None;
+        result
+    }
+
+    fn follow_pcs(&mut self, pcs: &[u64]) -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>> {
+        let mut trace = Vec::new();
+        for (step, &pc) in pcs.iter().enumerate() {
+            let guide = Guide::new(step, pcs.get(step + 1).cloned());
+            if self.ctx.ip() != pc {
+                return Err(guide.diverged(Divergence::Address {
+                    expected: pc,
+                    found: self.ctx.ip(),
+                }));
+            }
+            let next = guide.next;
+            self.guide = Some(guide);
+            let saved = self.ctx.clone();
+            loop {
+                let divergence = match self.step() {
+                    Ok(Some(entry)) => {
+                        let found = self.ctx.ip();
+                        match next {
+                            Some(expected) if expected != found => {
+                                Divergence::Address {
+                                    expected: expected,
+                                    found: found,
+                                }
+                            }
+                            _ => {
+                                trace.push(entry);
+                                break;
+                            }
+                        }
                     }
-                    RuneControl::Continue => continue,
-                    _ => break,
+                    Ok(None) => Divergence::Ended,
+                    Err(EngineError::Diverged { divergence, .. }) => divergence,
+                    Err(e) => return Err(e),
+                };
+                if divergence == Divergence::Ended || !self.guide.as_mut().unwrap().retry() {
+                    return Err(self.diverged(divergence));
                 }
+                self.ctx = saved.clone();
+                self.branches.clear();
             }
+        }
+        Ok(trace)
+    }
 
-            match self.explorer.next(&mut self.ctx) {
-                RuneControl::Continue => {}
-                _ => unimplemented!(),
+    /// Execute the next instruction, and compare its effects with those of `executor` on the
+    /// same state, see `engine::differential`. `None` once there is nothing left to execute.
+    pub fn check_step(&mut self,
+                      executor: &mut ConcreteExecutor,
+                      config: &Differential)
+                      -> EngineResult<Option<Report>> {
+        let ip = self.ctx.ip();
+        let decoded = match self.fetch()? {
+            Some(decoded) => decoded,
+            None => return Ok(None),
+        };
+        let bytes = decoded.opinfo
+                           .bytes
+                           .as_ref()
+                           .and_then(|b| fallback::parse_hex(b))
+                           .ok_or(EngineError::InvalidInstruction(ip))?;
+        let regs: Vec<(String, usize)> = executor.registers()
+                                                 .into_iter()
+                                                 .filter(|&(ref r, _)| self.ctx.has_reg(r))
+                                                 .collect();
+        let mut inputs = BTreeMap::new();
+        for &(ref reg, _) in &regs {
+            let value = if self.ctx.is_pc(reg) {
+                Some(ip)
+            } else {
+                let var = self.ctx.reg_read(reg);
+                self.ctx.as_constant(var)
+            };
+            let value = value.ok_or_else(|| EngineError::SymbolicInput(reg.clone()))?;
+            inputs.insert(reg.clone(), value);
+        }
+
+        let mut before = self.ctx.clone();
+        let mut symbolic = None;
+        let effects = {
+            let ctx = &mut self.ctx;
+            let symbolic = &mut symbolic;
+            let mut read = |addr: u64, len: usize| -> Vec<u8> {
+                (0..len as u64)
+                    .map(|i| {
+                        let a = addr.wrapping_add(i);
+                        differential::byte_at(ctx, a).unwrap_or_else(|| {
+                            *symbolic = Some(a);
+                            0
+                        })
+                    })
+                    .collect()
+            };
+            executor.execute(ip, &bytes, &inputs, &mut read).map_err(EngineError::FallbackFailed)?
+        };
+        if let Some(addr) = symbolic {
+            return Err(EngineError::SymbolicInput(format!("{:#x}", addr)));
+        }
+
+        self.ctx.set_tracing(true);
+        let entry = self.step();
+        self.ctx.set_tracing(false);
+        let entry = match entry? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let mut engine = Snapshot {
+            ip: self.ctx.ip(),
+            ..Default::default()
+        };
+        let mut native = Snapshot {
+            ip: effects.next_ip,
+            ..Default::default()
+        };
+        for &(ref reg, w) in &regs {
+            if self.ctx.is_pc(reg) {
+                continue;
+            }
+            let mask = if w >= 64 { !0 } else { (1 << w) - 1 };
+            let var = self.ctx.reg_read(reg);
+            engine.regs.insert(reg.clone(), self.ctx.as_constant(var).map(|v| v & mask));
+            let value = effects.regs.get(reg).cloned().unwrap_or(inputs[reg]);
+            native.regs.insert(reg.clone(), Some(value & mask));
+        }
+        let mut written: BTreeSet<u64> = effects.mem.keys().cloned().collect();
+        for effect in &entry.effects {
+            if let Effect::Mem { ref addr, size, .. } = *effect {
+                if let Some(a) = self.ctx.as_constant(addr.clone()) {
+                    written.extend((0..size as u64 / 8).map(|i| a.wrapping_add(i)));
+                }
             }
+        }
+        for addr in written {
+            engine.mem.insert(addr, differential::byte_at(&mut self.ctx, addr));
+            let byte = match effects.mem.get(&addr) {
+                Some(&byte) => Some(byte),
+                None => differential::byte_at(&mut before, addr),
+            };
+            native.mem.insert(addr, byte);
+        }
+        Ok(Some(Report {
+            addr: ip,
+            disasm: entry.disasm,
+            mismatches: config.compare(&engine, &native),
+        }))
+    }
 
+    fn trace<F>(&mut self,
+                mut pred: F,
+                limit: Option<usize>)
+                -> EngineResult<Trace<<Ctx as RegisterRead>::VarRef>>
+        where F: FnMut(&Ctx) -> bool
+    {
+        let mut trace = Vec::new();
+        self.ctx.set_tracing(true);
+        while limit.map_or(true, |n| trace.len() < n) && !pred(&self.ctx) {
+            match self.step() {
+                Ok(Some(entry)) => trace.push(entry),
+                Ok(None) => break,
+                Err(e) => {
+                    self.ctx.set_tracing(false);
+                    return Err(e);
+                }
+            }
         }
+        self.ctx.set_tracing(false);
+        Ok(trace)
+    }
 
+    // Write out to intermediates and return a token to it.
+    fn process_out(&mut self, res: &<Ctx as RegisterRead>::VarRef) -> Token {
+        self.intermediates.push(res.clone());
+        Token::EEntry(self.intermediates.len() - 1, Some(self.ctx.address_width() as u64))
+    }
+}
+
+impl<Ctx, Exp, S> Engine for Rune<Ctx, Exp, S>
+where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
+      Exp: PathExplorer<C = RuneControl, Ctx = Ctx>,
+      S: InstructionStream<Output = LOpInfo, Index = u64>
+{
+    fn run(&mut self) -> EngineResult<()> {
+        while self.step()?.is_some() {}
         Ok(())
     }
 }
 
+/// Operations of ESIL that the lexer does not know and that the engine evaluates itself, see
+/// `Rune::eval_stack_op`.
+const STACK_OPS: &'static [&'static str] = &["~/", "~%", "SWAP", "PICK"];
+
 trait ToSMTFn {
-    fn to_smt(&self) -> qf_abv::QF_ABV_Fn;
+    /// The function of the solver that implements this opcode, if there is one.
+    fn to_smt(&self) -> Option<qf_abv::QF_ABV_Fn>;
 }
 
 // Implement Into<Qf_Abv_Fn> for tokens in order to use them with RuneContext.
 impl ToSMTFn for Token {
-    fn to_smt(&self) -> qf_abv::QF_ABV_Fn {
-        match *self {
+    fn to_smt(&self) -> Option<qf_abv::QF_ABV_Fn> {
+        Some(match *self {
             Token::ECmp => core::OpCodes::Cmp.into(),
             Token::ELt => bitvec::OpCodes::BvULt.into(),
             Token::EGt => bitvec::OpCodes::BvUGt.into(),
@@ -278,9 +1853,282 @@ impl ToSMTFn for Token {
             Token::ESub => bitvec::OpCodes::BvSub.into(),
             Token::EDiv => bitvec::OpCodes::BvUDiv.into(),
             Token::EMod => bitvec::OpCodes::BvURem.into(),
-            Token::ERor => unimplemented!(),
-            Token::ERol => unimplemented!(),
-            _ => panic!("This opcode is either unimplemented or is not an opcode at all!"),
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use context::context::{ContextAPI, Evaluate};
+    use context::rune_ctx::{test_ctx, RuneContext};
+    use engine::fallback::ConcreteEffects;
+    use explorer::bfs::BFSExplorer;
+    use explorer::fork::ForkExplorer;
+    use memory::paged_mem::PagedMemory;
+    use regstore::regfile::RuneRegFile;
+
+    /// Stream without instructions, the tests evaluate ESIL themselves.
+    struct NoInsns;
+
+    impl InstructionStream for NoInsns {
+        type Output = LOpInfo;
+        type Index = u64;
+
+        fn new() -> NoInsns {
+            NoInsns
         }
+
+        fn at(&mut self, _: u64) -> Option<LOpInfo> {
+            None
+        }
+    }
+
+    /// One byte `nop`s, from 0x9000 up to 0x9010.
+    struct Nops;
+
+    impl InstructionStream for Nops {
+        type Output = LOpInfo;
+        type Index = u64;
+
+        fn new() -> Nops {
+            Nops
+        }
+
+        fn at(&mut self, addr: u64) -> Option<LOpInfo> {
+            if addr < 0x9000 || addr >= 0x9010 {
+                return None;
+            }
+            Some(LOpInfo {
+                esil: Some(String::new()),
+                offset: Some(addr),
+                opcode: Some("nop".to_owned()),
+                size: Some(1),
+                ..Default::default()
+            })
+        }
+    }
+
+    type TestCtx = RuneContext<PagedMemory, RuneRegFile>;
+    type TestRune = Rune<TestCtx, ForkExplorer<TestCtx>, NoInsns>;
+
+    fn rune() -> TestRune {
+        Rune::new(test_ctx(&[("rax", 64)]), ForkExplorer::new(), NoInsns)
+    }
+
+    /// Explores `Nops` from 0x9000, and the states at `ips` after that path.
+    fn explorer(ips: &[u64]) -> Rune<TestCtx, BFSExplorer<TestCtx>, Nops> {
+        let ctx = test_ctx(&[]);
+        let mut rune = Rune::new(ctx.clone(), BFSExplorer::new(), Nops);
+        for &ip in ips {
+            let mut state = ctx.clone();
+            state.set_ip(ip);
+            rune.explorer_mut().add_state(state);
+        }
+        rune
+    }
+
+    /// Constant result of `token` on `lhs` and `rhs`.
+    fn eval(rune: &mut TestRune, token: Token, lhs: u64, rhs: Option<u64>) -> Option<u64> {
+        let mut control = RuneControl::Continue;
+        let rhs = rhs.map(Token::EConstant);
+        let result = rune.process_op(token, Some(Token::EConstant(lhs)), rhs, &mut control)
+                         .unwrap();
+        result.and_then(|result| rune.ctx.as_constant(result))
+    }
+
+    /// Parser with `values` on its stack, the last one on top.
+    fn stack(values: &[u64]) -> Parser {
+        let mut p = Parser::init(None, Some(64));
+        for value in values {
+            p.push(Token::EConstant(*value));
+        }
+        p
+    }
+
+    /// Take the constants off the stack of `p`, the top one first.
+    fn drain(rune: &mut TestRune, p: &mut Parser, n: usize) -> Vec<u64> {
+        (0..n).map(|_| {
+                  let (top, _) = p.fetch_operands(&Token::ENeg);
+                  let top = rune.process_in(top.as_ref()).unwrap().unwrap();
+                  rune.ctx.as_constant(top).unwrap()
+              })
+              .collect()
+    }
+
+    #[test]
+    fn rotate() {
+        let mut rune = rune();
+        let value = 0x8000_0000_0000_0001;
+        assert_eq!(eval(&mut rune, Token::ERol, value, Some(0)), Some(value));
+        assert_eq!(eval(&mut rune, Token::ERor, value, Some(0)), Some(value));
+        assert_eq!(eval(&mut rune, Token::ERol, value, Some(64)), Some(value));
+        assert_eq!(eval(&mut rune, Token::ERol, value, Some(65)), Some(3));
+        assert_eq!(eval(&mut rune, Token::ERor, value, Some(129)), Some(0xc000_0000_0000_0000));
+    }
+
+    #[test]
+    fn stack_tokens() {
+        let mut rune = rune();
+        assert_eq!(eval(&mut rune, Token::EDup, 7, None), Some(7));
+        assert_eq!(eval(&mut rune, Token::EPop, 7, None), None);
+
+        let mut control = RuneControl::Continue;
+        assert!(rune.process_op(Token::EBreak, None, None, &mut control).unwrap().is_none());
+        assert_eq!(control, RuneControl::Break);
+    }
+
+    #[test]
+    fn unsupported_esil() {
+        let mut rune = rune();
+        let mut control = RuneControl::Continue;
+        match rune.process_op(Token::ETrap, None, None, &mut control) {
+            Err(EngineError::UnsupportedEsil { ref op, .. }) => assert_eq!(op, "ETrap"),
+            other => panic!("Unexpected {:?}", other),
+        }
+        let word = Some(Token::EIdentifier("RPICK".to_owned()));
+        match rune.process_op(Token::EAdd, word, Some(Token::EConstant(1)), &mut control) {
+            Err(EngineError::UnsupportedEsil { ref op, .. }) => assert_eq!(op, "RPICK"),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn signed_division() {
+        let mut rune = rune();
+        let minus_7 = (-7i64) as u64;
+        let mut p = stack(&[2, minus_7]);
+        assert!(rune.eval_op(&mut p, "~/").unwrap());
+        assert_eq!(drain(&mut rune, &mut p, 1), vec![(-3i64) as u64]);
+        let mut p = stack(&[2, minus_7]);
+        assert!(rune.eval_op(&mut p, "~%").unwrap());
+        assert_eq!(drain(&mut rune, &mut p, 1), vec![(-1i64) as u64]);
+    }
+
+    #[test]
+    fn swap_and_pick() {
+        let mut rune = rune();
+        let mut p = stack(&[1, 2]);
+        rune.eval_op(&mut p, "SWAP").unwrap();
+        assert_eq!(drain(&mut rune, &mut p, 2), vec![1, 2]);
+
+        let mut p = stack(&[10, 20, 30, 2]);
+        rune.eval_op(&mut p, "PICK").unwrap();
+        assert_eq!(drain(&mut rune, &mut p, 4), vec![20, 30, 20, 10]);
+
+        let mut p = stack(&[10, 0]);
+        match rune.eval_op(&mut p, "PICK") {
+            Err(EngineError::InCorrectOperand) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn carry_operands() {
+        // `$c` and `$b` are computed by the parser from the values before and after the last
+        // write.
+        let mut rune = rune();
+        let mut control = RuneControl::Continue;
+        rune.ctx.set_reg_as_const("rax", 3);
+        let rax = Some(Token::EIdentifier("rax".to_owned()));
+        rune.process_op(Token::EEq, rax, Some(Token::EConstant(5)), &mut control).unwrap();
+        let old = rune.process_in(Some(&Token::EOld)).unwrap().unwrap();
+        let cur = rune.process_in(Some(&Token::ECur)).unwrap().unwrap();
+        assert_eq!(rune.ctx.as_constant(old), Some(3));
+        assert_eq!(rune.ctx.as_constant(cur), Some(5));
+    }
+
+    #[test]
+    fn explore_find_and_avoid() {
+        // The first path is avoided at 0x9006, the ones from 0x9007 and 0x9008 are found at
+        // 0x900c, and the one from 0x900d runs out of code.
+        let (find, avoid) = ([0x900c], [0x9006]);
+        let ips = [0x9007, 0x9008, 0x900d];
+        let found = explorer(&ips).explore(&find, &avoid).unwrap();
+        assert_eq!(found.iter().map(|ctx| ctx.ip()).collect::<Vec<_>>(), vec![0x900c, 0x900c]);
+
+        let mut rune = explorer(&ips);
+        let found = rune.explore_n(&find, &avoid, 1).unwrap();
+        assert_eq!(found.len(), 1);
+        // The found path ended, the next one waits to be explored.
+        assert_eq!(rune.ctx().ip(), 0x9008);
+        assert_eq!(rune.explorer().waiting(), Some(1));
+        assert_eq!(rune.explore_n(&find, &avoid, 1).unwrap().len(), 1);
+        assert!(rune.explore(&find, &avoid).unwrap().is_empty());
+        assert!(rune.explore(&find, &avoid).unwrap().is_empty());
+    }
+
+    #[test]
+    fn probe_branch_inputs() {
+        // No path reaches a branch.
+        let mut nops = explorer(&[0x9008]);
+        assert_eq!(nops.branch_inputs(0x9004).unwrap(), BranchInputs::default());
+        assert!(nops.step().unwrap().is_none());
+
+        // Inputs are solved for where a path branches at the address of the probe.
+        let mut rune = rune();
+        let x = rune.ctx.set_mem_as_sym(0x1000, 8, Some("x")).var;
+        let condition = rune.ctx.eval(bitvec::OpCodes::Extract(0, 0), vec![x]);
+        let condition = rune.process_out(&condition);
+        rune.probe = Some((0x9004, BranchInputs::default()));
+        rune.current = (0x9000, 1);
+        let mut control = RuneControl::Continue;
+        let branch = |rune: &mut TestRune, control: &mut RuneControl| {
+            rune.process_op(Token::EIf, Some(condition.clone()), None, control).unwrap();
+            rune.probe.as_ref().unwrap().1.clone()
+        };
+        assert_eq!(branch(&mut rune, &mut control), BranchInputs::default());
+        rune.current = (0x9004, 1);
+        assert!(branch(&mut rune, &mut control).is_complete());
+    }
+
+    /// Executor of an instruction that increments `rax` and stores its low byte at 0x1000.
+    struct Increment;
+
+    impl ConcreteExecutor for Increment {
+        fn registers(&self) -> Vec<(String, usize)> {
+            vec![("rax".to_owned(), 64)]
+        }
+
+        fn execute(&mut self,
+                   addr: u64,
+                   _: &[u8],
+                   regs: &BTreeMap<String, u64>,
+                   _: &mut FnMut(u64, usize) -> Vec<u8>)
+                   -> Result<ConcreteEffects, String> {
+            let rax = regs["rax"] + 1;
+            let mut effects = ConcreteEffects { next_ip: addr + 1, ..Default::default() };
+            effects.regs.insert("rax".to_owned(), rax);
+            effects.mem.insert(0x1000, rax as u8);
+            Ok(effects)
+        }
+    }
+
+    #[test]
+    fn concrete_fallback() {
+        let mut rune = rune();
+        rune.set_fallback(Box::new(Increment));
+        rune.ctx.set_reg_as_sym("rax", 64, Some("x"));
+        let decoded = Decoded {
+            opinfo: LOpInfo { bytes: Some("90".to_owned()), ..Default::default() },
+            parts: Vec::new(),
+            unsupported: Some("nop".to_owned()),
+            syscall: false,
+            rep: None,
+            cpu: None,
+            atomic: None,
+            native: None,
+            invalid: false,
+            flow: None,
+        };
+        rune.execute_concretely(0x9000, &decoded).unwrap();
+
+        // The results only hold for the value picked for `x`, but are not inputs.
+        let rax = rune.ctx.reg_read("rax");
+        assert_eq!(rune.ctx.as_constant(rax), None);
+        assert_eq!(rune.ctx.symbols().keys().collect::<Vec<_>>(), vec!["x"]);
+        assert_eq!(rune.ctx.ip(), 0x9001);
     }
 }
@@ -0,0 +1,134 @@
+//! Summaries that are applied instead of executing the code at an address.
+//!
+//! A summary leaps over code that rune does not model, typically a call to a library function:
+//! installed at the entry of the function (or at the call instruction, with `pop_frame` unset),
+//! it gives the return register a fresh symbol or a constant, clobbers the registers that the
+//! calling convention does not preserve across calls, and returns to the caller. Unlike a
+//! `Hook`, a summary is data, so it can be built from a configuration.
+
+use libsmt::logics::qf_abv;
+
+use context::context::{Context, RegisterRead};
+use engine::engine::{EngineError, EngineResult};
+use engine::hook;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReturnValue {
+    /// The return register keeps its value.
+    Unchanged,
+    /// A fresh symbol, named after the summary.
+    Symbolic,
+    Constant(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// Name of the summarized code, which the fresh symbols are named after.
+    pub name: String,
+    pub ret: ReturnValue,
+    /// Give the caller saved registers of the architecture fresh symbols.
+    pub clobber: bool,
+    /// Return from the current call. Otherwise execution continues after the instruction the
+    /// summary is installed at.
+    pub pop_frame: bool,
+}
+
+impl Summary {
+    /// Summary of a function that returns a symbolic value and clobbers the caller saved
+    /// registers.
+    pub fn new(name: &str) -> Summary {
+        Summary {
+            name: name.to_owned(),
+            ret: ReturnValue::Symbolic,
+            clobber: true,
+            pop_frame: true,
+        }
+    }
+
+    /// Skip the instruction the summary is installed at, without changing the state.
+    pub fn skip() -> Summary {
+        Summary {
+            name: String::new(),
+            ret: ReturnValue::Unchanged,
+            clobber: false,
+            pop_frame: false,
+        }
+    }
+}
+
+/// Apply `summary` to `ctx`. Without `pop_frame`, ip must already be past the instruction the
+/// summary is installed at.
+pub fn apply<Ctx>(ctx: &mut Ctx, summary: &Summary) -> EngineResult<()>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let width = ctx.address_width();
+    if summary.clobber {
+        let regs: Vec<String> = match ctx.arch() {
+            Some(arch) => {
+                arch.caller_saved()
+                    .iter()
+                    .filter(|r| ctx.has_reg(r))
+                    .map(|r| (*r).to_owned())
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        for reg in regs {
+            let value = ctx.fresh_value(&format!("{}_{}", summary.name, reg), width);
+            ctx.reg_write(&reg, value);
+        }
+    }
+
+    let value: Option<<Ctx as RegisterRead>::VarRef> = match summary.ret {
+        ReturnValue::Unchanged => None,
+        ReturnValue::Symbolic => Some(ctx.fresh_value(&format!("{}_ret", summary.name), width)),
+        ReturnValue::Constant(c) => Some(ctx.define_const(c, width)),
+    };
+    if summary.pop_frame {
+        return hook::return_from_call(ctx, value);
+    }
+    if let Some(value) = value {
+        let reg = ctx.reg_of_alias("R0").ok_or(EngineError::Undefined)?;
+        ctx.reg_write(reg, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use r2api::structs::Endian;
+
+    use arch::arch::AArch64;
+    use context::context::ContextAPI;
+    use context::rune_ctx::test_ctx;
+
+    #[test]
+    fn apply_summary() {
+        let mut ctx = test_ctx(&[("x0", 64), ("x1", 64), ("x19", 64), ("x30", 64)]);
+        ctx.set_arch(Rc::new(AArch64 { endian: Endian::Little }));
+        ctx.set_reg_as_const("x1", 1);
+        ctx.set_reg_as_const("x19", 2);
+        ctx.set_reg_as_const("x30", 0x4010);
+
+        apply(&mut ctx, &Summary::new("strlen")).unwrap();
+        assert_eq!(ctx.ip(), 0x4010);
+        let (x0, x1, x19) = (ctx.reg_read("x0"), ctx.reg_read("x1"), ctx.reg_read("x19"));
+        assert_eq!(ctx.as_constant(x0), None);
+        assert_eq!(ctx.as_constant(x1), None);
+        assert_eq!(ctx.as_constant(x19), Some(2));
+
+        let summary = Summary {
+            ret: ReturnValue::Constant(3),
+            pop_frame: false,
+            ..Summary::new("puts")
+        };
+        apply(&mut ctx, &summary).unwrap();
+        assert_eq!(ctx.ip(), 0x4010);
+        let x0 = ctx.reg_read("x0");
+        assert_eq!(ctx.as_constant(x0), Some(3));
+    }
+}
@@ -0,0 +1,170 @@
+//! System calls.
+//!
+//! Instructions that make a system call (`syscall`, `int 0x80`, `svc`, ...) are not executed
+//! when a `SyscallHandler` is installed (see `Rune::set_syscall_handler`). The engine reads the
+//! number of the call from the register the architecture passes it in, and the handler models
+//! the call. `SyscallTable` dispatches calls to a function per number.
+
+use std::collections::HashMap;
+
+use libsmt::logics::qf_abv;
+
+use context::context::{Context, RegisterRead};
+use engine::engine::{EngineError, EngineResult};
+
+/// What the program sees of a system call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyscallAction<V> {
+    /// The call returns to the next instruction, with a value if it returns one.
+    Return(Option<V>),
+    /// The call does not return, as `exit` does, and the path ends.
+    Exit,
+}
+
+pub trait SyscallHandler<Ctx: Context> {
+    /// Model the system call `num`, whose arguments can be read with `arg`.
+    fn syscall(&mut self,
+               ctx: &mut Ctx,
+               num: u64)
+               -> EngineResult<SyscallAction<<Ctx as RegisterRead>::VarRef>>;
+}
+
+/// A function modeling a system call.
+pub type Syscall<Ctx> = fn(&mut Ctx) -> EngineResult<SyscallAction<<Ctx as RegisterRead>::VarRef>>;
+
+/// Handler with a function for each system call number. Other calls are an
+/// `EngineError::UnhandledSyscall`.
+pub struct SyscallTable<Ctx: Context> {
+    calls: HashMap<u64, Syscall<Ctx>>,
+}
+
+impl<Ctx: Context> Default for SyscallTable<Ctx> {
+    fn default() -> SyscallTable<Ctx> {
+        SyscallTable { calls: HashMap::new() }
+    }
+}
+
+impl<Ctx: Context> SyscallTable<Ctx> {
+    pub fn add(&mut self, num: u64, call: Syscall<Ctx>) {
+        self.calls.insert(num, call);
+    }
+}
+
+impl<Ctx: Context> SyscallHandler<Ctx> for SyscallTable<Ctx> {
+    fn syscall(&mut self,
+               ctx: &mut Ctx,
+               num: u64)
+               -> EngineResult<SyscallAction<<Ctx as RegisterRead>::VarRef>> {
+        match self.calls.get(&num) {
+            Some(call) => call(ctx),
+            None => Err(EngineError::UnhandledSyscall(num)),
+        }
+    }
+}
+
+/// Register of the system call role `role`: the number, the return value or an argument.
+fn syscall_reg<Ctx: Context>(ctx: &Ctx, role: SyscallReg) -> EngineResult<String> {
+    match ctx.arch() {
+        Some(arch) => {
+            match role {
+                SyscallReg::Num => Some(arch.syscall_num().to_owned()),
+                SyscallReg::Ret => Some(arch.syscall_ret().to_owned()),
+                SyscallReg::Arg(n) => arch.syscall_args().get(n).map(|r| (*r).to_owned()),
+            }
+        }
+        // Without an architecture, fall back to the aliases of the register profile.
+        None => {
+            match role {
+                SyscallReg::Num => ctx.reg_of_alias("SN"),
+                SyscallReg::Ret => ctx.reg_of_alias("R0"),
+                SyscallReg::Arg(n) => ctx.reg_of_alias(&format!("A{}", n)),
+            }
+        }
+    }
+    .ok_or(EngineError::Undefined)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SyscallReg {
+    Num,
+    Ret,
+    Arg(usize),
+}
+
+/// Value of the `n`th argument of the current system call.
+pub fn arg<Ctx>(ctx: &mut Ctx, n: usize) -> EngineResult<<Ctx as RegisterRead>::VarRef>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let reg = syscall_reg(ctx, SyscallReg::Arg(n))?;
+    Ok(ctx.reg_read(reg))
+}
+
+/// Number of the system call being made, which has to be a constant.
+pub fn number<Ctx>(ctx: &mut Ctx) -> EngineResult<u64>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let reg = syscall_reg(ctx, SyscallReg::Num)?;
+    let num = ctx.reg_read(reg);
+    ctx.as_constant(num).ok_or(EngineError::Undefined)
+}
+
+/// Return `value` from the current system call.
+pub fn set_return<Ctx>(ctx: &mut Ctx, value: <Ctx as RegisterRead>::VarRef) -> EngineResult<()>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let reg = syscall_reg(ctx, SyscallReg::Ret)?;
+    ctx.reg_write(reg, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::rc::Rc;
+
+
+    use arch::arch::X86;
+    use context::context::ContextAPI;
+    use context::rune_ctx::{RuneContext, test_ctx};
+    use memory::paged_mem::PagedMemory;
+    use regstore::regfile::RuneRegFile;
+
+    type Ctx = RuneContext<PagedMemory, RuneRegFile>;
+
+    fn getpid(ctx: &mut Ctx) -> EngineResult<SyscallAction<<Ctx as RegisterRead>::VarRef>> {
+        Ok(SyscallAction::Return(Some(ctx.define_const(42, 64))))
+    }
+
+    fn exit(_: &mut Ctx) -> EngineResult<SyscallAction<<Ctx as RegisterRead>::VarRef>> {
+        Ok(SyscallAction::Exit)
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut ctx = test_ctx(&[("rax", 64), ("rdi", 64)]);
+        ctx.set_arch(Rc::new(X86 { bits: 64 }));
+
+        let mut table = SyscallTable::default();
+        table.add(39, getpid);
+        table.add(60, exit);
+
+        ctx.set_reg_as_const("rax", 39);
+        ctx.set_reg_as_const("rdi", 7);
+        assert_eq!(number(&mut ctx).ok(), Some(39));
+        let first = arg(&mut ctx, 0).unwrap();
+        assert_eq!(ctx.as_constant(first), Some(7));
+        match table.syscall(&mut ctx, 39).unwrap() {
+            SyscallAction::Return(Some(value)) => set_return(&mut ctx, value).unwrap(),
+            action => panic!("Unexpected {:?}", action),
+        }
+        let ret = ctx.reg_read("rax");
+        assert_eq!(ctx.as_constant(ret), Some(42));
+
+        assert_eq!(table.syscall(&mut ctx, 60).ok(), Some(SyscallAction::Exit));
+        match table.syscall(&mut ctx, 1) {
+            Err(EngineError::UnhandledSyscall(1)) => {}
+            _ => panic!("Unhandled system call was handled"),
+        }
+    }
+}
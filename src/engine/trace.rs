@@ -0,0 +1,29 @@
+//! Structured traces of execution, returned by `Rune::step_n` and `Rune::run_until`.
+
+use engine::rune::RuneControl;
+
+/// A write made by an instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Effect<V> {
+    Reg(String, V),
+    Mem {
+        addr: V,
+        value: V,
+        /// Width of the write in bits.
+        size: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry<V> {
+    /// Address of the instruction.
+    pub addr: u64,
+    /// Disassembly of the instruction, empty if a hook ran instead of it.
+    pub disasm: String,
+    /// Writes to registers and memory, in order.
+    pub effects: Vec<Effect<V>>,
+    /// Conditions of the branches in the instruction, with the side the explorer chose.
+    pub branches: Vec<(V, RuneControl)>,
+}
+
+pub type Trace<V> = Vec<TraceEntry<V>>;
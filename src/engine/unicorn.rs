@@ -0,0 +1,162 @@
+//! `ConcreteExecutor` that runs instructions in the Unicorn emulator, for x86 targets.
+//!
+//! The memory that an instruction reads is not known before it runs, so the instruction is run
+//! until it only reads bytes that were copied from the context: every run maps the pages it
+//! touched, and the bytes it read that the emulator did not have yet are fetched for the next
+//! run. Each run starts from a fresh emulator.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use unicorn::{Cpu, CpuX86, MemHookType, MemType, Mode, Protection, RegisterX86, Unicorn};
+
+use engine::fallback::{ConcreteEffects, ConcreteExecutor};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Runs of an instruction before giving up on the memory it reads.
+const MAX_RUNS: usize = 16;
+
+pub struct UnicornExecutor {
+    mode: Mode,
+    regs: Vec<(&'static str, RegisterX86, usize)>,
+    pc: RegisterX86,
+}
+
+impl UnicornExecutor {
+    /// An executor for x86 code of `bits` bits, `None` for other widths.
+    pub fn x86(bits: usize) -> Option<UnicornExecutor> {
+        let (mode, regs, pc) = match bits {
+            64 => (Mode::MODE_64,
+                   vec![("rax", RegisterX86::RAX, 64),
+                        ("rbx", RegisterX86::RBX, 64),
+                        ("rcx", RegisterX86::RCX, 64),
+                        ("rdx", RegisterX86::RDX, 64),
+                        ("rsi", RegisterX86::RSI, 64),
+                        ("rdi", RegisterX86::RDI, 64),
+                        ("rbp", RegisterX86::RBP, 64),
+                        ("rsp", RegisterX86::RSP, 64),
+                        ("r8", RegisterX86::R8, 64),
+                        ("r9", RegisterX86::R9, 64),
+                        ("r10", RegisterX86::R10, 64),
+                        ("r11", RegisterX86::R11, 64),
+                        ("r12", RegisterX86::R12, 64),
+                        ("r13", RegisterX86::R13, 64),
+                        ("r14", RegisterX86::R14, 64),
+                        ("r15", RegisterX86::R15, 64),
+                        ("rflags", RegisterX86::EFLAGS, 64),
+                        ("rip", RegisterX86::RIP, 64)],
+                   RegisterX86::RIP),
+            32 => (Mode::MODE_32,
+                   vec![("eax", RegisterX86::EAX, 32),
+                        ("ebx", RegisterX86::EBX, 32),
+                        ("ecx", RegisterX86::ECX, 32),
+                        ("edx", RegisterX86::EDX, 32),
+                        ("esi", RegisterX86::ESI, 32),
+                        ("edi", RegisterX86::EDI, 32),
+                        ("ebp", RegisterX86::EBP, 32),
+                        ("esp", RegisterX86::ESP, 32),
+                        ("eflags", RegisterX86::EFLAGS, 32),
+                        ("eip", RegisterX86::EIP, 32)],
+                   RegisterX86::EIP),
+            _ => return None,
+        };
+        Some(UnicornExecutor {
+            mode: mode,
+            regs: regs,
+            pc: pc,
+        })
+    }
+}
+
+fn page(addr: u64) -> u64 {
+    addr & !(PAGE_SIZE - 1)
+}
+
+impl ConcreteExecutor for UnicornExecutor {
+    fn registers(&self) -> Vec<(String, usize)> {
+        self.regs.iter().map(|&(name, _, width)| (name.to_owned(), width)).collect()
+    }
+
+    fn execute(&mut self,
+               addr: u64,
+               bytes: &[u8],
+               regs: &BTreeMap<String, u64>,
+               read: &mut FnMut(u64, usize) -> Vec<u8>)
+               -> Result<ConcreteEffects, String> {
+        let end = addr + bytes.len() as u64;
+        let mut pages: BTreeSet<u64> = (page(addr)..end).step_by(PAGE_SIZE as usize).collect();
+        pages.insert(page(end - 1));
+        // Bytes copied from the context.
+        let mut known: BTreeMap<u64, u8> = BTreeMap::new();
+
+        for _ in 0..MAX_RUNS {
+            let mut emu = CpuX86::new(self.mode).map_err(|e| format!("{:?}", e))?;
+            for &p in &pages {
+                emu.mem_map(p, PAGE_SIZE as usize, Protection::ALL).map_err(|e| format!("{:?}", e))?;
+            }
+            for (&a, &b) in &known {
+                emu.mem_write(a, &[b]).map_err(|e| format!("{:?}", e))?;
+            }
+            emu.mem_write(addr, bytes).map_err(|e| format!("{:?}", e))?;
+            for &(name, reg, _) in &self.regs {
+                if let Some(&value) = regs.get(name) {
+                    emu.reg_write(reg, value).map_err(|e| format!("{:?}", e))?;
+                }
+            }
+
+            let accesses = Rc::new(RefCell::new(Vec::new()));
+            let log = accesses.clone();
+            emu.add_mem_hook(MemHookType::MEM_ALL,
+                             1,
+                             0,
+                             move |_: &Unicorn, access: MemType, a: u64, size: usize, _: i64| {
+                                 log.borrow_mut().push((access, a, size));
+                                 // Stop at accesses to memory that is not mapped yet.
+                                 access == MemType::READ || access == MemType::WRITE ||
+                                 access == MemType::FETCH
+                             })
+               .map_err(|e| format!("{:?}", e))?;
+            let result = emu.emu_start(addr, end, 0, 1);
+
+            let accesses = accesses.borrow().clone();
+            let mut missing = false;
+            for &(access, a, size) in &accesses {
+                for p in (page(a)..a + size as u64).step_by(PAGE_SIZE as usize) {
+                    missing |= pages.insert(p);
+                }
+                let reads = access == MemType::READ || access == MemType::READ_UNMAPPED;
+                if reads && (a..a + size as u64).any(|b| !known.contains_key(&b)) {
+                    for (i, b) in read(a, size).into_iter().enumerate() {
+                        known.insert(a + i as u64, b);
+                    }
+                    missing = true;
+                }
+            }
+            if missing {
+                continue;
+            }
+            result.map_err(|e| format!("{:?}", e))?;
+
+            let mut effects = ConcreteEffects::default();
+            for &(name, reg, _) in &self.regs {
+                let value = emu.reg_read(reg).map_err(|e| format!("{:?}", e))?;
+                if regs.get(name) != Some(&value) {
+                    effects.regs.insert(name.to_owned(), value);
+                }
+            }
+            for &(access, a, size) in &accesses {
+                if access == MemType::WRITE {
+                    let data = emu.mem_read_as_vec(a, size).map_err(|e| format!("{:?}", e))?;
+                    for (i, b) in data.into_iter().enumerate() {
+                        effects.mem.insert(a + i as u64, b);
+                    }
+                }
+            }
+            effects.next_ip = emu.reg_read(self.pc).map_err(|e| format!("{:?}", e))?;
+            return Ok(effects);
+        }
+        Err(format!("Unable to execute the instruction at {:#x} concretely", addr))
+    }
+}
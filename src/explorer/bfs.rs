@@ -86,4 +86,13 @@ where Ctx: Context {
         // to load a new job from the queue.
         RuneControl::TerminatePath
     }
+
+    fn add_state(&mut self, ctx: Self::Ctx) -> bool {
+        self.queue.push_back(SavedState::new(ctx, BranchType::True));
+        true
+    }
+
+    fn waiting(&self) -> Option<usize> {
+        Some(self.queue.len())
+    }
 }
@@ -0,0 +1,60 @@
+//! Checkpoints of an exploration, see `StrategyExplorer::checkpoint`.
+//!
+//! A checkpoint holds the frontier of a `StrategyExplorer`: the image of every state that is
+//! left to step (see `context::image`), which carries the statistics of its path, and the
+//! coverage that the strategy gathered. Saved as JSON, it lets a long exploration go on after
+//! the process stops, or on another machine, in contexts created for the same program.
+//!
+//! The loops of every path are saved with their iteration counts, without the values of their
+//! counters (see `LoopState::to_image`). Merge groups are not part of a checkpoint: the states of
+//! a group are not merged anymore.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::{from_reader, to_string};
+
+use context::image::ContextImage;
+use explorer::loops::LoopImage;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateImage {
+    pub context: ContextImage,
+    /// Symbolic branches on its path.
+    pub depth: usize,
+    #[serde(default)]
+    pub loops: LoopImage,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// States in the order they were forked, the current one last.
+    pub states: Vec<StateImage>,
+    /// Entries into the blocks that were reached, see `Coverage::entries`.
+    #[serde(default)]
+    pub coverage: Vec<(u64, u64)>,
+}
+
+impl Checkpoint {
+    /// Save the checkpoint to the file `path`, as JSON. The file is replaced once the checkpoint
+    /// is written, so that an interrupted save leaves the previous checkpoint alone.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let json = to_string(self).map_err(|e| e.to_string())?;
+        let mut partial = OsString::from(path.as_ref());
+        partial.push(".partial");
+        {
+            let mut file = File::create(&partial).map_err(|e| e.to_string())?;
+            file.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+            file.sync_all().map_err(|e| e.to_string())?;
+        }
+        fs::rename(&partial, path).map_err(|e| e.to_string())
+    }
+
+    /// Read a checkpoint saved by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        from_reader(file).map_err(|e| e.to_string())
+    }
+}
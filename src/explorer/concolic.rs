@@ -0,0 +1,134 @@
+//! `ConcolicExplorer`, a `PathExplorer` that follows a concrete input.
+//!
+//! Execution takes the side of every branch that the input takes, while the state stays
+//! symbolic, so that the path is never forked and its conditions are collected along the way.
+//! Flipping one of them (see `ConcolicExplorer::flip`) and solving gives an input that follows
+//! the same path up to that branch and then takes the other side, for the next run.
+//!
+//! Inputs give the values of the symbols of the context by name, as `RuneContext::symbols` and
+//! `RuneContext::symbol_values` do. `input_bytes` builds one for a buffer of symbolic bytes such
+//! as the one of `RuneContext::symbolize_stdin`.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::BTreeMap;
+
+use libsmt::theories::core;
+
+use explorer::explorer::PathExplorer;
+use engine::rune::RuneControl;
+use context::context::{Context, Evaluate, RegisterRead};
+use context::rune_ctx::RuneContext;
+use memory::paged_mem::PagedMemory;
+use regstore::regfile::RuneRegFile;
+
+/// A branch of the path, with the scope that was opened before its side was taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConcolicBranch {
+    pub ip: u64,
+    pub condition: NodeIndex,
+    pub taken: bool,
+    depth: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConcolicExplorer {
+    input: BTreeMap<String, u64>,
+    branches: Vec<ConcolicBranch>,
+}
+
+/// Input giving `bytes` to the symbols `name[0]`, `name[1]`, ...
+pub fn input_bytes(name: &str, bytes: &[u8]) -> BTreeMap<String, u64> {
+    bytes.iter()
+         .enumerate()
+         .map(|(i, b)| (format!("{}[{}]", name, i), *b as u64))
+         .collect()
+}
+
+impl ConcolicExplorer {
+    pub fn with_input(input: BTreeMap<String, u64>) -> ConcolicExplorer {
+        ConcolicExplorer {
+            input: input,
+            branches: Vec::new(),
+        }
+    }
+
+    pub fn input(&self) -> &BTreeMap<String, u64> {
+        &self.input
+    }
+
+    /// Branches of the path so far, in order.
+    pub fn branches(&self) -> &[ConcolicBranch] {
+        &self.branches
+    }
+
+    /// An input that takes the same sides as the current one up to the `n`th branch, and the
+    /// other side of it. `None` if no input does.
+    ///
+    /// `ctx` has to be the context that the path was executed in. It is rolled back to the
+    /// branch, so this ends the run. Later branches are forgotten.
+    pub fn flip(&mut self, ctx: &mut RuneContext<PagedMemory, RuneRegFile>, n: usize) -> Option<BTreeMap<String, u64>> {
+        let branch = *self.branches.get(n)?;
+        while ctx.scope_depth() >= branch.depth {
+            ctx.pop_scope();
+        }
+        self.branches.truncate(n);
+
+        let value = ctx.define_const(!branch.taken as u64, 1);
+        ctx.eval(core::OpCodes::Cmp, &[branch.condition, value]);
+        ctx.solve_inputs()
+    }
+}
+
+impl PathExplorer for ConcolicExplorer {
+    type C = RuneControl;
+    type Ctx = RuneContext<PagedMemory, RuneRegFile>;
+
+    fn new() -> ConcolicExplorer {
+        ConcolicExplorer::default()
+    }
+
+    fn next(&mut self, _: &mut Self::Ctx) -> RuneControl {
+        RuneControl::Continue
+    }
+
+    // There is a single path.
+    fn next_job(&mut self, _: &mut Self::Ctx) -> Option<RuneControl> {
+        None
+    }
+
+    fn register_branch(&mut self,
+                       ctx: &mut Self::Ctx,
+                       condition: <Self::Ctx as RegisterRead>::VarRef)
+                       -> RuneControl {
+        let taken = ctx.is_taken_by(condition, &self.input);
+        let depth = ctx.push_scope();
+        let value = ctx.define_const(taken as u64, 1);
+        let constraint = ctx.eval(core::OpCodes::Cmp, &[condition, value]);
+        ctx.record_branch(condition, constraint, taken);
+        self.branches.push(ConcolicBranch {
+            ip: ctx.path_constraints().last().map_or(0, |c| c.ip),
+            condition: condition,
+            taken: taken,
+            depth: depth,
+        });
+        if taken {
+            RuneControl::ExploreTrue
+        } else {
+            RuneControl::ExploreFalse
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stdin_input() {
+        let input = input_bytes("stdin", b"ok");
+        assert_eq!(input.get("stdin[0]"), Some(&(b'o' as u64)));
+        assert_eq!(input.get("stdin[1]"), Some(&(b'k' as u64)));
+        assert_eq!(input.len(), 2);
+    }
+}
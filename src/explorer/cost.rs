@@ -0,0 +1,118 @@
+//! `Strategy` that steps the states whose paths cost the solver little more often.
+//!
+//! A few paths with huge constraints, such as the ones through hash functions or through loops
+//! over symbolic data, make every query on them slow, and a strategy that does not know it
+//! spends most of the time of an exploration on them. `CostWeighted` picks states at random,
+//! weighted by a score of what their path cost the solver so far (`StateInfo::cost`): the size
+//! of their constraints and the time the solver took on them. Costly states are stepped less
+//! often, but not starved. The score is a closure, `default_score` unless it is set.
+
+use std::rc::Rc;
+use std::time::Duration;
+
+use explorer::strategy::{StateInfo, Strategy};
+use utils::utils::Rng;
+
+/// Weight of a state, a positive number that the cheaper states have larger.
+pub type Score = Rc<Fn(&StateInfo) -> f64>;
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+/// 1 for a state whose path cost nothing, halved by every 1000 nodes of constraints and by
+/// every 100 milliseconds spent in the solver.
+pub fn default_score(state: &StateInfo) -> f64 {
+    let size = state.cost.constraint_size as f64 / 1000.0;
+    let time = seconds(state.cost.solver_time) * 10.0;
+    1.0 / (1.0 + size + time)
+}
+
+#[derive(Clone)]
+pub struct CostWeighted {
+    score: Score,
+    rng: Rng,
+}
+
+impl CostWeighted {
+    /// Choices that are the same for the same `seed`, weighted by `default_score`.
+    pub fn new(seed: u64) -> CostWeighted {
+        CostWeighted::with_score(seed, Rc::new(default_score))
+    }
+
+    pub fn with_score(seed: u64, score: Score) -> CostWeighted {
+        CostWeighted {
+            score: score,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn set_score(&mut self, score: Score) {
+        self.score = score;
+    }
+}
+
+impl Default for CostWeighted {
+    fn default() -> CostWeighted {
+        CostWeighted::new(0)
+    }
+}
+
+impl Strategy for CostWeighted {
+    fn select(&mut self, states: &[StateInfo]) -> usize {
+        let weights: Vec<f64> = states.iter().map(|s| (self.score)(s).max(0.0)).collect();
+        let total: f64 = weights.iter().sum();
+        if total.is_nan() || total <= 0.0 {
+            // No state scores, or a score is not a number.
+            return states.len() - 1;
+        }
+        let mut point = self.rng.unit() * total;
+        for (i, weight) in weights.iter().enumerate() {
+            point -= *weight;
+            if point < 0.0 {
+                return i;
+            }
+        }
+        states.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use context::stats::SolverCost;
+
+    #[test]
+    fn weight_by_cost() {
+        let state = |size, millis| {
+            StateInfo {
+                cost: SolverCost {
+                    constraint_size: size,
+                    solver_time: Duration::from_millis(millis),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        };
+        let states = [state(0, 0), state(3000, 0), state(1000, 200)];
+        assert_eq!(states.iter().map(default_score).collect::<Vec<_>>(),
+                   vec![1.0, 0.25, 0.25]);
+
+        // The cheap state is picked about twice as often as the two others together.
+        let mut strategy = CostWeighted::new(3);
+        let mut picks = [0; 3];
+        for _ in 0..3000 {
+            picks[strategy.select(&states)] += 1;
+        }
+        assert!(picks[0] > 1800 && picks[0] < 2200, "{:?}", picks);
+        assert!(picks[1] > 0 && picks[2] > 0, "{:?}", picks);
+
+        // Only the size of the constraints, of which the last state has the least.
+        strategy.set_score(Rc::new(|s: &StateInfo| 1.0 / (1 + s.cost.constraint_size) as f64));
+        let picks: Vec<usize> = (0..100).map(|_| strategy.select(&states[1..])).collect();
+        assert!(picks.iter().filter(|&&i| i == 1).count() > 60, "{:?}", picks);
+        let mut zero = CostWeighted::with_score(3, Rc::new(|_: &StateInfo| 0.0));
+        assert_eq!(zero.select(&states), 2);
+    }
+}
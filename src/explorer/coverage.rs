@@ -0,0 +1,160 @@
+//! Basic block coverage of every explored state, and a `Strategy` that goes for new code.
+//!
+//! `Coverage` counts how often the paths entered each block of a `Cfg` (see `explorer::target`).
+//! Addresses outside of the graph, or every address if there is no graph, count as blocks of
+//! their own. `CoverageSearch` records the coverage as the states of a `StrategyExplorer` step,
+//! and steps the states that are about to enter the least covered blocks first, so that paths
+//! that go through blocks no path entered yet go before paths that loop. The coverage it
+//! gathered is `CoverageSearch::coverage`, to be exported with `Coverage::export` once the
+//! exploration is over.
+
+use std::collections::BTreeMap;
+
+use explorer::strategy::{StateInfo, Strategy};
+use explorer::target::Cfg;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    cfg: Cfg,
+    /// Entries into the blocks that were reached, by the start of the blocks.
+    hits: BTreeMap<u64, u64>,
+}
+
+impl Coverage {
+    pub fn new(cfg: Cfg) -> Coverage {
+        Coverage {
+            cfg: cfg,
+            hits: BTreeMap::new(),
+        }
+    }
+
+    /// Start of the block that holds `addr`, `addr` itself outside of the graph.
+    pub fn block_of(&self, addr: u64) -> u64 {
+        self.cfg.block_of(addr).unwrap_or(addr)
+    }
+
+    /// Record that a path reached `addr`. Only the first address of a block enters it, jumps into
+    /// the middle of a block cover it without counting an entry.
+    pub fn visit(&mut self, addr: u64) {
+        let block = self.block_of(addr);
+        let hits = self.hits.entry(block).or_insert(0);
+        if block == addr {
+            *hits += 1;
+        }
+    }
+
+    /// Whether a path reached the block of `addr`.
+    pub fn is_covered(&self, addr: u64) -> bool {
+        self.hits.contains_key(&self.block_of(addr))
+    }
+
+    /// Entries into the block of `addr`.
+    pub fn hits(&self, addr: u64) -> u64 {
+        self.hits.get(&self.block_of(addr)).cloned().unwrap_or(0)
+    }
+
+    /// Starts of the blocks that were reached, in order.
+    pub fn covered(&self) -> Vec<u64> {
+        self.hits.keys().cloned().collect()
+    }
+
+    /// Starts of the blocks of the graph that were not reached, in order.
+    pub fn uncovered(&self) -> Vec<u64> {
+        self.cfg.blocks().filter(|block| !self.hits.contains_key(block)).collect()
+    }
+
+    /// Entries into the blocks that were reached, as (start, entries) pairs in order.
+    pub fn entries(&self) -> Vec<(u64, u64)> {
+        self.hits.iter().map(|(&block, &hits)| (block, hits)).collect()
+    }
+
+    /// Count `entries` more entries into `block`, which is reached even if there are none. This
+    /// adds the coverage gathered elsewhere, such as the one of a checkpoint.
+    pub fn add_entries(&mut self, block: u64, entries: u64) {
+        *self.hits.entry(block).or_insert(0) += entries;
+    }
+
+    /// The blocks that were reached, one a line as their start in hex and their entries, as in
+    /// `0x401000 3`. Blocks that were only jumped into have 0 entries.
+    pub fn export(&self) -> String {
+        self.hits.iter().map(|(block, hits)| format!("0x{:x} {}\n", block, hits)).collect()
+    }
+}
+
+/// Steps the state whose block was entered the least times first, the deepest of them if there
+/// are several.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageSearch {
+    coverage: Coverage,
+}
+
+impl CoverageSearch {
+    pub fn new(coverage: Coverage) -> CoverageSearch {
+        CoverageSearch { coverage: coverage }
+    }
+
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+}
+
+impl Strategy for CoverageSearch {
+    fn select(&mut self, states: &[StateInfo]) -> usize {
+        let coverage = &self.coverage;
+        let key = |s: &StateInfo| (coverage.is_covered(s.ip), coverage.hits(s.ip), !s.depth);
+        let best = states.iter().map(&key).min();
+        states.iter().rposition(|s| Some(key(s)) == best).unwrap_or(0)
+    }
+
+    fn visit(&mut self, ip: u64) {
+        self.coverage.visit(ip);
+    }
+
+    fn coverage(&self) -> Option<&Coverage> {
+        Some(&self.coverage)
+    }
+
+    fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        Some(&mut self.coverage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn track_coverage() {
+        let mut cfg = Cfg::default();
+        cfg.add_block(0x10, 0x10, vec![0x20, 0x30]);
+        cfg.add_block(0x20, 8, vec![0x10]);
+        cfg.add_block(0x30, 8, vec![]);
+        let mut search = CoverageSearch::new(Coverage::new(cfg));
+        assert_eq!(search.coverage().uncovered(), vec![0x10, 0x20, 0x30]);
+        for &ip in &[0x10, 0x14, 0x20, 0x10, 0x14, 0x34, 0x50] {
+            search.visit(ip);
+        }
+        let coverage = search.coverage().clone();
+        assert_eq!((coverage.hits(0x18), coverage.hits(0x24), coverage.hits(0x30)), (2, 1, 0));
+        assert!(coverage.is_covered(0x30) && !coverage.is_covered(0x51));
+        assert_eq!(coverage.uncovered(), Vec::<u64>::new());
+        assert_eq!(coverage.export(), "0x10 2\n0x20 1\n0x30 0\n0x50 1\n");
+        let mut copy = Coverage::default();
+        for (block, entries) in coverage.entries() {
+            copy.add_entries(block, entries);
+        }
+        assert_eq!(copy.export(), coverage.export());
+
+        let state = |ip, depth| {
+            StateInfo {
+                ip: ip,
+                depth: depth,
+                ..Default::default()
+            }
+        };
+        // Blocks that no path reached, then the least entered ones, then the deepest states.
+        assert_eq!(search.select(&[state(0x10, 5), state(0x60, 1), state(0x20, 2)]), 1);
+        assert_eq!(search.select(&[state(0x10, 5), state(0x20, 2), state(0x30, 1)]), 2);
+        assert_eq!(search.select(&[state(0x10, 5), state(0x20, 2), state(0x24, 3)]), 2);
+    }
+}
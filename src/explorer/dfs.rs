@@ -2,7 +2,7 @@
 
 use std::collections::VecDeque;
 
-use libsmt::theories::core;
+use libsmt::theories::{bitvec, core};
 
 use explorer::explorer::PathExplorer;
 use engine::rune::RuneControl;
@@ -11,37 +11,26 @@ use context::rune_ctx::RuneContext;
 use memory::qword_mem::QWordMemory;
 use regstore::regfile::RuneRegFile;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-#[allow(dead_code)]
-enum BranchType {
-    True,
-    False,
-}
-
-#[derive(Clone, Debug)]
-struct SavedState<C: Context> {
-    pub ctx: C,
-    pub branch: BranchType,
-}
-
-impl<C: Context> SavedState<C> {
-    fn new(ctx: C, b: BranchType) -> SavedState<C> {
-        SavedState {
-            ctx: ctx,
-            branch: b,
-        }
-    }
+/// The false side of a branch that is left to explore.
+#[derive(Clone, Copy, Debug)]
+struct PendingBranch<V> {
+    /// The scope opened to explore the true side, see `RuneContext::push_scope`.
+    depth: usize,
+    condition: V,
 }
 
 /// An explorer that traverses the program states in a depth first order.
+///
+/// Instead of saving a copy of the context for every branch, the true side of a branch is
+/// explored in a scope of the context. Closing the scope returns to the branch, where only the
+/// constraint of the false side has to be added. Sides of a branch that the constraints of the
+/// path rule out are dropped at the branch, the other side is taken without a scope.
 #[derive(Default)]
 pub struct DFSExplorer<Ctx: Context> {
     /// Depth First Queue
-    queue: VecDeque<SavedState<Ctx>>,
+    queue: VecDeque<PendingBranch<<Ctx as RegisterRead>::VarRef>>,
 }
 
-// TODO: [X] Add constraints for T/F branch
-//       [ ] Check if the paths are feasible before enqueue
 impl PathExplorer for DFSExplorer<RuneContext<QWordMemory, RuneRegFile>> {
     type C = RuneControl;
     type Ctx = RuneContext<QWordMemory, RuneRegFile>;
@@ -59,12 +48,14 @@ impl PathExplorer for DFSExplorer<RuneContext<QWordMemory, RuneRegFile>> {
     // When rune finishes its execution, pop another unexplored path for it to
     // explore.
     fn next_job(&mut self, ctx: &mut Self::Ctx) -> Option<RuneControl> {
-        if let Some(ref state) = self.queue.pop_back() {
-            *ctx = state.ctx.clone();
-            Some(match state.branch {
-                BranchType::True => RuneControl::ExploreTrue,
-                BranchType::False => RuneControl::ExploreFalse,
-            })
+        if let Some(branch) = self.queue.pop_back() {
+            while ctx.scope_depth() >= branch.depth {
+                ctx.pop_scope();
+            }
+            let zero = ctx.define_const(0, 1);
+            let constraint = ctx.eval(core::OpCodes::Cmp, &[branch.condition, zero]);
+            ctx.record_branch(branch.condition, constraint, false);
+            Some(RuneControl::ExploreFalse)
         } else {
             None
         }
@@ -79,16 +70,27 @@ impl PathExplorer for DFSExplorer<RuneContext<QWordMemory, RuneRegFile>> {
         // true branch. Note that this choice is arbitrary and we could have as well
         // chosen the
         // other part without changing the nature of this explorer.
-        let mut false_ctx = ctx.clone();
-        {
+        if !ctx.is_feasible(condition) {
             let zero = ctx.define_const(0, 1);
-            false_ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
+            let constraint = ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
+            ctx.record_branch(condition, constraint, false);
+            return RuneControl::ExploreFalse;
         }
-        self.queue.push_back(SavedState::new(false_ctx, BranchType::False));
-        {
-            let one = ctx.define_const(1, 1);
-            ctx.eval(core::OpCodes::Cmp, &[condition, one]);
+        let not_taken = ctx.eval(bitvec::OpCodes::BvNot, &[condition]);
+        if ctx.is_feasible(not_taken) {
+            let depth = ctx.push_scope();
+            self.queue.push_back(PendingBranch {
+                depth: depth,
+                condition: condition,
+            });
         }
+        let one = ctx.define_const(1, 1);
+        let constraint = ctx.eval(core::OpCodes::Cmp, &[condition, one]);
+        ctx.record_branch(condition, constraint, true);
         RuneControl::ExploreTrue
     }
+
+    fn waiting(&self) -> Option<usize> {
+        Some(self.queue.len())
+    }
 }
@@ -82,13 +82,17 @@ impl PathExplorer for DirectedExplorer
                 let direction = self.d_map.get(&ctx.ip()).unwrap();
                 match *direction {
                     BranchType::True => {
-                        let one = ctx.define_const(1, 64);
-                        ctx.eval(core::OpCodes::Cmp, &[condition, one]);
+                        let width = ctx.address_width();
+                        let one = ctx.define_const(1, width);
+                        let constraint = ctx.eval(core::OpCodes::Cmp, &[condition, one]);
+                        ctx.record_branch(condition, constraint, true);
                         RuneControl::ExploreTrue
                     }
                     BranchType::False => {
-                        let zero = ctx.define_const(0, 64);
-                        ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
+                        let width = ctx.address_width();
+                        let zero = ctx.define_const(0, width);
+                        let constraint = ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
+                        ctx.record_branch(condition, constraint, false);
                         RuneControl::ExploreFalse
                     }
                     _ => panic!("Invalid branch type found!"),
@@ -3,15 +3,55 @@
 use context::context::Context;
 use std::fmt::Debug;
 
+use context::call_stack::BadReturn;
 use context::context::RegisterRead;
+use engine::breakpt::{WatchHit, WatchValue};
+use engine::fault::Fault;
+use memory::uninit::UninitRead;
 
 pub trait PathExplorer {
     type C: Clone + Debug;
     type Ctx: Context;
 
     fn new() -> Self;
+    /// Called by rune after every instruction. Returning `TerminatePath` ends the current path,
+    /// and rune goes on with `next_job`.
     fn next(&mut self, &mut Self::Ctx) -> Self::C;
     fn next_job(&mut self, &mut Self::Ctx) -> Option<Self::C>;
 
     fn register_branch(&mut self, &mut Self::Ctx, <Self::Ctx as RegisterRead>::VarRef) -> Self::C;
+
+    /// Called by rune after an instruction accessed memory watched by a watchpoint.
+    fn watchpoint_hit(&mut self,
+                      _ctx: &mut Self::Ctx,
+                      _hits: &[WatchHit<<Self::Ctx as RegisterRead>::VarRef>]) {
+    }
+
+    /// Called by rune after every instruction with the values of the watch expressions of the
+    /// context, if it has any.
+    fn watch_values(&mut self, _ctx: &mut Self::Ctx, _values: &[WatchValue]) { }
+
+    /// Called by rune after an instruction read uninitialized memory, unless the context is set
+    /// to treat such reads as errors.
+    fn uninit_read(&mut self, _ctx: &mut Self::Ctx, _reads: &[UninitRead]) { }
+
+    /// Called by rune when a path ends with a fault, with the state of the path at the fault,
+    /// see `Rune::set_fault_states`.
+    fn faulted(&mut self, _ctx: &mut Self::Ctx, _fault: &Fault) { }
+
+    /// Called by rune after an instruction returned to an address that does not follow a call
+    /// of the call stack of the path, see `context::call_stack`.
+    fn bad_return(&mut self, _ctx: &mut Self::Ctx, _ret: &BadReturn) { }
+
+    /// Called by rune with a state forked off the current path that goes on at an address of its
+    /// own, as at the targets of a jump to a symbolic address, see `engine::indirect`. Returns
+    /// false, dropping the state, if the explorer does not take such states.
+    fn add_state(&mut self, _ctx: Self::Ctx) -> bool {
+        false
+    }
+
+    /// States left to explore besides the current one, if the explorer counts them.
+    fn waiting(&self) -> Option<usize> {
+        None
+    }
 }
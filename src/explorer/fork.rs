@@ -0,0 +1,87 @@
+//! `PathExplorer` that follows a single path and hands its forks to someone else.
+//!
+//! This is the explorer of the workers of `engine::parallel`: the feasible false side of a
+//! symbolic branch is saved as a copy of the context, which the worker sends back to the pool
+//! once its path ends, instead of exploring it.
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+
+use context::context::{Context, RegisterRead};
+use engine::rune::RuneControl;
+use explorer::explorer::PathExplorer;
+
+#[derive(Default)]
+pub struct ForkExplorer<Ctx: Context> {
+    /// States forked off the current path, at the false side of their branch.
+    forks: Vec<Ctx>,
+}
+
+impl<Ctx: Context> ForkExplorer<Ctx> {
+    /// The states forked since the last call.
+    pub fn take_forks(&mut self) -> Vec<Ctx> {
+        self.forks.drain(..).collect()
+    }
+}
+
+/// Assume the branch `condition` of `ctx` to be `taken`.
+fn follow<Ctx>(ctx: &mut Ctx,
+               condition: <Ctx as RegisterRead>::VarRef,
+               taken: bool)
+               -> RuneControl
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let value = ctx.define_const(taken as u64, 1);
+    let constraint = ctx.eval(core::OpCodes::Cmp, vec![condition.clone(), value]);
+    ctx.record_branch(condition, constraint, taken);
+    if taken { RuneControl::ExploreTrue } else { RuneControl::ExploreFalse }
+}
+
+impl<Ctx> PathExplorer for ForkExplorer<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    type C = RuneControl;
+    type Ctx = Ctx;
+
+    fn new() -> ForkExplorer<Ctx> {
+        ForkExplorer { forks: Vec::new() }
+    }
+
+    fn next(&mut self, _: &mut Ctx) -> RuneControl {
+        RuneControl::Continue
+    }
+
+    // The path of the worker ends here, the pool decides what comes next.
+    fn next_job(&mut self, _: &mut Ctx) -> Option<RuneControl> {
+        None
+    }
+
+    fn register_branch(&mut self,
+                       ctx: &mut Ctx,
+                       condition: <Ctx as RegisterRead>::VarRef)
+                       -> RuneControl {
+        if let Some(c) = ctx.as_constant(condition.clone()) {
+            return follow(ctx, condition, c != 0);
+        }
+        let not_taken = ctx.eval(bitvec::OpCodes::BvNot, vec![condition.clone()]);
+        if !ctx.is_feasible(not_taken) {
+            return follow(ctx, condition, true);
+        }
+        if !ctx.is_feasible(condition.clone()) {
+            return follow(ctx, condition, false);
+        }
+        let mut fork = ctx.clone();
+        follow(&mut fork, condition.clone(), false);
+        self.forks.push(fork);
+        follow(ctx, condition, true)
+    }
+
+    fn add_state(&mut self, ctx: Ctx) -> bool {
+        self.forks.push(ctx);
+        true
+    }
+
+    fn waiting(&self) -> Option<usize> {
+        Some(self.forks.len())
+    }
+}
@@ -19,6 +19,34 @@ pub enum Command {
     Invalid,
     SetContext(SAssignment),
     SetVar(SAssignment),
+    /// Watch accesses to `size` bytes starting at `addr`.
+    Watch(u64, u64),
+    /// Print the value of an expression after every instruction.
+    WatchExpr(String),
+    /// Dump memory as JSON and raw segments.
+    DumpMemory,
+    /// Assume conditions on the current state, see `utils::conditions`.
+    Precondition(String),
+    /// Check that conditions hold on the current state.
+    Postcondition(String),
+    /// List the states left to explore.
+    ListStates,
+    /// Go on with a state left to explore, by its index in `ListStates`.
+    SelectState(usize),
+    /// Print the path of a state left to explore, of the current state if `None`.
+    InspectState(Option<usize>),
+    /// Drop a state left to explore, the current state if `None`.
+    KillState(Option<usize>),
+    /// Explore both sides of every branch without asking, until `Pause`.
+    AutoExplore,
+    /// Ask again at every branch, after `AutoExplore`.
+    Pause,
+    /// Print inputs that take the current branch and inputs that do not.
+    BranchInputs,
+    /// Print the statistics of the exploration.
+    Stats,
+    /// Print how the state of the path before its step `n` differs from its current state.
+    Seek(usize),
     Exit,
 }
 
@@ -33,13 +61,30 @@ impl Command {
 
     pub fn is_set(&self) -> bool {
         match *self {
-            Command::SetVar(_) | Command::SetContext(_) => true,
+            Command::SetVar(_) | Command::SetContext(_) | Command::Watch(_, _) => true,
+            _ => false,
+        }
+    }
+
+    pub fn takes_text(&self) -> bool {
+        match *self {
+            Command::Precondition(_) | Command::Postcondition(_) | Command::WatchExpr(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn takes_state(&self) -> bool {
+        match *self {
+            Command::SelectState(_) |
+            Command::InspectState(_) |
+            Command::KillState(_) |
+            Command::Seek(_) => true,
             _ => false,
         }
     }
 
     pub fn is_chainable(&self) -> bool {
-        !self.is_invalid() && !self.is_set()
+        !self.is_invalid() && !self.is_set() && !self.takes_text() && !self.takes_state()
     }
 }
 
@@ -49,6 +94,11 @@ impl Command {
  * Add method to set memory range as symbolic
  * Have r2 style self-documentation
  */
+/// The state index argument of `s`, `None` if there is none and `Some(None)` if it is invalid.
+fn state_arg(s: &str) -> Option<Option<usize>> {
+    s.split_whitespace().nth(1).map(|n| n.parse().ok())
+}
+
 impl From<String> for Command {
     fn from(s: String) -> Command {
         if let Some(c) = s.chars().nth(0) { 
@@ -58,7 +108,33 @@ impl From<String> for Command {
                 't' => Command::FollowTrue,
                 'f' => Command::FollowFalse,
                 'c' => Command::Continue,
+                'a' => Command::AutoExplore,
+                's' if s.starts_with("sel") => {
+                    // sel <state>
+                    match state_arg(&s) {
+                        Some(Some(n)) => Command::SelectState(n),
+                        _ => Command::Invalid,
+                    }
+                }
+                's' if s.trim() == "stats" => Command::Stats,
+                's' if s.starts_with("seek") => {
+                    // seek <step>
+                    match state_arg(&s) {
+                        Some(Some(n)) => Command::Seek(n),
+                        _ => Command::Invalid,
+                    }
+                }
                 's' => Command::Step,
+                'l' => Command::ListStates,
+                'i' if s.trim() == "inputs" => Command::BranchInputs,
+                'i' | 'k' => {
+                    // i [state] | k [state]
+                    let n = match state_arg(&s) {
+                        Some(None) => return Command::Invalid,
+                        n => n.and_then(|n| n),
+                    };
+                    if c == 'i' { Command::InspectState(n) } else { Command::KillState(n) }
+                }
                 'b' => {
                     let (_, addr) = s.split_at(2);
                     if let Some(val) = convert_to_u64(addr.trim()) {
@@ -75,6 +151,7 @@ impl From<String> for Command {
                         match c2 {
                             'S' => Command::DebugState,
                             'Q' => Command::DebugQuery,
+                            'M' => Command::DumpMemory,
                             _ => Command::Invalid,
                         } 
                     } else {
@@ -91,6 +168,28 @@ impl From<String> for Command {
                         Command::Invalid
                     }
                 }
+                'w' if s.starts_with("we ") => Command::WatchExpr(s[3..].trim().to_owned()),
+                'w' => {
+                    // w <addr> [size]
+                    let mut args = s.split_whitespace().skip(1);
+                    let addr = args.next().and_then(|a| convert_to_u64(a));
+                    let size = args.next().map_or(Some(8), |sz| convert_to_u64(sz));
+                    match (addr, size) {
+                        (Some(addr), Some(size)) => Command::Watch(addr, size),
+                        _ => Command::Invalid,
+                    }
+                }
+                'p' => {
+                    // pre <conditions> | post <conditions>
+                    let (cmd, conditions) = s.split_at(s.find(' ').unwrap_or(s.len()));
+                    let conditions = conditions.trim().to_owned();
+                    match cmd {
+                        "pre" => Command::Precondition(conditions),
+                        "post" => Command::Postcondition(conditions),
+                        "pause" => Command::Pause,
+                        _ => Command::Invalid,
+                    }
+                }
                 'S' => Command::Save,
                 'h' => Command::Help,
                 'r' => Command::Run,
@@ -102,3 +201,28 @@ impl From<String> for Command {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_state_commands() {
+        let parse = |s: &str| Command::from(s.to_owned());
+        assert_eq!(parse("ls"), Command::ListStates);
+        assert_eq!(parse("sel 2"), Command::SelectState(2));
+        assert_eq!(parse("sel"), Command::Invalid);
+        assert_eq!(parse("s"), Command::Step);
+        assert_eq!(parse("i"), Command::InspectState(None));
+        assert_eq!(parse("k 3"), Command::KillState(Some(3)));
+        assert_eq!(parse("k x"), Command::Invalid);
+        assert_eq!(parse("a"), Command::AutoExplore);
+        assert_eq!(parse("c"), Command::Continue);
+        assert_eq!(parse("pause"), Command::Pause);
+        assert_eq!(parse("inputs"), Command::BranchInputs);
+        assert_eq!(parse("stats"), Command::Stats);
+        assert_eq!(parse("seek 12"), Command::Seek(12));
+        assert_eq!(parse("seek"), Command::Invalid);
+        assert!(!parse("i 1").is_chainable());
+    }
+}
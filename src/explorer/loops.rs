@@ -0,0 +1,379 @@
+//! Bounds on the iterations of loops, see `StrategyExplorer::set_loop_bound`.
+//!
+//! A loop on a symbolic condition forks the state at every iteration, and the path that stays in
+//! the loop never ends. Back edges are found as the paths go: a jump from an instruction to an
+//! address at or before it is the back edge of a loop that starts at its target. Every path
+//! counts the times it took each back edge, and the `LoopAction` of the bound applies once it
+//! takes one more than `limit` times. Taking the back edge of a loop starts the count of the
+//! loops it holds over, so that inner loops are bounded per iteration of the outer ones.
+
+use std::collections::HashMap;
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec;
+
+use context::context::{Context, RegisterRead};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopAction {
+    /// End the path.
+    Drop,
+    /// Stop forking in the loop: its symbolic branches go the way a model of the constraints of
+    /// the path does, which fixes the values the loop counts to. The path still ends if it
+    /// takes the back edge `limit` more times.
+    Concretize,
+    /// Replace the remaining iterations of a simple counting loop by a symbolic number of them.
+    /// The loop is simple if each counter register either kept its value or changed by the
+    /// same constant over the last iterations, and if one of them changed. The counters that
+    /// changed are set to their value after any number of further iterations, and the loop
+    /// goes on: its exit condition is then symbolic, and the state that leaves the loop stands
+    /// for leaving it after any of them. Memory keeps the values of the last iteration. Loops
+    /// that are not simple, and summarized loops that are taken again, end the path.
+    Summarize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoopBound {
+    /// Times a path can take a back edge.
+    pub limit: usize,
+    pub action: LoopAction,
+    /// Registers that loops count in, for `LoopAction::Summarize`, of the width of addresses.
+    /// The argument, return value and caller saved registers of the architecture if empty.
+    pub counters: Vec<String>,
+}
+
+impl Default for LoopBound {
+    fn default() -> LoopBound {
+        LoopBound {
+            limit: 16,
+            action: LoopAction::Drop,
+            counters: Vec::new(),
+        }
+    }
+}
+
+/// A back edge, as the address of the jump and the address of the start of its loop.
+type Edge = (u64, u64);
+
+#[derive(Clone, Debug)]
+struct Iterations<V> {
+    count: usize,
+    /// Values of the counters the last three times the back edge was taken, the latest last.
+    values: Vec<Vec<V>>,
+    summarized: bool,
+}
+
+impl<V> Default for Iterations<V> {
+    fn default() -> Iterations<V> {
+        Iterations {
+            count: 0,
+            values: Vec::new(),
+            summarized: false,
+        }
+    }
+}
+
+/// The loops of a path. Forks start with the loops of their parent.
+#[derive(Clone, Debug)]
+pub struct LoopState<V> {
+    /// Address of the instruction the path executes.
+    last: Option<u64>,
+    loops: HashMap<Edge, Iterations<V>>,
+    /// Loop whose branches follow a model, see `LoopAction::Concretize`.
+    concrete: Option<Edge>,
+}
+
+impl<V> Default for LoopState<V> {
+    fn default() -> LoopState<V> {
+        LoopState {
+            last: None,
+            loops: HashMap::new(),
+            concrete: None,
+        }
+    }
+}
+
+/// The loops of a path as saved in checkpoints and spilled states, see `LoopState::to_image`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopImage {
+    pub last: Option<u64>,
+    /// Back edges, with the times the path took them and whether their loop was summarized.
+    pub loops: Vec<(Edge, usize, bool)>,
+    pub concrete: Option<Edge>,
+}
+
+impl<V> LoopState<V> {
+    /// The image of the loops, without the values of their counters: a resumed path summarizes
+    /// a loop once it took its back edge three more times.
+    pub fn to_image(&self) -> LoopImage {
+        let mut loops: Vec<_> = self.loops
+                                    .iter()
+                                    .map(|(&edge, i)| (edge, i.count, i.summarized))
+                                    .collect();
+        loops.sort();
+        LoopImage {
+            last: self.last,
+            loops: loops,
+            concrete: self.concrete,
+        }
+    }
+
+    /// The loops saved in `image` by `to_image`.
+    pub fn from_image(image: &LoopImage) -> LoopState<V> {
+        let loops = image.loops
+                         .iter()
+                         .map(|&(edge, count, summarized)| {
+                             let iterations = Iterations {
+                                 count: count,
+                                 values: Vec::new(),
+                                 summarized: summarized,
+                             };
+                             (edge, iterations)
+                         })
+                         .collect();
+        LoopState {
+            last: image.last,
+            loops: loops,
+            concrete: image.concrete,
+        }
+    }
+
+    /// Start the path over at `ip`, whose previous instruction is not known.
+    pub fn resume(&mut self, ip: u64) {
+        self.last = Some(ip);
+    }
+
+    /// Times the path took the back edge from `from` to `to`.
+    pub fn iterations(&self, from: u64, to: u64) -> usize {
+        self.loops.get(&(from, to)).map_or(0, |i| i.count)
+    }
+
+    /// Whether the branches of the instruction being executed follow a model.
+    pub fn is_concrete(&self) -> bool {
+        match (self.concrete, self.last) {
+            (Some((from, to)), Some(ip)) => to <= ip && ip <= from,
+            _ => false,
+        }
+    }
+}
+
+/// The counters of `bound` that `ctx` has.
+fn counters<Ctx: Context>(ctx: &Ctx, bound: &LoopBound) -> Vec<String> {
+    let mut regs = bound.counters.clone();
+    if regs.is_empty() {
+        if let Some(arch) = ctx.arch() {
+            let names = arch.args().iter().chain(arch.caller_saved()).cloned();
+            regs.extend(names.chain(Some(arch.ret())).map(|r| r.to_owned()));
+        }
+    }
+    let mut counters = Vec::new();
+    for reg in regs {
+        if ctx.has_reg(&reg) && !counters.contains(&reg) {
+            counters.push(reg);
+        }
+    }
+    counters
+}
+
+/// Steps of the counters over `values`, `None` if the loop is not simple.
+fn steps<Ctx>(ctx: &mut Ctx, values: &[Vec<<Ctx as RegisterRead>::VarRef>]) -> Option<Vec<u64>>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let mut steps = Vec::new();
+    for i in 0..values[0].len() {
+        let (a, b, c) = (&values[0][i], &values[1][i], &values[2][i]);
+        if a == b && b == c {
+            steps.push(0);
+            continue;
+        }
+        let (a, b, c) = (ctx.as_constant(a.clone()), ctx.as_constant(b.clone()),
+                         ctx.as_constant(c.clone()));
+        match (a, b, c) {
+            (Some(a), Some(b), Some(c)) if b.wrapping_sub(a) == c.wrapping_sub(b) => {
+                steps.push(c.wrapping_sub(b))
+            }
+            _ => return None,
+        }
+    }
+    if steps.iter().all(|&s| s == 0) { None } else { Some(steps) }
+}
+
+/// Set the counters of the loop that starts at `start` to their value after a symbolic number
+/// of further iterations. Returns false if the loop is not simple.
+fn summarize<Ctx>(ctx: &mut Ctx,
+                  counters: &[String],
+                  values: &[Vec<<Ctx as RegisterRead>::VarRef>],
+                  start: u64)
+                  -> bool
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let steps = match steps(ctx, values) {
+        Some(steps) => steps,
+        None => return false,
+    };
+    let width = ctx.address_width();
+    let iterations = ctx.fresh_value(&format!("loop_{:x}_iterations", start), width);
+    for (reg, (&step, value)) in counters.iter().zip(steps.iter().zip(&values[2])) {
+        if step == 0 {
+            continue;
+        }
+        let step = ctx.define_const(step, width);
+        let delta = ctx.eval(bitvec::OpCodes::BvMul, vec![step, iterations.clone()]);
+        let value = ctx.eval(bitvec::OpCodes::BvAdd, vec![value.clone(), delta]);
+        ctx.reg_write(reg, value);
+    }
+    true
+}
+
+impl<V: Clone + Eq> LoopState<V> {
+    /// Record that the path went on to the ip of `ctx`, and apply `bound` if that took a back
+    /// edge too many times. Returns false if the path ends.
+    pub fn step<Ctx>(&mut self, ctx: &mut Ctx, bound: &LoopBound) -> bool
+        where Ctx: Context<IFn = qf_abv::QF_ABV_Fn> + RegisterRead<VarRef = V>
+    {
+        let to = ctx.ip();
+        let from = match self.last.replace(to) {
+            Some(from) if to <= from => from,
+            _ => return true,
+        };
+        // The loops within this one start over.
+        self.loops.retain(|&edge, _| edge == (from, to) || edge.1 < to || edge.0 > from);
+        if self.concrete.map_or(false, |edge| !self.loops.contains_key(&edge)) {
+            self.concrete = None;
+        }
+
+        let counters = if bound.action == LoopAction::Summarize {
+            counters(ctx, bound)
+        } else {
+            Vec::new()
+        };
+        let values: Vec<V> = counters.iter().map(|r| ctx.reg_read(r)).collect();
+        let iterations = self.loops.entry((from, to)).or_insert_with(Iterations::default);
+        iterations.count += 1;
+        if bound.action == LoopAction::Summarize {
+            iterations.values.push(values);
+            if iterations.values.len() > 3 {
+                iterations.values.remove(0);
+            }
+        }
+        if iterations.count <= bound.limit {
+            return true;
+        }
+
+        match bound.action {
+            LoopAction::Drop => false,
+            LoopAction::Concretize => {
+                self.concrete = Some((from, to));
+                iterations.count <= 2 * bound.limit
+            }
+            LoopAction::Summarize => {
+                if iterations.summarized || iterations.values.len() < 3 {
+                    return false;
+                }
+                iterations.summarized = true;
+                summarize(ctx, &counters, &iterations.values, to)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use petgraph::graph::NodeIndex;
+
+    use context::context::RegisterWrite;
+    use context::rune_ctx::{RuneContext, test_ctx};
+    use memory::paged_mem::PagedMemory;
+    use regstore::regfile::RuneRegFile;
+
+    type Ctx = RuneContext<PagedMemory, RuneRegFile>;
+
+    /// Go `count` times around a loop from 0x1010 to 0x1020 that counts in rcx.
+    fn run(state: &mut LoopState<NodeIndex>,
+           ctx: &mut Ctx,
+           bound: &LoopBound,
+           count: u64)
+           -> bool {
+        let mut alive = true;
+        for i in 0..count {
+            let rcx = ctx.define_const(i * 4, 64);
+            ctx.reg_write("rcx", rcx);
+            ctx.set_ip(0x1020);
+            alive = alive && state.step(ctx, bound);
+            ctx.set_ip(0x1010);
+            alive = alive && state.step(ctx, bound);
+        }
+        alive
+    }
+
+    #[test]
+    fn bound_loops() {
+        let mut ctx = test_ctx(&[("rcx", 64), ("rdx", 64)]);
+        ctx.set_ip(0x1000);
+        let n = ctx.fresh_value("n", 64);
+        ctx.reg_write("rdx", n);
+
+        // An outer loop at 0x1000 around the loop of `run`.
+        let mut bound = LoopBound {
+            limit: 3,
+            counters: vec!["rcx".to_owned(), "rdx".to_owned(), "rax".to_owned()],
+            ..Default::default()
+        };
+        let mut state = LoopState::default();
+        ctx.set_ip(0x1010);
+        state.step(&mut ctx, &bound);
+        assert!(run(&mut state, &mut ctx, &bound, 3));
+        assert!(!run(&mut state, &mut ctx, &bound, 1));
+        ctx.set_ip(0x1030);
+        assert!(state.step(&mut ctx, &bound));
+        ctx.set_ip(0x1000);
+        assert!(state.step(&mut ctx, &bound));
+        assert_eq!((state.iterations(0x1030, 0x1000), state.iterations(0x1020, 0x1010)), (1, 0));
+        assert!(run(&mut state, &mut ctx, &bound, 3));
+
+        bound.action = LoopAction::Concretize;
+        assert!(run(&mut state, &mut ctx, &bound, 3));
+        assert!(state.is_concrete());
+        assert!(!run(&mut state, &mut ctx, &bound, 1));
+
+        // The fourth iteration leaves rdx alone and adds 4 to rcx, after which rcx can be any
+        // multiple of 4 from there on.
+        bound.action = LoopAction::Summarize;
+        let mut state = LoopState::default();
+        ctx.set_ip(0x1010);
+        state.step(&mut ctx, &bound);
+        assert!(run(&mut state, &mut ctx, &bound, 4));
+        let rcx = ctx.reg_read("rcx");
+        assert_eq!(ctx.as_constant(rcx), None);
+        let rdx = ctx.reg_read("rdx");
+        assert_eq!(rdx, n);
+        assert!(!run(&mut state, &mut ctx, &bound, 1));
+    }
+
+    #[test]
+    fn save_loops() {
+        let mut ctx = test_ctx(&[("rcx", 64)]);
+        let bound = LoopBound {
+            limit: 3,
+            action: LoopAction::Concretize,
+            ..Default::default()
+        };
+        let mut state = LoopState::default();
+        ctx.set_ip(0x1010);
+        state.step(&mut ctx, &bound);
+        assert!(run(&mut state, &mut ctx, &bound, 5));
+        assert!(state.is_concrete());
+
+        let image = state.to_image();
+        assert_eq!(image.loops, vec![((0x1020, 0x1010), 5, false)]);
+        let mut loaded: LoopState<NodeIndex> = LoopState::from_image(&image);
+        assert_eq!(loaded.iterations(0x1020, 0x1010), 5);
+        assert!(loaded.is_concrete());
+        assert_eq!(loaded.to_image(), image);
+        // The loaded path ends where the saved one would.
+        assert!(run(&mut loaded, &mut ctx, &bound, 1));
+        assert!(!run(&mut loaded, &mut ctx, &bound, 1));
+    }
+}
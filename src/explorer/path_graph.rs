@@ -0,0 +1,210 @@
+//! Tree of the explored paths, in the Graphviz DOT language, see `PathGraph::to_dot`.
+//!
+//! Paths start at the entry node, and go through a node for every symbolic branch they assumed
+//! (see `RuneContext::path_constraints`), along edges labeled with the constraint of the side
+//! they took, to a node for the address where they ended, or where they wait to be stepped.
+//! States that end or wait at the same address after the same branches share their node. A
+//! subtree that keeps growing shows where the exploration spends its time, and live states that
+//! pile up at one address show where it is stuck, in a loop for example.
+//!
+//! Ended paths are added as the engine reports them, with `observe` as a subscriber (see
+//! `Rune::subscribe`), and live states once the exploration stops, from the explorer (see
+//! `StrategyExplorer::live_states`).
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use petgraph::graph::NodeIndex;
+
+use context::context::Context;
+use context::rune_ctx::RuneContext;
+use engine::events::Event;
+use memory::memory::Memory;
+use regstore::regstore::RegStore;
+use utils::utils::to_smtlib;
+
+/// Longest label of an edge, longer constraints are cut.
+const MAX_LABEL: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathStatus {
+    /// The state is left to explore.
+    Live,
+    Ended,
+    Faulted,
+    /// The path reached a goal of `Rune::explore`.
+    Found,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    ip: u64,
+    /// `None` for the entry and the branches.
+    status: Option<PathStatus>,
+    /// States that end at the node.
+    states: usize,
+}
+
+/// A node reached from another, by the side taken at its branch (`None` from the entry), the
+/// address of the node and its status.
+type Key = (usize, Option<bool>, u64, Option<PathStatus>);
+
+#[derive(Clone, Debug)]
+pub struct PathGraph {
+    nodes: Vec<Node>,
+    /// Target and label of the edges.
+    edges: BTreeMap<Key, (usize, String)>,
+    /// Whether the path that dies next reached a goal.
+    found: bool,
+}
+
+fn escape(label: &str) -> String {
+    let mut cut: String = label.chars().take(MAX_LABEL).collect();
+    if label.chars().count() > MAX_LABEL {
+        cut.push_str("...");
+    }
+    cut.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl PathGraph {
+    /// Graph of the paths that start at `entry`.
+    pub fn new(entry: u64) -> PathGraph {
+        PathGraph {
+            nodes: vec![Node {
+                            ip: entry,
+                            status: None,
+                            states: 0,
+                        }],
+            edges: BTreeMap::new(),
+            found: false,
+        }
+    }
+
+    fn child(&mut self, key: Key, label: String) -> usize {
+        if let Some(&(node, _)) = self.edges.get(&key) {
+            return node;
+        }
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            ip: key.2,
+            status: key.3,
+            states: 0,
+        });
+        self.edges.insert(key, (node, label));
+        node
+    }
+
+    /// Add the path that assumed `branches`, as the address of the branch, the constraint of the
+    /// side taken and whether the branch was taken, and that has the status `status` at `end`.
+    pub fn add_path(&mut self, branches: &[(u64, String, bool)], end: u64, status: PathStatus) {
+        let mut node = 0;
+        let mut side = None;
+        let mut label = String::new();
+        for &(ip, ref constraint, taken) in branches {
+            node = self.child((node, side, ip, None), label);
+            side = Some(taken);
+            label = constraint.clone();
+        }
+        let leaf = self.child((node, side, end, Some(status)), label);
+        self.nodes[leaf].states += 1;
+    }
+
+    /// Add the path of `ctx`, which has the status `status` at its current address.
+    pub fn add_state<Mem, Reg>(&mut self, ctx: &RuneContext<Mem, Reg>, status: PathStatus)
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let branches: Vec<_> = ctx.path_constraints()
+                                  .iter()
+                                  .map(|c| (c.ip, to_smtlib(c.constraint, &ctx.solver), c.taken))
+                                  .collect();
+        self.add_path(&branches, ctx.ip(), status);
+    }
+
+    /// Add the paths that end with `event`, with their state `ctx`.
+    pub fn observe<Mem, Reg>(&mut self, ctx: &RuneContext<Mem, Reg>, event: &Event)
+        where Mem: Memory<VarRef = NodeIndex>,
+              Reg: RegStore<VarRef = NodeIndex>
+    {
+        let status = match *event {
+            Event::GoalReached { .. } => {
+                self.found = true;
+                return;
+            }
+            Event::Died { fault: Some(_), .. } => PathStatus::Faulted,
+            Event::Died { .. } if self.found => PathStatus::Found,
+            Event::Died { .. } => PathStatus::Ended,
+            _ => return,
+        };
+        self.found = false;
+        self.add_state(ctx, status);
+    }
+
+    /// Nodes of the graph, including the entry.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// The graph in the DOT language, as `dot -Tsvg` takes it.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph paths {\n    node [fontname=\"monospace\"];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let attrs = match node.status {
+                None if i == 0 => format!("label=\"entry {:#x}\", shape=doublecircle", node.ip),
+                None => format!("label=\"{:#x}\"", node.ip),
+                Some(status) => {
+                    let (name, style) = match status {
+                        PathStatus::Live => ("live", "style=dashed"),
+                        PathStatus::Ended => ("ended", "style=solid"),
+                        PathStatus::Faulted => ("faulted", "color=red"),
+                        PathStatus::Found => ("found", "color=green"),
+                    };
+                    format!("label=\"{:#x}\\n{} x{}\", shape=box, {}",
+                            node.ip,
+                            name,
+                            node.states,
+                            style)
+                }
+            };
+            let _ = writeln!(dot, "    n{} [{}];", i, attrs);
+        }
+        for (&(from, _, _, _), &(to, ref label)) in &self.edges {
+            let _ = writeln!(dot, "    n{} -> n{} [label=\"{}\"];", from, to, escape(label));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths_to_dot() {
+        let mut graph = PathGraph::new(0x1000);
+        assert!(graph.is_empty());
+        let taken = (0x1010, "(= c #b1)".to_owned(), true);
+        let not_taken = (0x1010, "(= c #b0)".to_owned(), false);
+        graph.add_path(&[taken.clone()], 0x1100, PathStatus::Ended);
+        graph.add_path(&[not_taken.clone()], 0x1020, PathStatus::Live);
+        graph.add_path(&[not_taken], 0x1020, PathStatus::Live);
+        graph.add_path(&[], 0x1004, PathStatus::Faulted);
+        // The entry, the branch and three leaves.
+        assert_eq!(graph.len(), 5);
+        assert_eq!(graph.to_dot(),
+                   "digraph paths {\n    node [fontname=\"monospace\"];\n    n0 [label=\"entry \
+                    0x1000\", shape=doublecircle];\n    n1 [label=\"0x1010\"];\n    n2 \
+                    [label=\"0x1100\\nended x1\", shape=box, style=solid];\n    n3 \
+                    [label=\"0x1020\\nlive x2\", shape=box, style=dashed];\n    n4 \
+                    [label=\"0x1004\\nfaulted x1\", shape=box, color=red];\n    n0 -> n4 \
+                    [label=\"\"];\n    n0 -> n1 [label=\"\"];\n    n1 -> n3 [label=\"(= c \
+                    #b0)\"];\n    n1 -> n2 [label=\"(= c #b1)\"];\n}\n");
+        assert_eq!(escape(&"x".repeat(70)), format!("{}...", "x".repeat(60)));
+        assert_eq!(escape("a\"b"), "a\\\"b");
+    }
+}
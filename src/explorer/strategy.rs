@@ -0,0 +1,941 @@
+//! `PathExplorer` that leaves the choice of the state to step next to a `Strategy`.
+//!
+//! Every feasible symbolic branch forks the state: the current path goes on with the true side,
+//! and a copy of the context takes the false side. Once the instruction of the branch finishes,
+//! and whenever a path ends, the strategy picks the state to go on with among the states waiting
+//! and the current one, which is saved if another one is picked. `DepthFirst`, `BreadthFirst`
+//! and `RandomPath` are the usual orders. Loops that the paths take too often are bounded as
+//! `set_loop_bound` sets, see `explorer::loops`.
+//!
+//! With `set_merging`, the two sides of a branch are merged back into one state where they join
+//! again, at the immediate post-dominator of the branch, as veritesting does: the side that gets
+//! there first waits for the other, which goes on as the merged state. This trades paths for
+//! harder queries, as the values that the sides set differently become `ite` expressions.
+//!
+//! The states of an exploration of `RuneContext`s can be saved with `checkpoint`, and the
+//! exploration resumed from there with `restore`, see `explorer::checkpoint`.
+//!
+//! `set_max_states` caps the states that are alive at once. Forks past the cap evict a waiting
+//! state as the `Eviction` policy says: it is dropped, or spilled to disk until there is room
+//! for it again.
+//!
+//! With `set_dedup`, forks that are in the same state as an earlier fork, such as the ones of a
+//! loop that comes back to where it was, are dropped instead of being explored again.
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+use petgraph::graph::NodeIndex;
+use serde_json::{from_reader, to_string};
+
+use context::context::{Context, RegisterRead};
+use context::rune_ctx::RuneContext;
+use context::stats::SolverCost;
+use engine::rune::RuneControl;
+use explorer::checkpoint::{Checkpoint, StateImage};
+use explorer::cost::Score;
+use explorer::coverage::Coverage;
+use explorer::explorer::PathExplorer;
+use explorer::loops::{LoopBound, LoopState};
+use explorer::target::Cfg;
+use memory::memory::Memory;
+use regstore::regstore::RegStore;
+use utils::utils::Rng;
+
+/// What a strategy knows of a state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateInfo {
+    /// Address the state goes on at.
+    pub ip: u64,
+    /// Symbolic branches on its path.
+    pub depth: usize,
+    /// What its path cost the solver, by the time it was saved.
+    pub cost: SolverCost,
+}
+
+pub trait Strategy {
+    /// The index of the state to step next in `states`, which are in the order they were
+    /// forked, the current state last if its path has not ended. `states` is not empty.
+    fn select(&mut self, states: &[StateInfo]) -> usize;
+
+    /// Called before the instruction at `ip` runs, on whichever state it is.
+    fn visit(&mut self, _ip: u64) { }
+
+    /// Coverage that the strategy gathers, which checkpoints of the explorer save.
+    fn coverage(&self) -> Option<&Coverage> {
+        None
+    }
+
+    fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        None
+    }
+}
+
+/// The deepest state, the latest of them. This follows the current path to its end.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DepthFirst;
+
+impl Strategy for DepthFirst {
+    fn select(&mut self, states: &[StateInfo]) -> usize {
+        let deepest = states.iter().map(|s| s.depth).max().unwrap_or(0);
+        states.iter().rposition(|s| s.depth == deepest).unwrap_or(0)
+    }
+}
+
+/// The shallowest state, the earliest of them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BreadthFirst;
+
+impl Strategy for BreadthFirst {
+    fn select(&mut self, states: &[StateInfo]) -> usize {
+        let shallowest = states.iter().map(|s| s.depth).min().unwrap_or(0);
+        states.iter().position(|s| s.depth == shallowest).unwrap_or(0)
+    }
+}
+
+/// A state at random, as if the tree of the paths was walked from its root with a random side
+/// at every branch: a state is half as likely as a state one branch less deep. Deep paths that
+/// fork often can not starve the others, as with a uniform choice.
+#[derive(Clone, Debug)]
+pub struct RandomPath {
+    rng: Rng,
+}
+
+impl RandomPath {
+    /// Choices that are the same for the same `seed`.
+    pub fn new(seed: u64) -> RandomPath {
+        RandomPath { rng: Rng::new(seed) }
+    }
+}
+
+impl Default for RandomPath {
+    fn default() -> RandomPath {
+        RandomPath::new(0)
+    }
+}
+
+impl Strategy for RandomPath {
+    fn select(&mut self, states: &[StateInfo]) -> usize {
+        let shallowest = states.iter().map(|s| s.depth).min().unwrap_or(0);
+        let weight = |s: &StateInfo| 0.5f64.powi((s.depth - shallowest).min(1000) as i32);
+        let total: f64 = states.iter().map(&weight).sum();
+        let mut point = self.rng.unit() * total;
+        for (i, state) in states.iter().enumerate() {
+            point -= weight(state);
+            if point < 0.0 {
+                return i;
+            }
+        }
+        states.len() - 1
+    }
+}
+
+/// What happens to a waiting state that a fork past the cap of `set_max_states` evicts.
+#[derive(Clone)]
+pub enum Eviction {
+    /// Drop the deepest state, the latest of them.
+    DropDeepest,
+    /// Drop the state with the lowest score, the earliest of them.
+    DropLowest(Score),
+    /// Save the deepest state to a file in the directory, and load it back, removing the file,
+    /// once a state is needed or there is room again, the last saved first. Saved states are
+    /// loaded into a copy of the first state the explorer stepped with this policy, which should
+    /// be set before the exploration starts so that every state descends from it. Contexts that
+    /// can not be saved (see `Context::to_image`) are dropped. The directory should be one of
+    /// the explorer's own.
+    Spill(PathBuf),
+}
+
+/// Where the states forked by a branch, the states of its group, are merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Join {
+    group: usize,
+    addr: u64,
+}
+
+struct Pending<Ctx: Context> {
+    ctx: Ctx,
+    depth: usize,
+    cost: SolverCost,
+    loops: LoopState<<Ctx as RegisterRead>::VarRef>,
+    /// Groups of the state, the innermost last.
+    joins: Vec<Join>,
+}
+
+struct Merging<Ctx: Context> {
+    cfg: Cfg,
+    /// Immediate post-dominators, by the start of the blocks that branched so far.
+    post_dominators: HashMap<u64, Option<u64>>,
+    groups: usize,
+    /// States of each group whose path has not ended.
+    live: HashMap<usize, usize>,
+    /// The state of each group that waits at its join for the others.
+    parked: HashMap<usize, Pending<Ctx>>,
+    merged: usize,
+}
+
+impl<Ctx: Context> Merging<Ctx> {
+    /// Count one state less in `group`.
+    fn leave(&mut self, group: usize) {
+        if let Some(live) = self.live.get_mut(&group) {
+            *live -= 1;
+        }
+    }
+}
+
+pub struct StrategyExplorer<Ctx: Context, S> {
+    strategy: S,
+    pending: Vec<Pending<Ctx>>,
+    /// Symbolic branches on the current path.
+    depth: usize,
+    bound: Option<LoopBound>,
+    /// Loops of the current path.
+    loops: LoopState<<Ctx as RegisterRead>::VarRef>,
+    merging: Option<Merging<Ctx>>,
+    /// Groups of the current state, the innermost last.
+    joins: Vec<Join>,
+    /// Address of the instruction being executed.
+    insn: Option<u64>,
+    /// Whether the current instruction forked the state.
+    forked: bool,
+    /// Live states at most, and the policy past them.
+    max_states: Option<(usize, Eviction)>,
+    /// Files of the spilled states, the last spilled last.
+    spilled: Vec<PathBuf>,
+    /// States spilled so far, which names their files.
+    spills: usize,
+    /// Copy of the first state, to load spilled states into.
+    first: Option<Ctx>,
+    /// States dropped by eviction.
+    evicted: usize,
+    /// Hashes of the states forked so far, if duplicates are dropped.
+    seen: Option<HashSet<u64>>,
+    /// States dropped as duplicates.
+    duplicates: usize,
+}
+
+impl<Ctx: Context, S: Strategy> StrategyExplorer<Ctx, S> {
+    pub fn with_strategy(strategy: S) -> StrategyExplorer<Ctx, S> {
+        StrategyExplorer {
+            strategy: strategy,
+            pending: Vec::new(),
+            depth: 0,
+            bound: None,
+            loops: LoopState::default(),
+            merging: None,
+            joins: Vec::new(),
+            insn: None,
+            forked: false,
+            max_states: None,
+            spilled: Vec::new(),
+            spills: 0,
+            first: None,
+            evicted: 0,
+            seen: None,
+            duplicates: 0,
+        }
+    }
+
+    /// Bound the iterations of the loops of every path from now on.
+    pub fn set_loop_bound(&mut self, bound: LoopBound) {
+        self.bound = Some(bound);
+    }
+
+    /// Merge the sides of the symbolic branches from now on, at their immediate post-dominator
+    /// in `cfg`. Branches outside of `cfg`, or without a post-dominator, are not merged.
+    pub fn set_merging(&mut self, cfg: Cfg) {
+        self.merging = Some(Merging {
+            cfg: cfg,
+            post_dominators: HashMap::new(),
+            groups: 0,
+            live: HashMap::new(),
+            parked: HashMap::new(),
+            merged: 0,
+        });
+    }
+
+    /// Keep `limit` states alive at most, the current one included, from now on. Forks past the
+    /// limit evict waiting states as `eviction` says.
+    pub fn set_max_states(&mut self, limit: usize, eviction: Eviction) {
+        self.max_states = Some((cmp::max(limit, 1), eviction));
+    }
+
+    /// Drop the states forked from now on that are in the same state as a state forked before,
+    /// with the same constraints, see `Context::state_hash`. A loop that reaches the same
+    /// address in the same state again forks the same states again, which are dropped.
+    pub fn set_dedup(&mut self) {
+        self.seen = Some(HashSet::new());
+    }
+
+    /// States dropped as duplicates so far.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// States dropped by eviction so far.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// States spilled to disk, waiting to be loaded back.
+    pub fn spilled(&self) -> usize {
+        self.spilled.len()
+    }
+
+    /// States merged into others so far.
+    pub fn merged(&self) -> usize {
+        self.merging.as_ref().map_or(0, |m| m.merged)
+    }
+
+    /// States waiting to be stepped, besides the ones waiting to be merged.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// States left to explore, including the ones waiting to be merged but not the ones
+    /// spilled to disk.
+    pub fn live_states(&self) -> Vec<&Ctx> {
+        let parked = self.merging.iter().flat_map(|m| m.parked.values());
+        self.pending.iter().chain(parked).map(|state| &state.ctx).collect()
+    }
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    pub fn strategy_mut(&mut self) -> &mut S {
+        &mut self.strategy
+    }
+
+    fn states(&self) -> Vec<StateInfo> {
+        self.pending
+            .iter()
+            .map(|p| {
+                StateInfo {
+                    ip: p.ctx.ip(),
+                    depth: p.depth,
+                    cost: p.cost,
+                }
+            })
+            .collect()
+    }
+
+    /// Make `next` the current state, and return the current one.
+    fn resume(&mut self, ctx: &mut Ctx, next: Pending<Ctx>) -> Pending<Ctx> {
+        let cost = ctx.solver_cost();
+        let previous = Pending {
+            ctx: mem::replace(ctx, next.ctx),
+            depth: mem::replace(&mut self.depth, next.depth),
+            cost: cost,
+            loops: mem::replace(&mut self.loops, next.loops),
+            joins: mem::replace(&mut self.joins, next.joins),
+        };
+        self.loops.resume(ctx.ip());
+        previous
+    }
+
+    /// Go on with the state the strategy picks, among the waiting ones and `current`.
+    fn switch(&mut self, ctx: &mut Ctx, current: Option<StateInfo>) -> bool {
+        self.unspill(current.is_none());
+        let mut states = self.states();
+        states.extend(current);
+        if states.is_empty() {
+            return false;
+        }
+        let picked = self.strategy.select(&states);
+        if picked >= self.pending.len() {
+            // The current state.
+            return true;
+        }
+        let next = self.pending.remove(picked);
+        let previous = self.resume(ctx, next);
+        if current.is_some() {
+            self.pending.push(previous);
+        }
+        true
+    }
+
+    /// Count a fork of the current state in its groups, and return the join of the branch
+    /// that forked it, if it is merged.
+    fn fork_joins(&mut self) -> Option<Join> {
+        self.count_fork();
+        let merging = self.merging.as_mut()?;
+        let block = merging.cfg.block_of(self.insn?)?;
+        let cfg = &merging.cfg;
+        let addr = merging.post_dominators
+                          .entry(block)
+                          .or_insert_with(|| cfg.post_dominator(block))
+                          .clone()?;
+        let group = merging.groups;
+        merging.groups += 1;
+        merging.live.insert(group, 2);
+        Some(Join {
+            group: group,
+            addr: addr,
+        })
+    }
+
+    /// Whether a state was forked before in the same state as `ctx`, which is counted as seen.
+    fn is_duplicate(&mut self, ctx: &Ctx) -> bool {
+        let seen = match self.seen {
+            Some(ref mut seen) => seen,
+            None => return false,
+        };
+        match ctx.state_hash() {
+            Some(hash) if !seen.insert(hash) => {
+                self.duplicates += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Count a fork of the current state in its groups.
+    fn count_fork(&mut self) {
+        if let Some(ref mut merging) = self.merging {
+            for join in &self.joins {
+                *merging.live.entry(join.group).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Leave the groups of the current state, whose path ended.
+    fn end_path(&mut self) {
+        let joins = mem::replace(&mut self.joins, Vec::new());
+        self.leave_groups(joins);
+    }
+
+    /// Leave the groups `joins` of a state that is not explored any further. A state that waits
+    /// for the others of a group goes on once they all left.
+    fn leave_groups(&mut self, joins: Vec<Join>) {
+        let merging = match self.merging {
+            Some(ref mut merging) => merging,
+            None => return,
+        };
+        for join in joins {
+            let live = merging.live.remove(&join.group).unwrap_or(1) - 1;
+            if live > 1 {
+                merging.live.insert(join.group, live);
+            } else if let Some(mut state) = merging.parked.remove(&join.group) {
+                state.joins.pop();
+                self.pending.push(state);
+            } else if live == 1 {
+                merging.live.insert(join.group, live);
+            }
+        }
+    }
+
+    /// Evict waiting states while there are more live states than `set_max_states` allows.
+    fn evict(&mut self) {
+        let (limit, eviction) = match self.max_states {
+            Some((limit, ref eviction)) => (limit, eviction.clone()),
+            None => return,
+        };
+        while self.pending.len() + 1 > limit {
+            let states = self.states();
+            let victim = match eviction {
+                Eviction::DropLowest(ref score) => {
+                    let scores: Vec<f64> = states.iter().map(|s| score(s)).collect();
+                    let lowest = scores.iter().cloned().fold(f64::INFINITY, f64::min);
+                    scores.iter().position(|&s| s == lowest).unwrap_or(0)
+                }
+                _ => DepthFirst.select(&states),
+            };
+            let state = self.pending.remove(victim);
+            self.leave_groups(state.joins.clone());
+            if let Eviction::Spill(ref dir) = eviction {
+                if let Ok(path) = self.spill(dir, &state) {
+                    self.spilled.push(path);
+                    continue;
+                }
+            }
+            self.evicted += 1;
+        }
+    }
+
+    /// Save `state` to a new file in `dir`, and return its path.
+    fn spill(&mut self, dir: &Path, state: &Pending<Ctx>) -> Result<PathBuf, String> {
+        let image = StateImage {
+            context: state.ctx.to_image()?,
+            depth: state.depth,
+            loops: state.loops.to_image(),
+        };
+        let json = to_string(&image).map_err(|e| e.to_string())?;
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("state-{}.json", self.spills));
+        self.spills += 1;
+        let mut file = File::create(&path).map_err(|e| e.to_string())?;
+        file.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    /// Load spilled states back while there is room for them, or, if `needed`, one if no state
+    /// waits. States that can not be loaded back are dropped.
+    fn unspill(&mut self, needed: bool) {
+        let limit = self.max_states.as_ref().map_or(0, |m| m.0);
+        while !self.spilled.is_empty() &&
+              (self.pending.len() + 1 < limit || (needed && self.pending.is_empty())) {
+            let path = self.spilled.pop().expect("no spilled states");
+            let loaded = self.load_spilled(&path);
+            let _ = fs::remove_file(&path);
+            match loaded {
+                Ok(state) => self.pending.push(state),
+                Err(_) => self.evicted += 1,
+            }
+        }
+    }
+
+    fn load_spilled(&self, path: &Path) -> Result<Pending<Ctx>, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let image: StateImage = from_reader(file).map_err(|e| e.to_string())?;
+        let mut ctx = self.first.clone().ok_or_else(|| "No state to load into".to_owned())?;
+        ctx.load_image(&image.context)?;
+        Ok(Pending {
+            cost: ctx.solver_cost(),
+            ctx: ctx,
+            depth: image.depth,
+            loops: LoopState::from_image(&image.loops),
+            joins: Vec::new(),
+        })
+    }
+
+    /// Merge the current state with the state that waits at the join it reached, and wait for
+    /// the rest of the group there, going on with another state, as long as the current state
+    /// is at the join of its innermost group.
+    fn arrive(&mut self, ctx: &mut Ctx) {
+        while let Some(&join) = self.joins.last() {
+            if join.addr != ctx.ip() {
+                return;
+            }
+            let mut merging = self.merging.take().expect("joins without merging");
+            if let Some(mut other) = merging.parked.remove(&join.group) {
+                merging.leave(join.group);
+                if ctx.merge(&other.ctx).is_ok() {
+                    merging.merged += 1;
+                    self.depth = cmp::max(self.depth, other.depth);
+                    for outer in &self.joins[..self.joins.len() - 1] {
+                        merging.leave(outer.group);
+                    }
+                } else {
+                    // Both go on, unmerged.
+                    other.joins.pop();
+                    self.pending.push(other);
+                }
+            }
+            if merging.live.get(&join.group).map_or(false, |&live| live > 1) &&
+               !self.pending.is_empty() {
+                let states = self.states();
+                let picked = self.strategy.select(&states);
+                let next = self.pending.remove(picked);
+                let previous = self.resume(ctx, next);
+                merging.parked.insert(join.group, previous);
+            } else {
+                // The others ended.
+                merging.live.remove(&join.group);
+                self.joins.pop();
+            }
+            self.merging = Some(merging);
+        }
+    }
+}
+
+impl<Mem, Reg, S> StrategyExplorer<RuneContext<Mem, Reg>, S>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>,
+          S: Strategy
+{
+    /// Checkpoint of the exploration, with `ctx` as the current state. States that wait to be
+    /// merged are saved with the others.
+    pub fn checkpoint(&self, ctx: &RuneContext<Mem, Reg>) -> Result<Checkpoint, String> {
+        let parked = self.merging.iter().flat_map(|m| m.parked.values());
+        let mut states = Vec::new();
+        for state in self.pending.iter().chain(parked) {
+            states.push(StateImage {
+                context: state.ctx.to_image()?,
+                depth: state.depth,
+                loops: state.loops.to_image(),
+            });
+        }
+        states.push(StateImage {
+            context: ctx.to_image()?,
+            depth: self.depth,
+            loops: self.loops.to_image(),
+        });
+        Ok(Checkpoint {
+            states: states,
+            coverage: self.strategy.coverage().map_or(Vec::new(), |c| c.entries()),
+        })
+    }
+
+    /// Go on with the exploration saved in `checkpoint`, in place of the states of this
+    /// explorer. Every state is loaded into a copy of `ctx`, a context newly created for the
+    /// program (see `RuneContext::load_image`), and `ctx` becomes the current one. The coverage
+    /// of the checkpoint adds to the one of the strategy. Returns false, leaving `ctx` alone, if
+    /// the checkpoint has no states.
+    pub fn restore(&mut self,
+                   ctx: &mut RuneContext<Mem, Reg>,
+                   checkpoint: &Checkpoint)
+                   -> Result<bool, String> {
+        let mut states = Vec::new();
+        for state in &checkpoint.states {
+            let mut loaded = ctx.clone();
+            loaded.load_image(&state.context)?;
+            let cost = loaded.solver_cost();
+            states.push(Pending {
+                ctx: loaded,
+                depth: state.depth,
+                cost: cost,
+                loops: LoopState::from_image(&state.loops),
+                joins: Vec::new(),
+            });
+        }
+        let current = match states.pop() {
+            Some(current) => current,
+            None => return Ok(false),
+        };
+        self.pending = states;
+        if let Some(ref mut merging) = self.merging {
+            merging.live.clear();
+            merging.parked.clear();
+        }
+        self.resume(ctx, current);
+        self.insn = None;
+        self.forked = false;
+        if let Some(coverage) = self.strategy.coverage_mut() {
+            for &(block, entries) in &checkpoint.coverage {
+                coverage.add_entries(block, entries);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Assume the branch `condition` of `ctx` to be `taken`.
+fn follow<Ctx>(ctx: &mut Ctx, condition: <Ctx as RegisterRead>::VarRef, taken: bool) -> RuneControl
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let value = ctx.define_const(taken as u64, 1);
+    let constraint = ctx.eval(core::OpCodes::Cmp, vec![condition.clone(), value]);
+    ctx.record_branch(condition, constraint, taken);
+    if taken { RuneControl::ExploreTrue } else { RuneControl::ExploreFalse }
+}
+
+impl<Ctx, S> PathExplorer for StrategyExplorer<Ctx, S>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>,
+          S: Strategy + Default
+{
+    type C = RuneControl;
+    type Ctx = Ctx;
+
+    fn new() -> StrategyExplorer<Ctx, S> {
+        StrategyExplorer::with_strategy(S::default())
+    }
+
+    fn next(&mut self, ctx: &mut Ctx) -> RuneControl {
+        if self.first.is_none() {
+            if let Some((_, Eviction::Spill(_))) = self.max_states {
+                self.first = Some(ctx.clone());
+            }
+        }
+        if let Some(ref bound) = self.bound {
+            if !self.loops.step(ctx, bound) {
+                self.forked = false;
+                return RuneControl::TerminatePath;
+            }
+        }
+        if mem::replace(&mut self.forked, false) {
+            let current = StateInfo {
+                ip: ctx.ip(),
+                depth: self.depth,
+                cost: ctx.solver_cost(),
+            };
+            self.switch(ctx, Some(current));
+        }
+        self.arrive(ctx);
+        self.insn = Some(ctx.ip());
+        self.strategy.visit(ctx.ip());
+        RuneControl::Continue
+    }
+
+    fn next_job(&mut self, ctx: &mut Ctx) -> Option<RuneControl> {
+        self.forked = false;
+        self.end_path();
+        if !self.switch(ctx, None) {
+            return None;
+        }
+        self.arrive(ctx);
+        self.insn = Some(ctx.ip());
+        self.strategy.visit(ctx.ip());
+        Some(RuneControl::Continue)
+    }
+
+    fn register_branch(&mut self,
+                       ctx: &mut Ctx,
+                       condition: <Ctx as RegisterRead>::VarRef)
+                       -> RuneControl {
+        if let Some(c) = ctx.as_constant(condition.clone()) {
+            return follow(ctx, condition, c != 0);
+        }
+        if self.bound.is_some() && self.loops.is_concrete() {
+            if let Some(values) = ctx.concrete_values(&[(condition.clone(), 1)]) {
+                return follow(ctx, condition, values[0] != 0);
+            }
+        }
+        let not_taken = ctx.eval(bitvec::OpCodes::BvNot, vec![condition.clone()]);
+        if !ctx.is_feasible(not_taken) {
+            return follow(ctx, condition, true);
+        }
+        if !ctx.is_feasible(condition.clone()) {
+            return follow(ctx, condition, false);
+        }
+        let mut fork = ctx.clone();
+        follow(&mut fork, condition.clone(), false);
+        if self.is_duplicate(&fork) {
+            return follow(ctx, condition, true);
+        }
+        self.depth += 1;
+        self.forked = true;
+        let mut joins = self.joins.clone();
+        if let Some(join) = self.fork_joins() {
+            self.joins.push(join);
+            joins.push(join);
+        }
+        let cost = fork.solver_cost();
+        self.pending.push(Pending {
+            ctx: fork,
+            depth: self.depth,
+            cost: cost,
+            loops: self.loops.clone(),
+            joins: joins,
+        });
+        self.evict();
+        follow(ctx, condition, true)
+    }
+
+    // The state is one level deeper than the current one, in the same groups.
+    fn add_state(&mut self, ctx: Ctx) -> bool {
+        if self.is_duplicate(&ctx) {
+            return true;
+        }
+        self.forked = true;
+        self.count_fork();
+        let cost = ctx.solver_cost();
+        self.pending.push(Pending {
+            ctx: ctx,
+            depth: self.depth + 1,
+            cost: cost,
+            loops: self.loops.clone(),
+            joins: self.joins.clone(),
+        });
+        self.evict();
+        true
+    }
+
+    // Spilled states count, they are explored once there is room for them.
+    fn waiting(&self) -> Option<usize> {
+        Some(self.live_states().len() + self.spilled.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::process;
+    use std::rc::Rc;
+
+
+    use context::context::RegisterWrite;
+    use context::rune_ctx::test_ctx;
+    use explorer::coverage::CoverageSearch;
+    use memory::paged_mem::PagedMemory;
+    use regstore::regfile::RuneRegFile;
+
+    fn states(depths: &[usize]) -> Vec<StateInfo> {
+        depths.iter()
+              .enumerate()
+              .map(|(i, &depth)| {
+                  StateInfo {
+                      ip: 0x1000 + i as u64,
+                      depth: depth,
+                      ..Default::default()
+                  }
+              })
+              .collect()
+    }
+
+    #[test]
+    fn select_states() {
+        let waiting = states(&[1, 3, 2, 3]);
+        assert_eq!(DepthFirst.select(&waiting), 3);
+        assert_eq!(BreadthFirst.select(&waiting), 0);
+        assert_eq!(DepthFirst.select(&states(&[0])), 0);
+
+        // The shallow state is picked about as often as the three deep ones together.
+        let mut random = RandomPath::new(7);
+        let waiting = states(&[1, 2, 3, 3]);
+        let mut picks = [0; 4];
+        for _ in 0..4000 {
+            picks[random.select(&waiting)] += 1;
+        }
+        assert!(picks[0] > 1700 && picks[0] < 2300, "{:?}", picks);
+        assert!(picks[1] > picks[2] && picks[1] > picks[3], "{:?}", picks);
+        let mut again = RandomPath::new(7);
+        let mut random = RandomPath::new(7);
+        assert_eq!(again.select(&waiting), random.select(&waiting));
+    }
+
+    #[test]
+    fn merge_branches() {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_ip(0x60);
+        let condition = ctx.fresh_value("c", 1);
+
+        // The diamond of `Cfg::post_dominator`, whose sides set rax.
+        let mut cfg = Cfg::default();
+        cfg.add_block(0x60, 8, vec![0x68, 0x70]);
+        cfg.add_block(0x68, 8, vec![0x78]);
+        cfg.add_block(0x70, 8, vec![0x78]);
+        cfg.add_block(0x78, 8, vec![]);
+        let mut explorer: StrategyExplorer<_, DepthFirst> = StrategyExplorer::new();
+        explorer.set_merging(cfg);
+        explorer.next(&mut ctx);
+
+        ctx.set_ip(0x70);
+        assert_eq!(explorer.register_branch(&mut ctx, condition), RuneControl::ExploreTrue);
+        ctx.set_ip(0x68);
+        explorer.next(&mut ctx);
+        let side = |ctx: &mut RuneContext<_, _>, explorer: &mut StrategyExplorer<_, _>, rax| {
+            let value = ctx.define_const(rax, 64);
+            ctx.reg_write("rax", value);
+            ctx.set_ip(0x78);
+            explorer.next(ctx);
+        };
+        // The true side waits for the false one, which goes on merged.
+        side(&mut ctx, &mut explorer, 1);
+        assert_eq!((ctx.ip(), explorer.pending(), explorer.merged()), (0x70, 0, 0));
+        side(&mut ctx, &mut explorer, 2);
+        assert_eq!((ctx.ip(), explorer.pending(), explorer.merged()), (0x78, 0, 1));
+        let rax = ctx.reg_read("rax");
+        assert_eq!(ctx.as_constant(rax), None);
+        assert!(explorer.next_job(&mut ctx).is_none());
+    }
+
+    #[test]
+    fn checkpoint_and_restore() {
+        let mut ctx = branching_ctx();
+        let mut explorer: StrategyExplorer<_, CoverageSearch> = StrategyExplorer::new();
+        explorer.next(&mut ctx);
+        let condition = ctx.fresh_value("c", 1);
+        explorer.register_branch(&mut ctx, condition);
+        let rax = ctx.define_const(7, 64);
+        ctx.reg_write("rax", rax);
+
+        let checkpoint = explorer.checkpoint(&ctx).unwrap();
+        assert_eq!(checkpoint.states.iter().map(|s| s.depth).collect::<Vec<_>>(), vec![1, 1]);
+        assert_eq!(checkpoint.coverage, vec![(0x100, 1)]);
+
+        // The current state goes on where it was, then the other one.
+        let mut resumed = branching_ctx();
+        let mut explorer: StrategyExplorer<_, CoverageSearch> = StrategyExplorer::new();
+        assert_eq!(explorer.restore(&mut resumed, &checkpoint), Ok(true));
+        let rax = resumed.reg_read("rax");
+        assert_eq!(resumed.as_constant(rax), Some(7));
+        assert_eq!((explorer.pending(), resumed.stats().branches), (1, 1));
+        assert_eq!(explorer.strategy().coverage().hits(0x100), 1);
+        assert!(explorer.next_job(&mut resumed).is_some());
+        assert_eq!((explorer.pending(), resumed.ip()), (0, 0x100));
+        assert!(explorer.next_job(&mut resumed).is_none());
+        assert_eq!(explorer.restore(&mut resumed, &Checkpoint::default()), Ok(false));
+    }
+
+    fn branching_ctx() -> RuneContext<PagedMemory, RuneRegFile> {
+        let mut ctx = test_ctx(&[("rax", 64)]);
+        ctx.set_ip(0x100);
+        ctx
+    }
+
+    /// Fork the state at `count` symbolic branches.
+    fn branch<S>(explorer: &mut StrategyExplorer<RuneContext<PagedMemory, RuneRegFile>, S>,
+                 ctx: &mut RuneContext<PagedMemory, RuneRegFile>,
+                 count: usize)
+        where S: Strategy + Default
+    {
+        for i in 0..count {
+            let condition = ctx.fresh_value(&format!("c{}", i), 1);
+            explorer.register_branch(ctx, condition);
+            explorer.next(ctx);
+        }
+    }
+
+    #[test]
+    fn evict_states() {
+        let mut ctx = branching_ctx();
+        let mut explorer: StrategyExplorer<_, DepthFirst> = StrategyExplorer::new();
+        explorer.set_max_states(2, Eviction::DropDeepest);
+        branch(&mut explorer, &mut ctx, 3);
+        assert_eq!((explorer.pending(), explorer.evicted()), (1, 2));
+        assert!(explorer.next_job(&mut ctx).is_some());
+        assert_eq!(ctx.path_constraints().len(), 1);
+
+        // Shallow states score low.
+        let mut ctx = branching_ctx();
+        let mut explorer: StrategyExplorer<_, DepthFirst> = StrategyExplorer::new();
+        explorer.set_max_states(2, Eviction::DropLowest(Rc::new(|s: &StateInfo| s.depth as f64)));
+        branch(&mut explorer, &mut ctx, 3);
+        assert_eq!((explorer.pending(), explorer.evicted()), (1, 2));
+        assert!(explorer.next_job(&mut ctx).is_some());
+        assert_eq!(ctx.path_constraints().len(), 3);
+    }
+
+    #[test]
+    fn drop_duplicates() {
+        let mut ctx = branching_ctx();
+        let mut explorer: StrategyExplorer<_, DepthFirst> = StrategyExplorer::new();
+        explorer.set_dedup();
+        let fork = ctx.clone();
+        assert!(explorer.add_state(fork.clone()));
+        assert!(explorer.add_state(fork));
+        assert_eq!((explorer.pending(), explorer.duplicates()), (1, 1));
+        ctx.set_ip(0x200);
+        assert!(explorer.add_state(ctx));
+        assert_eq!((explorer.pending(), explorer.duplicates()), (2, 1));
+    }
+
+    #[test]
+    fn spill_states() {
+        let dir = env::temp_dir().join(format!("rune-spill-{}", process::id()));
+        let mut ctx = branching_ctx();
+        let mut explorer: StrategyExplorer<_, DepthFirst> = StrategyExplorer::new();
+        explorer.set_max_states(2, Eviction::Spill(dir.clone()));
+        explorer.set_loop_bound(LoopBound::default());
+        explorer.next(&mut ctx);
+        branch(&mut explorer, &mut ctx, 3);
+        assert_eq!((explorer.pending(), explorer.spilled(), explorer.evicted()), (1, 2, 0));
+        let taken = |ctx: &RuneContext<_, _>| {
+            ctx.path_constraints().iter().map(|c| c.taken).collect::<Vec<_>>()
+        };
+
+        // Spilled states come back once no state waits, the last spilled first, with their
+        // path and the iterations of the loop `branch` stays at.
+        assert!(explorer.next_job(&mut ctx).is_some());
+        assert_eq!(explorer.spilled(), 2);
+        assert!(explorer.next_job(&mut ctx).is_some());
+        assert_eq!((explorer.pending(), explorer.spilled()), (0, 1));
+        assert_eq!(taken(&ctx), vec![true, true, false]);
+        assert_eq!(explorer.loops.iterations(0x100, 0x100), 2);
+        assert!(explorer.next_job(&mut ctx).is_some());
+        assert_eq!(taken(&ctx), vec![true, false]);
+        assert_eq!(explorer.loops.iterations(0x100, 0x100), 1);
+        assert!(explorer.next_job(&mut ctx).is_none());
+        assert_eq!(fs::read_dir(&dir).map(|d| d.count()).unwrap_or(0), 0);
+        let _ = fs::remove_dir(&dir);
+    }
+}
@@ -0,0 +1,325 @@
+//! `Strategy` that steps the states closest to a target address first.
+//!
+//! Distances are counted in basic blocks of the control flow graphs that r2 recovered (`agj`),
+//! following jumps, the cases of switch tables and calls into the entry of the functions they
+//! call. Returns are not followed, so a target can only be reached from the functions that lead
+//! to it. States that can not reach the target are stepped last, depth first. The binary has to
+//! be analyzed (`aaa`) before `Cfg::from_r2`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use r2pipe::r2::R2;
+use serde_json::from_str;
+
+use explorer::strategy::{StateInfo, Strategy};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Op {
+    #[serde(default, rename = "type")]
+    optype: String,
+    jump: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Case {
+    jump: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct SwitchOp {
+    #[serde(default)]
+    cases: Vec<Case>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct GraphBlock {
+    offset: u64,
+    #[serde(default)]
+    size: u64,
+    jump: Option<u64>,
+    fail: Option<u64>,
+    switchop: Option<SwitchOp>,
+    #[serde(default)]
+    ops: Vec<Op>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct GraphFunction {
+    #[serde(default)]
+    blocks: Vec<GraphBlock>,
+}
+
+/// The part of `aflj` that holds the address of a function.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Function {
+    offset: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Block {
+    end: u64,
+    /// Addresses that the block goes on at.
+    succs: Vec<u64>,
+    /// Functions that the block calls.
+    calls: Vec<u64>,
+}
+
+/// Basic blocks of the functions of a program, with the edges between them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    blocks: BTreeMap<u64, Block>,
+}
+
+impl Cfg {
+    /// Add the functions of `json`, the output of `agj`.
+    pub fn add_graph(&mut self, json: &str) -> Result<(), String> {
+        let functions: Vec<GraphFunction> = from_str(json).map_err(|e| e.to_string())?;
+        for block in functions.into_iter().flat_map(|f| f.blocks) {
+            let mut succs: Vec<u64> = block.jump.into_iter().chain(block.fail).collect();
+            if let Some(switch) = block.switchop {
+                succs.extend(switch.cases.into_iter().filter_map(|c| c.jump));
+            }
+            self.add_block(block.offset, block.size, succs);
+            let calls = block.ops.into_iter().filter(|op| op.optype == "call");
+            for target in calls.filter_map(|op| op.jump) {
+                self.add_call(block.offset, target);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the block of `size` bytes at `start`, which goes on at `succs`.
+    pub fn add_block(&mut self, start: u64, size: u64, succs: Vec<u64>) {
+        self.blocks.insert(start,
+                           Block {
+                               end: start + size,
+                               succs: succs,
+                               calls: Vec::new(),
+                           });
+    }
+
+    /// Add a call to `target` from the block at `start`, which has to be added first.
+    pub fn add_call(&mut self, start: u64, target: u64) {
+        if let Some(block) = self.blocks.get_mut(&start) {
+            block.calls.push(target);
+        }
+    }
+
+    /// The graphs of every function that r2 found in the binary it opened.
+    pub fn from_r2(r2: &mut R2) -> Cfg {
+        r2.send("aflj");
+        let functions: Vec<Function> = from_str(&r2.recv()).unwrap_or_default();
+        let mut cfg = Cfg::default();
+        for function in functions {
+            r2.send(&format!("agj @ {}", function.offset));
+            // Functions whose graph r2 can not give out are left out.
+            let _ = cfg.add_graph(&r2.recv());
+        }
+        cfg
+    }
+
+    /// Starts of the blocks, in order.
+    pub fn blocks<'a>(&'a self) -> Box<Iterator<Item = u64> + 'a> {
+        Box::new(self.blocks.keys().cloned())
+    }
+
+    /// Start of the block that holds `addr`.
+    pub fn block_of(&self, addr: u64) -> Option<u64> {
+        self.blocks
+            .range(..=addr)
+            .next_back()
+            .filter(|&(_, block)| addr < block.end)
+            .map(|(&start, _)| start)
+    }
+
+    /// Distances in blocks to the block of `target`, by the start of the blocks that reach it.
+    pub fn distances_to(&self, target: u64) -> HashMap<u64, usize> {
+        let mut preds: HashMap<u64, Vec<u64>> = HashMap::new();
+        for (&start, block) in &self.blocks {
+            let succs = block.succs.iter().chain(&block.calls);
+            for succ in succs.filter_map(|&s| self.block_of(s)) {
+                preds.entry(succ).or_insert_with(Vec::new).push(start);
+            }
+        }
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        if let Some(block) = self.block_of(target) {
+            distances.insert(block, 0);
+            queue.push_back(block);
+        }
+        while let Some(block) = queue.pop_front() {
+            let distance = distances[&block] + 1;
+            for &pred in preds.get(&block).map_or(&[][..], |p| &p[..]) {
+                if !distances.contains_key(&pred) {
+                    distances.insert(pred, distance);
+                    queue.push_back(pred);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Start of the first block that every path from the block of `addr` to the end of its
+    /// function goes through, its immediate post-dominator. Calls are not followed.
+    pub fn post_dominator(&self, addr: u64) -> Option<u64> {
+        let start = self.block_of(addr)?;
+        // The blocks that the function can reach from there, with their successors.
+        let mut succs: BTreeMap<u64, Option<Vec<u64>>> = BTreeMap::new();
+        let mut queue = vec![start];
+        while let Some(block) = queue.pop() {
+            if succs.contains_key(&block) {
+                continue;
+            }
+            let targets = &self.blocks[&block].succs;
+            let blocks: Vec<u64> = targets.iter().filter_map(|&t| self.block_of(t)).collect();
+            queue.extend(&blocks);
+            // Blocks that leave the graph, or return, go to the end of the function.
+            let exits = targets.is_empty() || blocks.len() < targets.len();
+            succs.insert(block, if exits { None } else { Some(blocks) });
+        }
+
+        let all: BTreeSet<u64> = succs.keys().cloned().collect();
+        let mut pdoms: HashMap<u64, BTreeSet<u64>> = all.iter()
+                                                       .map(|&b| (b, all.clone()))
+                                                       .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (&block, targets) in &succs {
+                let mut pdom = match *targets {
+                    Some(ref targets) => {
+                        let mut sets = targets.iter().map(|t| &pdoms[t]);
+                        let first = sets.next().cloned().unwrap_or_default();
+                        sets.fold(first, |acc, set| acc.intersection(set).cloned().collect())
+                    }
+                    None => BTreeSet::new(),
+                };
+                pdom.insert(block);
+                if pdom != pdoms[&block] {
+                    pdoms.insert(block, pdom);
+                    changed = true;
+                }
+            }
+        }
+
+        // The post-dominators form a chain, whose first one is post-dominated by the others.
+        let mut strict = pdoms[&start].clone();
+        strict.remove(&start);
+        strict.iter().cloned().find(|d| pdoms[d] == strict)
+    }
+}
+
+/// Steps the state closest to `target` first, the deepest of them if there are several.
+#[derive(Clone, Debug, Default)]
+pub struct TargetSearch {
+    cfg: Cfg,
+    target: u64,
+    distances: HashMap<u64, usize>,
+}
+
+impl TargetSearch {
+    pub fn new(cfg: Cfg, target: u64) -> TargetSearch {
+        let distances = cfg.distances_to(target);
+        TargetSearch {
+            cfg: cfg,
+            target: target,
+            distances: distances,
+        }
+    }
+
+    pub fn target(&self) -> u64 {
+        self.target
+    }
+
+    /// Distance in blocks from `ip` to the target, `None` if it can not reach it.
+    pub fn distance(&self, ip: u64) -> Option<usize> {
+        self.cfg.block_of(ip).and_then(|block| self.distances.get(&block).cloned())
+    }
+}
+
+impl Strategy for TargetSearch {
+    fn select(&mut self, states: &[StateInfo]) -> usize {
+        let key = |s: &StateInfo| (self.distance(s.ip).unwrap_or(usize::max_value()), !s.depth);
+        let best = states.iter().map(&key).min();
+        states.iter().rposition(|s| Some(key(s)) == best).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // main: 0x10 calls check at 0x40 and either returns or loops back, check: 0x40 branches
+    // to 0x48 or to the target at 0x50.
+    fn program() -> Cfg {
+        let mut cfg = Cfg::default();
+        cfg.add_block(0x10, 0x10, vec![0x20]);
+        cfg.add_call(0x10, 0x40);
+        cfg.add_block(0x20, 8, vec![0x10, 0x28]);
+        cfg.add_block(0x28, 4, vec![]);
+        cfg.add_block(0x40, 8, vec![0x48, 0x50]);
+        cfg.add_block(0x48, 8, vec![]);
+        cfg.add_block(0x50, 8, vec![]);
+        cfg
+    }
+
+    #[test]
+    fn parse_graphs() {
+        let graphs = r#"[{"name": "main", "offset": 16, "blocks": [
+            {"offset": 16, "size": 16, "jump": 32,
+             "ops": [{"offset": 20, "type": "call", "jump": 64}]},
+            {"offset": 32, "size": 8, "jump": 16, "fail": 40},
+            {"offset": 40, "size": 4}]},
+            {"name": "check", "offset": 64, "blocks": [
+            {"offset": 64, "size": 8, "jump": 72, "fail": 80},
+            {"offset": 72, "size": 8},
+            {"offset": 80, "size": 8}]}]"#;
+        let mut cfg = Cfg::default();
+        cfg.add_graph(graphs).unwrap();
+        assert_eq!(cfg, program());
+        assert!(cfg.add_graph("[{").is_err());
+    }
+
+    #[test]
+    fn distances() {
+        let cfg = program();
+        assert_eq!((cfg.block_of(0x14), cfg.block_of(0x2c), cfg.block_of(0x30)),
+                   (Some(0x10), None, None));
+
+        let mut search = TargetSearch::new(cfg, 0x54);
+        let distances: Vec<_> = [0x10, 0x20, 0x28, 0x40, 0x48, 0x50, 0x54]
+                                    .iter()
+                                    .map(|&ip| search.distance(ip))
+                                    .collect();
+        assert_eq!(distances,
+                   vec![Some(2), Some(3), None, Some(1), None, Some(0), Some(0)]);
+
+        let state = |ip, depth| {
+            StateInfo {
+                ip: ip,
+                depth: depth,
+                ..Default::default()
+            }
+        };
+        assert_eq!(search.select(&[state(0x28, 9), state(0x20, 1), state(0x10, 1)]), 2);
+        assert_eq!(search.select(&[state(0x40, 3), state(0x40, 4), state(0x40, 2)]), 1);
+        assert_eq!(search.select(&[state(0x28, 1), state(0x48, 2)]), 1);
+    }
+
+    #[test]
+    fn post_dominators() {
+        let mut cfg = program();
+        // A diamond at 0x60 that joins at 0x78, which jumps out of the graph.
+        cfg.add_block(0x60, 8, vec![0x68, 0x70]);
+        cfg.add_block(0x68, 8, vec![0x78]);
+        cfg.add_block(0x70, 8, vec![0x7c, 0x78]);
+        cfg.add_block(0x78, 8, vec![0x1000]);
+        let pdoms: Vec<_> = [0x14, 0x20, 0x28, 0x40, 0x60, 0x64, 0x70, 0x100]
+                                .iter()
+                                .map(|&ip| cfg.post_dominator(ip))
+                                .collect();
+        assert_eq!(pdoms,
+                   vec![Some(0x20), Some(0x28), None, None, Some(0x78), Some(0x78), Some(0x78),
+                        None]);
+    }
+}
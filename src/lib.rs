@@ -17,26 +17,60 @@ extern crate libsmt;
 #[macro_use] extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
+#[cfg(feature = "unicorn")]
+extern crate unicorn;
+
+pub mod arch {
+    pub mod arch;
+}
 
 pub mod context {
     pub mod context;
     pub mod rune_ctx;
+    pub mod concretize;
+    pub mod stack;
+    pub mod buffer;
+    pub mod image;
+    pub mod expr;
+    pub mod builder;
+    pub mod metadata;
+    pub mod core_dump;
+    pub mod stats;
+    pub mod sat_cache;
+    pub mod path_export;
+    pub mod call_stack;
+    pub mod history;
+    pub mod fd;
+    pub mod env;
 }
 
 pub mod utils {
     pub mod utils;
     pub mod state;
+    pub mod smtlib;
+    pub mod conditions;
 }
 
 pub mod memory {
     pub mod memory;
     pub mod qword_mem;
     pub mod seg_mem;
+    pub mod paged_mem;
+    pub mod loader;
+    pub mod layout;
+    pub mod lazy_mem;
+    pub mod diff;
+    pub mod heap;
+    pub mod image;
+    pub mod mmio;
+    pub mod dump;
+    pub mod uninit;
 }
 
 pub mod regstore {
     pub mod regstore;
     pub mod regfile;
+    pub mod diff;
 }
 
 pub mod explorer {
@@ -45,6 +79,15 @@ pub mod explorer {
     pub mod bfs;
     pub mod interactive;
     pub mod directed;
+    pub mod concolic;
+    pub mod fork;
+    pub mod strategy;
+    pub mod loops;
+    pub mod target;
+    pub mod coverage;
+    pub mod cost;
+    pub mod checkpoint;
+    pub mod path_graph;
 }
 
 pub mod engine {
@@ -52,6 +95,30 @@ pub mod engine {
     pub mod rune;
     pub mod hook;
     pub mod breakpt;
+    pub mod branch_inputs;
+    pub mod budget;
+    pub mod float;
+    pub mod cache;
+    pub mod lifter;
+    pub mod fallback;
+    pub mod differential;
+    pub mod events;
+    pub mod dashboard;
+    pub mod syscall;
+    pub mod rep;
+    pub mod indirect;
+    pub mod atomic;
+    pub mod cpu;
+    pub mod fault;
+    pub mod function;
+    pub mod esil_op;
+    pub mod replay;
+    pub mod pc_trace;
+    pub mod parallel;
+    pub mod summary;
+    pub mod trace;
+    #[cfg(feature = "unicorn")]
+    pub mod unicorn;
 }
 
 pub mod stream;
@@ -0,0 +1,93 @@
+//! Compute the difference between two memory states.
+//!
+//! Useful to understand what a function modified along a path: take the memory before and after
+//! executing it and list the bytes that changed.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::BTreeMap;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use memory::memory::Memory;
+use utils::utils::to_smtlib;
+
+/// A byte of memory that differs between two states.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellDiff {
+    pub addr: u64,
+    /// Expression held by the cell in the first state, `None` if the cell was never accessed.
+    pub old: Option<String>,
+    /// Expression held by the cell in the second state, `None` if the cell was never accessed.
+    pub new: Option<String>,
+}
+
+/// Compare memory `a` (whose expressions live in `a_solver`) against memory `b` (whose
+/// expressions live in `b_solver`). Cells are compared by their expressions, so the two states
+/// need not share a solver.
+pub fn diff<M>(a: &M,
+               a_solver: &SMTLib2<qf_abv::QF_ABV>,
+               b: &M,
+               b_solver: &SMTLib2<qf_abv::QF_ABV>)
+               -> Vec<CellDiff>
+    where M: Memory<VarRef = NodeIndex>
+{
+    let mut cells: BTreeMap<u64, (Option<String>, Option<String>)> = BTreeMap::new();
+
+    for (addr, cell) in a.cells() {
+        cells.entry(addr).or_insert((None, None)).0 = Some(to_smtlib(cell, a_solver));
+    }
+
+    for (addr, cell) in b.cells() {
+        cells.entry(addr).or_insert((None, None)).1 = Some(to_smtlib(cell, b_solver));
+    }
+
+    cells.into_iter()
+         .filter(|&(_, (ref old, ref new))| old != new)
+         .map(|(addr, (old, new))| {
+             CellDiff {
+                 addr: addr,
+                 old: old,
+                 new: new,
+             }
+         })
+         .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::backends::backend::SMTBackend;
+    use libsmt::theories::bitvec::OpCodes::Const;
+    use r2api::structs::Endian;
+    use memory::paged_mem::PagedMemory;
+
+    #[test]
+    fn diff_memory() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut a = PagedMemory::new(64, Endian::Little);
+        a.write_bytes(0x1000, &[1, 2, 3], &mut solver);
+
+        // `b` lives in a solver of its own.
+        let mut b_solver = solver.clone();
+        let mut b = a.clone();
+        b.write_bytes(0x1001, &[2, 4], &mut b_solver);
+        let addr = b_solver.new_const(Const(0x2000, 64));
+        let input = b_solver.new_var(Some("input"), qf_abv::bv_sort(8));
+        b.write(addr, input, 8, &mut b_solver);
+
+        assert!(diff(&a, &solver, &a.clone(), &b_solver).is_empty());
+        assert_eq!(diff(&a, &solver, &b, &b_solver),
+                   vec![CellDiff {
+                            addr: 0x1002,
+                            old: Some("(_ bv3 8)".to_owned()),
+                            new: Some("(_ bv4 8)".to_owned()),
+                        },
+                        CellDiff {
+                            addr: 0x2000,
+                            old: None,
+                            new: Some("input".to_owned()),
+                        }]);
+    }
+}
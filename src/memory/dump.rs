@@ -0,0 +1,120 @@
+//! Sparse dumps of memory, for consumption by external tooling.
+//!
+//! A dump maps every byte of memory that was accessed to either its concrete value or, for
+//! symbolic bytes, the name of the symbol (or the SMT-LIB2 expression) it holds. Dumps can be
+//! written out as JSON, or as raw segments of contiguous concrete bytes.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+use libsmt::logics::qf_abv::QF_ABV_Fn::BVOps;
+use libsmt::theories::bitvec::OpCodes::Const;
+
+use serde_json;
+
+use memory::memory::Memory;
+use utils::utils::to_smtlib;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DumpCell {
+    Concrete(u8),
+    /// Name of the symbol held by the byte, or its expression if it is not a plain symbol.
+    Symbolic(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryDump {
+    pub cells: BTreeMap<u64, DumpCell>,
+}
+
+impl MemoryDump {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Unable to serialize memory dump")
+    }
+
+    /// Runs of contiguous concrete bytes, as (start address, bytes) pairs. Symbolic bytes end a
+    /// segment.
+    pub fn segments(&self) -> Vec<(u64, Vec<u8>)> {
+        let mut segments: Vec<(u64, Vec<u8>)> = Vec::new();
+        let mut next = None;
+        for (&addr, cell) in &self.cells {
+            let byte = match *cell {
+                DumpCell::Concrete(byte) => byte,
+                DumpCell::Symbolic(_) => {
+                    next = None;
+                    continue;
+                }
+            };
+            if next == Some(addr) {
+                segments.last_mut().unwrap().1.push(byte);
+            } else {
+                segments.push((addr, vec![byte]));
+            }
+            next = addr.checked_add(1);
+        }
+        segments
+    }
+
+    /// Write every segment to a file named after its start address in `dir`, which is created
+    /// if needed. Returns the paths of the files written.
+    pub fn write_segments(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir)?;
+        let mut paths = Vec::new();
+        for (addr, bytes) in self.segments() {
+            let path = dir.join(format!("{:#x}.bin", addr));
+            File::create(&path)?.write_all(&bytes)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// Dump the cells of `mem`, whose expressions live in `solver`.
+pub fn dump<M>(mem: &M, solver: &SMTLib2<qf_abv::QF_ABV>) -> MemoryDump
+    where M: Memory<VarRef = NodeIndex>
+{
+    let cells = mem.cells()
+                   .into_iter()
+                   .map(|(addr, cell)| {
+                       let cell = match *solver.get_node_info(cell) {
+                           BVOps(Const(byte, _)) => DumpCell::Concrete(byte as u8),
+                           _ => DumpCell::Symbolic(to_smtlib(cell, solver)),
+                       };
+                       (addr, cell)
+                   })
+                   .collect();
+    MemoryDump { cells: cells }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::backends::backend::SMTBackend;
+    use memory::paged_mem::PagedMemory;
+    use r2api::structs::Endian;
+
+    #[test]
+    fn dump_segments() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        for &(addr, byte) in &[(0x9000, 0x41), (0x9001, 0x42), (0x9003, 0x43)] {
+            let addr = solver.new_const(Const(addr, 64));
+            let byte = solver.new_const(Const(byte, 8));
+            mem.write(addr, byte, 8, &mut solver);
+        }
+        let addr = solver.new_const(Const(0x9004, 64));
+        let sym = solver.new_var(Some("sym"), qf_abv::bv_sort(8));
+        mem.write(addr, sym, 8, &mut solver);
+
+        let dump = dump(&mem, &solver);
+        assert_eq!(dump.cells[&0x9004], DumpCell::Symbolic("sym".to_owned()));
+        assert_eq!(dump.segments(),
+                   vec![(0x9000, vec![0x41, 0x42]), (0x9003, vec![0x43])]);
+    }
+}
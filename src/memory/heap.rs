@@ -0,0 +1,231 @@
+//! Model of a heap allocator.
+//!
+//! `Heap` keeps track of the chunks handed out by `malloc`/`realloc` and released by `free`.
+//! Memory of freed chunks is never handed out again, so that any later access to it can be
+//! reported as a use-after-free. The allocator hooks at the end of this module replace the libc
+//! functions with this model.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::BTreeMap;
+
+use context::rune_ctx::RuneContext;
+use engine::engine::EngineResult;
+use engine::hook::{self, Hook};
+use memory::memory::Memory;
+use regstore::regstore::RegStore;
+
+/// Default address at which the heap starts.
+pub const HEAP_BASE: u64 = 0x2000_0000;
+
+/// Alignment of chunks returned by `malloc`.
+const ALIGNMENT: u64 = 0x10;
+
+/// Bytes left unallocated between two chunks, so that small overflows do not run into the next
+/// chunk.
+const REDZONE: u64 = 0x10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub addr: u64,
+    pub size: u64,
+    /// Address of the instruction that allocated the chunk.
+    pub alloc_ip: u64,
+    /// Address of the instruction that freed the chunk, `None` if it is still allocated.
+    pub free_ip: Option<u64>,
+}
+
+impl Chunk {
+    pub fn is_freed(&self) -> bool {
+        self.free_ip.is_some()
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.addr && addr - self.addr < self.size
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeapError {
+    /// A chunk was freed more than once.
+    DoubleFree(Chunk),
+    /// The address passed to `free`/`realloc` was not returned by the allocator.
+    InvalidFree(u64),
+    /// Memory of a freed chunk was accessed.
+    UseAfterFree { addr: u64, chunk: Chunk },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heap {
+    top: u64,
+    chunks: BTreeMap<u64, Chunk>,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap::new(HEAP_BASE)
+    }
+}
+
+impl Heap {
+    pub fn new(base: u64) -> Heap {
+        Heap {
+            top: base,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// Allocate a chunk of `size` bytes, `ip` being the address of the allocating instruction.
+    /// Returns a null pointer if the chunk does not fit below the end of the address space.
+    pub fn malloc(&mut self, size: u64, ip: u64) -> u64 {
+        let addr = self.top;
+        let top = size.checked_add(ALIGNMENT - 1)
+                      .map(|size| size & !(ALIGNMENT - 1))
+                      .and_then(|used| addr.checked_add(used))
+                      .and_then(|end| end.checked_add(REDZONE));
+        self.top = match top {
+            Some(top) => top,
+            None => return 0,
+        };
+        self.chunks.insert(addr, Chunk {
+            addr: addr,
+            size: size,
+            alloc_ip: ip,
+            free_ip: None,
+        });
+        addr
+    }
+
+    /// Free the chunk at `addr`. Freeing a null pointer does nothing.
+    pub fn free(&mut self, addr: u64, ip: u64) -> Result<(), HeapError> {
+        if addr == 0 {
+            return Ok(());
+        }
+        match self.chunks.get_mut(&addr) {
+            Some(chunk) => {
+                if chunk.is_freed() {
+                    return Err(HeapError::DoubleFree(*chunk));
+                }
+                chunk.free_ip = Some(ip);
+                Ok(())
+            }
+            None => Err(HeapError::InvalidFree(addr)),
+        }
+    }
+
+    /// Chunk that `addr` points into, if any.
+    pub fn chunk_at(&self, addr: u64) -> Option<&Chunk> {
+        self.chunks
+            .range(..=addr)
+            .next_back()
+            .map(|(_, chunk)| chunk)
+            .filter(|chunk| chunk.contains(addr))
+    }
+
+    /// Check an access of `size` bytes at `addr` against the freed chunks.
+    pub fn check_access(&self, addr: u64, size: u64) -> Result<(), HeapError> {
+        for a in addr..addr.saturating_add(size) {
+            if let Some(chunk) = self.chunk_at(a) {
+                if chunk.is_freed() {
+                    return Err(HeapError::UseAfterFree {
+                        addr: a,
+                        chunk: *chunk,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// All chunks ever allocated, ordered by address.
+    pub fn chunks(&self) -> Vec<Chunk> {
+        self.chunks.values().cloned().collect()
+    }
+}
+
+/// Hook for `void *malloc(size_t size)`.
+pub fn malloc_hook<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>) -> EngineResult<()>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    let size = hook::arg(ctx, 0)?;
+    let ptr = ctx.malloc(size);
+    hook::return_from_call(ctx, Some(ptr))
+}
+
+/// Hook for `void free(void *ptr)`.
+pub fn free_hook<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>) -> EngineResult<()>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    let ptr = hook::arg(ctx, 0)?;
+    ctx.free(ptr);
+    hook::return_from_call(ctx, None)
+}
+
+/// Hook for `void *realloc(void *ptr, size_t size)`.
+pub fn realloc_hook<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>) -> EngineResult<()>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    let ptr = hook::arg(ctx, 0)?;
+    let size = hook::arg(ctx, 1)?;
+    let new_ptr = ctx.realloc(ptr, size);
+    hook::return_from_call(ctx, Some(new_ptr))
+}
+
+/// Hooks for the libc allocator functions, to be installed with `Rune::hook_imports`.
+pub fn allocator_hooks<Mem, Reg>() -> Vec<(&'static str, Hook<RuneContext<Mem, Reg>>)>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex>
+{
+    vec![("malloc", malloc_hook::<Mem, Reg> as Hook<RuneContext<Mem, Reg>>),
+         ("free", free_hook::<Mem, Reg>),
+         ("realloc", realloc_hook::<Mem, Reg>)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn double_free_and_use_after_free() {
+        let mut heap = Heap::default();
+        let a = heap.malloc(0x20, 0x400000);
+        let b = heap.malloc(0x20, 0x400010);
+        assert!(b >= a + 0x20);
+
+        assert_eq!(heap.check_access(a + 0x18, 8), Ok(()));
+        assert_eq!(heap.free(a, 0x400020), Ok(()));
+        match heap.check_access(a + 0x18, 8) {
+            Err(HeapError::UseAfterFree { addr, chunk }) => {
+                assert_eq!(addr, a + 0x18);
+                assert_eq!(chunk.free_ip, Some(0x400020));
+            }
+            other => panic!("Expected use after free, got {:?}", other),
+        }
+        assert_eq!(heap.check_access(b, 8), Ok(()));
+
+        match heap.free(a, 0x400030) {
+            Err(HeapError::DoubleFree(chunk)) => assert_eq!(chunk.addr, a),
+            other => panic!("Expected double free, got {:?}", other),
+        }
+        assert_eq!(heap.free(a + 8, 0x400040), Err(HeapError::InvalidFree(a + 8)));
+        assert_eq!(heap.free(0, 0x400050), Ok(()));
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        let end = u64::max_value();
+        let mut heap = Heap::new(end - 0x2f);
+        let a = heap.malloc(0x10, 0x400000);
+        assert_eq!(heap.chunk_at(a + 0xf).map(|c| c.addr), Some(a));
+        assert_eq!(heap.chunk_at(end), None);
+        assert_eq!(heap.check_access(end - 1, 8), Ok(()));
+
+        // Neither the next chunk with its redzone, nor a huge one fit.
+        assert_eq!(heap.malloc(0x10, 0x400010), 0);
+        assert_eq!(heap.malloc(end, 0x400020), 0);
+        assert_eq!(heap.chunks().len(), 1);
+    }
+}
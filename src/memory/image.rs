@@ -0,0 +1,108 @@
+//! Serializable images of memory.
+//!
+//! The cells of a memory backend are nodes of the solver they were built in, so backends cannot
+//! be serialized on their own. A `MemoryImage` stores the expressions of the cells in SMT-LIB2
+//! syntax (see `utils::smtlib`) along with the permissions of the memory, and can be loaded into
+//! a memory backed by any solver.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::HashMap;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec;
+
+use memory::memory::{Memory, Permission};
+use utils::smtlib::{ExprTable, Exporter, Importer};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MemoryImage {
+    pub exprs: ExprTable,
+    /// Bytes of memory, as (address, index into `exprs`) pairs.
+    pub cells: Vec<(u64, usize)>,
+    /// Expression of the entire memory, for backends that model memory as a single SMT array.
+    pub array: Option<usize>,
+    /// Permissions of memory ranges [start, end), as "rwx" strings.
+    pub regions: Vec<(u64, u64, String)>,
+}
+
+/// Image of the cells and permissions of `mem`, whose expressions live in `solver`.
+pub fn save<M>(mem: &M, solver: &SMTLib2<qf_abv::QF_ABV>) -> MemoryImage
+    where M: Memory<VarRef = NodeIndex>
+{
+    let mut exporter = Exporter::new(solver);
+    let cells = mem.cells()
+                   .into_iter()
+                   .map(|(addr, cell)| (addr, exporter.add(cell)))
+                   .collect();
+    MemoryImage {
+        exprs: exporter.finish(),
+        cells: cells,
+        array: None,
+        regions: mem.regions()
+                    .into_iter()
+                    .map(|(start, end, perm)| (start, end, perm.to_string()))
+                    .collect(),
+    }
+}
+
+/// Write the cells and permissions of `image` to `mem`, returning the variables of the image by
+/// name. Variables in `vars` are bound to their nodes instead of being declared.
+pub fn load<M>(image: &MemoryImage,
+               vars: &HashMap<String, NodeIndex>,
+               mem: &mut M,
+               solver: &mut SMTLib2<qf_abv::QF_ABV>)
+               -> Result<HashMap<String, NodeIndex>, String>
+    where M: Memory<VarRef = NodeIndex>
+{
+    let (nodes, vars) = {
+        let mut importer = Importer::new(solver);
+        for (name, &var) in vars {
+            importer.bind(name, var);
+        }
+        let nodes = importer.import(&image.exprs)?;
+        (nodes, importer.vars())
+    };
+    for &(addr, cell) in &image.cells {
+        let cell = *nodes.get(cell).ok_or_else(|| format!("Invalid cell expression {}", cell))?;
+        let addr = solver.new_const(bitvec::OpCodes::Const(addr, mem.address_width()));
+        mem.write(addr, cell, 8, solver);
+    }
+    for &(start, end, ref perm) in &image.regions {
+        mem.set_permissions(start, end, Permission::from(perm.as_str()));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::theories::bitvec::OpCodes::Const;
+    use memory::paged_mem::PagedMemory;
+    use r2api::structs::Endian;
+    use utils::smtlib::ExprNode;
+
+    #[test]
+    fn load_image() {
+        let image = MemoryImage {
+            exprs: ExprTable {
+                vars: vec![],
+                nodes: vec![ExprNode { op: "#x41".to_owned(), args: vec![] }],
+            },
+            cells: vec![(0x9000, 0), (0x9001, 0)],
+            array: None,
+            regions: vec![(0x9000, 0xa000, "r--".to_owned())],
+        };
+
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        load(&image, &HashMap::new(), &mut mem, &mut solver).unwrap();
+
+        assert_eq!(mem.cells().len(), 2);
+        assert_eq!(mem.permissions(0x9000), Some(Permission::new(true, false, false)));
+        let addr = solver.new_const(Const(0x9001, 64));
+        let cell = mem.read(addr, 8, &mut solver);
+        assert_eq!(mem.cells()[1].1, cell);
+    }
+}
@@ -0,0 +1,138 @@
+//! Where the binary is loaded in the address space of the context.
+//!
+//! A position independent executable can be loaded anywhere, and r2 picks a base for it that
+//! does not have to match the base of a real process (or of a core dump, or of another tool).
+//! `rebase` moves the binary opened in r2 to the base given, so that the addresses r2 shows, the
+//! targets in the ESIL it emits and RIP-relative data references all agree with the addresses of
+//! the context. An `ImageLayout` translates between addresses in the context, addresses relative
+//! to the base the binary was opened at and offsets in the file.
+
+use r2pipe::r2::R2;
+use serde_json::from_str;
+
+use memory::loader::{self, Section};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct BinBase {
+    #[serde(default)]
+    baddr: u64,
+}
+
+/// The part of `ij` that holds the base address.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct BinInfo {
+    #[serde(default)]
+    bin: BinBase,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImageLayout {
+    /// Base address r2 opened the binary at, before it was rebased.
+    pub link_base: u64,
+    /// Base address of the binary in the context, which r2 shows once it is rebased.
+    pub load_base: u64,
+    /// Sections of the binary, at their addresses in the context.
+    pub sections: Vec<Section>,
+}
+
+impl ImageLayout {
+    pub fn new(link_base: u64, load_base: u64, sections: Vec<Section>) -> ImageLayout {
+        ImageLayout {
+            link_base: link_base,
+            load_base: load_base,
+            sections: sections,
+        }
+    }
+
+    /// Distance the binary was moved by.
+    pub fn slide(&self) -> u64 {
+        self.load_base.wrapping_sub(self.link_base)
+    }
+
+    /// Address in the context of `addr`, an address at the base r2 opened the binary at.
+    pub fn to_loaded(&self, addr: u64) -> u64 {
+        addr.wrapping_add(self.slide())
+    }
+
+    /// Address at the base r2 opened the binary at of `addr`, an address in the context.
+    pub fn to_linked(&self, addr: u64) -> u64 {
+        addr.wrapping_sub(self.slide())
+    }
+
+    /// Address in the context of the byte at `offset` in the file, if a mapped section holds it.
+    pub fn offset_to_addr(&self, offset: u64) -> Option<u64> {
+        self.sections
+            .iter()
+            .find(|s| s.is_mapped() && offset >= s.paddr && offset - s.paddr < s.size)
+            .map(|s| s.vaddr + (offset - s.paddr))
+    }
+
+    /// Offset in the file of the byte at `addr` in the context. Bytes of `.bss` and past the end
+    /// of the data of a section are not in the file.
+    pub fn addr_to_offset(&self, addr: u64) -> Option<u64> {
+        self.sections
+            .iter()
+            .find(|s| {
+                s.is_mapped() && !s.is_bss() && addr >= s.vaddr && addr - s.vaddr < s.size
+            })
+            .map(|s| s.paddr + (addr - s.vaddr))
+    }
+}
+
+/// Base address of the binary opened in `r2`.
+pub fn base_addr(r2: &mut R2) -> u64 {
+    r2.send("ij");
+    let info: BinInfo = from_str(&r2.recv()).expect("Unable to parse binary information");
+    info.bin.baddr
+}
+
+/// Layout of the binary opened in `r2`, at the base it is opened at.
+pub fn layout(r2: &mut R2) -> Result<ImageLayout, String> {
+    let base = base_addr(r2);
+    Ok(ImageLayout::new(base, base, loader::sections(r2)?))
+}
+
+/// Rebase the binary opened in `r2` to `base` (`obr`), and return its layout.
+pub fn rebase(r2: &mut R2, base: u64) -> Result<ImageLayout, String> {
+    let link_base = base_addr(r2);
+    r2.send(&format!("obr {}", base));
+    r2.recv();
+    let mut layout = layout(r2)?;
+    layout.link_base = link_base;
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn section(name: &str, paddr: u64, vaddr: u64, size: u64) -> Section {
+        Section {
+            name: name.to_owned(),
+            size: size,
+            vsize: size,
+            paddr: paddr,
+            vaddr: vaddr,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn translate_addresses() {
+        let sections = vec![section(".text", 0x1000, 0x555555555000, 0x200),
+                            section(".data", 0x3000, 0x555555558000, 0x20),
+                            section(".bss", 0x3020, 0x555555558020, 0x40),
+                            section(".comment", 0x3020, 0, 0x10)];
+        let layout = ImageLayout::new(0, 0x555555554000, sections);
+        assert_eq!(layout.slide(), 0x555555554000);
+        assert_eq!(layout.to_loaded(0x1139), 0x555555555139);
+        assert_eq!(layout.to_linked(0x555555555139), 0x1139);
+
+        assert_eq!(layout.offset_to_addr(0x1139), Some(0x555555555139));
+        assert_eq!(layout.offset_to_addr(0x3008), Some(0x555555558008));
+        assert_eq!(layout.offset_to_addr(0x2000), None);
+        assert_eq!(layout.addr_to_offset(0x555555555139), Some(0x1139));
+        assert_eq!(layout.addr_to_offset(0x555555558030), None);
+        assert_eq!(layout.addr_to_offset(0x1139), None);
+    }
+}
@@ -0,0 +1,284 @@
+//! `LazyMemory`, a `Memory` that fetches the contents of the binary from r2 on demand.
+//!
+//! Instead of treating unknown bytes as fresh symbols, the first access to a page that belongs
+//! to a section of the binary reads the concrete bytes of the page from r2. This keeps the
+//! formula small when the program reads large constant tables, without having to load the
+//! entire binary upfront.
+//!
+//! When r2 debugs a process, `LazyMemory::with_process` fetches pages from the memory of the
+//! process instead.
+
+use petgraph::graph::NodeIndex;
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use r2api::structs::Endian;
+use r2pipe::r2::R2;
+
+use memory::loader;
+use memory::memory::{Memory, Permission};
+use memory::paged_mem::{PagedMemory, PAGE_SIZE};
+use utils::utils::try_simplify_constant;
+
+/// Where the bytes of the mapped ranges are fetched from.
+pub trait ByteSource {
+    fn read_bytes(&mut self, addr: u64, len: u64) -> Vec<u8>;
+}
+
+impl ByteSource for R2 {
+    fn read_bytes(&mut self, addr: u64, len: u64) -> Vec<u8> {
+        // Bytes that r2 can not read are left as fresh symbols.
+        loader::read_bytes(self, addr, len).unwrap_or_default()
+    }
+}
+
+#[derive(Clone)]
+pub struct LazyMemory {
+    mem: PagedMemory,
+    source: Option<Rc<RefCell<ByteSource>>>,
+    /// Address ranges [start, end) that are backed by the binary.
+    mapped: Vec<(u64, u64)>,
+    /// Pages that have already been fetched from r2.
+    fetched: BTreeSet<u64>,
+}
+
+impl fmt::Debug for LazyMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LazyMemory {{ mem: {:?}, mapped: {:?}, fetched: {:?} }}",
+               self.mem, self.mapped, self.fetched)
+    }
+}
+
+impl LazyMemory {
+    /// Create a memory backed by the binary opened in `r2`. Memory outside the sections of the
+    /// binary behaves exactly like `PagedMemory`.
+    pub fn with_r2(address_width: usize,
+                   endian: Endian,
+                   r2: Rc<RefCell<R2>>)
+                   -> Result<LazyMemory, String> {
+        let mapped = loader::sections(&mut r2.borrow_mut())?
+                         .iter()
+                         .filter(|s| s.is_mapped())
+                         .map(|s| (s.vaddr, s.vaddr + s.mapped_size()))
+                         .collect();
+        Ok(LazyMemory::with_source(address_width, endian, r2, mapped))
+    }
+
+    /// Create a memory that fetches the ranges [start, end) in `mapped` from `source`.
+    pub fn with_source(address_width: usize,
+                       endian: Endian,
+                       source: Rc<RefCell<ByteSource>>,
+                       mapped: Vec<(u64, u64)>)
+                       -> LazyMemory {
+        let mut mem = LazyMemory::new(address_width, endian);
+        mem.source = Some(source);
+        mem.mapped = mapped;
+        mem
+    }
+
+    /// Create a memory backed by the process that `r2` is attached to (`r2 -d`). All memory
+    /// maps of the process are fetched on demand with their permissions, so execution can start
+    /// from the state the process is in at a breakpoint.
+    pub fn with_process(address_width: usize,
+                        endian: Endian,
+                        r2: Rc<RefCell<R2>>)
+                        -> Result<LazyMemory, String> {
+        let maps = loader::memory_maps(&mut r2.borrow_mut())?;
+        let mapped = maps.iter().map(|m| (m.addr, m.addr_end)).collect();
+        let mut mem = LazyMemory::with_source(address_width, endian, r2, mapped);
+        for map in &maps {
+            mem.mem.set_permissions(map.addr, map.addr_end, map.permissions());
+        }
+        Ok(mem)
+    }
+
+    fn fetch_page(&mut self, base: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        if !self.fetched.insert(base) {
+            return;
+        }
+
+        let source = match self.source {
+            Some(ref source) => source.clone(),
+            None => return,
+        };
+
+        for &(start, end) in &self.mapped {
+            let low = cmp::max(start, base);
+            let high = cmp::min(end, base.saturating_add(PAGE_SIZE));
+            if low < high {
+                let bytes = source.borrow_mut().read_bytes(low, high - low);
+                self.mem.write_bytes(low, &bytes, solver);
+            }
+        }
+    }
+
+    /// Make sure all pages touched by the `len` bytes starting at `addr` have been fetched.
+    fn fetch_range(&mut self, addr: u64, len: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        if len == 0 {
+            return;
+        }
+        let last = addr.saturating_add(len - 1);
+        let mut base = addr & !(PAGE_SIZE - 1);
+        while base <= last {
+            self.fetch_page(base, solver);
+            base = match base.checked_add(PAGE_SIZE) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+
+    fn fetch(&mut self, addr: NodeIndex, size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        // Pages can only be fetched for concrete addresses. Symbolic accesses range over the
+        // bytes that are already known.
+        if let Some(addr) = try_simplify_constant(addr, solver) {
+            self.fetch_range(addr, (size / 8) as u64, solver);
+        }
+    }
+}
+
+impl Memory for LazyMemory {
+    type VarRef = NodeIndex;
+
+    fn new(address_width: usize, endian: Endian) -> LazyMemory {
+        LazyMemory {
+            mem: PagedMemory::new(address_width, endian),
+            source: None,
+            mapped: Vec::new(),
+            fetched: BTreeSet::new(),
+        }
+    }
+
+    fn address_width(&self) -> usize {
+        self.mem.address_width()
+    }
+
+    fn endian(&self) -> Endian {
+        self.mem.endian()
+    }
+
+    fn init_memory(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        self.mem.init_memory(solver);
+        self.fetched = BTreeSet::new();
+    }
+
+    fn read(&mut self, addr: NodeIndex, read_size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        self.fetch(addr, read_size, solver);
+        self.mem.read(addr, read_size, solver)
+    }
+
+    fn write(&mut self, addr: NodeIndex, data: NodeIndex, write_size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        // Fetch before writing, a later fetch would otherwise overwrite this data.
+        self.fetch(addr, write_size, solver);
+        self.mem.write(addr, data, write_size, solver);
+    }
+
+    fn write_bytes(&mut self, addr: u64, bytes: &[u8], solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        self.fetch_range(addr, bytes.len() as u64, solver);
+        self.mem.write_bytes(addr, bytes, solver);
+    }
+
+    fn zero_fill(&mut self, start: u64, end: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        self.mem.zero_fill(start, end, solver);
+    }
+
+    fn cells(&self) -> Vec<(u64, NodeIndex)> {
+        self.mem.cells()
+    }
+
+    fn models_symbolic(&self) -> bool {
+        self.mem.models_symbolic()
+    }
+
+    fn set_symbolic_limit(&mut self, cells: usize) {
+        self.mem.set_symbolic_limit(cells);
+    }
+
+    fn set_permissions(&mut self, start: u64, end: u64, perm: Permission) {
+        self.mem.set_permissions(start, end, perm);
+    }
+
+    fn regions(&self) -> Vec<(u64, u64, Permission)> {
+        self.mem.regions()
+    }
+
+    fn permissions(&self, addr: u64) -> Option<Permission> {
+        self.mem.permissions(addr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use libsmt::backends::backend::SMTBackend;
+    use libsmt::theories::bitvec::OpCodes::Const;
+
+    /// Bytes that are the low byte of their address, recording the ranges that were read.
+    struct Counting {
+        reads: Vec<(u64, u64)>,
+    }
+
+    impl ByteSource for Counting {
+        fn read_bytes(&mut self, addr: u64, len: u64) -> Vec<u8> {
+            self.reads.push((addr, len));
+            (addr..addr + len).map(|a| a as u8).collect()
+        }
+    }
+
+    fn lazy_mem(mapped: Vec<(u64, u64)>) -> (LazyMemory, Rc<RefCell<Counting>>) {
+        let source = Rc::new(RefCell::new(Counting { reads: Vec::new() }));
+        let mem = LazyMemory::with_source(64, Endian::Little, source.clone(), mapped);
+        (mem, source)
+    }
+
+    #[test]
+    fn fetch_pages() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let (mut mem, source) = lazy_mem(vec![(0x1800, 0x2100), (0x3000, 0x3010)]);
+
+        // A read straddling two pages fetches the mapped parts of both.
+        let addr = solver.new_const(Const(0x1ffe, 64));
+        let value = mem.read(addr, 32, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x0100fffe));
+        assert_eq!(source.borrow().reads, vec![(0x1800, 0x800), (0x2000, 0x100)]);
+
+        // Pages are fetched only once, and not at all outside of the mapped ranges.
+        mem.read(addr, 8, &mut solver);
+        let addr = solver.new_const(Const(0x4000, 64));
+        mem.read(addr, 8, &mut solver);
+        mem.fetch_range(0x2fff, 2, &mut solver);
+        assert_eq!(source.borrow().reads.len(), 3);
+        assert_eq!(source.borrow().reads[2], (0x3000, 0x10));
+
+        // The last page of the address space.
+        mem.fetch_range(u64::max_value() - 1, 8, &mut solver);
+        assert!(mem.fetched.contains(&(u64::max_value() & !(PAGE_SIZE - 1))));
+    }
+
+    #[test]
+    fn writes_survive_fetch() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let (mut mem, _) = lazy_mem(vec![(0x1000, 0x3000)]);
+
+        let addr = solver.new_const(Const(0x1ffc, 64));
+        let data = solver.new_const(Const(0xdeadbeef, 32));
+        mem.write(addr, data, 32, &mut solver);
+        mem.write_bytes(0x2ffe, &[0x41, 0x42], &mut solver);
+
+        // Reading the rest of the pages fetches nothing over the written bytes.
+        let addr = solver.new_const(Const(0x1ffa, 64));
+        let value = mem.read(addr, 64, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x0100deadbeeffbfa));
+        let addr = solver.new_const(Const(0x2ffd, 64));
+        let value = mem.read(addr, 24, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x4241fd));
+    }
+}
@@ -0,0 +1,248 @@
+//! Populate memory with the contents of the binary opened in r2.
+//!
+//! Without loading, every read from global data returns a fresh symbol. Loading the sections
+//! ensures that reads of initialized data return the actual bytes from the binary, and that
+//! `.bss` reads as zero.
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+use r2pipe::r2::R2;
+use serde_json::from_str;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use memory::memory::{Memory, Permission};
+
+/// Section information as reported by r2 (`iSj`).
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Section {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub vsize: u64,
+    #[serde(default)]
+    pub paddr: u64,
+    #[serde(default)]
+    pub vaddr: u64,
+    /// Permissions of the section, eg. "-r-x". Older versions of r2 report these as `flags`.
+    #[serde(default)]
+    pub flags: Option<String>,
+    #[serde(default)]
+    pub perm: Option<String>,
+}
+
+impl Section {
+    /// Sections that are not mapped into the address space (`.comment`, `.symtab`, ...) are
+    /// reported with a virtual address of zero.
+    pub fn is_mapped(&self) -> bool {
+        self.vaddr != 0
+    }
+
+    pub fn is_bss(&self) -> bool {
+        self.name.ends_with("bss")
+    }
+
+    pub fn permissions(&self) -> Permission {
+        match self.perm.as_ref().or(self.flags.as_ref()) {
+            Some(perm) => Permission::from(perm.as_str()),
+            None => Permission::rwx(),
+        }
+    }
+
+    /// Size of the section once it is mapped into memory.
+    pub fn mapped_size(&self) -> u64 {
+        cmp::max(self.size, self.vsize)
+    }
+
+    /// Bytes of the section that are in the file, none for `.bss`.
+    pub fn file_size(&self) -> u64 {
+        if self.is_bss() { 0 } else { self.size }
+    }
+}
+
+/// A memory map of a process being debugged by r2 (`dmj`).
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MemoryMap {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub addr: u64,
+    #[serde(default)]
+    pub addr_end: u64,
+    /// Permissions of the map, eg. "r-x".
+    #[serde(default)]
+    pub perm: Option<String>,
+}
+
+impl MemoryMap {
+    pub fn permissions(&self) -> Permission {
+        match self.perm {
+            Some(ref perm) => Permission::from(perm.as_str()),
+            None => Permission::rwx(),
+        }
+    }
+}
+
+/// Fetch the list of sections of the binary.
+pub fn sections(r2: &mut R2) -> Result<Vec<Section>, String> {
+    r2.send("iSj");
+    parse_sections(&r2.recv())
+}
+
+/// Fetch the memory maps of the process r2 is attached to, when r2 was started with `-d`.
+pub fn memory_maps(r2: &mut R2) -> Result<Vec<MemoryMap>, String> {
+    r2.send("dmj");
+    parse_memory_maps(&r2.recv())
+}
+
+/// Fetch the current values of the registers of the process r2 is attached to (`drj`).
+pub fn debug_registers(r2: &mut R2) -> Result<Vec<(String, u64)>, String> {
+    r2.send("drj");
+    parse_registers(&r2.recv())
+}
+
+/// Read `size` bytes at virtual address `addr` from the binary, or from the memory of the
+/// process when r2 is debugging one.
+pub fn read_bytes(r2: &mut R2, addr: u64, size: u64) -> Result<Vec<u8>, String> {
+    r2.send(&format!("p8 {} @ {}", size, addr));
+    parse_bytes(&r2.recv(), size).map_err(|e| format!("Unable to read {:#x}: {}", addr, e))
+}
+
+/// Write the contents of all mapped sections of the binary into `mem` and set the permissions of
+/// the corresponding regions. `.bss` like sections, and the part of sections that is not in
+/// the file, read as zero (see `Memory::zero_fill`). Returns the sections that were loaded.
+pub fn load_sections<M: Memory>(r2: &mut R2,
+                                mem: &mut M,
+                                solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                                -> Result<Vec<Section>, String> {
+    let sections: Vec<Section> = sections(r2)?.into_iter().filter(|s| s.is_mapped()).collect();
+    for section in &sections {
+        let end = section.vaddr + section.mapped_size();
+        let file_size = section.file_size();
+        if file_size > 0 {
+            let bytes = read_bytes(r2, section.vaddr, file_size)?;
+            mem.write_bytes(section.vaddr, &bytes, solver);
+        }
+        if section.vaddr + file_size < end {
+            mem.zero_fill(section.vaddr + file_size, end, solver);
+        }
+        mem.set_permissions(section.vaddr, end, section.permissions());
+    }
+    Ok(sections)
+}
+
+/// Parse the `size` bytes that `p8` prints as hex.
+pub fn parse_bytes(hex: &str, size: u64) -> Result<Vec<u8>, String> {
+    let hex = hex.trim();
+    if hex.len() as u64 != 2 * size {
+        return Err(format!("Expected {} bytes, got {:?}", size, hex));
+    }
+    if !hex.is_ascii() {
+        return Err(format!("Invalid hex {:?}", hex));
+    }
+    (0..hex.len() / 2)
+        .map(|i| {
+            u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+                .map_err(|_| format!("Invalid hex {:?}", hex))
+        })
+        .collect()
+}
+
+/// Parse the section information of `iSj`.
+pub fn parse_sections(json: &str) -> Result<Vec<Section>, String> {
+    from_str(json).map_err(|e| format!("Unable to parse section information: {}", e))
+}
+
+/// Parse the memory maps of `dmj`.
+pub fn parse_memory_maps(json: &str) -> Result<Vec<MemoryMap>, String> {
+    from_str(json).map_err(|e| format!("Unable to parse memory maps: {}", e))
+}
+
+/// Parse the registers of `drj`, ordered by name.
+pub fn parse_registers(json: &str) -> Result<Vec<(String, u64)>, String> {
+    let regs: BTreeMap<String, u64> = from_str(json)
+        .map_err(|e| format!("Unable to parse registers: {}", e))?;
+    Ok(regs.into_iter().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_section_info() {
+        let json = r#"[{"name":".text","size":512,"vsize":512,"paddr":4096,"vaddr":4198400,
+                        "perm":"-r-x"},
+                       {"name":".bss","size":64,"vsize":128,"paddr":8192,"vaddr":4210688,
+                        "flags":"-rw-"},
+                       {"name":".comment","size":42,"paddr":9000}]"#;
+        let sections = parse_sections(json).unwrap();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].vaddr, 0x401000);
+        assert_eq!(sections[1].flags, Some("-rw-".to_owned()));
+        assert!(!sections[2].is_mapped());
+        assert_eq!(sections[2].vsize, 0);
+        assert!(parse_sections("not json").is_err());
+    }
+
+    #[test]
+    fn parse_process_state() {
+        let maps = r#"[{"name":"/bin/ls","addr":4194304,"addr_end":4198400,"perm":"r-x"},
+                       {"name":"[stack]","addr":140737488216064,"addr_end":140737488351232,
+                        "perm":"rw-","user":1}]"#;
+        let maps = parse_memory_maps(maps).unwrap();
+        assert_eq!(maps.len(), 2);
+        assert_eq!((maps[0].addr, maps[0].addr_end), (0x400000, 0x401000));
+        assert_eq!(maps[0].permissions(), Permission::new(true, false, true));
+        assert_eq!(maps[1].name, "[stack]");
+        assert!(parse_memory_maps("{").is_err());
+
+        let regs = parse_registers(r#"{"rip":4195578,"rax":0,"rsp":140737488347712}"#).unwrap();
+        assert_eq!(regs,
+                   vec![("rax".to_owned(), 0), ("rip".to_owned(), 0x4004fa),
+                        ("rsp".to_owned(), 0x7fffffffdf40)]);
+        assert!(parse_registers(r#"{"rip":"pc"}"#).is_err());
+    }
+
+    #[test]
+    fn parse_hex_bytes() {
+        assert_eq!(parse_bytes("41ff00\n", 3), Ok(vec![0x41, 0xff, 0x00]));
+        assert!(parse_bytes("41f", 2).is_err());
+        assert!(parse_bytes("41", 2).is_err());
+        assert!(parse_bytes("4g", 1).is_err());
+        assert!(parse_bytes("\u{e9}", 1).is_err());
+    }
+
+    #[test]
+    fn section_sizes() {
+        let bss = Section {
+            name: ".bss".to_owned(),
+            size: 64,
+            vsize: 128,
+            vaddr: 0x402000,
+            ..Default::default()
+        };
+        assert!(bss.is_bss());
+        assert!(bss.is_mapped());
+        assert_eq!(bss.mapped_size(), 128);
+        assert_eq!(bss.file_size(), 0);
+        assert_eq!(bss.permissions(), Permission::rwx());
+
+        let data = Section {
+            name: ".data".to_owned(),
+            size: 32,
+            vsize: 16,
+            vaddr: 0x403000,
+            perm: Some("-r--".to_owned()),
+            ..Default::default()
+        };
+        assert!(!data.is_bss());
+        assert_eq!(data.mapped_size(), 32);
+        assert_eq!(data.file_size(), 32);
+        assert_eq!(data.permissions(), Permission::new(true, false, false));
+    }
+}
@@ -1,10 +1,77 @@
 use libsmt::backends::backend::SMTBackend;
 use libsmt::backends::smtlib2::{SMTLib2, SMTProc};
 use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
 use r2api::structs::Endian;
+use petgraph::graph::NodeIndex;
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use memory::image::{self, MemoryImage};
+
+/// Access permissions of a region of memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permission {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl Permission {
+    pub fn new(read: bool, write: bool, exec: bool) -> Permission {
+        Permission {
+            read: read,
+            write: write,
+            exec: exec,
+        }
+    }
+
+    pub fn rwx() -> Permission {
+        Permission::new(true, true, true)
+    }
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.exec,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}",
+               if self.read { 'r' } else { '-' },
+               if self.write { 'w' } else { '-' },
+               if self.exec { 'x' } else { '-' })
+    }
+}
+
+/// Parses permission strings as reported by r2, eg. "-r-x" or "rw-".
+impl<'a> From<&'a str> for Permission {
+    fn from(s: &'a str) -> Permission {
+        Permission::new(s.contains('r'), s.contains('w'), s.contains('x'))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Raised when memory is accessed in a way that is not allowed by its permissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessViolation {
+    pub addr: u64,
+    pub access: Access,
+    /// Permissions of the memory, `None` if the address is not mapped.
+    pub perm: Option<Permission>,
+}
 
 pub trait Memory: Clone + Debug {
     type VarRef;
@@ -12,6 +79,12 @@ pub trait Memory: Clone + Debug {
     /// Create a new memory instance
     fn new(addr_width: usize, endian: Endian) -> Self;
     
+    /// Width of addresses in bits.
+    fn address_width(&self) -> usize;
+
+    /// Order of the bytes of values wider than a byte.
+    fn endian(&self) -> Endian;
+
     /// Initialize memory to be a new variable with the solver
     fn init_memory(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>);
 
@@ -20,5 +93,128 @@ pub trait Memory: Clone + Debug {
 
     /// Write x bytes of memory at a certain location
     fn write(&mut self, addr: Self::VarRef, data: Self::VarRef, write_size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>);
+
+    /// Write concrete bytes to memory starting at a concrete address
+    fn write_bytes(&mut self, addr: u64, bytes: &[u8], solver: &mut SMTLib2<qf_abv::QF_ABV>);
+
+    /// Make the bytes of [start, end) that were never written read as zero, as those of `.bss`.
+    /// Backends that can not hold such a region write the zeros.
+    fn zero_fill(&mut self, start: u64, end: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        self.write_bytes(start, &vec![0; (end - start) as usize], solver);
+    }
+
+    /// Whether accesses through a symbolic address are cheap enough to model as they are. The
+    /// context concretizes the addresses of the others, see `RuneContext::set_concretization`.
+    fn models_symbolic(&self) -> bool {
+        true
+    }
+
+    /// Set the number of held bytes up to which backends that model accesses through a symbolic
+    /// address by ranging over their bytes do so, see `paged_mem`. Other backends ignore this.
+    fn set_symbolic_limit(&mut self, _cells: usize) { }
+
+    /// Set permissions for the region [start, end). Memory that does not track permissions
+    /// ignores this.
+    fn set_permissions(&mut self, _start: u64, _end: u64, _perm: Permission) { }
+
+    /// Permissions of the region containing `addr`, `None` if no permissions were set for it.
+    fn permissions(&self, _addr: u64) -> Option<Permission> {
+        None
+    }
+
+    /// Regions [start, end) with the permissions set for them, in the order they were set.
+    fn regions(&self) -> Vec<(u64, u64, Permission)> {
+        Vec::new()
+    }
+
+    /// Serializable image of this memory, see `memory::image`.
+    fn to_image(&self, solver: &SMTLib2<qf_abv::QF_ABV>) -> MemoryImage
+        where Self: Memory<VarRef = NodeIndex>
+    {
+        image::save(self, solver)
+    }
+
+    /// Load an image created by `to_image` on top of the current contents of memory. Variables
+    /// of the image that are in `vars` refer to these nodes, the others are declared. Returns
+    /// the variables of the image by name, for expressions imported later to refer to.
+    fn load_image(&mut self,
+                  image: &MemoryImage,
+                  vars: &HashMap<String, NodeIndex>,
+                  solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                  -> Result<HashMap<String, NodeIndex>, String>
+        where Self: Memory<VarRef = NodeIndex>
+    {
+        image::load(image, vars, self, solver)
+    }
+
+    /// All cells held in memory as (address, value), one per byte and in increasing order of
+    /// address. Backends that cannot enumerate their contents return an empty list.
+    fn cells(&self) -> Vec<(u64, Self::VarRef)> {
+        Vec::new()
+    }
+
+    /// Take a snapshot of the current state of memory, which can later be passed to `restore`.
+    /// Backends that share data between clones (such as `PagedMemory`) make this cheap.
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Roll back memory to a previously taken snapshot.
+    fn restore(&mut self, snapshot: Self) {
+        *self = snapshot;
+    }
+
+    /// Merge `other` into this memory: afterwards every cell holds its value in this memory if
+    /// `condition` holds, and its value in `other` otherwise. Both memories must have been built
+    /// in the same solver.
+    ///
+    /// The default implementation merges the cells returned by `cells`, backends that cannot
+    /// enumerate their cells should override it.
+    fn merge(&mut self, other: &Self, condition: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>)
+        where Self: Memory<VarRef = NodeIndex>
+    {
+        let mut other = other.clone();
+        let ours: BTreeMap<u64, NodeIndex> = self.cells().into_iter().collect();
+        let theirs: BTreeMap<u64, NodeIndex> = other.cells().into_iter().collect();
+        let addrs: BTreeSet<u64> = ours.keys().chain(theirs.keys()).cloned().collect();
+        let width = self.address_width();
+
+        for addr in addrs {
+            let addr_ = solver.new_const(bitvec::OpCodes::Const(addr, width));
+            // A cell that is missing on one side holds whatever that memory reads there.
+            let a = match ours.get(&addr) {
+                Some(cell) => *cell,
+                None => self.read(addr_, 8, solver),
+            };
+            let b = match theirs.get(&addr) {
+                Some(cell) => *cell,
+                None => other.read(addr_, 8, solver),
+            };
+            if a != b {
+                let cell = solver.assert(core::OpCodes::ITE, &[condition, a, b]);
+                self.write(addr_, cell, 8, solver);
+            }
+        }
+    }
+
+    /// Whether `addr` is in a region with permissions. Memory without permissions maps every
+    /// address.
+    fn is_mapped(&self, addr: u64) -> bool {
+        self.permissions(addr).is_some() || self.regions().is_empty()
+    }
+
+    /// Check if `addr` may be accessed. Memory without permissions allows every access.
+    fn check_access(&self, addr: u64, access: Access) -> Result<(), AccessViolation> {
+        match self.permissions(addr) {
+            Some(perm) if !perm.allows(access) => {
+                Err(AccessViolation {
+                    addr: addr,
+                    access: access,
+                    perm: Some(perm),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
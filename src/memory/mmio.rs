@@ -0,0 +1,85 @@
+//! Memory mapped I/O regions.
+//!
+//! Loads from an MMIO region do not return what was last stored there, registers of a device
+//! can change at any time. By default every load returns a fresh symbol; a callback can be used
+//! to model the device instead. Stores are recorded but never reach memory.
+//!
+//! Only accesses through concrete addresses are recognized as MMIO.
+
+use petgraph::graph::NodeIndex;
+
+use std::fmt;
+use std::rc::Rc;
+
+use libsmt::backends::backend::SMTBackend;
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use memory::memory::Access;
+
+/// Models a load of `size` bits from `addr`, returning the value read.
+pub type MmioRead = Rc<Fn(&mut SMTLib2<qf_abv::QF_ABV>, u64, usize) -> NodeIndex>;
+
+#[derive(Clone)]
+pub struct MmioRegion {
+    pub start: u64,
+    pub end: u64,
+    read: Option<MmioRead>,
+}
+
+impl fmt::Debug for MmioRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MmioRegion {{ start: {:#x}, end: {:#x}, callback: {} }}",
+               self.start, self.end, self.read.is_some())
+    }
+}
+
+impl MmioRegion {
+    /// MMIO range [start, end) where every load returns a fresh symbol.
+    pub fn new(start: u64, end: u64) -> MmioRegion {
+        MmioRegion {
+            start: start,
+            end: end,
+            read: None,
+        }
+    }
+
+    /// MMIO range [start, end) where loads are modeled by `read`.
+    pub fn with_callback(start: u64, end: u64, read: MmioRead) -> MmioRegion {
+        MmioRegion {
+            start: start,
+            end: end,
+            read: Some(read),
+        }
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr < self.end
+    }
+
+    /// Value of a load of `size` bits from `addr`. `n` is used to give fresh symbols unique
+    /// names.
+    pub fn load(&self, solver: &mut SMTLib2<qf_abv::QF_ABV>, addr: u64, size: usize, n: usize) -> NodeIndex {
+        match self.read {
+            Some(ref read) => read(solver, addr, size),
+            None => {
+                let name = format!("mmio_{:x}_{}", addr, n);
+                solver.new_var(Some(&name), qf_abv::bv_sort(size))
+            }
+        }
+    }
+}
+
+/// A load or store to an MMIO region.
+#[derive(Clone, Debug)]
+pub struct MmioAccess {
+    /// Instruction pointer of the context at the time of the access. Note that rune advances it
+    /// past an instruction before executing it.
+    pub ip: u64,
+    pub addr: u64,
+    /// Size of the access in bits.
+    pub size: usize,
+    pub access: Access,
+    /// Value that was loaded or stored.
+    pub value: NodeIndex,
+}
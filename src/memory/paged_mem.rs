@@ -0,0 +1,487 @@
+//! `PagedMemory`, a byte addressable `Memory` backed by copy-on-write pages.
+//!
+//! Memory is split into pages of `PAGE_SIZE` bytes. Pages are reference counted and are only
+//! copied when they are written to, which means that cloning a context (for example, when the
+//! explorer forks at a branch) only copies the page table and not the contents of the memory.
+//!
+//! Accesses through a symbolic address are modeled as ITE chains over all the bytes that are
+//! currently held in memory. This avoids concretizing the address, at the cost of larger
+//! expressions. Writes through a symbolic address can only affect bytes which have been
+//! accessed before. Past a limit of held bytes (`SYMBOLIC_CELL_LIMIT` unless set with
+//! `Memory::set_symbolic_limit`) the chains get too large, and the context concretizes the
+//! address to upto `concretize::FALLBACK_SAMPLES` values instead (see `Memory::models_symbolic`).
+//! The sections of a binary usually hold more bytes than the default limit, so contexts that
+//! load them need a higher limit for symbolic addresses to be modeled. The value of a byte at a
+//! symbolic address that is none of the held bytes is a fresh symbol, which is kept per address
+//! so that accesses through the same address agree.
+//!
+//! Accesses that run past the end of the address space wrap around to its start.
+//!
+//! Regions such as `.bss` read as zero without holding their bytes until they are accessed (see
+//! `Memory::zero_fill`). Accesses through a symbolic address do not range over the bytes of
+//! these regions that were never accessed.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::backends::backend::SMTBackend;
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec::OpCodes::*;
+use libsmt::theories::core::OpCodes::{Cmp, ITE};
+
+use r2api::structs::Endian;
+
+use memory::memory::{Memory, Permission};
+use utils::utils::{mask, try_simplify_constant};
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+/// Bytes held in memory up to which accesses through a symbolic address range over them all,
+/// unless set with `Memory::set_symbolic_limit`.
+pub const SYMBOLIC_CELL_LIMIT: usize = 0x400;
+
+#[derive(Clone, Debug)]
+struct Page {
+    /// Solver node for every byte in the page. `None` if the byte has never been accessed.
+    cells: Vec<Option<NodeIndex>>,
+}
+
+impl Page {
+    fn new() -> Page {
+        Page { cells: vec![None; PAGE_SIZE as usize] }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PagedMemory {
+    addr_width: usize,
+    endian: Endian,
+    pages: BTreeMap<u64, Rc<Page>>,
+    /// Regions with explicit permissions as (start, end, permission). Later entries take
+    /// precedence over earlier ones.
+    regions: Vec<(u64, u64, Permission)>,
+    /// Regions [start, end) whose bytes read as zero until they are written, see `zero_fill`.
+    zeroed: Rc<Vec<(u64, u64)>>,
+    /// Value of the byte at (address node, offset) if the address is none of the held bytes.
+    unknown: Rc<BTreeMap<(usize, u64), NodeIndex>>,
+    /// Bytes held in memory up to which accesses through a symbolic address are modeled.
+    symbolic_limit: usize,
+}
+
+impl PagedMemory {
+    fn page_of(addr: u64) -> (u64, usize) {
+        (addr & !(PAGE_SIZE - 1), (addr & (PAGE_SIZE - 1)) as usize)
+    }
+
+    /// The address `offset` bytes past `addr`, wrapped around the address space.
+    fn byte_addr(&self, addr: u64, offset: u64) -> u64 {
+        mask(addr.wrapping_add(offset), self.addr_width)
+    }
+
+    /// Number of pages held by this memory instance.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Number of pages that are shared with atleast one other memory instance.
+    pub fn shared_page_count(&self) -> usize {
+        self.pages.values().filter(|p| Rc::strong_count(p) > 1).count()
+    }
+
+    fn read_byte(&mut self, addr: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        let (base, offset) = PagedMemory::page_of(addr);
+        if let Some(page) = self.pages.get(&base) {
+            if let Some(byte) = page.cells[offset] {
+                return byte;
+            }
+        }
+
+        // The byte was never written to. Treat it as a fresh symbol, or zero, and remember it so
+        // that later reads are consistent with this one.
+        let byte = if self.zeroed.iter().any(|&(start, end)| addr >= start && addr < end) {
+            solver.new_const(Const(0, 8))
+        } else {
+            let key = format!("mem_{}_1", addr);
+            solver.new_var(Some(&key), qf_abv::bv_sort(8))
+        };
+        self.write_byte(addr, byte);
+        byte
+    }
+
+    fn write_byte(&mut self, addr: u64, byte: NodeIndex) {
+        let (base, offset) = PagedMemory::page_of(addr);
+        let page = self.pages.entry(base).or_insert_with(|| Rc::new(Page::new()));
+        // Copies the page only if it is shared with another memory instance.
+        Rc::make_mut(page).cells[offset] = Some(byte);
+    }
+
+    /// Cells that an access through a symbolic address ranges over.
+    fn known_cells(&self) -> Vec<(u64, NodeIndex)> {
+        self.cells()
+    }
+
+    /// Number of bytes held in memory.
+    pub fn cell_count(&self) -> usize {
+        self.pages.values().map(|p| p.cells.iter().filter(|c| c.is_some()).count()).sum()
+    }
+
+    /// Returns a node that evaluates to `addr + offset`.
+    fn offset_addr(&self, addr: NodeIndex, offset: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        if offset == 0 {
+            addr
+        } else {
+            let offset = solver.new_const(Const(offset, self.addr_width));
+            solver.assert(BvAdd, &[addr, offset])
+        }
+    }
+
+    fn read_byte_symbolic(&mut self, addr: NodeIndex, offset: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        let ptr = self.offset_addr(addr, offset, solver);
+        // Value of the byte if the address does not resolve to any of the known cells.
+        let mut byte = match self.unknown.get(&(addr.index(), offset)) {
+            Some(&byte) => byte,
+            None => {
+                let key = format!("mem_sym_{}_{}", addr.index(), offset);
+                let byte = solver.new_var(Some(&key), qf_abv::bv_sort(8));
+                Rc::make_mut(&mut self.unknown).insert((addr.index(), offset), byte);
+                byte
+            }
+        };
+        for (cell_addr, cell) in self.known_cells() {
+            let cell_addr = solver.new_const(Const(cell_addr, self.addr_width));
+            let cond = solver.assert(Cmp, &[ptr, cell_addr]);
+            byte = solver.assert(ITE, &[cond, cell, byte]);
+        }
+        byte
+    }
+
+    fn write_byte_symbolic(&mut self, addr: NodeIndex, offset: u64, byte: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        let ptr = self.offset_addr(addr, offset, solver);
+        for (cell_addr, cell) in self.known_cells() {
+            let const_addr = solver.new_const(Const(cell_addr, self.addr_width));
+            let cond = solver.assert(Cmp, &[ptr, const_addr]);
+            let new_cell = solver.assert(ITE, &[cond, byte, cell]);
+            self.write_byte(cell_addr, new_cell);
+        }
+        // Reads through the same address see the byte, even if it is none of the known cells.
+        Rc::make_mut(&mut self.unknown).insert((addr.index(), offset), byte);
+    }
+
+    /// Combines bytes (in increasing order of address) into a single value, honoring the
+    /// endianness of the memory.
+    fn concat_bytes(&self, bytes: Vec<NodeIndex>, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        let mut result: Option<NodeIndex> = None;
+        for byte in bytes {
+            // Concat places its first operand in the higher bits.
+            result = Some(match result {
+                None => byte,
+                Some(acc) => {
+                    match self.endian {
+                        Endian::Little => solver.assert(Concat, &[byte, acc]),
+                        Endian::Big => solver.assert(Concat, &[acc, byte]),
+                    }
+                }
+            });
+        }
+        result.expect("Access of size zero")
+    }
+
+    /// Splits `data` into bytes, in the order in which the bytes are laid out in memory.
+    fn split_bytes(&self, data: NodeIndex, size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> Vec<NodeIndex> {
+        let bytes = (size / 8) as u64;
+        if bytes == 1 {
+            return vec![data];
+        }
+
+        (0..bytes).map(|i| {
+            let low = match self.endian {
+                Endian::Little => i * 8,
+                Endian::Big => (bytes - i - 1) * 8,
+            };
+            solver.assert(Extract(low + 7, low), &[data])
+        }).collect()
+    }
+}
+
+impl Memory for PagedMemory {
+    type VarRef = NodeIndex;
+
+    fn new(address_width: usize, endian: Endian) -> PagedMemory {
+        PagedMemory {
+            addr_width: address_width,
+            endian: endian,
+            pages: BTreeMap::new(),
+            regions: Vec::new(),
+            zeroed: Rc::new(Vec::new()),
+            unknown: Rc::new(BTreeMap::new()),
+            symbolic_limit: SYMBOLIC_CELL_LIMIT,
+        }
+    }
+
+    fn address_width(&self) -> usize {
+        self.addr_width
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    fn init_memory(&mut self, _solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        self.pages = BTreeMap::new();
+    }
+
+    fn read(&mut self, addr: NodeIndex, read_size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        let bytes = (read_size / 8) as u64;
+        let cells = if let Some(addr) = try_simplify_constant(addr, solver) {
+            (0..bytes).map(|i| {
+                          let addr = self.byte_addr(addr, i);
+                          self.read_byte(addr, solver)
+                      })
+                      .collect()
+        } else {
+            (0..bytes).map(|i| self.read_byte_symbolic(addr, i, solver)).collect()
+        };
+        self.concat_bytes(cells, solver)
+    }
+
+    fn write(&mut self, addr: NodeIndex, data: NodeIndex, write_size: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        let cells = self.split_bytes(data, write_size, solver);
+        if let Some(addr) = try_simplify_constant(addr, solver) {
+            for (i, byte) in cells.into_iter().enumerate() {
+                let addr = self.byte_addr(addr, i as u64);
+                self.write_byte(addr, byte);
+            }
+        } else {
+            for (i, byte) in cells.into_iter().enumerate() {
+                self.write_byte_symbolic(addr, i as u64, byte, solver);
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, addr: u64, bytes: &[u8], solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        // Share the constant nodes between bytes of the same value, this keeps the solver graph
+        // small when loading large regions of memory.
+        let mut consts: Vec<Option<NodeIndex>> = vec![None; 256];
+        for (i, byte) in bytes.iter().enumerate() {
+            let node = match consts[*byte as usize] {
+                Some(node) => node,
+                None => {
+                    let node = solver.new_const(Const(*byte as u64, 8));
+                    consts[*byte as usize] = Some(node);
+                    node
+                }
+            };
+            let addr = self.byte_addr(addr, i as u64);
+            self.write_byte(addr, node);
+        }
+    }
+
+    fn cells(&self) -> Vec<(u64, NodeIndex)> {
+        let mut cells = Vec::new();
+        for (base, page) in &self.pages {
+            for (offset, cell) in page.cells.iter().enumerate() {
+                if let Some(byte) = *cell {
+                    cells.push((base + offset as u64, byte));
+                }
+            }
+        }
+        cells
+    }
+
+    fn zero_fill(&mut self, start: u64, end: u64, _solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        Rc::make_mut(&mut self.zeroed).push((start, end));
+    }
+
+    fn models_symbolic(&self) -> bool {
+        self.cell_count() <= self.symbolic_limit
+    }
+
+    fn set_symbolic_limit(&mut self, cells: usize) {
+        self.symbolic_limit = cells;
+    }
+
+    fn set_permissions(&mut self, start: u64, end: u64, perm: Permission) {
+        self.regions.push((start, end, perm));
+    }
+
+    fn regions(&self) -> Vec<(u64, u64, Permission)> {
+        self.regions.clone()
+    }
+
+    fn permissions(&self, addr: u64) -> Option<Permission> {
+        self.regions
+            .iter()
+            .rev()
+            .find(|&&(start, end, _)| addr >= start && addr < end)
+            .map(|&(_, _, perm)| perm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use utils::utils::to_smtlib;
+
+    #[test]
+    fn read_after_write() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+
+        let addr = solver.new_const(Const(0x9000, 64));
+        let data = solver.new_const(Const(0x41, 8));
+        mem.write(addr, data, 8, &mut solver);
+
+        assert_eq!(mem.read(addr, 8, &mut solver), data);
+    }
+
+    #[test]
+    fn byte_order() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let orders = [(Endian::Little, 0x78563412, 0x3412, 0xef),
+                      (Endian::Big, 0x12345678, 0x1234, 0xde)];
+        for &(endian, word, half, first) in &orders {
+            let mut mem = PagedMemory::new(32, endian);
+            mem.write_bytes(0x1000, &[0x12, 0x34, 0x56, 0x78], &mut solver);
+            let addr = solver.new_const(Const(0x1000, 32));
+            let value = mem.read(addr, 32, &mut solver);
+            assert_eq!(try_simplify_constant(value, &mut solver), Some(word));
+            let value = mem.read(addr, 16, &mut solver);
+            assert_eq!(try_simplify_constant(value, &mut solver), Some(half));
+
+            // Writes lay values out in the same order.
+            let addr = solver.new_const(Const(0x2000, 32));
+            let data = solver.new_const(Const(0xdeadbeef, 32));
+            mem.write(addr, data, 32, &mut solver);
+            let value = mem.read(addr, 8, &mut solver);
+            assert_eq!(try_simplify_constant(value, &mut solver), Some(first));
+        }
+    }
+
+    #[test]
+    fn zero_filled() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        mem.zero_fill(0x4000, 0x4100, &mut solver);
+        assert_eq!(mem.cell_count(), 0);
+
+        mem.write_bytes(0x4010, &[0x41], &mut solver);
+        let addr = solver.new_const(Const(0x400f, 64));
+        let value = mem.read(addr, 24, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x4100));
+
+        // Bytes past the region are still unknown.
+        let addr = solver.new_const(Const(0x4100, 64));
+        let value = mem.read(addr, 8, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), None);
+    }
+
+    #[test]
+    fn wrap_around() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        let addr = solver.new_const(Const(0xffff_ffff_ffff_fffc, 64));
+        let data = solver.new_const(Const(0x1122_3344_5566_7788, 64));
+        mem.write(addr, data, 64, &mut solver);
+        let value = mem.read(addr, 64, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x1122_3344_5566_7788));
+        let start = solver.new_const(Const(0, 64));
+        let value = mem.read(start, 32, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x1122_3344));
+
+        // At the width of the addresses.
+        let mut mem = PagedMemory::new(32, Endian::Little);
+        mem.write_bytes(0xffff_fffe, &[0x41, 0x42, 0x43], &mut solver);
+        let start = solver.new_const(Const(0, 32));
+        let value = mem.read(start, 8, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x43));
+        assert_eq!(mem.cell_count(), 3);
+    }
+
+    #[test]
+    fn clone_shares_pages() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+
+        let addr = solver.new_const(Const(0x9000, 64));
+        let data = solver.new_const(Const(0x41, 8));
+        mem.write(addr, data, 8, &mut solver);
+
+        let mut forked = mem.clone();
+        assert_eq!(forked.shared_page_count(), 1);
+
+        let new_data = solver.new_const(Const(0x42, 8));
+        forked.write(addr, new_data, 8, &mut solver);
+
+        assert_eq!(forked.shared_page_count(), 0);
+        assert_eq!(mem.read(addr, 8, &mut solver), data);
+        assert_eq!(forked.read(addr, 8, &mut solver), new_data);
+    }
+
+    #[test]
+    fn symbolic_accesses() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+        mem.write_bytes(0x1000, &[0x41, 0x42], &mut solver);
+        let addr = solver.new_var(Some("addr"), qf_abv::bv_sort(64));
+
+        // Either of the known bytes, or a fresh one if the address is neither.
+        let byte = mem.read(addr, 8, &mut solver);
+        let chain = to_smtlib(byte, &solver);
+        assert!(chain.starts_with("(ite (= addr "), "{}", chain);
+        assert_eq!(chain.matches("(ite").count(), 2);
+        assert!(chain.contains("mem_sym_"));
+        // Reading through the same address again gives the same value.
+        let again = mem.read(addr, 8, &mut solver);
+        assert_eq!(to_smtlib(again, &solver), chain);
+
+        // Every known byte is updated if the address is its own.
+        let data = solver.new_const(Const(0x43, 8));
+        mem.write(addr, data, 8, &mut solver);
+        let cells = mem.cells();
+        assert_eq!(cells.len(), 2);
+        for (_, cell) in cells {
+            assert!(to_smtlib(cell, &solver).starts_with("(ite (= addr "));
+        }
+
+        assert!(mem.models_symbolic());
+        mem.write_bytes(0x2000, &[0; SYMBOLIC_CELL_LIMIT], &mut solver);
+        assert_eq!(mem.cell_count(), SYMBOLIC_CELL_LIMIT + 2);
+        assert!(!mem.models_symbolic());
+        // Accesses still range over all the bytes when made directly, the two written through
+        // `addr` being chains of their own.
+        let byte = mem.read(addr, 8, &mut solver);
+        assert_eq!(to_smtlib(byte, &solver).matches("(ite").count(), SYMBOLIC_CELL_LIMIT + 4);
+
+        mem.set_symbolic_limit(2 * SYMBOLIC_CELL_LIMIT);
+        assert!(mem.models_symbolic());
+    }
+
+    #[test]
+    fn merge_differing_cells() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = PagedMemory::new(64, Endian::Little);
+
+        let addr = solver.new_const(Const(0x9000, 64));
+        let same = solver.new_const(Const(0x41, 8));
+        mem.write(addr, same, 8, &mut solver);
+
+        let mut other = mem.clone();
+        let addr_1 = solver.new_const(Const(0x9001, 64));
+        let data = solver.new_const(Const(0x42, 8));
+        mem.write(addr_1, data, 8, &mut solver);
+        let other_data = solver.new_const(Const(0x43, 8));
+        other.write(addr_1, other_data, 8, &mut solver);
+
+        let cond = solver.new_var(Some("cond"), qf_abv::bv_sort(1));
+        mem.merge(&other, cond, &mut solver);
+
+        assert_eq!(mem.read(addr, 8, &mut solver), same);
+        let merged = mem.read(addr_1, 8, &mut solver);
+        // Operands are returned last operand first.
+        assert_eq!(solver.get_operands(merged), vec![other_data, data, cond]);
+    }
+}
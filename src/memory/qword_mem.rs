@@ -1,40 +1,287 @@
 use petgraph::graph::NodeIndex;
 
+use std::collections::HashMap;
+
 use libsmt::backends::smtlib2::{SMTLib2, SMTProc};
 use libsmt::backends::backend::SMTBackend;
 use libsmt::logics::qf_abv;
 use libsmt::theories::{array_ex, bitvec, core};
 use r2api::structs::Endian;
 
+use memory::image::MemoryImage;
 use memory::memory::Memory;
+use utils::smtlib::{Exporter, Importer};
 
-// Not using address_width/endianness
+/// Memory modeled as a single SMT array from addresses to cells of `cell_width` bits.
+///
+/// Since every access is a `select`/`store` on the array, addresses can be arbitrary symbolic
+/// bitvectors and never need to be concretized. By default cells are qword sized, use
+/// `with_cell_width(.., 8)` for byte addressable memory.
+///
+/// Cells are aligned to their width. Accesses that are not aligned, or that straddle two cells,
+/// are composed from the neighbouring cells according to the endianness of the memory.
 #[derive(Clone, Debug)]
 pub struct QWordMemory {
     map: Option<NodeIndex>,
     address_width: usize,
     endian: Endian,
+    cell_width: usize,
 }
 
-impl Memory for QWordMemory {
-    type VarRef = NodeIndex;
-
-    fn new(address_width: usize, endian: Endian) -> QWordMemory {
-        QWordMemory { 
+impl QWordMemory {
+    pub fn with_cell_width(address_width: usize, endian: Endian, cell_width: usize) -> QWordMemory {
+        assert_eq!(cell_width % 8, 0, "Cell width is not divisible by 8");
+        assert!((cell_width / 8).is_power_of_two(), "Cell width is not a power of two");
+        QWordMemory {
             map: None,
             address_width: address_width,
             endian: endian,
+            cell_width: cell_width,
         }
     }
 
+    /// Width of the cells in bits.
+    pub fn cell_width(&self) -> usize {
+        self.cell_width
+    }
+
+    /// Read a `width` bit value at `addr`, which need not be aligned to the cell width.
+    pub fn read_mem(&mut self,
+                    addr: NodeIndex,
+                    width: usize,
+                    solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                    -> NodeIndex {
+        if width > self.cell_width {
+            assert_eq!(width % self.cell_width, 0, "Read size is not a multiple of the cell width");
+            let n = width / self.cell_width;
+            let mut chunks = Vec::new();
+            for i in 0..n {
+                let chunk_addr = self.cell_addr(addr, i, solver);
+                let chunk = self.read_mem(chunk_addr, self.cell_width, solver);
+                chunks.push((self.cell_shift(i, n), chunk));
+            }
+            // Concat places its first operand in the higher bits.
+            chunks.sort_by(|a, b| b.0.cmp(&a.0));
+            let mut iter = chunks.into_iter().map(|(_, chunk)| chunk);
+            let first = iter.next().unwrap();
+            return iter.fold(first, |acc, chunk| solver.assert(bitvec::OpCodes::Concat, &[acc, chunk]));
+        }
+
+        if self.cell_width == 8 {
+            // Byte cells, an access can never straddle two cells.
+            let mem = self.mem(solver);
+            let cell = solver.assert(array_ex::OpCodes::Select, &[mem, addr]);
+            return if width < self.cell_width {
+                solver.assert(bitvec::OpCodes::Extract((width - 1) as u64, 0), &[cell])
+            } else {
+                cell
+            };
+        }
+
+        let (base, next, shift) = self.align(addr, solver);
+        let pair = self.load_pair(base, next, solver);
+        let pair_width = 2 * self.cell_width;
+        match self.endian {
+            Endian::Little => {
+                let shifted = solver.assert(bitvec::OpCodes::BvLShr, &[pair, shift]);
+                solver.assert(bitvec::OpCodes::Extract((width - 1) as u64, 0), &[shifted])
+            }
+            Endian::Big => {
+                let shifted = solver.assert(bitvec::OpCodes::BvShl, &[pair, shift]);
+                solver.assert(bitvec::OpCodes::Extract((pair_width - 1) as u64,
+                                                       (pair_width - width) as u64),
+                              &[shifted])
+            }
+        }
+    }
+
+    /// Write the `width` bit value `data` at `addr`, which need not be aligned to the cell width.
+    /// Bytes of the cells that are not covered by the write are preserved.
+    pub fn write_mem(&mut self,
+                     addr: NodeIndex,
+                     data: NodeIndex,
+                     width: usize,
+                     solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        if width > self.cell_width {
+            assert_eq!(width % self.cell_width, 0, "Write size is not a multiple of the cell width");
+            let n = width / self.cell_width;
+            for i in 0..n {
+                let chunk_addr = self.cell_addr(addr, i, solver);
+                let low = self.cell_shift(i, n);
+                let chunk = solver.assert(bitvec::OpCodes::Extract((low + self.cell_width - 1) as u64,
+                                                                   low as u64),
+                                          &[data]);
+                self.write_mem(chunk_addr, chunk, self.cell_width, solver);
+            }
+            return;
+        }
+
+        if self.cell_width == 8 {
+            let mem = self.mem(solver);
+            let data = if width < self.cell_width {
+                // Preserve the higher bits of the cell that are not covered by the write.
+                let old = solver.assert(array_ex::OpCodes::Select, &[mem, addr]);
+                let high = solver.assert(bitvec::OpCodes::Extract((self.cell_width - 1) as u64,
+                                                                  width as u64),
+                                         &[old]);
+                solver.assert(bitvec::OpCodes::Concat, &[high, data])
+            } else {
+                data
+            };
+            self.map = Some(solver.assert(array_ex::OpCodes::Store, &[mem, addr, data]));
+            return;
+        }
+
+        let (base, next, shift) = self.align(addr, solver);
+        let pair = self.load_pair(base, next, solver);
+
+        let zero = solver.new_const(bitvec::OpCodes::Const(0, width));
+        let ones = solver.assert(bitvec::OpCodes::BvNot, &[zero]);
+        let mask = self.place(ones, width, shift, solver);
+        let mask = solver.assert(bitvec::OpCodes::BvNot, &[mask]);
+        let data = self.place(data, width, shift, solver);
+
+        let kept = solver.assert(bitvec::OpCodes::BvAnd, &[pair, mask]);
+        let pair = solver.assert(bitvec::OpCodes::BvOr, &[kept, data]);
+        self.store_pair(base, next, pair, solver);
+    }
+
+    fn mem(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        if self.map.is_none() {
+            self.init_memory(solver);
+        }
+        self.map.unwrap()
+    }
+
+    /// Address of the `i`th cell starting at `addr`.
+    fn cell_addr(&self, addr: NodeIndex, i: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        if i == 0 {
+            addr
+        } else {
+            let offset = (i * self.cell_width / 8) as u64;
+            let offset = solver.new_const(bitvec::OpCodes::Const(offset, self.address_width));
+            solver.assert(bitvec::OpCodes::BvAdd, &[addr, offset])
+        }
+    }
+
+    /// Bit offset of the lowest bit of the `i`th cell (out of `n`) in a value spanning `n` cells.
+    fn cell_shift(&self, i: usize, n: usize) -> usize {
+        match self.endian {
+            Endian::Little => i * self.cell_width,
+            Endian::Big => (n - i - 1) * self.cell_width,
+        }
+    }
+
+    /// Split `addr` into the address of the cell containing it, the address of the cell after
+    /// that and the offset of `addr` (in bits) into the cell.
+    ///
+    /// The offset is `2 * cell_width` bits wide, so that it can be used to shift the value of the
+    /// two cells returned by `load_pair`.
+    fn align(&self,
+             addr: NodeIndex,
+             solver: &mut SMTLib2<qf_abv::QF_ABV>)
+             -> (NodeIndex, NodeIndex, NodeIndex) {
+        let mask = (self.cell_width / 8 - 1) as u64;
+        let mask = solver.new_const(bitvec::OpCodes::Const(mask, self.address_width));
+        let offset = solver.assert(bitvec::OpCodes::BvAnd, &[addr, mask]);
+        let base = solver.assert(bitvec::OpCodes::BvSub, &[addr, offset]);
+        let next = self.cell_addr(base, 1, solver);
+
+        let pair_width = 2 * self.cell_width;
+        let offset = if self.address_width < pair_width {
+            solver.assert(bitvec::OpCodes::ZeroExtend((pair_width - self.address_width) as u64),
+                          &[offset])
+        } else if self.address_width > pair_width {
+            solver.assert(bitvec::OpCodes::Extract((pair_width - 1) as u64, 0), &[offset])
+        } else {
+            offset
+        };
+        let three = solver.new_const(bitvec::OpCodes::Const(3, pair_width));
+        let shift = solver.assert(bitvec::OpCodes::BvShl, &[offset, three]);
+        (base, next, shift)
+    }
+
+    /// Value of the cells at `base` and `next` as a single bitvector, laid out so that a byte at
+    /// offset `k` from `base` is found at the same position as in a `2 * cell_width` bit value
+    /// stored at `base`.
+    fn load_pair(&mut self,
+                 base: NodeIndex,
+                 next: NodeIndex,
+                 solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                 -> NodeIndex {
+        let mem = self.mem(solver);
+        let first = solver.assert(array_ex::OpCodes::Select, &[mem, base]);
+        let second = solver.assert(array_ex::OpCodes::Select, &[mem, next]);
+        match self.endian {
+            Endian::Little => solver.assert(bitvec::OpCodes::Concat, &[second, first]),
+            Endian::Big => solver.assert(bitvec::OpCodes::Concat, &[first, second]),
+        }
+    }
+
+    /// Inverse of `load_pair`.
+    fn store_pair(&mut self,
+                  base: NodeIndex,
+                  next: NodeIndex,
+                  pair: NodeIndex,
+                  solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        let width = self.cell_width as u64;
+        let high = solver.assert(bitvec::OpCodes::Extract(2 * width - 1, width), &[pair]);
+        let low = solver.assert(bitvec::OpCodes::Extract(width - 1, 0), &[pair]);
+        let (first, second) = match self.endian {
+            Endian::Little => (low, high),
+            Endian::Big => (high, low),
+        };
+        let mem = self.mem(solver);
+        let mem = solver.assert(array_ex::OpCodes::Store, &[mem, base, first]);
+        self.map = Some(solver.assert(array_ex::OpCodes::Store, &[mem, next, second]));
+    }
+
+    /// Move the `width` bit value `value` to the position of an access at bit offset `shift`
+    /// within the value returned by `load_pair`.
+    fn place(&self,
+             value: NodeIndex,
+             width: usize,
+             shift: NodeIndex,
+             solver: &mut SMTLib2<qf_abv::QF_ABV>)
+             -> NodeIndex {
+        let pair_width = 2 * self.cell_width;
+        let value = solver.assert(bitvec::OpCodes::ZeroExtend((pair_width - width) as u64), &[value]);
+        match self.endian {
+            Endian::Little => solver.assert(bitvec::OpCodes::BvShl, &[value, shift]),
+            Endian::Big => {
+                // Move the value to the top bits, then down to the offset of the access.
+                let top = solver.new_const(bitvec::OpCodes::Const((pair_width - width) as u64,
+                                                                  pair_width));
+                let value = solver.assert(bitvec::OpCodes::BvShl, &[value, top]);
+                solver.assert(bitvec::OpCodes::BvLShr, &[value, shift])
+            }
+        }
+    }
+}
+
+impl Memory for QWordMemory {
+    type VarRef = NodeIndex;
+
+    fn new(address_width: usize, endian: Endian) -> QWordMemory {
+        QWordMemory::with_cell_width(address_width, endian, 64)
+    }
+
+    fn address_width(&self) -> usize {
+        self.address_width
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
     fn init_memory(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
-        let bv_array = qf_abv::array_sort(qf_abv::bv_sort(64), 
-                                          qf_abv::bv_sort(64));
+        let bv_array = qf_abv::array_sort(qf_abv::bv_sort(self.address_width),
+                                          qf_abv::bv_sort(self.cell_width));
         let idx_ = solver.new_var(Some("mem"), bv_array);
         // Set memory to all 0s
-        let arr_const_ty = qf_abv::array_const(qf_abv::bv_sort(64),
-                                               qf_abv::bv_sort(64),
-                                               bitvec::OpCodes::Const(0, 64));
+        let arr_const_ty = qf_abv::array_const(qf_abv::bv_sort(self.address_width),
+                                               qf_abv::bv_sort(self.cell_width),
+                                               bitvec::OpCodes::Const(0, self.cell_width));
 
         let const_0 = solver.new_const(arr_const_ty);
         solver.assert(core::OpCodes::Cmp, &[idx_, const_0]);
@@ -46,16 +293,7 @@ impl Memory for QWordMemory {
                 read_size: usize,
                 solver: &mut SMTLib2<qf_abv::QF_ABV>)
                 -> NodeIndex {
-        if self.map.is_none() {
-            self.init_memory(solver);
-        }
-        let mem = self.map.unwrap();
-        let idx = solver.assert(array_ex::OpCodes::Select, &[mem, addr]);
-        if read_size < 64 {
-            solver.assert(bitvec::OpCodes::Extract((read_size - 1) as u64, 1), &[idx])
-        } else {
-            idx
-        }
+        self.read_mem(addr, read_size, solver)
     }
 
     fn write(&mut self,
@@ -63,13 +301,153 @@ impl Memory for QWordMemory {
                  data: NodeIndex,
                  write_size: usize,
                  solver: &mut SMTLib2<qf_abv::QF_ABV>) {
-        if self.map.is_none() {
-            self.init_memory(solver);
+        self.write_mem(addr, data, write_size, solver)
+    }
+
+    fn write_bytes(&mut self, addr: u64, bytes: &[u8], solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        for (i, byte) in bytes.iter().enumerate() {
+            let addr = solver.new_const(bitvec::OpCodes::Const(addr + i as u64, self.address_width));
+            let data = solver.new_const(bitvec::OpCodes::Const(*byte as u64, 8));
+            self.write(addr, data, 8, solver);
+        }
+    }
+
+    fn merge(&mut self, other: &QWordMemory, condition: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        let ours = self.mem(solver);
+        let theirs = other.clone().mem(solver);
+        if ours != theirs {
+            self.map = Some(solver.assert(core::OpCodes::ITE, &[condition, ours, theirs]));
+        }
+    }
+
+    fn to_image(&self, solver: &SMTLib2<qf_abv::QF_ABV>) -> MemoryImage {
+        let mut exporter = Exporter::new(solver);
+        let array = self.map.map(|map| exporter.add(map));
+        MemoryImage {
+            exprs: exporter.finish(),
+            array: array,
+            ..Default::default()
         }
+    }
 
-        let mem = self.map.unwrap();
-        let new_mem = solver.assert(array_ex::OpCodes::Store, &[mem, addr, data]);
-        self.map = Some(new_mem);
+    fn load_image(&mut self,
+                  image: &MemoryImage,
+                  vars: &HashMap<String, NodeIndex>,
+                  solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                  -> Result<HashMap<String, NodeIndex>, String> {
+        let array = match image.array {
+            Some(array) => array,
+            None => return Ok(HashMap::new()),
+        };
+        // The stores of the image are applied to the initial array of this memory.
+        let mem = self.mem(solver);
+        let mut importer = Importer::new(solver);
+        for (name, &var) in vars {
+            importer.bind(name, var);
+        }
+        importer.bind("mem", mem);
+        let nodes = importer.import(&image.exprs)?;
+        self.map = Some(*nodes.get(array).ok_or("Invalid array expression")?);
+        Ok(importer.vars())
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::backends::z3;
+    use libsmt::theories::bitvec::OpCodes::Const;
+
+    use utils::utils::to_smtlib;
+
+    /// Memory of `cell_width` bit cells with the qwords 0x1122334455667788 at 0x1000 and
+    /// 0x99aabbccddeeff00 at 0x1008.
+    fn setup(endian: Endian, cell_width: usize) -> (QWordMemory, SMTLib2<qf_abv::QF_ABV>) {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = QWordMemory::with_cell_width(64, endian, cell_width);
+        for &(addr, data) in &[(0x1000, 0x1122334455667788), (0x1008, 0x99aabbccddeeff00)] {
+            let addr = solver.new_const(Const(addr, 64));
+            let data = solver.new_const(Const(data, 64));
+            mem.write_mem(addr, data, 64, &mut solver);
+        }
+        (mem, solver)
+    }
+
+    /// Assert that the `width` bit value at `addr` is necessarily `expected`.
+    fn assert_value(mem: &mut QWordMemory,
+                    solver: &SMTLib2<qf_abv::QF_ABV>,
+                    addr: u64,
+                    width: usize,
+                    expected: u64) {
+        let mut solver = solver.clone();
+        let addr = solver.new_const(Const(addr, 64));
+        let value = mem.read_mem(addr, width, &mut solver);
+        let expected = solver.new_const(Const(expected, width));
+        let eq = solver.assert(core::OpCodes::Cmp, &[value, expected]);
+        solver.assert(core::OpCodes::Not, &[eq]);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(solver.solve(&mut z3).is_err());
+    }
+
+    #[test]
+    fn partial_reads_little_endian() {
+        for &cell_width in &[8, 64] {
+            let (mut mem, solver) = setup(Endian::Little, cell_width);
+            assert_value(&mut mem, &solver, 0x1000, 8, 0x88);
+            assert_value(&mut mem, &solver, 0x1001, 16, 0x6677);
+            assert_value(&mut mem, &solver, 0x1006, 32, 0xff001122);
+            assert_value(&mut mem, &solver, 0x1004, 64, 0xddeeff0011223344);
+        }
+    }
+
+    #[test]
+    fn partial_reads_big_endian() {
+        for &cell_width in &[8, 64] {
+            let (mut mem, solver) = setup(Endian::Big, cell_width);
+            assert_value(&mut mem, &solver, 0x1000, 8, 0x11);
+            assert_value(&mut mem, &solver, 0x1001, 16, 0x2233);
+            assert_value(&mut mem, &solver, 0x1006, 32, 0x778899aa);
+            assert_value(&mut mem, &solver, 0x1004, 64, 0x5566778899aabbcc);
+        }
+    }
+
+    #[test]
+    fn straddling_write_little_endian() {
+        for &cell_width in &[8, 64] {
+            let (mut mem, mut solver) = setup(Endian::Little, cell_width);
+            let addr = solver.new_const(Const(0x1007, 64));
+            let data = solver.new_const(Const(0xbeef, 16));
+            mem.write_mem(addr, data, 16, &mut solver);
+            assert_value(&mut mem, &solver, 0x1000, 64, 0xef22334455667788);
+            assert_value(&mut mem, &solver, 0x1008, 64, 0x99aabbccddeeffbe);
+        }
+    }
+
+    #[test]
+    fn straddling_write_big_endian() {
+        for &cell_width in &[8, 64] {
+            let (mut mem, mut solver) = setup(Endian::Big, cell_width);
+            let addr = solver.new_const(Const(0x1007, 64));
+            let data = solver.new_const(Const(0xbeef, 16));
+            mem.write_mem(addr, data, 16, &mut solver);
+            assert_value(&mut mem, &solver, 0x1000, 64, 0x11223344556677be);
+            assert_value(&mut mem, &solver, 0x1008, 64, 0xefaabbccddeeff00);
+        }
+    }
+
+    #[test]
+    fn byte_cells() {
+        assert_eq!(QWordMemory::new(64, Endian::Little).cell_width(), 64);
+
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = QWordMemory::with_cell_width(64, Endian::Little, 8);
+
+        // An unaligned byte store only touches its own cell.
+        let addr = solver.new_const(Const(0x1003, 64));
+        let data = solver.new_const(Const(0x41, 8));
+        mem.write_mem(addr, data, 8, &mut solver);
+        let value = mem.read_mem(addr, 8, &mut solver);
+        assert!(to_smtlib(value, &solver).starts_with("(select (store"));
+        assert_value(&mut mem, &solver, 0x1003, 8, 0x41);
+    }
+}
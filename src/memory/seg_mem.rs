@@ -17,7 +17,7 @@ use libsmt::backends::z3::Z3;
 use r2api::structs::Endian;
 
 use memory::memory::Memory;
-use utils::utils::simplify_constant;
+use utils::utils::{simplify_constant, try_simplify_constant};
 
 #[derive(Copy, Clone, Debug)]
 pub struct MemRange {
@@ -140,6 +140,14 @@ impl Memory for SegMem {
         }
     }
 
+    fn address_width(&self) -> usize {
+        self.addr_width
+    }
+
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
     fn init_memory(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
         self.segments = BTreeMap::new();
     }
@@ -266,6 +274,33 @@ impl Memory for SegMem {
         let idx = self.read(addr, write_size, solver);
         solver.assert(Cmp, &[idx, data]);
     }
+
+    fn write_bytes(&mut self, addr: u64, bytes: &[u8], solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        let end = addr + bytes.len() as u64;
+        if !self.segments.keys().any(|r| r.start < end && addr < r.end) {
+            // Memory that was never accessed takes the bytes as segments of constants, instead
+            // of asserting the value of every byte.
+            for (i, chunk) in bytes.chunks(8).enumerate() {
+                let start = addr + 8 * i as u64;
+                // Lower addresses are held in the lower bits of a segment.
+                let value = chunk.iter().rev().fold(0, |v, b| (v << 8) | *b as u64);
+                let node = solver.new_const(Const(value, chunk.len() * 8));
+                let range = MemRange::new(start, start + chunk.len() as u64);
+                self.segments.insert(range, MemBlock::new(range, Some(node)));
+            }
+            return;
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            let addr = solver.new_const(Const(addr + i as u64, self.addr_width));
+            let data = solver.new_const(Const(*byte as u64, 8));
+            self.write(addr, data, 8, solver);
+        }
+    }
+
+    /// Segments are keyed by concrete ranges.
+    fn models_symbolic(&self) -> bool {
+        false
+    }
 }
 
 mod test {
@@ -295,6 +330,21 @@ mod test {
 
         panic!("ZZ");
     }
+
+    #[test]
+    fn bulk_write() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut mem = SegMem::new(64, Endian::Little);
+        mem.write_bytes(0x9000, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], &mut solver);
+        assert_eq!(mem.segments.len(), 2);
+
+        let addr = solver.new_const(Const(0x9000, 64));
+        let value = mem.read(addr, 64, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x0807_0605_0403_0201));
+        let addr = solver.new_const(Const(0x9007, 64));
+        let value = mem.read(addr, 16, &mut solver);
+        assert_eq!(try_simplify_constant(value, &mut solver), Some(0x0908));
+    }
 }
 
 
@@ -0,0 +1,108 @@
+//! Detection of reads of uninitialized memory.
+//!
+//! The context tracks the ranges of memory that were written, either by the program or while
+//! setting up the initial state (`consts`, stack setup, loaded sections, ...). A read through a
+//! concrete address that touches a byte outside of these ranges is reported as an
+//! `UninitRead`. What happens then is decided by the `UninitPolicy` of the context.
+//!
+//! Bytes fetched on demand by `LazyMemory` are not known to the context, use
+//! `RuneContext::mark_initialized` for them.
+
+use std::collections::BTreeMap;
+
+/// How reads of uninitialized memory are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UninitPolicy {
+    /// Do not track uninitialized reads.
+    Ignore,
+    /// Report the read to the explorer and continue.
+    Warn,
+    /// Stop the engine with `EngineError::UninitializedRead`.
+    Error,
+}
+
+impl Default for UninitPolicy {
+    fn default() -> UninitPolicy {
+        UninitPolicy::Warn
+    }
+}
+
+/// A read that touched memory that was never written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UninitRead {
+    /// Address of the instruction that performed the read.
+    pub ip: u64,
+    /// First uninitialized byte that was read.
+    pub addr: u64,
+    /// Size of the read in bits.
+    pub size: usize,
+}
+
+/// Set of initialized addresses, stored as disjoint ranges [start, end) keyed by their start.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InitMap {
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl InitMap {
+    /// Mark [start, end) as initialized.
+    pub fn insert(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        let (mut start, mut end) = (start, end);
+        // Ranges that overlap or touch [start, end) are merged into it.
+        let merged: Vec<(u64, u64)> = self.ranges
+                                          .range(..end.saturating_add(1))
+                                          .rev()
+                                          .take_while(|&(_, &e)| e >= start)
+                                          .map(|(&s, &e)| (s, e))
+                                          .collect();
+        for (s, e) in merged {
+            self.ranges.remove(&s);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.ranges
+            .range(..addr.saturating_add(1))
+            .next_back()
+            .map_or(false, |(_, &end)| addr < end)
+    }
+
+    /// First address in [start, end) that is not initialized, if any.
+    pub fn first_uninit(&self, start: u64, end: u64) -> Option<u64> {
+        let mut addr = start;
+        while addr < end {
+            match self.ranges.range(..addr.saturating_add(1)).next_back() {
+                Some((_, &e)) if addr < e => addr = e,
+                _ => return Some(addr),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn init_map_ranges() {
+        let mut map = InitMap::default();
+        map.insert(0x10, 0x18);
+        map.insert(0x20, 0x28);
+        assert_eq!(map.first_uninit(0x10, 0x18), None);
+        assert_eq!(map.first_uninit(0x14, 0x24), Some(0x18));
+        assert!(!map.contains(0x1f));
+
+        // Fills the gap, the three ranges are merged.
+        map.insert(0x18, 0x20);
+        assert_eq!(map.ranges.len(), 1);
+        assert_eq!(map.first_uninit(0x10, 0x28), None);
+        assert_eq!(map.first_uninit(0x0, 0x28), Some(0x0));
+    }
+}
@@ -0,0 +1,90 @@
+//! Compute the difference between two register states.
+//!
+//! The register counterpart of `memory::diff`: lists the registers whose expressions differ
+//! between two contexts, for example before and after executing a path.
+
+use petgraph::graph::NodeIndex;
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use regstore::regstore::RegStore;
+use utils::utils::to_smtlib;
+
+/// A register that differs between two states.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegDiff {
+    pub name: String,
+    /// Expression held by the register in the first state, `None` if it was never set.
+    pub old: Option<String>,
+    /// Expression held by the register in the second state, `None` if it was never set.
+    pub new: Option<String>,
+}
+
+/// Compare registers `a` (whose expressions live in `a_solver`) against registers `b` (whose
+/// expressions live in `b_solver`). Both must have been built from the same register profile.
+pub fn diff<R>(a: &R,
+               a_solver: &SMTLib2<qf_abv::QF_ABV>,
+               b: &R,
+               b_solver: &SMTLib2<qf_abv::QF_ABV>)
+               -> Vec<RegDiff>
+    where R: RegStore<VarRef = NodeIndex>
+{
+    a.values()
+     .into_iter()
+     .zip(b.values())
+     .filter_map(|((name, old), (_, new))| {
+         let old = old.map(|v| to_smtlib(v, a_solver));
+         let new = new.map(|v| to_smtlib(v, b_solver));
+         if old != new {
+             Some(RegDiff {
+                 name: name,
+                 old: old,
+                 new: new,
+             })
+         } else {
+             None
+         }
+     })
+     .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::backends::backend::SMTBackend;
+    use libsmt::theories::bitvec::OpCodes::Const;
+    use r2api::structs::{LRegInfo, LRegProfile};
+    use regstore::regfile::RuneRegFile;
+
+    #[test]
+    fn diff_registers() {
+        let reg = |name: &str, offset| {
+            LRegProfile {
+                name: name.to_owned(),
+                offset: offset,
+                size: 64,
+                type_str: "gpr".to_owned(),
+                ..Default::default()
+            }
+        };
+        let mut reginfo = LRegInfo {
+            reg_info: vec![reg("rax", 0), reg("rbx", 64)],
+            ..Default::default()
+        };
+        let mut a = RuneRegFile::new(&mut reginfo);
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let one = solver.new_const(Const(1, 64));
+        a.write("rax", one, &mut solver);
+        a.write("rbx", one, &mut solver);
+
+        let mut b = a.clone();
+        let two = solver.new_const(Const(2, 64));
+        b.write("rbx", two, &mut solver);
+
+        let diffs = diff(&a, &solver, &b, &solver);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].name, "rbx");
+        assert!(diffs[0].old != diffs[0].new);
+    }
+}
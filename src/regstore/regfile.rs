@@ -1,19 +1,89 @@
+//! Register file backed by the register profile of r2.
+//!
+//! Registers that overlap in the profile share storage: every register that is contained in a
+//! larger register of the same arena is a slice of it, as given by the offsets in `LRegInfo`.
+//! Every type of register (`fpu`, `xmm`, `seg`, ...) has an arena of its own, except for the
+//! flags which live in the general purpose arena. Reading a sub-register extracts its bits and zero
+//! extends them to the width of the containing register. Writing to the low 32 bits of a 64 bit
+//! register zero extends the value into the whole register, as on x86-64 and AArch64; other
+//! partial writes preserve the remaining bits.
+//!
+//! Flags (`zf`, `cf`, ...) are the exception: every flag holds its own bits, which ESIL updates
+//! directly. Reading the flags register (`rflags`, `cpsr`, `pstate`, ...) composes it from the
+//! flags, and writing it updates every flag.
+//!
+//! Registers that the profile declares more than once under different names (`x29` and `fp` on
+//! AArch64, `r13` and `sp` on ARM) share their storage. Banked registers of ARM are only
+//! modeled if the profile lists them, in which case they are registers of their own.
+//!
+//! Registers that r2 does not know about, typically system registers such as `tpidr_el0`, can
+//! be added with `add_reg`.
+//!
+//! Registers outside of the general purpose arena (FPU, SIMD, segment registers, ...) are
+//! modeled as opaque bitvectors of their full width. Since programs commonly move data through
+//! them without initializing them first, they read as zero until they are written.
+
 use petgraph::graph::NodeIndex;
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
-use regstore::regstore::{RegStore, RegStoreAPI, RegEntry};
+use memory::memory::Access;
+use regstore::regstore::{RegStore, RegStoreAPI, RegEntry, RegFault, RegHook};
 
-use r2api::structs::LRegInfo;
+use r2api::structs::{LRegInfo, LRegProfile};
 use libsmt::backends::smtlib2::{SMTLib2, SMTProc};
 use libsmt::logics::qf_abv;
 use libsmt::theories::{array_ex, bitvec, core};
 use libsmt::backends::backend::SMTBackend;
 
+/// Name of the T bit of `cpsr` in the ARM profile of r2.
+const THUMB_FLAG: &'static str = "tf";
+
+/// A flag that has its own storage, and the bits of the register it is a part of.
 #[derive(Clone, Debug, Default)]
-pub struct RuneRegFile {
-    current_regs: Vec<Option<NodeIndex>>,
+struct Flag {
+    idx: usize,
+    container: usize,
+    start_bit: usize,
+    size: usize,
+}
+
+/// Layout of the registers, shared between clones of a register file.
+#[derive(Clone, Debug, Default)]
+struct Layout {
     regfile: HashMap<String, RegEntry>,
     alias_info: HashMap<String, String>,
+    /// Width of every storage, by idx.
+    widths: Vec<usize>,
+    /// Whether the storage reads as zero until it is written, by idx.
+    zero_init: Vec<bool>,
+    /// Name of the register that owns every storage, by idx.
+    names: Vec<String>,
+    flags: Vec<Flag>,
+}
+
+/// Register hooks, by the idx of the storage they watch.
+#[derive(Clone, Default)]
+struct Hooks(Vec<(usize, RegHook<NodeIndex>)>);
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hooks {{ storages: {:?} }}", self.0.iter().map(|h| h.0).collect::<Vec<_>>())
+    }
+}
+
+/// Cloning a `RuneRegFile` only copies the values of the registers, which makes snapshots cheap.
+#[derive(Clone, Debug, Default)]
+pub struct RuneRegFile {
+    current_regs: Vec<Option<NodeIndex>>,
+    /// Taint bit of every storage, by idx.
+    taint: Vec<bool>,
+    /// Whether storages that were never written read as fresh symbols.
+    lazy: bool,
+    layout: Rc<Layout>,
+    hooks: Hooks,
+    fault: Option<RegFault>,
 }
 
 impl RegStore for RuneRegFile {
@@ -22,24 +92,51 @@ impl RegStore for RuneRegFile {
     fn new(reginfo: &mut LRegInfo) -> RuneRegFile {
         let mut cur_regs = Vec::new();
         let mut regfile = HashMap::new();
-        let mut seen_offsets = Vec::new();
+        // (idx, arena, offset, size) of the registers that have a storage of their own.
+        let mut wholes: Vec<(usize, &str, usize, usize)> = Vec::new();
+        let mut widths = Vec::new();
+        let mut zero_init = Vec::new();
+        let mut names = Vec::new();
+        let mut flags = Vec::new();
         let mut alias_info = HashMap::new();
-        reginfo.reg_info.sort_by(|x, y| (y.offset + y.size).cmp(&(x.offset + x.size)));
-        for register in &reginfo.reg_info {
-            let (idx, s_bit, e_bit, is_whole) = if !seen_offsets.contains(&register.offset) &&
-                                                   (register.type_str == "gpr" || register.type_str == "flg") {
+        // Larger registers first, so that containing registers are seen before the registers
+        // they contain.
+        reginfo.reg_info.sort_by(|x, y| y.size.cmp(&x.size).then(x.offset.cmp(&y.offset)));
+        for register in reginfo.reg_info.iter().filter(|r| r.size > 0) {
+            let end = register.offset + register.size;
+            let arena = arena(register);
+            let container = wholes.iter()
+                                  .find(|&&(_, a, offset, size)| {
+                                      a == arena && offset <= register.offset && end <= offset + size
+                                  })
+                                  .cloned();
+            let (idx, s_bit, e_bit, is_whole) = if let Some((c_idx, _, offset, size)) = container {
+                if offset == register.offset && size == register.size {
+                    // Another name of the same register (`fp` and `x29`, `sp` and `r13`, ...).
+                    (c_idx, 0, register.size - 1, true)
+                } else if is_flag(register) {
+                    cur_regs.push(None);
+                    widths.push(register.size);
+                    zero_init.push(false);
+                    names.push(register.name.clone());
+                    flags.push(Flag {
+                        idx: cur_regs.len() - 1,
+                        container: c_idx,
+                        start_bit: register.offset - offset,
+                        size: register.size,
+                    });
+                    (cur_regs.len() - 1, 0, register.size - 1, true)
+                } else {
+                    let s_bit = register.offset - offset;
+                    (c_idx, s_bit, s_bit + register.size - 1, false)
+                }
+            } else {
                 cur_regs.push(None);
-                seen_offsets.push(register.offset);
+                widths.push(register.size);
+                zero_init.push(arena != "gpr");
+                names.push(register.name.clone());
+                wholes.push((cur_regs.len() - 1, arena, register.offset, register.size));
                 (cur_regs.len() - 1, 0, register.size - 1, true)
-            } else {
-                let mut found = 0;
-                for (i, offset) in seen_offsets.iter().enumerate() {
-                    if register.offset == *offset {
-                        found = i;
-                        break;
-                    }
-                }
-                (found, 0, register.size - 1, false)
             };
 
             regfile.insert(register.name.clone(),
@@ -55,49 +152,576 @@ impl RegStore for RuneRegFile {
         }
 
         RuneRegFile {
+            taint: vec![false; cur_regs.len()],
+            lazy: false,
             current_regs: cur_regs,
-            regfile: regfile,
-            alias_info: alias_info,
+            layout: Rc::new(Layout {
+                regfile: regfile,
+                alias_info: alias_info,
+                widths: widths,
+                zero_init: zero_init,
+                names: names,
+                flags: flags,
+            }),
+            hooks: Hooks::default(),
+            fault: None,
         }
     }
 
     fn read(&mut self, reg_name: &str, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
-        let rentry = &self.regfile.get(reg_name).expect("Unknown Register");
-        let idx = self.current_regs[rentry.idx].expect("Unset register - Undefined Behavior. \
-                                                        Consider setting an initial value before use!");
-        if rentry.is_whole {
-            idx
-        } else {
-            solver.assert(bitvec::OpCodes::Extract((rentry.end_bit) as u64, 0), &[idx])
-        }
+        let value = self.read_value(reg_name, solver);
+        self.run_hooks(reg_name, Access::Read, value);
+        value
     }
 
     // TODO: This is not totally correct as the sizes of registers may not match.
-    fn write(&mut self, dest: &str, source: NodeIndex) -> Option<NodeIndex> {
-        let rentry = &self.regfile[dest];
-        let e_old = self.current_regs[rentry.idx];
-        self.current_regs[rentry.idx] = Some(source);
-        e_old
+    fn write(&mut self, dest: &str, source: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> Option<NodeIndex> {
+        if !self.run_hooks(dest, Access::Write, source) {
+            return None;
+        }
+        self.write_value(dest, source, solver)
+    }
+
+    fn add_hook(&mut self, reg: &str, hook: RegHook<NodeIndex>) {
+        let idx = self.layout.regfile[reg].idx;
+        self.hooks.0.push((idx, hook));
+    }
+
+    fn take_fault(&mut self) -> Option<RegFault> {
+        self.fault.take()
     }
 
     fn get_reg_entry(&self, r_string: &str) -> RegEntry {
-        self.regfile[r_string].clone()
+        self.layout.regfile[r_string].clone()
+    }
+
+    fn storage_width(&self, reg: &str) -> usize {
+        self.layout.widths[self.layout.regfile[reg].idx]
+    }
+
+    fn reg_of_alias(&self, alias: &str) -> Option<String> {
+        self.layout.alias_info.get(alias).cloned()
+    }
+
+    fn flag(&self, name: &str) -> Option<NodeIndex> {
+        self.layout.regfile
+            .get(name)
+            .and_then(|entry| self.flag_at(entry.idx))
+            .and_then(|flag| self.current_regs[flag.idx])
+    }
+
+    fn mode_flag(&self) -> Option<&'static str> {
+        if self.layout.regfile.contains_key(THUMB_FLAG) {
+            Some(THUMB_FLAG)
+        } else {
+            None
+        }
+    }
+
+    fn add_reg(&mut self, name: &str, width: usize) -> bool {
+        if self.has_reg(name) {
+            return false;
+        }
+        let idx = self.current_regs.len();
+        self.current_regs.push(None);
+        self.taint.push(false);
+        // Clones made before the register was added keep their layout.
+        let layout = Rc::make_mut(&mut self.layout);
+        layout.widths.push(width);
+        layout.zero_init.push(false);
+        layout.names.push(name.to_owned());
+        layout.regfile.insert(name.to_owned(),
+                              RegEntry::new(name.to_owned(), idx, 0, width - 1, true, None));
+        true
+    }
+
+    fn has_reg(&self, reg: &str) -> bool {
+        self.layout.regfile.contains_key(reg)
+    }
+
+    fn values(&self) -> Vec<(String, Option<NodeIndex>)> {
+        self.layout.names.iter().cloned().zip(self.current_regs.iter().cloned()).collect()
+    }
+
+    fn set_lazy_symbols(&mut self, lazy: bool) {
+        self.lazy = lazy;
+    }
+
+    fn is_tainted(&self, reg: &str) -> bool {
+        let idx = self.layout.regfile[reg].idx;
+        // The flags register is tainted by its flags.
+        self.taint[idx] || self.layout.flags.iter().any(|f| f.container == idx && self.taint[f.idx])
+    }
+
+    fn set_taint(&mut self, reg: &str, tainted: bool) {
+        let rentry = &self.layout.regfile[reg];
+        let size = rentry.end_bit - rentry.start_bit + 1;
+        let overwrites = rentry.is_whole || (rentry.start_bit == 0 && size == 32 && self.layout.widths[rentry.idx] == 64);
+        if overwrites {
+            self.taint[rentry.idx] = tainted;
+            for flag in self.layout.flags.iter().filter(|f| f.container == rentry.idx) {
+                self.taint[flag.idx] = tainted;
+            }
+        } else {
+            // The rest of the register keeps its taint.
+            self.taint[rentry.idx] |= tainted;
+        }
     }
 
     fn get_reg_ref(&self, r_string: &str) -> Option<NodeIndex> {
-        let rentry = &self.regfile[r_string];
+        let rentry = &self.layout.regfile[r_string];
         self.current_regs[rentry.idx]
     }
 
     fn set_reg(&mut self, r_string: &str, cval: NodeIndex) {
-        let idx = self.regfile[r_string].idx;
+        let idx = self.layout.regfile[r_string].idx;
         self.current_regs[idx] = Some(cval);
     }
  
 }
 
+impl RuneRegFile {
+    fn read_value(&mut self, reg_name: &str, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        let rentry = self.layout.regfile.get(reg_name).expect("Unknown Register").clone();
+        self.init_storage(rentry.idx, solver);
+        let (value, width) = if let Some(flag) = self.flag_at(rentry.idx).cloned() {
+            // Flags are read as wide as the register they are a part of.
+            (self.current_regs[flag.idx], self.layout.widths[flag.container])
+        } else {
+            (self.whole(rentry.idx, solver), self.layout.widths[rentry.idx])
+        };
+        let value = value.expect("Unset register - Undefined Behavior. \
+                                  Consider setting an initial value before use!");
+        if rentry.is_whole && self.layout.widths[rentry.idx] == width {
+            return value;
+        }
+        let slice = if rentry.is_whole {
+            value
+        } else {
+            solver.assert(bitvec::OpCodes::Extract(rentry.end_bit as u64, rentry.start_bit as u64),
+                          &[value])
+        };
+        let size = rentry.end_bit - rentry.start_bit + 1;
+        if width > size {
+            solver.assert(bitvec::OpCodes::ZeroExtend((width - size) as u64), &[slice])
+        } else {
+            slice
+        }
+    }
+
+    fn write_value(&mut self, dest: &str, source: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> Option<NodeIndex> {
+        let rentry = self.layout.regfile[dest].clone();
+        let size = rentry.end_bit - rentry.start_bit + 1;
+        if !rentry.is_whole {
+            // The bits of the register that are not written are kept.
+            self.init_storage(rentry.idx, solver);
+        }
+        if let Some(flag) = self.flag_at(rentry.idx).cloned() {
+            let e_old = self.current_regs[flag.idx].map(|_| self.read_value(dest, solver));
+            let value = solver.assert(bitvec::OpCodes::Extract(size as u64 - 1, 0), &[source]);
+            self.current_regs[flag.idx] = Some(value);
+            return e_old;
+        }
+
+        let old = self.whole(rentry.idx, solver);
+        let e_old = old.map(|_| self.read_value(dest, solver));
+        let width = self.layout.widths[rentry.idx];
+        let new = if rentry.is_whole {
+            source
+        } else {
+            // Values computed by rune are as wide as an address, keep the bits of the register.
+            let value = solver.assert(bitvec::OpCodes::Extract(size as u64 - 1, 0), &[source]);
+            if rentry.start_bit == 0 && size == 32 && width == 64 {
+                solver.assert(bitvec::OpCodes::ZeroExtend(32), &[value])
+            } else {
+                let old = match old {
+                    Some(old) => old,
+                    None => solver.new_const(bitvec::OpCodes::Const(0, width)),
+                };
+                splice(solver, old, width, rentry.start_bit, size, value)
+            }
+        };
+        self.current_regs[rentry.idx] = Some(new);
+
+        // The flags that are part of the register take its new bits.
+        let flags: Vec<Flag> = self.layout.flags.iter().filter(|f| f.container == rentry.idx).cloned().collect();
+        for flag in flags {
+            let bits = bitvec::OpCodes::Extract((flag.start_bit + flag.size - 1) as u64, flag.start_bit as u64);
+            self.current_regs[flag.idx] = Some(solver.assert(bits, &[new]));
+        }
+        e_old
+    }
+
+    /// Run the hooks of the storage of `reg`, recording a fault if one rejects the access.
+    /// Returns whether the access may proceed.
+    fn run_hooks(&mut self, reg: &str, access: Access, value: NodeIndex) -> bool {
+        let idx = self.layout.regfile[reg].idx;
+        let allowed = self.hooks.0.iter().filter(|h| h.0 == idx).all(|h| (h.1)(reg, access, &value));
+        if !allowed && self.fault.is_none() {
+            self.fault = Some(RegFault {
+                reg: reg.to_owned(),
+                access: access,
+            });
+        }
+        allowed
+    }
+
+    /// Give the storage `idx` its initial value if it was never written.
+    fn init_storage(&mut self, idx: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        if self.current_regs[idx].is_some() {
+            return;
+        }
+        let width = self.layout.widths[idx];
+        if self.layout.zero_init[idx] {
+            self.current_regs[idx] = Some(solver.new_const(bitvec::OpCodes::Const(0, width)));
+        } else if self.lazy {
+            let sym = solver.new_var(Some(&self.layout.names[idx]), qf_abv::bv_sort(width));
+            self.current_regs[idx] = Some(sym);
+        }
+    }
+
+    fn flag_at(&self, idx: usize) -> Option<&Flag> {
+        self.layout.flags.iter().find(|f| f.idx == idx)
+    }
+
+    /// Value of the storage `idx`, with the bits of the flags that are part of it.
+    fn whole(&self, idx: usize, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> Option<NodeIndex> {
+        let flags: Vec<(&Flag, NodeIndex)> = self.layout.flags
+                                                 .iter()
+                                                 .filter(|f| f.container == idx)
+                                                 .filter_map(|f| self.current_regs[f.idx].map(|v| (f, v)))
+                                                 .collect();
+        if flags.is_empty() {
+            return self.current_regs[idx];
+        }
+        let width = self.layout.widths[idx];
+        let mut value = match self.current_regs[idx] {
+            Some(value) => value,
+            None => solver.new_const(bitvec::OpCodes::Const(0, width)),
+        };
+        for (flag, bits) in flags {
+            value = splice(solver, value, width, flag.start_bit, flag.size, bits);
+        }
+        Some(value)
+    }
+}
+
+/// `old` (of `width` bits) with the `size` bits starting at `start_bit` replaced by `value`.
+fn splice(solver: &mut SMTLib2<qf_abv::QF_ABV>,
+          old: NodeIndex,
+          width: usize,
+          start_bit: usize,
+          size: usize,
+          value: NodeIndex)
+          -> NodeIndex {
+    let end_bit = start_bit + size - 1;
+    let mut new = value;
+    if start_bit > 0 {
+        let low = solver.assert(bitvec::OpCodes::Extract(start_bit as u64 - 1, 0), &[old]);
+        new = solver.assert(bitvec::OpCodes::Concat, &[new, low]);
+    }
+    if end_bit + 1 < width {
+        let high = solver.assert(bitvec::OpCodes::Extract(width as u64 - 1, end_bit as u64 + 1), &[old]);
+        new = solver.assert(bitvec::OpCodes::Concat, &[high, new]);
+    }
+    new
+}
+
+/// Flags are typed `flg` in the profiles of some architectures (x86), while others (ARM)
+/// declare them as single bit general purpose registers.
+fn is_flag(register: &LRegProfile) -> bool {
+    register.type_str == "flg" || (register.type_str == "gpr" && register.size == 1)
+}
+
+/// Arena of `register`. Flags live in the same arena as the general purpose registers.
+fn arena(register: &LRegProfile) -> &str {
+    match register.type_str.as_str() {
+        "flg" => "gpr",
+        t => t,
+    }
+}
+
 impl RegStoreAPI for RuneRegFile {
     fn get_regs(&self) -> Vec<Option<NodeIndex>> {
         self.current_regs.clone()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::logics::qf_abv::QF_ABV_Fn::BVOps;
+    use r2api::structs::LRegProfile;
+
+    fn reg(name: &str, offset: usize, size: usize, type_str: &str) -> LRegProfile {
+        LRegProfile {
+            name: name.to_owned(),
+            offset: offset,
+            size: size,
+            type_str: type_str.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn x86_64_regs() -> RuneRegFile {
+        let mut reginfo = LRegInfo {
+            reg_info: vec![reg("al", 640, 8, "gpr"),
+                           reg("ah", 648, 8, "gpr"),
+                           reg("ax", 640, 16, "gpr"),
+                           reg("eax", 640, 32, "gpr"),
+                           reg("rax", 640, 64, "gpr"),
+                           reg("rflags", 1152, 64, "gpr"),
+                           reg("zf", 1158, 1, "flg")],
+            ..Default::default()
+        };
+        RuneRegFile::new(&mut reginfo)
+    }
+
+    #[test]
+    fn sub_register_layout() {
+        let regs = x86_64_regs();
+        let rax = regs.get_reg_entry("rax");
+        let ah = regs.get_reg_entry("ah");
+        assert!(rax.is_whole && !ah.is_whole);
+        assert_eq!((ah.idx, ah.start_bit, ah.end_bit), (rax.idx, 8, 15));
+        assert_eq!(regs.storage_width("eax"), 64);
+
+        // Flags have a storage of their own.
+        let zf = regs.get_reg_entry("zf");
+        assert!(zf.is_whole);
+        assert!(zf.idx != regs.get_reg_entry("rflags").idx);
+        assert_eq!(regs.storage_width("zf"), 1);
+    }
+
+    #[test]
+    fn sub_register_writes() {
+        let mut regs = x86_64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let rax = solver.new_const(bitvec::OpCodes::Const(0x1122334455667788, 64));
+        regs.write("rax", rax, &mut solver);
+
+        // eax zero extends into rax.
+        let v = solver.new_const(bitvec::OpCodes::Const(0x41, 64));
+        assert!(regs.write("eax", v, &mut solver).is_some());
+        let new = regs.get_reg_ref("rax").unwrap();
+        match *solver.get_node_info(new) {
+            BVOps(bitvec::OpCodes::ZeroExtend(32)) => {}
+            ref op => panic!("Unexpected write {:?}", op),
+        }
+
+        // ah keeps the rest of rax around it.
+        regs.write("ah", v, &mut solver);
+        let new = regs.get_reg_ref("rax").unwrap();
+        match *solver.get_node_info(new) {
+            BVOps(bitvec::OpCodes::Concat) => {}
+            ref op => panic!("Unexpected write {:?}", op),
+        }
+    }
+
+    #[test]
+    fn flag_bits() {
+        let mut regs = x86_64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let rflags = solver.new_const(bitvec::OpCodes::Const(0, 64));
+        regs.write("rflags", rflags, &mut solver);
+
+        let one = solver.new_const(bitvec::OpCodes::Const(1, 64));
+        regs.write("zf", one, &mut solver);
+        let zf = regs.flag("zf").unwrap();
+        let extract = solver.get_operands(zf);
+        assert_eq!(extract, vec![one]);
+
+        // rflags is rebuilt from the flags.
+        let composed = regs.read("rflags", &mut solver);
+        match *solver.get_node_info(composed) {
+            BVOps(bitvec::OpCodes::Concat) => {}
+            ref op => panic!("Unexpected rflags {:?}", op),
+        }
+        assert!(regs.flag("rax").is_none());
+    }
+
+    #[test]
+    fn simd_registers() {
+        let mut reginfo = LRegInfo {
+            reg_info: vec![reg("rax", 640, 64, "gpr"),
+                           reg("xmm0", 640, 128, "fpu"),
+                           reg("xmm0h", 640, 64, "fpu"),
+                           reg("xmm0l", 704, 64, "fpu")],
+            ..Default::default()
+        };
+        let mut regs = RuneRegFile::new(&mut reginfo);
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+
+        // Arenas are distinct, even though the offsets overlap.
+        assert!(regs.get_reg_entry("rax").is_whole);
+        assert!(regs.get_reg_entry("xmm0").is_whole);
+        let xmm0l = regs.get_reg_entry("xmm0l");
+        assert_eq!((xmm0l.idx, xmm0l.start_bit, xmm0l.end_bit),
+                   (regs.get_reg_entry("xmm0").idx, 64, 127));
+        assert_eq!(regs.storage_width("xmm0h"), 128);
+
+        // Unset SIMD registers read as zero.
+        let xmm0 = regs.read("xmm0", &mut solver);
+        match *solver.get_node_info(xmm0) {
+            BVOps(bitvec::OpCodes::Const(0, 128)) => {}
+            ref op => panic!("Unexpected xmm0 {:?}", op),
+        }
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut regs = x86_64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let one = solver.new_const(bitvec::OpCodes::Const(1, 64));
+        let two = solver.new_const(bitvec::OpCodes::Const(2, 64));
+
+        regs.write("rax", one, &mut solver);
+        let snapshot = regs.snapshot();
+        assert!(Rc::ptr_eq(&snapshot.layout, &regs.layout));
+
+        regs.write("rax", two, &mut solver);
+        regs.restore(snapshot);
+        assert_eq!(regs.read("rax", &mut solver), one);
+    }
+
+    /// Subset of the AArch64 profile of r2.
+    fn aarch64_regs() -> RuneRegFile {
+        let mut reginfo = LRegInfo {
+            reg_info: vec![reg("x0", 0, 64, "gpr"),
+                           reg("w0", 0, 32, "gpr"),
+                           reg("x29", 1856, 64, "gpr"),
+                           reg("fp", 1856, 64, "gpr"),
+                           reg("sp", 1984, 64, "gpr"),
+                           reg("wsp", 1984, 32, "gpr"),
+                           reg("pc", 2048, 64, "gpr"),
+                           reg("pstate", 2240, 64, "gpr"),
+                           reg("vf", 2268, 1, "gpr"),
+                           reg("cf", 2269, 1, "gpr"),
+                           reg("zf", 2270, 1, "gpr"),
+                           reg("nf", 2271, 1, "gpr")],
+            ..Default::default()
+        };
+        RuneRegFile::new(&mut reginfo)
+    }
+
+    /// Subset of the ARM profile of r2.
+    fn arm_regs() -> RuneRegFile {
+        let mut reginfo = LRegInfo {
+            reg_info: vec![reg("r0", 0, 32, "gpr"),
+                           reg("r13", 416, 32, "gpr"),
+                           reg("sp", 416, 32, "gpr"),
+                           reg("lr", 448, 32, "gpr"),
+                           reg("pc", 480, 32, "gpr"),
+                           reg("cpsr", 512, 32, "gpr"),
+                           reg("tf", 517, 1, "gpr"),
+                           reg("vf", 540, 1, "gpr"),
+                           reg("cf", 541, 1, "gpr"),
+                           reg("zf", 542, 1, "gpr"),
+                           reg("nf", 543, 1, "gpr")],
+            ..Default::default()
+        };
+        RuneRegFile::new(&mut reginfo)
+    }
+
+    #[test]
+    fn aarch64_layout() {
+        let mut regs = aarch64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+
+        assert_eq!(regs.get_reg_entry("fp").idx, regs.get_reg_entry("x29").idx);
+        assert!(regs.get_reg_entry("fp").is_whole);
+        let w0 = regs.get_reg_entry("w0");
+        assert_eq!((w0.idx, w0.start_bit, w0.end_bit), (regs.get_reg_entry("x0").idx, 0, 31));
+
+        // Writes to wX zero extend into xX.
+        let v = solver.new_const(bitvec::OpCodes::Const(0x41, 64));
+        regs.write("w0", v, &mut solver);
+        match *solver.get_node_info(regs.get_reg_ref("x0").unwrap()) {
+            BVOps(bitvec::OpCodes::ZeroExtend(32)) => {}
+            ref op => panic!("Unexpected write {:?}", op),
+        }
+
+        let zf = regs.get_reg_entry("zf");
+        assert!(zf.is_whole);
+        assert_eq!(regs.storage_width("zf"), 1);
+        regs.write("zf", v, &mut solver);
+        assert!(regs.flag("zf").is_some());
+    }
+
+    #[test]
+    fn arm_layout() {
+        let mut regs = arm_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+
+        assert_eq!(regs.get_reg_entry("sp").idx, regs.get_reg_entry("r13").idx);
+        assert_eq!(regs.storage_width("r0"), 32);
+
+        // Writing cpsr sets the flags, including the thumb bit.
+        let cpsr = solver.new_const(bitvec::OpCodes::Const(0x6000_0030, 32));
+        regs.write("cpsr", cpsr, &mut solver);
+        let tf = regs.flag("tf").unwrap();
+        match *solver.get_node_info(tf) {
+            BVOps(bitvec::OpCodes::Extract(5, 5)) => {}
+            ref op => panic!("Unexpected thumb bit {:?}", op),
+        }
+        assert!(regs.flag("zf").is_some());
+    }
+
+    #[test]
+    fn lazy_symbols() {
+        let mut regs = x86_64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        regs.set_lazy_symbols(true);
+
+        let rax = regs.read("rax", &mut solver);
+        match *solver.get_node_info(rax) {
+            BVOps(bitvec::OpCodes::FreeVar(ref name)) => assert_eq!(name, "rax"),
+            ref op => panic!("Unexpected rax {:?}", op),
+        }
+        // The symbol is created once.
+        assert_eq!(regs.read("rax", &mut solver), rax);
+        let zf = regs.read("zf", &mut solver);
+        assert_eq!(solver.get_operands(zf).len(), 1);
+    }
+
+    #[test]
+    fn system_registers() {
+        let mut regs = aarch64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        assert!(!regs.has_reg("tpidr_el0"));
+        let snapshot = regs.snapshot();
+
+        assert!(regs.add_reg("tpidr_el0", 64));
+        assert!(!regs.add_reg("tpidr_el0", 64));
+        assert_eq!(regs.storage_width("tpidr_el0"), 64);
+        let tls = solver.new_const(bitvec::OpCodes::Const(0x7000_0000, 64));
+        regs.write("tpidr_el0", tls, &mut solver);
+        assert_eq!(regs.read("tpidr_el0", &mut solver), tls);
+        assert!(!snapshot.has_reg("tpidr_el0"));
+    }
+
+    #[test]
+    fn register_hooks() {
+        use std::cell::RefCell;
+
+        let mut regs = x86_64_regs();
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let one = solver.new_const(bitvec::OpCodes::Const(1, 64));
+        let two = solver.new_const(bitvec::OpCodes::Const(2, 64));
+        regs.write("rax", one, &mut solver);
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let accesses = log.clone();
+        regs.add_hook("rax", Rc::new(move |reg: &str, access: Access, _: &NodeIndex| {
+            accesses.borrow_mut().push((reg.to_owned(), access));
+            access != Access::Write
+        }));
+
+        regs.read("eax", &mut solver);
+        assert!(regs.take_fault().is_none());
+        regs.write("rax", two, &mut solver);
+        assert_eq!(regs.take_fault(), Some(RegFault { reg: "rax".to_owned(), access: Access::Write }));
+        assert_eq!(regs.get_reg_ref("rax"), Some(one));
+        assert_eq!(*log.borrow(), vec![("eax".to_owned(), Access::Read), ("rax".to_owned(), Access::Write)]);
+    }
+}
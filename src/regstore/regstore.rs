@@ -5,6 +5,21 @@ use r2api::structs::LRegInfo;
 
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::rc::Rc;
+
+use memory::memory::Access;
+
+/// Callback run on every access to a register, with the name of the register as accessed, the
+/// kind of access and the value read or written. Returning `false` faults the access; a write
+/// that faults is not performed.
+pub type RegHook<V> = Rc<Fn(&str, Access, &V) -> bool>;
+
+/// Raised when a register hook rejects an access.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegFault {
+    pub reg: String,
+    pub access: Access,
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct RegEntry {
@@ -43,13 +58,81 @@ pub trait RegStore: Clone + Debug {
 
     fn get_reg_entry(&self, &str) -> RegEntry;
 
+    /// Width in bits of the value that `set_reg` expects for `reg`. This is the width of the
+    /// whole register that `reg` is a part of.
+    fn storage_width(&self, reg: &str) -> usize {
+        let entry = self.get_reg_entry(reg);
+        entry.end_bit - entry.start_bit + 1
+    }
+
+    /// Name of the register that has the role `alias` in the register profile.
+    fn reg_of_alias(&self, _alias: &str) -> Option<String> {
+        None
+    }
+
+    /// Bits of the flag `name` (`zf`, `cf`, ...), if it is a flag that has been set.
+    fn flag(&self, _name: &str) -> Option<Self::VarRef> {
+        None
+    }
+
+    /// Flag that selects the instruction set (the T bit on ARM), if the architecture has several.
+    fn mode_flag(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// If `lazy` is set, registers that were never written read as a fresh symbol named after
+    /// the register instead of being undefined.
+    fn set_lazy_symbols(&mut self, _lazy: bool) { }
+
+    /// Whether the value of `reg` is tainted. Registers are not tracked by default.
+    fn is_tainted(&self, _reg: &str) -> bool {
+        false
+    }
+
+    /// Record whether the value just written to `reg` is tainted.
+    fn set_taint(&mut self, _reg: &str, _tainted: bool) { }
+
+    /// Add a register of `width` bits that is not part of the register profile, such as a system
+    /// register. Returns `false` if the register already exists or registers cannot be added.
+    fn add_reg(&mut self, _name: &str, _width: usize) -> bool {
+        false
+    }
+
+    fn has_reg(&self, reg: &str) -> bool;
+
+    /// Run `hook` on every read and write of `reg`, including accesses through registers that
+    /// share its storage (`esp` for `rsp`, ...).
+    fn add_hook(&mut self, _reg: &str, _hook: RegHook<Self::VarRef>) { }
+
+    /// Returns the fault raised by a hook since the last call, if any.
+    fn take_fault(&mut self) -> Option<RegFault> {
+        None
+    }
+
+    /// Names and values of the registers that have a storage of their own, in a fixed order.
+    fn values(&self) -> Vec<(String, Option<Self::VarRef>)> {
+        Vec::new()
+    }
+
+    /// Take a snapshot of the current values of the registers, which can later be passed to
+    /// `restore`.
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Roll back the registers to a previously taken snapshot.
+    fn restore(&mut self, snapshot: Self) {
+        *self = snapshot;
+    }
+
     fn get_reg_ref(&self, &str) -> Option<Self::VarRef>;
 
     fn set_reg(&mut self, &str, Self::VarRef);
 
     fn read(&mut self, &str, &mut SMTLib2<qf_abv::QF_ABV>) -> Self::VarRef;
 
-    fn write(&mut self, &str, Self::VarRef) -> Option<Self::VarRef>;
+    /// Write `source` to `reg`, returning the previous value of `reg` if it was set.
+    fn write(&mut self, &str, Self::VarRef, &mut SMTLib2<qf_abv::QF_ABV>) -> Option<Self::VarRef>;
 }
 
 pub trait RegStoreAPI: RegStore {
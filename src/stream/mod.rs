@@ -15,12 +15,43 @@ use r2pipe::r2::R2;
 use r2api::structs::LOpInfo;
 use r2api::api_trait::R2Api;
 
+use memory::loader;
+
+/// Longest instruction of the supported architectures, in bytes.
+pub const MAX_INSN_SIZE: u64 = 16;
+
+/// Instruction set to decode instructions with, for architectures that have more than one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IsaMode {
+    /// The only (or default) instruction set of the architecture, ARM on ARM targets.
+    Normal,
+    Thumb,
+}
+
+impl Default for IsaMode {
+    fn default() -> IsaMode {
+        IsaMode::Normal
+    }
+}
+
 pub trait InstructionStream {
     type Output: Debug + Clone;
     type Index: Debug + Clone;
 
     fn new() -> Self;
     fn at(&mut self, Self::Index) -> Option<Self::Output>;
+
+    /// Decode the instruction at `addr` with the bytes in `patches` in place of the bytes that
+    /// were loaded. Streams that can not decode arbitrary bytes ignore the patches.
+    fn at_patched(&mut self,
+                  addr: Self::Index,
+                  _patches: &[(Self::Index, u8)])
+                  -> Option<Self::Output> {
+        self.at(addr)
+    }
+
+    /// Decode the instructions that follow with `mode`. Only called when the mode changes.
+    fn set_mode(&mut self, _mode: IsaMode) { }
 }
 
 
@@ -36,6 +67,33 @@ impl InstructionStream for R2 {
         let addr_ = format!("{}", addr);
         Some(self.insts(Some(1), Some(&addr_)).unwrap()[0].clone())
     }
+
+    fn at_patched(&mut self, addr: u64, patches: &[(u64, u8)]) -> Option<Self::Output> {
+        let mut bytes = match loader::read_bytes(self, addr, MAX_INSN_SIZE) {
+            Ok(bytes) => bytes,
+            Err(_) => return None,
+        };
+        bytes.resize(MAX_INSN_SIZE as usize, 0);
+        for &(a, b) in patches {
+            if a >= addr && a < addr + MAX_INSN_SIZE {
+                bytes[(a - addr) as usize] = b;
+            }
+        }
+        // `@x:` makes r2 decode the given bytes as if they were at `addr`.
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let addr_ = format!("{} @x:{}", addr, hex);
+        Some(self.insts(Some(1), Some(&addr_)).unwrap()[0].clone())
+    }
+
+    fn set_mode(&mut self, mode: IsaMode) {
+        // Modes are only switched on ARM targets.
+        let bits = match mode {
+            IsaMode::Normal => 32,
+            IsaMode::Thumb => 16,
+        };
+        self.send(&format!("e asm.bits={}", bits));
+        self.recv();
+    }
 }
 
 // InstructionStream that reads and provides instructions from files.
@@ -0,0 +1,328 @@
+//! A small language for preconditions and postconditions.
+//!
+//! Conditions are separated by `;`. Each one either makes a register or memory symbolic, or
+//! states a condition over registers, memory and constants:
+//!
+//! ```text
+//! rdi == 0x10; [rsp+8] sym 64; rsi < 256 && [rdi]:8 != 0
+//! ```
+//!
+//! `[addr]` reads memory of the address width, `[addr]:N` reads `N` bits. Expressions use the
+//! operators `+ - * & | ^ << >>` with the precedence of C, and comparisons are unsigned
+//! (`== != < <= > >=`), joined by `&&` and `||`. Widths of `sym` are in bits, and default to
+//! the width of the register or the address width.
+//!
+//! Preconditions are applied to the initial state with `assume`, and postconditions are
+//! checked with `check` once a path reaches the target address. `parse_expr` parses a single
+//! expression, such as a watch expression (see `RuneContext::add_watch_expr`).
+
+use petgraph::graph::NodeIndex;
+
+use context::context::{Context, ContextAPI};
+use context::expr::{self, Cond, Expr};
+use context::rune_ctx::RuneContext;
+use memory::memory::Memory;
+use regstore::regstore::{RegStore, RegStoreAPI};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// Make a register (`Expr::Reg`) or memory (`Expr::Load`) symbolic, with the width given.
+    Symbolic(Expr, Option<usize>),
+    Assert(Cond),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Num(u64),
+    Op(&'static str),
+}
+
+/// Operators, longest first so that `<=` is not read as `<`.
+const OPERATORS: &'static [&'static str] = &["<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "<", ">",
+                                             "+", "-", "*", "&", "|", "^", "(", ")", "[", "]", ":"];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_left();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_owned()));
+            len
+        } else if c.is_digit(10) {
+            let len = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+            let num = &rest[..len];
+            let value = if num.starts_with("0x") {
+                u64::from_str_radix(&num[2..], 16)
+            } else {
+                num.parse::<u64>()
+            };
+            tokens.push(Token::Num(value.map_err(|_| format!("Invalid number {}", num))?));
+            len
+        } else {
+            let op = OPERATORS.iter()
+                              .find(|op| rest.starts_with(*op))
+                              .ok_or_else(|| format!("Unexpected character {}", c))?;
+            tokens.push(Token::Op(op));
+            op.len()
+        };
+        rest = rest[len..].trim_left();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    address_width: usize,
+}
+
+/// Binary operators of expressions, from the lowest precedence to the highest.
+const LEVELS: &'static [&'static [&'static str]] = &[&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*"]];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the operator `op` if it is next.
+    fn eat(&mut self, op: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Op(o)) if o == op => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(format!("Expected {}", op))
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(n),
+            _ => Err("Expected a number".to_owned()),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let lhs = self.expr(0)?;
+        if let Some(&Token::Ident(ref kw)) = self.peek() {
+            if kw != "sym" {
+                return Err(format!("Unexpected {}", kw));
+            }
+        } else {
+            return Ok(Condition::Assert(self.or_cond(Some(lhs))?));
+        }
+        self.pos += 1;
+        match lhs {
+            Expr::Reg(_) | Expr::Load(_, _) => {}
+            _ => return Err("Only registers and memory can be symbolic".to_owned()),
+        }
+        let width = if self.peek().is_some() { Some(self.number()? as usize) } else { None };
+        Ok(Condition::Symbolic(lhs, width))
+    }
+
+    /// `lhs` is the left operand of the first comparison, if it was already parsed.
+    fn or_cond(&mut self, lhs: Option<Expr>) -> Result<Cond, String> {
+        let mut cond = self.and_cond(lhs)?;
+        while self.eat("||") {
+            cond = cond.or(self.and_cond(None)?);
+        }
+        Ok(cond)
+    }
+
+    fn and_cond(&mut self, lhs: Option<Expr>) -> Result<Cond, String> {
+        let mut cond = self.comparison(lhs)?;
+        while self.eat("&&") {
+            cond = cond.and(self.comparison(None)?);
+        }
+        Ok(cond)
+    }
+
+    fn comparison(&mut self, lhs: Option<Expr>) -> Result<Cond, String> {
+        let lhs = match lhs {
+            Some(lhs) => lhs,
+            None => self.expr(0)?,
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => return Err("Expected a comparison".to_owned()),
+        };
+        let rhs = self.expr(0)?;
+        Ok(match op {
+            "==" => lhs.eq(rhs),
+            "!=" => lhs.ne(rhs),
+            "<" => lhs.ult(rhs),
+            "<=" => lhs.ule(rhs),
+            ">" => lhs.ugt(rhs),
+            ">=" => lhs.uge(rhs),
+            _ => return Err(format!("Invalid comparison {}", op)),
+        })
+    }
+
+    /// An expression of the operators of precedence `level` and higher.
+    fn expr(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.primary();
+        }
+        let mut lhs = self.expr(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(&Token::Op(op)) if LEVELS[level].contains(&op) => op,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.expr(level + 1)?;
+            lhs = match op {
+                "|" => lhs.or(rhs),
+                "^" => lhs.xor(rhs),
+                "&" => lhs.and(rhs),
+                "<<" => lhs.shl(rhs),
+                ">>" => lhs.lshr(rhs),
+                "+" => lhs.add(rhs),
+                "-" => lhs.sub(rhs),
+                _ => lhs.mul(rhs),
+            };
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(expr::constant(n)),
+            Some(Token::Ident(name)) => Ok(expr::reg(name)),
+            Some(Token::Op("(")) => {
+                let e = self.expr(0)?;
+                self.expect(")")?;
+                Ok(e)
+            }
+            Some(Token::Op("[")) => {
+                let addr = self.expr(0)?;
+                self.expect("]")?;
+                let width = if self.eat(":") { self.number()? as usize } else { self.address_width };
+                Ok(expr::load(addr, width))
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of condition".to_owned()),
+        }
+    }
+}
+
+/// Parse conditions separated by `;`, for a target with addresses of `address_width` bits.
+pub fn parse(s: &str, address_width: usize) -> Result<Vec<Condition>, String> {
+    let mut conditions = Vec::new();
+    for part in s.split(';').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let mut parser = Parser {
+            tokens: tokenize(part)?,
+            pos: 0,
+            address_width: address_width,
+        };
+        let condition = parser.condition().map_err(|e| format!("{} in `{}`", e, part))?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!("Unexpected {:?} in `{}`", parser.tokens[parser.pos], part));
+        }
+        conditions.push(condition);
+    }
+    Ok(conditions)
+}
+
+/// Parse a single expression, for a target with addresses of `address_width` bits.
+pub fn parse_expr(s: &str, address_width: usize) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+        address_width: address_width,
+    };
+    let e = parser.expr(0).map_err(|e| format!("{} in `{}`", e, s))?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("Unexpected {:?} in `{}`", parser.tokens[parser.pos], s));
+    }
+    Ok(e)
+}
+
+/// Apply preconditions to the state of `ctx`: make registers and memory symbolic, and assume
+/// the conditions. Addresses of symbolic memory have to be concrete.
+pub fn assume<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>, conditions: &[Condition]) -> Result<(), String>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex> + RegStoreAPI
+{
+    for condition in conditions {
+        match *condition {
+            Condition::Symbolic(Expr::Reg(ref reg), width) => {
+                let (_, reg_width) = ctx.eval_expr(&Expr::Reg(reg.clone()))?;
+                ctx.set_reg_as_sym(reg, width.unwrap_or(reg_width), None);
+            }
+            Condition::Symbolic(Expr::Load(ref addr, _), width) => {
+                let (addr, _) = ctx.eval_expr(addr)?;
+                let addr = ctx.as_constant(addr).ok_or_else(|| "Symbolic address".to_owned())?;
+                let width = width.unwrap_or_else(|| ctx.address_width());
+                ctx.set_mem_as_sym(addr, width, None);
+            }
+            Condition::Symbolic(ref e, _) => return Err(format!("{:?} cannot be symbolic", e)),
+            Condition::Assert(ref cond) => ctx.assume(cond)?,
+        }
+    }
+    Ok(())
+}
+
+/// Check postconditions on the state of `ctx`, returning the conditions that some input
+/// following the path violates.
+pub fn check<Mem, Reg>(ctx: &mut RuneContext<Mem, Reg>, conditions: &[Condition]) -> Result<Vec<Cond>, String>
+    where Mem: Memory<VarRef = NodeIndex>,
+          Reg: RegStore<VarRef = NodeIndex> + RegStoreAPI
+{
+    let mut violated = Vec::new();
+    for condition in conditions {
+        match *condition {
+            Condition::Assert(ref cond) => {
+                if !ctx.holds(cond)? {
+                    violated.push(cond.clone());
+                }
+            }
+            Condition::Symbolic(_, _) => return Err("Postconditions cannot make values symbolic".to_owned()),
+        }
+    }
+    Ok(violated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use context::expr::{constant, load, reg};
+
+    #[test]
+    fn parse_conditions() {
+        let conditions = parse("rdi == 0x10; [rsp+8] sym 64; rsi < 256 && [rdi]:8 != 0; rax sym", 64).unwrap();
+        assert_eq!(conditions,
+                   vec![Condition::Assert(reg("rdi").eq(constant(0x10))),
+                        Condition::Symbolic(load(reg("rsp").add(constant(8)), 64), Some(64)),
+                        Condition::Assert(reg("rsi").ult(constant(256)).and(load(reg("rdi"), 8).ne(constant(0)))),
+                        Condition::Symbolic(reg("rax"), None)]);
+
+        // Precedence of C: a + b * c << 1 is (a + (b * c)) << 1.
+        let conditions = parse("rax + rbx * 2 << 1 == rcx", 32).unwrap();
+        let lhs = reg("rax").add(reg("rbx").mul(constant(2))).shl(constant(1));
+        assert_eq!(conditions, vec![Condition::Assert(lhs.eq(reg("rcx")))]);
+
+        assert!(parse("rax ==", 64).is_err());
+        assert!(parse("rax == 1 1", 64).is_err());
+        assert!(parse("1 sym 8", 64).is_err());
+        assert!(parse("rax # 2", 64).is_err());
+
+        assert_eq!(parse_expr("[rbp-0x10]:32", 64), Ok(load(reg("rbp").sub(constant(0x10)), 32)));
+        assert!(parse_expr("rax == 1", 64).is_err());
+    }
+}
@@ -0,0 +1,625 @@
+//! Reading and writing expressions in SMT-LIB2 syntax.
+//!
+//! Expressions built in a solver refer to its nodes and cannot outlive it. `ExprTable` is a
+//! solver independent form of a set of expressions: every node is stored as its SMT-LIB2
+//! function (or constant, or variable name) and the indices of its operands, which keeps shared
+//! subexpressions shared. An `ExprTable` can be serialized and later imported into another
+//! solver with `Importer`.
+
+use petgraph::graph::NodeIndex;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use libsmt::backends::backend::SMTBackend;
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+use libsmt::theories::{array_ex, bitvec, core};
+
+/// An s-expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SExpr {
+    Atom(String),
+    List(Vec<SExpr>),
+}
+
+impl fmt::Display for SExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SExpr::Atom(ref s) => write!(f, "{}", s),
+            SExpr::List(ref l) => {
+                let l = l.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "({})", l.join(" "))
+            }
+        }
+    }
+}
+
+impl SExpr {
+    /// Parse all the s-expressions in `s`.
+    pub fn parse(s: &str) -> Result<Vec<SExpr>, String> {
+        let mut stack: Vec<Vec<SExpr>> = vec![Vec::new()];
+        let mut atom = String::new();
+        for c in s.chars() {
+            if c == '(' || c == ')' || c.is_whitespace() {
+                if !atom.is_empty() {
+                    stack.last_mut().unwrap().push(SExpr::Atom(atom.clone()));
+                    atom.clear();
+                }
+            } else {
+                atom.push(c);
+            }
+
+            if c == '(' {
+                stack.push(Vec::new());
+            } else if c == ')' {
+                if stack.len() < 2 {
+                    return Err("Unbalanced ')'".to_owned());
+                }
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(SExpr::List(list));
+            }
+        }
+
+        if !atom.is_empty() {
+            stack.last_mut().unwrap().push(SExpr::Atom(atom));
+        }
+        if stack.len() != 1 {
+            return Err("Unbalanced '('".to_owned());
+        }
+        Ok(stack.pop().unwrap())
+    }
+
+    /// Parse `s`, which must contain exactly one s-expression.
+    pub fn parse_one(s: &str) -> Result<SExpr, String> {
+        let mut exprs = SExpr::parse(s)?;
+        if exprs.len() != 1 {
+            return Err(format!("Expected a single expression in {:?}", s));
+        }
+        Ok(exprs.pop().unwrap())
+    }
+
+    fn atom(&self) -> Option<&str> {
+        match *self {
+            SExpr::Atom(ref s) => Some(s),
+            SExpr::List(_) => None,
+        }
+    }
+
+    fn num(&self) -> Result<u64, String> {
+        self.atom()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| format!("Expected a number, found {}", self))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ExprNode {
+    /// SMT-LIB2 function, constant or variable name.
+    pub op: String,
+    /// Indices of the operands in the table.
+    pub args: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ExprTable {
+    /// Free variables referenced by the nodes, as (name, sort) pairs in SMT-LIB2 syntax.
+    pub vars: Vec<(String, String)>,
+    /// Nodes of the expressions. A node only refers to nodes that come before it.
+    pub nodes: Vec<ExprNode>,
+}
+
+impl ExprTable {
+    /// Add the expression `expr` to the table and return its index. Subexpressions are not
+    /// shared.
+    pub fn add_sexpr(&mut self, expr: &SExpr) -> usize {
+        let node = match *expr {
+            SExpr::List(ref l) if !l.is_empty() && !is_constant(expr) => {
+                ExprNode {
+                    op: l[0].to_string(),
+                    args: l[1..].iter().map(|arg| self.add_sexpr(arg)).collect(),
+                }
+            }
+            _ => ExprNode { op: expr.to_string(), args: Vec::new() },
+        };
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Append the nodes of `other` to this table, returning the index of its first node in this
+    /// table.
+    pub fn append(&mut self, other: ExprTable) -> usize {
+        let offset = self.nodes.len();
+        for var in other.vars {
+            if !self.vars.contains(&var) {
+                self.vars.push(var);
+            }
+        }
+        self.nodes.extend(other.nodes.into_iter().map(|node| {
+            ExprNode {
+                op: node.op,
+                args: node.args.iter().map(|arg| arg + offset).collect(),
+            }
+        }));
+        offset
+    }
+}
+
+/// Whether `expr` is a constant written as a list, `(_ bv42 8)` or `((as const ...) ...)`.
+fn is_constant(expr: &SExpr) -> bool {
+    if let SExpr::List(ref l) = *expr {
+        match l.get(0) {
+            Some(&SExpr::Atom(ref head)) => head == "_",
+            Some(&SExpr::List(ref head)) => head.get(0).and_then(|e| e.atom()) == Some("as"),
+            None => false,
+        }
+    } else {
+        false
+    }
+}
+
+/// The assertions of `solver`, as a table and the index of every assertion in it.
+pub fn assertions(solver: &SMTLib2<qf_abv::QF_ABV>) -> Result<(ExprTable, Vec<usize>), String> {
+    parse_assertions(&solver.generate_asserts())
+}
+
+/// The assertions of `solver` in SMT-LIB2 syntax, sorted and without duplicates, so that they do
+/// not depend on the order in which they were made.
+pub fn canonical_assertions(solver: &SMTLib2<qf_abv::QF_ABV>) -> BTreeSet<String> {
+    let mut assertions = BTreeSet::new();
+    for expr in SExpr::parse(&solver.generate_asserts()).unwrap_or_default() {
+        if let SExpr::List(ref l) = expr {
+            if l.len() == 2 && l[0].atom() == Some("assert") {
+                assertions.insert(l[1].to_string());
+            }
+        }
+    }
+    assertions
+}
+
+/// Parse the declarations and assertions of an SMT-LIB2 script.
+pub fn parse_assertions(script: &str) -> Result<(ExprTable, Vec<usize>), String> {
+    let mut table = ExprTable::default();
+    let mut roots = Vec::new();
+    for expr in SExpr::parse(script)? {
+        if let SExpr::List(ref l) = expr {
+            match (l.get(0).and_then(|e| e.atom()), l.len()) {
+                (Some("declare-fun"), 4) => table.vars.push((l[1].to_string(), l[3].to_string())),
+                (Some("declare-const"), 3) => table.vars.push((l[1].to_string(), l[2].to_string())),
+                (Some("assert"), 2) => roots.push(table.add_sexpr(&l[1])),
+                _ => {}
+            }
+        }
+    }
+    Ok((table, roots))
+}
+
+/// Sorts of the variables declared in `solver`, by name.
+pub fn declarations(solver: &SMTLib2<qf_abv::QF_ABV>) -> HashMap<String, String> {
+    let mut decls = HashMap::new();
+    for expr in SExpr::parse(&solver.generate_asserts()).unwrap_or_default() {
+        if let SExpr::List(ref l) = expr {
+            let (name, sort) = match (l.get(0).and_then(|e| e.atom()), l.len()) {
+                (Some("declare-fun"), 4) => (&l[1], &l[3]),
+                (Some("declare-const"), 3) => (&l[1], &l[2]),
+                _ => continue,
+            };
+            if let Some(name) = name.atom() {
+                decls.insert(name.to_owned(), sort.to_string());
+            }
+        }
+    }
+    decls
+}
+
+/// Variables the expressions rooted at `roots` refer to, by name.
+pub fn free_vars(solver: &SMTLib2<qf_abv::QF_ABV>, roots: &[NodeIndex]) -> HashMap<String, NodeIndex> {
+    let mut vars = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut stack = roots.to_vec();
+    while let Some(ni) = stack.pop() {
+        if !seen.insert(ni) {
+            continue;
+        }
+        match *solver.get_node_info(ni) {
+            qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::FreeVar(ref name)) |
+            qf_abv::QF_ABV_Fn::CoreOps(core::OpCodes::FreeVar(ref name)) |
+            qf_abv::QF_ABV_Fn::ArrayOps(array_ex::OpCodes::FreeVar(ref name)) => {
+                vars.insert(name.clone(), ni);
+            }
+            _ => stack.extend(solver.get_operands(ni)),
+        }
+    }
+    vars
+}
+
+/// Nodes of the expressions rooted at `roots`, counting shared subexpressions once.
+pub fn expr_size(solver: &SMTLib2<qf_abv::QF_ABV>, roots: &[NodeIndex]) -> usize {
+    let mut seen = HashSet::new();
+    let mut stack = roots.to_vec();
+    while let Some(ni) = stack.pop() {
+        if seen.insert(ni) {
+            stack.extend(solver.get_operands(ni));
+        }
+    }
+    seen.len()
+}
+
+/// Whether the node `ni` of `solver` is a boolean expression, as opposed to a bitvector or an
+/// array. Variables and constants are not considered.
+fn is_bool_expr(solver: &SMTLib2<qf_abv::QF_ABV>, ni: NodeIndex) -> bool {
+    match *solver.get_node_info(ni) {
+        qf_abv::QF_ABV_Fn::CoreOps(core::OpCodes::ITE) => {
+            solver.get_operands(ni).first().map_or(false, |&e| is_bool_expr(solver, e))
+        }
+        qf_abv::QF_ABV_Fn::CoreOps(core::OpCodes::True) |
+        qf_abv::QF_ABV_Fn::CoreOps(core::OpCodes::False) |
+        qf_abv::QF_ABV_Fn::CoreOps(core::OpCodes::FreeVar(_)) => false,
+        qf_abv::QF_ABV_Fn::CoreOps(_) => true,
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvULt) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvULe) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvUGt) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvUGe) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvSLt) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvSLe) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvSGt) |
+        qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::BvSGe) => true,
+        _ => false,
+    }
+}
+
+/// Boolean expressions among the nodes created after `start` and before `end` that no other of
+/// these nodes refers to. Unless a later node refers to them, they are asserted when solving.
+pub fn assertions_between(solver: &SMTLib2<qf_abv::QF_ABV>, start: NodeIndex, end: NodeIndex) -> Vec<NodeIndex> {
+    let nodes: Vec<NodeIndex> = (start.index() + 1..end.index()).map(NodeIndex::new).collect();
+    let operands: HashSet<NodeIndex> = nodes.iter().flat_map(|&ni| solver.get_operands(ni)).collect();
+    nodes.into_iter().filter(|ni| !operands.contains(ni) && is_bool_expr(solver, *ni)).collect()
+}
+
+/// Builds an `ExprTable` from expressions of a solver.
+pub struct Exporter<'a> {
+    solver: &'a SMTLib2<qf_abv::QF_ABV>,
+    decls: HashMap<String, String>,
+    index: HashMap<NodeIndex, usize>,
+    table: ExprTable,
+}
+
+impl<'a> Exporter<'a> {
+    pub fn new(solver: &'a SMTLib2<qf_abv::QF_ABV>) -> Exporter<'a> {
+        Exporter {
+            solver: solver,
+            decls: declarations(solver),
+            index: HashMap::new(),
+            table: Default::default(),
+        }
+    }
+
+    /// Add the expression rooted at `root` to the table and return its index.
+    pub fn add(&mut self, root: NodeIndex) -> usize {
+        // Iterative post-order traversal, expressions can be very deep.
+        let mut stack = vec![(root, false)];
+        while let Some((ni, expanded)) = stack.pop() {
+            if self.index.contains_key(&ni) {
+                continue;
+            }
+            // Operands are returned last operand first.
+            let mut operands = self.solver.get_operands(ni);
+            operands.reverse();
+            if expanded {
+                let op = self.solver.get_node_info(ni).to_string();
+                if operands.is_empty() {
+                    self.declare(&op);
+                }
+                let node = ExprNode {
+                    op: op,
+                    args: operands.iter().map(|o| self.index[o]).collect(),
+                };
+                self.index.insert(ni, self.table.nodes.len());
+                self.table.nodes.push(node);
+            } else {
+                stack.push((ni, true));
+                for o in operands.into_iter().rev() {
+                    stack.push((o, false));
+                }
+            }
+        }
+        self.index[&root]
+    }
+
+    fn declare(&mut self, name: &str) {
+        if let Some(sort) = self.decls.get(name) {
+            if !self.table.vars.iter().any(|&(ref n, _)| n == name) {
+                self.table.vars.push((name.to_owned(), sort.clone()));
+            }
+        }
+    }
+
+    pub fn finish(self) -> ExprTable {
+        self.table
+    }
+}
+
+/// Rebuilds the expressions of an `ExprTable` in a solver.
+pub struct Importer<'a> {
+    solver: &'a mut SMTLib2<qf_abv::QF_ABV>,
+    vars: HashMap<String, NodeIndex>,
+}
+
+impl<'a> Importer<'a> {
+    pub fn new(solver: &'a mut SMTLib2<qf_abv::QF_ABV>) -> Importer<'a> {
+        Importer {
+            solver: solver,
+            vars: HashMap::new(),
+        }
+    }
+
+    /// Use `ni` for the variable `name` instead of declaring a new variable.
+    pub fn bind(&mut self, name: &str, ni: NodeIndex) {
+        self.vars.insert(name.to_owned(), ni);
+    }
+
+    /// Variables declared or bound so far, by name.
+    pub fn vars(self) -> HashMap<String, NodeIndex> {
+        self.vars
+    }
+
+    fn bind_declared(&mut self, table: &ExprTable) {
+        let names: HashSet<&str> = table.vars.iter().map(|v| v.0.as_str()).collect();
+        // Nodes are numbered in order of creation, the new node bounds the indices.
+        let end = self.solver.new_const(core::OpCodes::True);
+        for ni in (0..end.index()).map(NodeIndex::new) {
+            let name = match *self.solver.get_node_info(ni) {
+                qf_abv::QF_ABV_Fn::BVOps(bitvec::OpCodes::FreeVar(ref name)) |
+                qf_abv::QF_ABV_Fn::CoreOps(core::OpCodes::FreeVar(ref name)) |
+                qf_abv::QF_ABV_Fn::ArrayOps(array_ex::OpCodes::FreeVar(ref name)) => name.clone(),
+                _ => continue,
+            };
+            if names.contains(name.as_str()) && !self.vars.contains_key(&name) {
+                self.vars.insert(name, ni);
+            }
+        }
+    }
+
+    /// Import all nodes of `table`, returning their indices in the solver. Variables that are
+    /// not bound but already declared in the solver are bound to the existing variable, declaring
+    /// them again would make two different variables of the same name.
+    pub fn import(&mut self, table: &ExprTable) -> Result<Vec<NodeIndex>, String> {
+        if table.vars.iter().any(|v| !self.vars.contains_key(&v.0)) {
+            self.bind_declared(table);
+        }
+        for &(ref name, ref sort) in &table.vars {
+            if !self.vars.contains_key(name) {
+                let sort = parse_sort(&SExpr::parse_one(sort)?)?;
+                let var = self.solver.new_var(Some(name), sort);
+                self.vars.insert(name.clone(), var);
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(table.nodes.len());
+        for node in &table.nodes {
+            let mut args = Vec::new();
+            for &i in &node.args {
+                args.push(*nodes.get(i).ok_or_else(|| format!("Invalid operand {}", i))?);
+            }
+            let ni = if let Some(var) = self.vars.get(&node.op) {
+                *var
+            } else {
+                let op = SExpr::parse_one(&node.op)?;
+                if args.is_empty() {
+                    self.solver.new_const(parse_const(&op)?)
+                } else {
+                    self.solver.assert(parse_fn(&op)?, &args)
+                }
+            };
+            nodes.push(ni);
+        }
+        Ok(nodes)
+    }
+}
+
+fn parse_sort(sort: &SExpr) -> Result<qf_abv::QF_ABV_Sorts, String> {
+    if let SExpr::List(ref l) = *sort {
+        match (l.get(0).and_then(|e| e.atom()), l.get(1).and_then(|e| e.atom()), l.len()) {
+            (Some("_"), Some("BitVec"), 3) => return Ok(qf_abv::bv_sort(l[2].num()? as usize)),
+            (Some("Array"), _, 3) => return Ok(qf_abv::array_sort(parse_sort(&l[1])?, parse_sort(&l[2])?)),
+            _ => {}
+        }
+    }
+    Err(format!("Unsupported sort {}", sort))
+}
+
+fn parse_bv_const(c: &SExpr) -> Result<bitvec::OpCodes, String> {
+    match *c {
+        SExpr::Atom(ref s) if s.starts_with("#x") || s.starts_with("#b") => {
+            let (radix, bits) = if s.starts_with("#x") { (16, 4) } else { (2, 1) };
+            let digits = &s[2..];
+            u64::from_str_radix(digits, radix)
+                .map(|v| bitvec::OpCodes::Const(v, digits.len() * bits))
+                .map_err(|e| format!("Invalid constant {}: {}", s, e))
+        }
+        SExpr::List(ref l) if l.len() == 3 && l[0].atom() == Some("_") => {
+            match l[1].atom() {
+                Some(bv) if bv.starts_with("bv") => {
+                    let value = bv[2..].parse().map_err(|e| format!("Invalid constant {}: {}", c, e))?;
+                    Ok(bitvec::OpCodes::Const(value, l[2].num()? as usize))
+                }
+                _ => Err(format!("Invalid constant {}", c)),
+            }
+        }
+        _ => Err(format!("Invalid constant {}", c)),
+    }
+}
+
+fn parse_const(c: &SExpr) -> Result<qf_abv::QF_ABV_Fn, String> {
+    match c.atom() {
+        Some("true") => return Ok(core::OpCodes::True.into()),
+        Some("false") => return Ok(core::OpCodes::False.into()),
+        _ => {}
+    }
+
+    // ((as const (Array I V)) value)
+    if let SExpr::List(ref l) = *c {
+        if let (2, Some(&SExpr::List(ref as_const))) = (l.len(), l.get(0)) {
+            if as_const.len() == 3 && as_const[0].atom() == Some("as") &&
+               as_const[1].atom() == Some("const") {
+                if let SExpr::List(ref sort) = as_const[2] {
+                    if sort.len() == 3 && sort[0].atom() == Some("Array") {
+                        return Ok(qf_abv::array_const(parse_sort(&sort[1])?,
+                                                      parse_sort(&sort[2])?,
+                                                      parse_bv_const(&l[1])?));
+                    }
+                }
+            }
+        }
+    }
+
+    parse_bv_const(c).map(|c| c.into())
+}
+
+fn parse_fn(f: &SExpr) -> Result<qf_abv::QF_ABV_Fn, String> {
+    use libsmt::theories::bitvec::OpCodes::*;
+
+    if let SExpr::List(ref l) = *f {
+        if l.len() >= 3 && l[0].atom() == Some("_") {
+            let n = l[2].num()?;
+            let op = match (l[1].atom(), l.len()) {
+                (Some("extract"), 4) => Extract(n, l[3].num()?),
+                (Some("zero_extend"), 3) => ZeroExtend(n),
+                (Some("sign_extend"), 3) => SignExtend(n),
+                (Some("repeat"), 3) => Repeat(n),
+                (Some("rotate_left"), 3) => RotateLeft(n),
+                (Some("rotate_right"), 3) => RotateRight(n),
+                _ => return Err(format!("Unsupported function {}", f)),
+            };
+            return Ok(op.into());
+        }
+        return Err(format!("Unsupported function {}", f));
+    }
+
+    let name = f.atom().unwrap();
+    let op = match name {
+        "=" => core::OpCodes::Cmp.into(),
+        "ite" => core::OpCodes::ITE.into(),
+        "not" => core::OpCodes::Not.into(),
+        "and" => core::OpCodes::And.into(),
+        "or" => core::OpCodes::Or.into(),
+        "xor" => core::OpCodes::Xor.into(),
+        "=>" => core::OpCodes::Imply.into(),
+        "distinct" => core::OpCodes::Distinct.into(),
+        "select" => array_ex::OpCodes::Select.into(),
+        "store" => array_ex::OpCodes::Store.into(),
+        _ => {
+            let op = match name {
+                "concat" => Concat,
+                "bvnot" => BvNot,
+                "bvand" => BvAnd,
+                "bvor" => BvOr,
+                "bvneg" => BvNeg,
+                "bvadd" => BvAdd,
+                "bvmul" => BvMul,
+                "bvudiv" => BvUDiv,
+                "bvurem" => BvURem,
+                "bvshl" => BvShl,
+                "bvlshr" => BvLShr,
+                "bvult" => BvULt,
+                "bvnand" => BvNand,
+                "bvnor" => BvNor,
+                "bvxor" => BvXor,
+                "bvxnor" => BvXnor,
+                "bvcomp" => BvComp,
+                "bvsub" => BvSub,
+                "bvsdiv" => BvSDiv,
+                "bvsrem" => BvSRem,
+                "bvsmod" => BvSMod,
+                "bvashr" => BvAShr,
+                "bvule" => BvULe,
+                "bvugt" => BvUGt,
+                "bvuge" => BvUGe,
+                "bvslt" => BvSLt,
+                "bvsle" => BvSLe,
+                "bvsgt" => BvSGt,
+                "bvsge" => BvSGe,
+                _ => return Err(format!("Unsupported function {}", name)),
+            };
+            op.into()
+        }
+    };
+    Ok(op)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libsmt::logics::qf_abv::QF_ABV_Fn::BVOps;
+    use libsmt::theories::bitvec::OpCodes;
+
+    #[test]
+    fn parse_sexpr() {
+        let exprs = SExpr::parse("(declare-fun x () (_ BitVec 8)) foo").unwrap();
+        assert_eq!(exprs.len(), 2);
+        assert_eq!(exprs[0].to_string(), "(declare-fun x () (_ BitVec 8))");
+        assert_eq!(exprs[1], SExpr::Atom("foo".to_owned()));
+        assert!(SExpr::parse("(a (b)").is_err());
+        assert!(SExpr::parse("a)").is_err());
+    }
+
+    #[test]
+    fn import_table() {
+        let table = ExprTable {
+            vars: vec![("x".to_owned(), "(_ BitVec 8)".to_owned())],
+            nodes: vec![ExprNode { op: "x".to_owned(), args: vec![] },
+                        ExprNode { op: "#x2a".to_owned(), args: vec![] },
+                        ExprNode { op: "bvadd".to_owned(), args: vec![0, 1] },
+                        ExprNode { op: "(_ extract 3 0)".to_owned(), args: vec![2] }],
+        };
+
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let nodes = Importer::new(&mut solver).import(&table).unwrap();
+        assert_eq!(nodes.len(), 4);
+        match *solver.get_node_info(nodes[1]) {
+            BVOps(OpCodes::Const(0x2a, 8)) => {}
+            ref other => panic!("Unexpected node {:?}", other),
+        }
+        match *solver.get_node_info(nodes[3]) {
+            BVOps(OpCodes::Extract(3, 0)) => {}
+            ref other => panic!("Unexpected node {:?}", other),
+        }
+        assert_eq!(solver.get_operands(nodes[2]).len(), 2);
+    }
+
+    #[test]
+    fn parse_script() {
+        let script = "(declare-fun x () (_ BitVec 8))
+                      (assert (= ((_ extract 3 0) x) (_ bv2 4)))
+                      (check-sat)";
+        let (table, roots) = parse_assertions(script).unwrap();
+        assert_eq!(table.vars, vec![("x".to_owned(), "(_ BitVec 8)".to_owned())]);
+        assert_eq!(roots, vec![3]);
+        assert_eq!(table.nodes[3], ExprNode { op: "=".to_owned(), args: vec![1, 2] });
+        assert_eq!(table.nodes[1].op, "(_ extract 3 0)");
+        assert_eq!(table.nodes[2], ExprNode { op: "(_ bv2 4)".to_owned(), args: vec![] });
+
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let nodes = Importer::new(&mut solver).import(&table).unwrap();
+        assert_eq!(solver.get_operands(nodes[3]).len(), 2);
+    }
+
+    #[test]
+    fn import_binds_vars() {
+        let table = ExprTable {
+            vars: vec![("x".to_owned(), "(_ BitVec 8)".to_owned())],
+            nodes: vec![ExprNode { op: "x".to_owned(), args: vec![] }],
+        };
+
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let x = solver.new_var(Some("x"), qf_abv::bv_sort(8));
+        let nodes = {
+            let mut importer = Importer::new(&mut solver);
+            importer.bind("x", x);
+            importer.import(&table).unwrap()
+        };
+        assert_eq!(nodes, vec![x]);
+        // Variables already declared are found by name.
+        assert_eq!(Importer::new(&mut solver).import(&table).unwrap(), vec![x]);
+    }
+}
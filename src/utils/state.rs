@@ -5,10 +5,13 @@ use r2api::structs::LRegInfo;
 use std::collections::HashMap;
 
 use serde_json::{to_string, from_reader};
-use utils::utils::{Key, new_rune_ctx};
+use utils::utils::Key;
 
-use context::context::Context;
+use context::builder::ContextBuilder;
+use context::core_dump::CoreDump;
+use engine::breakpt::Watchpoint;
 use context::rune_ctx::RuneContext;
+use context::stack::StackSetup;
 use memory::qword_mem::QWordMemory;
 use memory::seg_mem::SegMem;
 use regstore::regfile::RuneRegFile;
@@ -22,8 +25,23 @@ pub struct RInitialState {
     // end_addr: Option<u64>,
     breakpoints: Option<Vec<u64>>,
     sym_vars: Option<HashMap<Key, u64>>,
-    constants: Option<HashMap<Key, (u64, u64)>>,
+    /// Constants, with their widths in bits if they are not pointer sized.
+    constants: Option<HashMap<Key, (u64, Option<u64>)>>,
     env_vars: Option<HashMap<String, String>>,
+    watchpoints: Option<Vec<Watchpoint>>,
+    stack: Option<StackSetup>,
+    /// Initial values of system registers, see `RuneContext::set_system_reg`.
+    #[serde(default)]
+    system_regs: HashMap<String, u64>,
+    /// Registers that are not set read as fresh symbols instead of zero.
+    #[serde(default)]
+    lazy_registers: bool,
+    /// Path of a core file to start from, see `context::core_dump`.
+    #[serde(default)]
+    core_dump: Option<String>,
+    /// Base address to load a position independent executable at.
+    #[serde(default)]
+    image_base: Option<u64>,
 }
 
 impl RInitialState {
@@ -60,10 +78,44 @@ impl RInitialState {
         }
     }
 
+    pub fn add_watchpoint(&mut self, wp: Watchpoint) {
+        self.watchpoints.get_or_insert(Vec::new()).push(wp);
+    }
+
+    /// Start with a realistic stack instead of zeroed stack and base pointers.
+    pub fn set_stack(&mut self, stack: StackSetup) {
+        self.stack = Some(stack);
+    }
+
+    /// Start with the system register `reg` set to `value`.
+    pub fn set_system_reg(&mut self, reg: &str, value: u64) {
+        self.system_regs.insert(reg.to_owned(), value);
+    }
+
+    /// Let unset registers read as fresh symbols instead of zero.
+    pub fn set_lazy_registers(&mut self, lazy: bool) {
+        self.lazy_registers = lazy;
+    }
+
+    /// Load the binary at `base`, see `ContextBuilder::image_base`.
+    pub fn set_image_base(&mut self, base: u64) {
+        self.image_base = Some(base);
+    }
+
+    /// Start from the state saved in the core file at `path`.
+    pub fn set_core_dump(&mut self, path: &str) {
+        self.core_dump = Some(path.to_owned());
+    }
+
+    /// Run the program with the environment variable `name` set to `value`.
+    pub fn add_env_var(&mut self, name: &str, value: &str) {
+        self.env_vars.get_or_insert(HashMap::new()).insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Set a register, or the pointer sized word of memory at an address, to a constant.
     pub fn add_const(&mut self, const_val: (Key, u64)) {
-        // FIXME
         if let Some(ref mut constants) = self.constants {
-            constants.insert(const_val.0, (const_val.1, 64));
+            constants.insert(const_val.0, (const_val.1, None));
         }
     }
 
@@ -88,7 +140,38 @@ impl RInitialState {
 
     pub fn create_context(&self, r2: &mut R2) -> RuneContext<SegMem, RuneRegFile>
     {
-        new_rune_ctx(self.start_addr, self.sym_vars.clone(), self.constants.clone(), r2)
+        let mut builder = ContextBuilder::new().lazy_registers(self.lazy_registers);
+        if let Some(ip) = self.start_addr {
+            builder = builder.ip(ip);
+        }
+        if let Some(base) = self.image_base {
+            builder = builder.image_base(base);
+        }
+        if let Some(ref path) = self.core_dump {
+            builder = builder.core_dump(CoreDump::open(path).expect("Unable to read the core file"));
+        }
+        for (key, &width) in self.sym_vars.iter().flat_map(|syms| syms.iter()) {
+            builder = builder.symbolic(key.clone(), width as usize);
+        }
+        for (key, &(value, width)) in self.constants.iter().flat_map(|consts| consts.iter()) {
+            builder = match width {
+                Some(width) => builder.constant_sized(key.clone(), value, width as usize),
+                None => builder.constant(key.clone(), value),
+            };
+        }
+        for (name, value) in self.env_vars.iter().flat_map(|vars| vars.iter()) {
+            builder = builder.env(name, value);
+        }
+        if let Some(ref stack) = self.stack {
+            builder = builder.stack(stack.clone());
+        }
+        for (reg, &value) in &self.system_regs {
+            builder = builder.system_reg(reg, value);
+        }
+        for &wp in self.watchpoints.iter().flat_map(|wps| wps.iter()) {
+            builder = builder.watchpoint(wp);
+        }
+        builder.build(r2).expect("Unable to create the context")
     }
 }
 
@@ -101,6 +184,12 @@ impl Default for RInitialState {
             constants: Some(HashMap::new()),
             sym_vars: Some(HashMap::new()),
             env_vars: Some(HashMap::new()),
+            watchpoints: Some(Vec::new()),
+            stack: None,
+            system_regs: HashMap::new(),
+            lazy_registers: false,
+            core_dump: None,
+            image_base: None,
         }
     }
 }
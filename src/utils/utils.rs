@@ -1,29 +1,19 @@
 //! Utilities and other miscellaneous functions for `RuneContext`
 
-use r2pipe::r2::R2;
 use r2api::structs::LRegInfo;
-use r2api::api_trait::R2Api;
 
-use context::rune_ctx::RuneContext;
-use context::context::{ContextAPI};
-
-use memory::memory::Memory;
 use memory::qword_mem::QWordMemory;
-use memory::seg_mem::SegMem;
-
-use regstore::regstore::RegStore;
-use regstore::regfile::RuneRegFile;
 
 use petgraph::graph::NodeIndex;
 
+use std::collections::{HashMap, HashSet};
+
 use libsmt::backends::smtlib2::SMTLib2;
 use libsmt::logics::qf_abv;
 use libsmt::logics::qf_abv::QF_ABV_Fn::BVOps;
 use libsmt::theories::bitvec::OpCodes::*;
 use libsmt::theories::core::OpCodes::*;
 
-use std::collections::HashMap;
-
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ValType {
     Concrete(usize),
@@ -104,56 +94,81 @@ pub fn convert_to_u64<T: AsRef<str>>(s: T) -> Option<u64> {
     }
 }
 
-// This function needs to be updated to support arbitrary Ctx and mem sizes
-pub fn new_rune_ctx(
-    ip: Option<u64>,
-    syms: Option<HashMap<Key, u64>>,
-    consts: Option<HashMap<Key, (u64, u64)>>,
-    mut r2: &mut R2) -> RuneContext<SegMem, RuneRegFile> {
-
-    let mut lreginfo = r2.reg_info().unwrap();
-    let rregfile     = RuneRegFile::new(&mut lreginfo);
-
-    let bin      = r2.bin_info().unwrap().bin.unwrap();
-    let bits     = bin.bits.unwrap();
-    let endian   = bin.endian.unwrap();
-    let mut rmem = SegMem::new(bits, endian);
-
-    let mut smt = SMTLib2::new(Some(qf_abv::QF_ABV));
-    
-    let mut ctx = RuneContext::new(ip, rmem, rregfile, smt);
+// Ideally, this should be implemented for all logics. 
+// But since we are using only bitvecs, we can use this function for now I guess.
+pub fn simplify_constant(ni: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> u64 {
+    try_simplify_constant(ni, solver).expect("Unimplemented!")
+}
 
-    if let Some(sym_vars) = syms {
-        for (sym, size) in sym_vars.iter() {
-            match *sym {
-                Key::Mem(addr)    => ctx.set_mem_as_sym(addr as u64, *size as usize),
-                Key::Reg(ref reg) => ctx.set_reg_as_sym(reg),
-            };
+/// Render the expression rooted at `ni` in SMT-LIB2 syntax. Subexpressions that are used more
+/// than once are bound with `let` and rendered once, so that the result grows with the number of
+/// nodes rather than the number of paths through the expression. The bindings are numbered in
+/// the order they are rendered, so expressions of the same shape render the same, even in
+/// different solvers.
+pub fn to_smtlib(ni: NodeIndex, solver: &SMTLib2<qf_abv::QF_ABV>) -> String {
+    // Count the uses of every node below `ni`.
+    let mut uses: HashMap<NodeIndex, usize> = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![ni];
+    while let Some(node) = stack.pop() {
+        if !seen.insert(node) {
+            continue;
         }
-    }
-
-    if let Some(const_vars) = consts {
-        for (key, val) in const_vars.iter() {
-            match *key {
-                Key::Mem(addr)    => ctx.set_mem_as_const(addr as u64, val.0, val.1 as usize),
-                Key::Reg(ref reg) => ctx.set_reg_as_const(reg, val.0),
-            };
+        for op in solver.get_operands(node) {
+            *uses.entry(op).or_insert(0) += 1;
+            stack.push(op);
         }
     }
 
-    // Setting unset registers to zero!
-    for register in &lreginfo.reg_info {
-        ctx.set_reg_as_const(register.name.clone(), 0);
+    let mut names = HashMap::new();
+    let mut bindings = Vec::new();
+    let body = render(ni, solver, &uses, &mut names, &mut bindings);
+    bindings.into_iter()
+            .rev()
+            .fold(body, |body, (name, expr)| format!("(let (({} {})) {})", name, expr, body))
+}
+
+/// Render `ni` for `to_smtlib`, adding the shared subexpressions to `bindings`.
+fn render(ni: NodeIndex,
+          solver: &SMTLib2<qf_abv::QF_ABV>,
+          uses: &HashMap<NodeIndex, usize>,
+          names: &mut HashMap<NodeIndex, String>,
+          bindings: &mut Vec<(String, String)>)
+          -> String {
+    if let Some(name) = names.get(&ni) {
+        return name.clone();
+    }
+    // Operands are returned last operand first.
+    let mut operands = solver.get_operands(ni);
+    operands.reverse();
+    if operands.is_empty() {
+        return format!("{}", solver.get_node_info(ni));
+    }
+    let operands = operands.iter()
+                           .map(|op| render(*op, solver, uses, names, bindings))
+                           .collect::<Vec<_>>();
+    let expr = format!("({} {})", solver.get_node_info(ni), operands.join(" "));
+    if uses.get(&ni).map_or(false, |&n| n > 1) {
+        let name = format!("_let_{}", bindings.len());
+        bindings.push((name.clone(), expr));
+        names.insert(ni, name.clone());
+        name
+    } else {
+        expr
     }
+}
 
-    ctx
+/// Same as `simplify_constant`, but returns `None` instead of panicking when `ni` cannot be
+/// reduced to a constant (for example, if it depends on a symbolic variable).
+pub fn try_simplify_constant(ni: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> Option<u64> {
+    simplify_sized(ni, solver).map(|(c, _)| c)
 }
 
-// Ideally, this should be implemented for all logics. 
-// But since we are using only bitvecs, we can use this function for now I guess.
-pub fn simplify_constant(ni: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> u64 {
+/// The constant `ni` reduces to, with its width. Arithmetic wraps around at the width of the
+/// operands, as it does in the solver.
+fn simplify_sized(ni: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> Option<(u64, usize)> {
     let c = match solver.get_node_info(ni) {
-        &BVOps(Const(x, _)) => x,
+        &BVOps(Const(x, width)) => (mask(x, width), width),
         &BVOps(BvSub) => {
             let oper     = solver.get_operands(ni);
             let mut iter = oper.iter();
@@ -161,7 +176,9 @@ pub fn simplify_constant(ni: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) ->
             let first    = iter.next().unwrap();
             let second   = iter.next().unwrap();
 
-            simplify_constant(*second, solver) - simplify_constant(*first, solver)
+            let (lhs, width) = simplify_sized(*second, solver)?;
+            let (rhs, _) = simplify_sized(*first, solver)?;
+            (mask(lhs.wrapping_sub(rhs), width), width)
         },
         &BVOps(BvAdd) => {
             let oper     = solver.get_operands(ni);
@@ -170,10 +187,137 @@ pub fn simplify_constant(ni: NodeIndex, solver: &mut SMTLib2<qf_abv::QF_ABV>) ->
             let first    = iter.next().unwrap();
             let second   = iter.next().unwrap();
 
-            simplify_constant(*second, solver) + simplify_constant(*first, solver)
+            let (lhs, width) = simplify_sized(*second, solver)?;
+            let (rhs, _) = simplify_sized(*first, solver)?;
+            (mask(lhs.wrapping_add(rhs), width), width)
+        },
+        // Values composed of the bytes of memory, in the order of its endianness.
+        &BVOps(Concat) => {
+            let oper = solver.get_operands(ni);
+            let (low, low_width) = simplify_sized(oper[0], solver)?;
+            let (high, high_width) = simplify_sized(oper[1], solver)?;
+            let width = high_width + low_width;
+            if width > 64 {
+                return None;
+            }
+            (high << low_width | low, width)
+        },
+        &BVOps(Extract(high, low)) => {
+            let oper = solver.get_operands(ni);
+            let (value, _) = simplify_sized(oper[0], solver)?;
+            let width = (high - low + 1) as usize;
+            (mask(value >> low, width), width)
         },
-        _ => panic!("Unimplemented!"),
+        &BVOps(ZeroExtend(n)) => {
+            let oper = solver.get_operands(ni);
+            let (value, width) = simplify_sized(oper[0], solver)?;
+            (value, width + n as usize)
+        },
+        &BVOps(BvOr) | &BVOps(BvShl) | &BVOps(BvLShr) | &BVOps(BvURem) | &BVOps(BvSDiv) |
+        &BVOps(BvSRem) => {
+            let op = solver.get_node_info(ni).clone();
+            let oper = solver.get_operands(ni);
+            let (lhs, width) = simplify_sized(oper[1], solver)?;
+            let (rhs, _) = simplify_sized(oper[0], solver)?;
+            (mask(fold(&op, lhs, rhs, width)?, width), width)
+        },
+        _ => return None,
     };
 
-    c
+    Some(c)
+}
+
+/// `lhs op rhs`, for the binary operations of `simplify_sized` on values of `width` bits.
+/// Divisions by zero are left to the solver.
+fn fold(op: &qf_abv::QF_ABV_Fn, lhs: u64, rhs: u64, width: usize) -> Option<u64> {
+    let signed = |value: u64| ((value << (64 - width)) as i64) >> (64 - width);
+    let c = match *op {
+        BVOps(BvOr) => lhs | rhs,
+        BVOps(BvShl) if rhs >= width as u64 => 0,
+        BVOps(BvShl) => lhs << rhs,
+        BVOps(BvLShr) if rhs >= width as u64 => 0,
+        BVOps(BvLShr) => lhs >> rhs,
+        _ if rhs == 0 => return None,
+        BVOps(BvURem) => lhs % rhs,
+        BVOps(BvSDiv) => signed(lhs).wrapping_div(signed(rhs)) as u64,
+        BVOps(BvSRem) => signed(lhs).wrapping_rem(signed(rhs)) as u64,
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// The low `width` bits of `value`.
+pub fn mask(value: u64, width: usize) -> u64 {
+    if width >= 64 {
+        value
+    } else {
+        value & ((1 << width) - 1)
+    }
+}
+
+/// xorshift64*, for choices that have to be the same for the same seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Mixed, so that the common seed 0 does not leave xorshift at 0.
+        Rng(seed ^ 0x9e3779b97f4a7c15)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// A number below `n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// A number in `[0, 1)`.
+    pub fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use libsmt::backends::backend::SMTBackend;
+
+    /// `x` doubled `n` times, as a chain of additions that each use the previous sum twice.
+    fn doubled(solver: &mut SMTLib2<qf_abv::QF_ABV>, n: usize) -> NodeIndex {
+        let x = solver.new_var(Some("x"), qf_abv::bv_sort(64));
+        (0..n).fold(x, |sum, _| solver.assert(BvAdd, &[sum, sum]))
+    }
+
+    #[test]
+    fn smtlib_shares_subexpressions() {
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let x = solver.new_var(Some("x"), qf_abv::bv_sort(64));
+        let one = solver.new_const(Const(1, 64));
+        let sum = solver.assert(BvAdd, &[x, one]);
+        assert_eq!(to_smtlib(sum, &solver), "(bvadd x (_ bv1 64))");
+
+        let twice = solver.assert(BvMul, &[sum, sum]);
+        assert_eq!(to_smtlib(twice, &solver),
+                   "(let ((_let_0 (bvadd x (_ bv1 64)))) (bvmul _let_0 _let_0))");
+
+        // Linear in the number of nodes, where the tree would have 2^64 leaves.
+        let mut solver = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let sum = doubled(&mut solver, 64);
+        let rendered = to_smtlib(sum, &solver);
+        assert_eq!(rendered.matches("(let").count(), 63);
+        assert!(rendered.len() < 64 * 64);
+
+        // The same expression renders the same in another solver.
+        let mut other = SMTLib2::new(Some(qf_abv::QF_ABV));
+        other.new_const(Const(0, 8));
+        let other_sum = doubled(&mut other, 64);
+        assert_eq!(to_smtlib(other_sum, &other), rendered);
+    }
 }